@@ -20,3 +20,6 @@ pub mod prelude;
 
 // Re-export dvine_types for convenience
 pub use dvine_types;
+
+// Re-export dvine_vfs for convenience
+pub use dvine_vfs;
@@ -25,3 +25,11 @@ pub use dvine_types::prelude::*;
 // Re-export the entire dvine_types module for advanced usage
 #[doc(inline)]
 pub use dvine_types;
+
+// Virtual file system types
+#[doc(inline)]
+pub use dvine_vfs::{Mount as VfsMount, MountKind as VfsMountKind, Vfs, VfsError};
+
+// Re-export the entire dvine_vfs module for advanced usage
+#[doc(inline)]
+pub use dvine_vfs;
@@ -5,7 +5,7 @@
 
 use std::io::Read;
 
-use crate::file::{DvFileError, FileType};
+use crate::file::{DvFileError, FileType, ParseLimits};
 
 use super::{constants, sequence::AnimationSequence};
 
@@ -606,6 +606,20 @@ impl File {
 	/// # }
 	/// ```
 	pub fn from_bytes(data: &[u8]) -> Result<Self, DvFileError> {
+		Self::from_bytes_with_limits(data, &ParseLimits::default())
+	}
+
+	/// Parses an ANM file from bytes, rejecting files over `limits`.
+	///
+	/// Each sequence is already bounded by the loop detection of
+	/// [`ParseConfig`](super::ParseConfig); `limits` caps the total number
+	/// of frames over all sequences.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the file is too small, contains invalid data or
+	/// exceeds the frame limit
+	pub fn from_bytes_with_limits(data: &[u8], limits: &ParseLimits) -> Result<Self, DvFileError> {
 		let min_size = constants::HEADER_SIZE + constants::INDEX_TABLE_SIZE;
 		if data.len() < min_size {
 			return Err(DvFileError::insufficient_data(FileType::Anm, min_size, data.len()));
@@ -629,6 +643,7 @@ impl File {
 		// This is LEGAL and intentional - used for space optimization or animation variants.
 		// We parse each slot independently, even if offsets overlap.
 		let mut sequences = Vec::new();
+		let mut frame_count = 0;
 		let slot_windows = compute_slot_windows(&index_table, data.len());
 
 		for (slot, &word_offset_value) in index_table.iter().enumerate() {
@@ -646,6 +661,8 @@ impl File {
 			// because the slot might start at a different position in shared data
 			match AnimationSequence::from_bytes(&data[window.start..window.end]) {
 				Ok((sequence, _)) => {
+					frame_count += sequence.len();
+					limits.check_frames(FileType::Anm, frame_count)?;
 					if !sequence.is_empty() {
 						sequences.push((slot, sequence));
 					}
@@ -686,10 +703,22 @@ impl File {
 	/// # Ok(())
 	/// # }
 	/// ```
-	pub fn from_reader<R: Read>(mut reader: R) -> Result<Self, DvFileError> {
-		let mut data = Vec::new();
-		reader.read_to_end(&mut data)?;
-		Self::from_bytes(&data)
+	pub fn from_reader<R: Read>(reader: R) -> Result<Self, DvFileError> {
+		Self::from_reader_with_limits(reader, &ParseLimits::default())
+	}
+
+	/// Parses an ANM file from a reader, rejecting files over `limits`.
+	///
+	/// # Errors
+	///
+	/// Returns an error if reading fails, the data is invalid or it exceeds
+	/// the limits
+	pub fn from_reader_with_limits<R: Read>(
+		mut reader: R,
+		limits: &ParseLimits,
+	) -> Result<Self, DvFileError> {
+		let data = limits.allocation(FileType::Anm).read_to_end(&mut reader)?;
+		Self::from_bytes_with_limits(&data, limits)
 	}
 
 	/// Parses an ANM file from bytes in raw mode (without simulating jumps).
@@ -458,7 +458,7 @@ mod tests {
 	use crate::file::anm::{FrameDescriptor, ParseConfig, constants};
 
 	fn serialize_frames(frames: &[FrameDescriptor]) -> Vec<u8> {
-		frames.iter().flat_map(|frame| frame.to_bytes()).collect()
+		frames.iter().flat_map(FrameDescriptor::to_bytes).collect()
 	}
 
 	#[test]
@@ -0,0 +1,387 @@
+//! Content-based file type detection.
+//!
+//! PFT entries only carry 8-character names without extensions, so the type
+//! of an archive entry has to be inferred from its contents. Each supported
+//! format is scored by how many of its structural invariants hold, and
+//! [`FileType::detect`] returns the best match together with that score.
+//!
+//! Only KG has a real magic number; the other formats are recognized by
+//! the layout of their header and offset tables, so their scores are lower
+//! unless the tables line up exactly with the data.
+//!
+//! [`FileType::detect_prefix`] works on the first [`DETECT_PREFIX_LEN`] bytes
+//! and the total size, so archive entries can be sniffed without reading
+//! them in full.
+
+use super::{FileType, anm, efc, kg, mfd, spr};
+
+/// Number of leading bytes that cover the headers and tables of every format
+pub const DETECT_PREFIX_LEN: usize = 0x1000;
+
+/// Largest frame dimension considered plausible for sprites and cursors
+const MAX_DIMENSION: u32 = 0x1000;
+
+/// Largest frame count considered plausible for a sprite file
+const MAX_FRAMES: u32 = 0x4000;
+
+impl FileType {
+	/// Guesses the file type of raw data
+	///
+	/// # Arguments
+	///
+	/// * `data` - Complete file contents
+	///
+	/// # Returns
+	///
+	/// The most likely file type and a confidence score between `0.0` and
+	/// `1.0`, or `None` if the data matches none of the known layouts
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use dvine_types::file::{FileType, kg};
+	///
+	/// let data = kg::compress(&[0u8; 4 * 4 * 3], 4, 4).unwrap();
+	/// let (file_type, confidence) = FileType::detect(&data).unwrap();
+	/// assert_eq!(file_type, FileType::Kg);
+	/// assert!(confidence > 0.9);
+	///
+	/// assert_eq!(FileType::detect(b"plain text"), None);
+	/// ```
+	pub fn detect(data: &[u8]) -> Option<(FileType, f32)> {
+		Self::detect_prefix(data, data.len())
+	}
+
+	/// Guesses the file type from the start of a file and its total size
+	///
+	/// Offsets are checked against `size`, but only the table entries inside
+	/// `prefix` are inspected. A prefix of [`DETECT_PREFIX_LEN`] bytes covers
+	/// every header; sprite and font tables beyond it are not checked.
+	///
+	/// # Arguments
+	///
+	/// * `prefix` - Leading bytes of the file, the complete file if shorter
+	///   than [`DETECT_PREFIX_LEN`]
+	/// * `size` - Total file size in bytes
+	///
+	/// # Returns
+	///
+	/// The same as [`FileType::detect`]
+	pub fn detect_prefix(prefix: &[u8], size: usize) -> Option<(FileType, f32)> {
+		let candidates = [
+			(FileType::Kg, score_kg(prefix, size)),
+			(FileType::Anm, score_anm(prefix, size)),
+			(FileType::Efc, score_efc(prefix, size)),
+			(FileType::Mfd, score_mfd(prefix, size)),
+			(FileType::Spr, score_spr(prefix, size)),
+		];
+
+		candidates
+			.into_iter()
+			.filter_map(|(file_type, score)| score.map(|score| (file_type, score)))
+			.max_by(|a, b| a.1.total_cmp(&b.1))
+	}
+}
+
+/// Reads a little-endian `u16` at `offset`
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+	Some(u16::from_le_bytes(data.get(offset..offset + 2)?.try_into().ok()?))
+}
+
+/// Reads a little-endian `u32` at `offset`
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+	Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+/// KG: "KG" magic followed by a parseable header
+fn score_kg(data: &[u8], size: usize) -> Option<f32> {
+	if !data.starts_with(&kg::constants::MAGIC) {
+		return None;
+	}
+
+	let Ok(header) = kg::Header::from_bytes(data) else {
+		// Right magic, unknown compression or truncated header
+		return Some(0.4);
+	};
+
+	let mut score = 0.6;
+	if header.width() > 0 && header.height() > 0 {
+		score += 0.1;
+	}
+	let data_offset = header.data_offset() as usize;
+	if data_offset >= kg::constants::HEADER_SIZE && data_offset <= size {
+		score += 0.1;
+	}
+	if header.file_size() as usize == size {
+		score += 0.2;
+	}
+	Some(score)
+}
+
+/// ANM: ASCII SPR file name, zero padding and a word offset table into the
+/// animation data
+fn score_anm(data: &[u8], size: usize) -> Option<f32> {
+	if data.len() < anm::constants::ANIMATION_DATA_OFFSET {
+		return None;
+	}
+
+	let filename = &data[..anm::constants::SPR_FILENAME_FIELD_LEN];
+	let nul = filename.iter().position(|&b| b == 0)?;
+	if !filename[..nul].iter().all(u8::is_ascii_graphic) {
+		return None;
+	}
+
+	let padding = &data[anm::constants::HEADER_PADDING_OFFSET..anm::constants::HEADER_SIZE];
+	if padding.iter().any(|&b| b != 0) {
+		return None;
+	}
+
+	let mut used = 0;
+	for slot in 0..anm::constants::ANIMATION_SLOT_COUNT {
+		let word_offset = read_u16(data, anm::constants::INDEX_TABLE_OFFSET + slot * 2)?;
+		if word_offset == anm::constants::NO_ANIMATION {
+			continue;
+		}
+		let start = anm::constants::ANIMATION_DATA_OFFSET + word_offset as usize * 2;
+		if start + anm::constants::FRAME_DESCRIPTOR_SIZE > size {
+			return None;
+		}
+		used += 1;
+	}
+	// Zero-filled data would otherwise pass with every slot at offset zero
+	if used == 0 || (nul == 0 && used == anm::constants::ANIMATION_SLOT_COUNT) {
+		return None;
+	}
+
+	let mut score = 0.5;
+	if nul > 0 {
+		score += 0.2;
+		if filename[..nul].to_ascii_uppercase().ends_with(b".SPR") {
+			score += 0.2;
+		}
+	}
+	if used < anm::constants::ANIMATION_SLOT_COUNT {
+		score += 0.1;
+	}
+	Some(score)
+}
+
+/// EFC: 256 absolute offsets, zero for unused slots, ascending and pointing
+/// past the index table
+fn score_efc(data: &[u8], size: usize) -> Option<f32> {
+	let table_size = efc::INDEX_TABLE_SIZE;
+	if data.len() < table_size {
+		return None;
+	}
+
+	let mut offsets = Vec::new();
+	for id in 0..efc::MAX_EFFECTS {
+		let offset = read_u32(data, id * 4)? as usize;
+		if offset != 0 {
+			offsets.push(offset);
+		}
+	}
+
+	let first = *offsets.first()?;
+	if first < table_size || offsets.windows(2).any(|pair| pair[0] >= pair[1]) {
+		return None;
+	}
+	let last = *offsets.last()?;
+	if last + efc::SOUND_HEADER_SIZE > size {
+		return None;
+	}
+
+	let mut score = 0.6;
+	if first == table_size {
+		score += 0.3;
+	}
+	if offsets.len() > 1 {
+		score += 0.1;
+	}
+	Some(score)
+}
+
+/// MFD: header offsets that place the glyph and animation tables inside the
+/// file, with every glyph bitmap before the metadata region
+fn score_mfd(data: &[u8], size: usize) -> Option<f32> {
+	let metadata_offset = read_u32(data, mfd::constants::METADATA_OFFSET_FIELD)? as usize;
+	let animation_count = read_u32(data, mfd::constants::ANIMATION_COUNT_FIELD)? as usize;
+	let frame_count = read_u32(data, mfd::constants::FRAME_COUNT_OFFSET)? as usize;
+	let anim_entries = read_u32(data, mfd::constants::ANIM_TABLE_ENTRY_COUNT_FIELD)? as usize;
+	if frame_count == 0 {
+		return None;
+	}
+
+	let metadata_start = mfd::constants::BITMAP_DATA_START.checked_add(metadata_offset)?;
+	let glyph_table = metadata_start
+		.checked_add(animation_count.checked_mul(mfd::constants::ANIM_SEQ_ENTRY_SIZE)?)?;
+	let anim_table =
+		glyph_table.checked_add(frame_count.checked_mul(mfd::constants::GLYPH_ENTRY_SIZE)?)?;
+	let end = anim_table.checked_add(anim_entries.checked_mul(mfd::constants::ANIM_ENTRY_SIZE)?)?;
+	if end > size {
+		return None;
+	}
+
+	for frame in 0..frame_count {
+		let glyph = glyph_table + frame * mfd::constants::GLYPH_ENTRY_SIZE;
+		if glyph + mfd::constants::GLYPH_ENTRY_SIZE > data.len() {
+			break;
+		}
+		let width = read_u16(data, glyph)? as usize;
+		let height = read_u16(data, glyph + 2)? as usize;
+		let bitmap = read_u32(data, glyph + 8)? as usize;
+		let bitmap_end = mfd::constants::BITMAP_DATA_START + bitmap + width * height;
+		if width == 0 || height == 0 || bitmap_end > metadata_start {
+			return None;
+		}
+	}
+
+	let mut score = 0.5;
+	if end == size {
+		score += 0.4;
+	}
+	if animation_count > 0 {
+		score += 0.1;
+	}
+	Some(score)
+}
+
+/// SPR: frame count followed by descriptors whose color and mask ranges fit
+/// in the data area
+fn score_spr(data: &[u8], size: usize) -> Option<f32> {
+	let frame_count = read_u32(data, spr::constants::FRAME_COUNT_OFFSET)?;
+	if frame_count == 0 || frame_count > MAX_FRAMES {
+		return None;
+	}
+
+	let data_start =
+		spr::constants::HEADER_SIZE + frame_count as usize * spr::constants::FRAME_DESCRIPTOR_SIZE;
+	if data_start > size {
+		return None;
+	}
+	let data_len = size - data_start;
+
+	let mut first_color_offset = None;
+	for frame in 0..frame_count as usize {
+		let descriptor =
+			spr::constants::HEADER_SIZE + frame * spr::constants::FRAME_DESCRIPTOR_SIZE;
+		if descriptor + spr::constants::FRAME_DESCRIPTOR_SIZE > data.len() {
+			break;
+		}
+		let color_offset = read_u32(data, descriptor)? as usize;
+		let mask_offset = read_u32(data, descriptor + 4)? as usize;
+		let width = read_u32(data, descriptor + 8)?;
+		let height = read_u32(data, descriptor + 12)?;
+		if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+			return None;
+		}
+
+		let pixels = width as usize * height as usize;
+		if color_offset + pixels > data_len || mask_offset + pixels > data_len {
+			return None;
+		}
+		first_color_offset.get_or_insert(color_offset);
+	}
+
+	let mut score = 0.5;
+	let reserved = data.get(4..spr::constants::HEADER_SIZE)?;
+	if reserved.iter().all(|&b| b == 0) {
+		score += 0.2;
+	}
+	if first_color_offset == Some(0) {
+		score += 0.2;
+	}
+	if frame_count > 1 {
+		score += 0.1;
+	}
+	Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn spr_bytes() -> Vec<u8> {
+		let mut data = vec![0u8; spr::constants::HEADER_SIZE];
+		data[0] = 2;
+		for (color, mask) in [(0u32, 4u32), (8, 12)] {
+			for value in [color, mask, 2, 2, 1, 1] {
+				data.extend_from_slice(&value.to_le_bytes());
+			}
+		}
+		data.extend_from_slice(&[0xB0; 16]);
+		data
+	}
+
+	fn mfd_bytes() -> Vec<u8> {
+		let mut data = Vec::new();
+		for value in [4u32, 1, 1, 2] {
+			data.extend_from_slice(&value.to_le_bytes());
+		}
+		data.extend_from_slice(&[1, 2, 3, 4]);
+		data.extend_from_slice(&0u32.to_le_bytes());
+		data.extend_from_slice(&[2, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+		data.extend_from_slice(&[0, 0, 0, 0, 10, 0, 0, 0]);
+		data.extend_from_slice(&[0xFF; 8]);
+		data
+	}
+
+	#[test]
+	fn test_detect_kg() {
+		let data = kg::compress(&[0x40u8; 8 * 8 * 3], 8, 8).unwrap();
+		let (file_type, confidence) = FileType::detect(&data).unwrap();
+		assert_eq!(file_type, FileType::Kg);
+		assert!(confidence >= 0.99);
+	}
+
+	#[test]
+	fn test_detect_anm() {
+		let mut anm = anm::File::new();
+		anm.set_spr_filename("AGMAGIC.SPR").unwrap();
+		let mut sequence = anm::AnimationSequence::new();
+		sequence.add_hold_marker();
+		anm.set_sequence(0, sequence).unwrap();
+		let data = anm.to_bytes();
+
+		let (file_type, confidence) = FileType::detect(&data).unwrap();
+		assert_eq!(file_type, FileType::Anm);
+		assert!(confidence > 0.8);
+	}
+
+	#[test]
+	fn test_detect_efc() {
+		let mut data = vec![0u8; efc::INDEX_TABLE_SIZE];
+		data[0..4].copy_from_slice(&0x400u32.to_le_bytes());
+		data[4..8].copy_from_slice(&0x500u32.to_le_bytes());
+		data.resize(0x600, 0);
+
+		assert_eq!(FileType::detect(&data).map(|(t, _)| t), Some(FileType::Efc));
+	}
+
+	#[test]
+	fn test_detect_spr_and_mfd() {
+		assert_eq!(FileType::detect(&spr_bytes()).map(|(t, _)| t), Some(FileType::Spr));
+		assert_eq!(FileType::detect(&mfd_bytes()).map(|(t, _)| t), Some(FileType::Mfd));
+		assert!(mfd::File::from_bytes(&mfd_bytes()).is_ok());
+		assert!(spr::File::from_bytes(&spr_bytes()).unwrap().get_frame(1).is_some());
+	}
+
+	#[test]
+	fn test_detect_prefix() {
+		let data = kg::compress(&[0x40u8; 64 * 64 * 3], 64, 64).unwrap();
+		let prefix = &data[..kg::constants::HEADER_SIZE];
+		assert_eq!(FileType::detect_prefix(prefix, data.len()), FileType::detect(&data));
+
+		let spr = spr_bytes();
+		let prefix = &spr[..spr::constants::HEADER_SIZE + spr::constants::FRAME_DESCRIPTOR_SIZE];
+		assert_eq!(FileType::detect_prefix(prefix, spr.len()), FileType::detect(&spr));
+		assert_eq!(FileType::detect_prefix(prefix, prefix.len()), None);
+	}
+
+	#[test]
+	fn test_detect_unknown() {
+		assert_eq!(FileType::detect(&[]), None);
+		assert_eq!(FileType::detect(b"just some text that is not a game file"), None);
+		assert_eq!(FileType::detect(&[0u8; 4096]), None);
+	}
+}
@@ -0,0 +1,384 @@
+//! Block usage analysis and compaction for DSK/PFT pairs.
+//!
+//! Archives produced by [`ArchiveBuilder`](super::ArchiveBuilder) are dense:
+//! every block belongs to exactly one entry. Repeated in-place patching (see
+//! [`File::replace_entry`]) or hand editing can leave blocks that no entry
+//! references, and entries whose block ranges overlap. [`File::analyze`]
+//! reports these, and [`File::compact`] rewrites the archive densely.
+
+use std::{
+	fs,
+	io::{Read, Seek, Write},
+	ops::Range,
+	path::Path,
+};
+
+use crate::file::{DvFileError, FileType, pft};
+
+use super::{File, builder};
+
+/// Two PFT entries whose block ranges overlap
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Overlap {
+	/// Position of the first entry in the PFT
+	pub first: usize,
+	/// Position of the second entry in the PFT
+	pub second: usize,
+	/// Blocks referenced by both entries
+	pub blocks: Range<u32>,
+}
+
+/// Unreferenced blocks between two consecutive entries (in block order)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gap {
+	/// Position in the PFT of the entry before the gap
+	pub after: usize,
+	/// Position in the PFT of the entry after the gap
+	pub before: usize,
+	/// Unreferenced blocks
+	pub blocks: Range<u32>,
+}
+
+/// Block usage report of a DSK/PFT pair
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Analysis {
+	/// Total number of blocks in the DSK file
+	pub total_blocks: u32,
+	/// Number of blocks referenced by at least one entry
+	pub used_blocks: u32,
+	/// Ranges of blocks no entry references, in ascending order
+	pub orphan_blocks: Vec<Range<u32>>,
+	/// Entries whose block ranges overlap
+	pub overlaps: Vec<Overlap>,
+	/// Unreferenced blocks between consecutive entries
+	pub gaps: Vec<Gap>,
+	/// Positions of entries that reference blocks past the end of the DSK
+	pub out_of_range: Vec<usize>,
+}
+
+impl Analysis {
+	/// Computes the block usage of a PFT against a DSK of `total_blocks` blocks
+	pub fn new(pft: &pft::File, total_blocks: u32) -> Self {
+		// Entries that occupy at least one block, sorted by block position
+		let mut ranges: Vec<(usize, Range<u32>)> = pft
+			.entries()
+			.iter()
+			.enumerate()
+			.filter(|(_, entry)| entry.blocks_needed() > 0)
+			.map(|(i, entry)| (i, entry.index..entry.index.saturating_add(entry.blocks_needed())))
+			.collect();
+		ranges.sort_by_key(|(i, range)| (range.start, range.end, *i));
+
+		let out_of_range =
+			ranges.iter().filter(|(_, range)| range.end > total_blocks).map(|(i, _)| *i).collect();
+
+		let mut overlaps = Vec::new();
+		for (a, (first, range_a)) in ranges.iter().enumerate() {
+			for (second, range_b) in ranges[a + 1..].iter() {
+				if range_b.start >= range_a.end {
+					break;
+				}
+				let (first, second) = ((*first).min(*second), (*first).max(*second));
+				overlaps.push(Overlap {
+					first,
+					second,
+					blocks: range_b.start..range_a.end.min(range_b.end),
+				});
+			}
+		}
+
+		let mut gaps = Vec::new();
+		let mut orphan_blocks = Vec::new();
+		let mut used_blocks = 0;
+		let mut covered_end = 0u32;
+		let mut last_entry: Option<usize> = None;
+
+		for (i, range) in &ranges {
+			let start = range.start.min(total_blocks);
+			let end = range.end.min(total_blocks);
+
+			if start > covered_end {
+				orphan_blocks.push(covered_end..start);
+				if let Some(after) = last_entry {
+					gaps.push(Gap {
+						after,
+						before: *i,
+						blocks: covered_end..start,
+					});
+				}
+			}
+			if end > covered_end {
+				used_blocks += end - covered_end.max(start);
+				covered_end = end;
+			}
+			last_entry = Some(*i);
+		}
+		if covered_end < total_blocks {
+			orphan_blocks.push(covered_end..total_blocks);
+		}
+
+		Self {
+			total_blocks,
+			used_blocks,
+			orphan_blocks,
+			overlaps,
+			gaps,
+			out_of_range,
+		}
+	}
+
+	/// Returns the number of blocks no entry references
+	pub fn orphan_block_count(&self) -> u32 {
+		self.orphan_blocks.iter().map(|range| range.end - range.start).sum()
+	}
+
+	/// Returns true if every block is referenced exactly once and every
+	/// entry lies within the DSK
+	pub fn is_clean(&self) -> bool {
+		self.orphan_blocks.is_empty() && self.overlaps.is_empty() && self.out_of_range.is_empty()
+	}
+}
+
+impl std::fmt::Display for Analysis {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		writeln!(
+			f,
+			"Blocks: {} total, {} used, {} orphaned",
+			self.total_blocks,
+			self.used_blocks,
+			self.orphan_block_count()
+		)?;
+		for range in &self.orphan_blocks {
+			writeln!(f, "  Orphaned blocks {}..{}", range.start, range.end)?;
+		}
+		for gap in &self.gaps {
+			writeln!(
+				f,
+				"  Gap of {} blocks between entries {} and {}",
+				gap.blocks.end - gap.blocks.start,
+				gap.after,
+				gap.before
+			)?;
+		}
+		for overlap in &self.overlaps {
+			writeln!(
+				f,
+				"  Entries {} and {} overlap in blocks {}..{}",
+				overlap.first, overlap.second, overlap.blocks.start, overlap.blocks.end
+			)?;
+		}
+		for index in &self.out_of_range {
+			writeln!(f, "  Entry {} references blocks past the end", index)?;
+		}
+		Ok(())
+	}
+}
+
+impl<R: Read + Seek> File<R> {
+	/// Analyzes block usage of the archive
+	///
+	/// # Errors
+	///
+	/// Returns an error if determining the DSK size fails
+	///
+	/// # Examples
+	///
+	/// ```no_run
+	/// use dvine_types::file::dsk;
+	///
+	/// let mut dsk = dsk::File::open("data", "KG").unwrap();
+	/// let analysis = dsk.analyze().unwrap();
+	/// if !analysis.is_clean() {
+	///     print!("{}", analysis);
+	/// }
+	/// ```
+	pub fn analyze(&mut self) -> Result<Analysis, DvFileError> {
+		let total_blocks = u32::try_from(self.num_blocks()?).unwrap_or(u32::MAX);
+		Ok(Analysis::new(self.pft(), total_blocks))
+	}
+
+	/// Writes a compacted copy of the archive to `writer`
+	///
+	/// Entries keep their PFT order and are laid out contiguously in that
+	/// order, dropping orphaned blocks. Entries that referenced exactly the
+	/// same blocks keep sharing them; otherwise overlapping entries each get
+	/// their own copy of the data.
+	///
+	/// # Returns
+	///
+	/// The PFT metadata matching the written DSK data
+	///
+	/// # Errors
+	///
+	/// Returns an error if an entry cannot be read or writing fails
+	pub fn compact<W: Write>(&mut self, writer: &mut W) -> Result<pft::File, DvFileError> {
+		let old_entries = self.pft().entries().to_vec();
+		let mut new_entries: Vec<pft::Entry> = Vec::with_capacity(old_entries.len());
+		let mut current_block = 0u32;
+
+		for (position, entry) in old_entries.iter().enumerate() {
+			let blocks = entry.blocks_needed();
+
+			let shared = old_entries[..position]
+				.iter()
+				.zip(&new_entries)
+				.find(|(old, _)| {
+					blocks > 0 && old.index == entry.index && old.blocks_needed() == blocks
+				})
+				.map(|(_, new)| new.index);
+
+			let index = match shared {
+				Some(index) => index,
+				None if blocks == 0 => current_block,
+				None => {
+					let data = self.read_blocks(entry.index as usize, blocks as usize)?;
+					writer.write_all(&data)?;
+					let index = current_block;
+					current_block =
+						current_block.checked_add(blocks).ok_or(DvFileError::FileTooLarge {
+							file_type: FileType::Dsk,
+							size: entry.actual_size as usize,
+							blocks_needed: blocks as usize,
+							blocks_available: (u32::MAX - index) as usize,
+						})?;
+					index
+				}
+			};
+
+			new_entries.push(pft::Entry {
+				index,
+				..*entry
+			});
+		}
+
+		Ok(pft::File::new(new_entries))
+	}
+
+	/// Writes a compacted copy of the archive to the given paths
+	///
+	/// Like [`ArchiveBuilder::save_to_paths`](super::ArchiveBuilder::save_to_paths),
+	/// both files are written to temporary files first and renamed into
+	/// place afterwards, so the paths may point at the archive being read.
+	/// If the PFT rename fails, the old DSK is restored.
+	///
+	/// # Errors
+	///
+	/// Returns an error if compaction or writing either file fails
+	pub fn compact_to_paths(
+		&mut self,
+		pft_path: impl AsRef<Path>,
+		dsk_path: impl AsRef<Path>,
+	) -> Result<pft::File, DvFileError> {
+		let pft_path = pft_path.as_ref();
+		let dsk_path = dsk_path.as_ref();
+		let pft_temp = builder::temp_path(pft_path);
+		let dsk_temp = builder::temp_path(dsk_path);
+
+		let mut pft = None;
+		let result = builder::write_synced(&dsk_temp, |file| {
+			pft = Some(self.compact(file)?);
+			Ok(())
+		})
+		.and_then(|()| {
+			let bytes = pft.as_ref().map(pft::File::to_bytes).unwrap_or_default();
+			builder::write_synced(&pft_temp, |file| Ok(file.write_all(&bytes)?))
+		})
+		.and_then(|()| builder::replace_pair((&pft_temp, pft_path), (&dsk_temp, dsk_path)));
+
+		if let Err(err) = result {
+			let _ = fs::remove_file(&dsk_temp);
+			let _ = fs::remove_file(&pft_temp);
+			return Err(err);
+		}
+
+		Ok(pft.unwrap_or_default())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::file::{DSK_BLOCK_SIZE, dsk::DskMemory};
+
+	fn dsk_with(entries: Vec<pft::Entry>, blocks: usize) -> DskMemory {
+		let data = (0..blocks * DSK_BLOCK_SIZE).map(|i| (i / DSK_BLOCK_SIZE) as u8).collect();
+		File::from_bytes(data, pft::File::new(entries)).unwrap()
+	}
+
+	#[test]
+	fn test_analyze_clean_archive() {
+		let mut dsk = dsk_with(
+			vec![
+				pft::Entry::new("A", 0, 2048),
+				pft::Entry::new("EMPTY", 1, 0),
+				pft::Entry::new("B", 1, 3000),
+			],
+			3,
+		);
+		let analysis = dsk.analyze().unwrap();
+		assert!(analysis.is_clean());
+		assert_eq!(analysis.used_blocks, 3);
+		assert!(analysis.gaps.is_empty());
+	}
+
+	#[test]
+	fn test_analyze_orphans_gaps_and_overlaps() {
+		let mut dsk = dsk_with(
+			vec![
+				pft::Entry::new("A", 1, 4096),
+				pft::Entry::new("B", 2, 2048),
+				pft::Entry::new("C", 5, 100),
+				pft::Entry::new("D", 9, 100),
+			],
+			7,
+		);
+		let analysis = dsk.analyze().unwrap();
+		assert!(!analysis.is_clean());
+		assert_eq!(analysis.orphan_blocks, vec![0..1, 3..5, 6..7]);
+		assert_eq!(analysis.orphan_block_count(), 4);
+		assert_eq!(analysis.used_blocks, 3);
+		assert_eq!(
+			analysis.overlaps,
+			vec![Overlap {
+				first: 0,
+				second: 1,
+				blocks: 2..3,
+			}]
+		);
+		assert_eq!(analysis.gaps.len(), 2);
+		assert_eq!(analysis.gaps[0].blocks, 3..5);
+		assert_eq!((analysis.gaps[0].after, analysis.gaps[0].before), (1, 2));
+		assert_eq!(analysis.gaps[1].blocks, 6..7);
+		assert_eq!(analysis.out_of_range, vec![3]);
+	}
+
+	#[test]
+	fn test_compact() {
+		let mut dsk = dsk_with(
+			vec![
+				pft::Entry::new("A", 3, 10),
+				pft::Entry::new("B", 1, 4096),
+				pft::Entry::new("SAME", 3, 10),
+				pft::Entry::new("PART", 2, 100),
+			],
+			6,
+		);
+		let expected: Vec<_> = ["A", "B", "SAME", "PART"]
+			.iter()
+			.map(|name| dsk.extract_by_name(name).unwrap())
+			.collect();
+
+		let mut data = Vec::new();
+		let pft = dsk.compact(&mut data).unwrap();
+		assert_eq!(data.len(), 4 * DSK_BLOCK_SIZE);
+
+		let mut compacted = File::from_bytes(data, pft).unwrap();
+		let analysis = compacted.analyze().unwrap();
+		assert!(analysis.orphan_blocks.is_empty());
+		assert_eq!(analysis.overlaps.len(), 1);
+		assert_eq!(compacted.pft().entries()[2].index, compacted.pft().entries()[0].index);
+		for (name, expected) in ["A", "B", "SAME", "PART"].iter().zip(expected) {
+			assert_eq!(compacted.extract_by_name(name).unwrap(), expected);
+		}
+	}
+}
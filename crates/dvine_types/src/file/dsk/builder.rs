@@ -0,0 +1,467 @@
+//! Archive construction for DSK/PFT pairs.
+//!
+//! [`ArchiveBuilder`] collects named entries and lays them out the same way
+//! the original archives are laid out:
+//!
+//! - Entries appear in the PFT in insertion order, and their data appears in
+//!   the DSK in the same order, so block indices are strictly ascending.
+//! - The first entry starts at block 0 and every entry starts on the block
+//!   right after the previous one, there are no gaps.
+//! - Every entry is zero-padded up to the next [`DSK_BLOCK_SIZE`] boundary,
+//!   empty entries occupy no blocks.
+//! - Names are stored as up to 8 ASCII bytes, NUL-padded.
+
+use std::{
+	fs,
+	io::Write,
+	path::{Path, PathBuf},
+};
+
+use crate::file::{DSK_BLOCK_SIZE, DvFileError, FileType, pft};
+
+/// Maximum length of an entry name in bytes
+pub const MAX_NAME_LEN: usize = 8;
+
+/// Builder for DSK/PFT archive pairs
+///
+/// # Examples
+///
+/// ```no_run
+/// use dvine_types::file::dsk::ArchiveBuilder;
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let mut builder = ArchiveBuilder::new();
+/// builder.add_entry("TITLE", std::fs::read("TITLE")?)?;
+/// builder.add_file("extracted/BLACK")?;
+///
+/// // Writes out/KG.PFT and out/KG.DSK
+/// let pft = builder.save("out", "KG")?;
+/// assert_eq!(pft.num_entries(), 2);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ArchiveBuilder {
+	/// Entries in insertion order
+	entries: Vec<(String, Vec<u8>)>,
+}
+
+impl ArchiveBuilder {
+	/// Creates a new empty archive builder
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Appends an entry to the archive
+	///
+	/// # Arguments
+	///
+	/// * `name` - Entry name, 1 to 8 printable ASCII characters
+	/// * `data` - Entry contents
+	///
+	/// # Errors
+	///
+	/// Returns an error if the name is invalid or already used
+	/// (names are compared case-insensitively, like [`pft::File::find_entry`]),
+	/// or if the data is larger than 4 GiB
+	pub fn add_entry(&mut self, name: &str, data: impl Into<Vec<u8>>) -> Result<(), DvFileError> {
+		validate_name(name)?;
+
+		if self.has_entry(name) {
+			return Err(DvFileError::InvalidEntryName {
+				file_type: FileType::Dsk,
+				name: name.to_string(),
+				message: "duplicate entry name".to_string(),
+			});
+		}
+
+		let data = data.into();
+		if u32::try_from(data.len()).is_err() {
+			return Err(DvFileError::FileTooLarge {
+				file_type: FileType::Dsk,
+				size: data.len(),
+				blocks_needed: data.len().div_ceil(DSK_BLOCK_SIZE),
+				blocks_available: u32::MAX as usize / DSK_BLOCK_SIZE,
+			});
+		}
+
+		self.entries.push((name.to_string(), data));
+		Ok(())
+	}
+
+	/// Appends a file from disk, using its file name as entry name
+	///
+	/// # Errors
+	///
+	/// Returns an error if the file cannot be read or its name is invalid
+	pub fn add_file(&mut self, path: impl AsRef<Path>) -> Result<(), DvFileError> {
+		let path = path.as_ref();
+		let name = path.file_name().and_then(|name| name.to_str()).ok_or_else(|| {
+			DvFileError::InvalidEntryName {
+				file_type: FileType::Dsk,
+				name: path.display().to_string(),
+				message: "path has no valid file name".to_string(),
+			}
+		})?;
+
+		let data = fs::read(path)?;
+		self.add_entry(name, data)
+	}
+
+	/// Removes an entry by name, returning its data
+	pub fn remove_entry(&mut self, name: &str) -> Option<Vec<u8>> {
+		let position = self.entries.iter().position(|(n, _)| n.eq_ignore_ascii_case(name))?;
+		Some(self.entries.remove(position).1)
+	}
+
+	/// Checks if an entry with the given name exists
+	pub fn has_entry(&self, name: &str) -> bool {
+		self.entries.iter().any(|(n, _)| n.eq_ignore_ascii_case(name))
+	}
+
+	/// Returns the number of entries
+	pub fn entry_count(&self) -> usize {
+		self.entries.len()
+	}
+
+	/// Returns the entry names in archive order
+	pub fn names(&self) -> impl Iterator<Item = &str> {
+		self.entries.iter().map(|(name, _)| name.as_str())
+	}
+
+	/// Returns the total number of blocks the DSK file will occupy
+	pub fn num_blocks(&self) -> usize {
+		self.entries.iter().map(|(_, data)| data.len().div_ceil(DSK_BLOCK_SIZE)).sum()
+	}
+
+	/// Builds the PFT metadata for the current entries
+	///
+	/// # Errors
+	///
+	/// Returns an error if the archive would exceed the addressable block range
+	pub fn build_pft(&self) -> Result<pft::File, DvFileError> {
+		let mut entries = Vec::with_capacity(self.entries.len());
+		let mut current_block = 0usize;
+
+		for (name, data) in &self.entries {
+			let index = u32::try_from(current_block).map_err(|_| DvFileError::FileTooLarge {
+				file_type: FileType::Dsk,
+				size: data.len(),
+				blocks_needed: current_block,
+				blocks_available: u32::MAX as usize,
+			})?;
+			entries.push(pft::Entry::new(name, index, data.len() as u32));
+			current_block += data.len().div_ceil(DSK_BLOCK_SIZE);
+		}
+
+		Ok(pft::File::new(entries))
+	}
+
+	/// Writes the DSK data to the given writer
+	///
+	/// # Errors
+	///
+	/// Returns an error if writing fails
+	pub fn write_dsk<W: Write>(&self, writer: &mut W) -> Result<(), DvFileError> {
+		let zeros = [0u8; DSK_BLOCK_SIZE];
+
+		for (_, data) in &self.entries {
+			writer.write_all(data)?;
+
+			let padding = data.len().next_multiple_of(DSK_BLOCK_SIZE) - data.len();
+			writer.write_all(&zeros[..padding])?;
+		}
+
+		Ok(())
+	}
+
+	/// Serializes the archive to PFT metadata and DSK bytes
+	///
+	/// # Errors
+	///
+	/// Returns an error if the archive is too large
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use dvine_types::file::{DSK_BLOCK_SIZE, dsk::ArchiveBuilder};
+	///
+	/// let mut builder = ArchiveBuilder::new();
+	/// builder.add_entry("A", vec![1u8; 10]).unwrap();
+	/// builder.add_entry("B", vec![2u8; DSK_BLOCK_SIZE + 1]).unwrap();
+	///
+	/// let (pft, dsk) = builder.to_bytes().unwrap();
+	/// assert_eq!(pft.entries()[1].index, 1);
+	/// assert_eq!(dsk.len(), DSK_BLOCK_SIZE * 3);
+	/// ```
+	pub fn to_bytes(&self) -> Result<(pft::File, Vec<u8>), DvFileError> {
+		let pft = self.build_pft()?;
+
+		let mut dsk = Vec::with_capacity(self.num_blocks() * DSK_BLOCK_SIZE);
+		self.write_dsk(&mut dsk)?;
+
+		Ok((pft, dsk))
+	}
+
+	/// Saves the archive as `NAME.PFT` and `NAME.DSK` in the given directory
+	///
+	/// See [`ArchiveBuilder::save_to_paths`] for details.
+	///
+	/// # Errors
+	///
+	/// Returns an error if writing either file fails
+	pub fn save(&self, dir: impl AsRef<Path>, name: &str) -> Result<pft::File, DvFileError> {
+		let dir = dir.as_ref();
+		self.save_to_paths(dir.join(format!("{}.PFT", name)), dir.join(format!("{}.DSK", name)))
+	}
+
+	/// Saves the archive to the given PFT and DSK paths
+	///
+	/// Both files are first written and synced to temporary files next to
+	/// their destinations, and only then renamed into place. An existing
+	/// archive is therefore never left half-written: if anything fails
+	/// before the renames, the old files are untouched, and if the PFT
+	/// rename fails, the old DSK is moved back so the pair still matches.
+	///
+	/// # Returns
+	///
+	/// The PFT metadata that was written
+	///
+	/// # Errors
+	///
+	/// Returns an error if writing or renaming either file fails. If the old
+	/// DSK cannot be restored either, the error names the DSK path that no
+	/// longer matches its PFT.
+	pub fn save_to_paths(
+		&self,
+		pft_path: impl AsRef<Path>,
+		dsk_path: impl AsRef<Path>,
+	) -> Result<pft::File, DvFileError> {
+		let pft_path = pft_path.as_ref();
+		let dsk_path = dsk_path.as_ref();
+		let pft = self.build_pft()?;
+
+		let pft_temp = temp_path(pft_path);
+		let dsk_temp = temp_path(dsk_path);
+
+		let result = write_synced(&dsk_temp, |file| self.write_dsk(file))
+			.and_then(|()| write_synced(&pft_temp, |file| Ok(file.write_all(&pft.to_bytes())?)))
+			.and_then(|()| replace_pair((&pft_temp, pft_path), (&dsk_temp, dsk_path)));
+
+		if let Err(err) = result {
+			let _ = fs::remove_file(&dsk_temp);
+			let _ = fs::remove_file(&pft_temp);
+			return Err(err);
+		}
+
+		Ok(pft)
+	}
+}
+
+/// Validates an entry name
+pub(super) fn validate_name(name: &str) -> Result<(), DvFileError> {
+	let message = if name.is_empty() {
+		"name is empty"
+	} else if name.len() > MAX_NAME_LEN {
+		"name is longer than 8 bytes"
+	} else if !name.bytes().all(|b| b.is_ascii_graphic()) {
+		"name must only contain printable ASCII characters"
+	} else {
+		return Ok(());
+	};
+
+	Err(DvFileError::InvalidEntryName {
+		file_type: FileType::Dsk,
+		name: name.to_string(),
+		message: message.to_string(),
+	})
+}
+
+/// Returns the temporary path used while writing `path`
+pub(super) fn temp_path(path: &Path) -> PathBuf {
+	let mut name = path.file_name().unwrap_or_default().to_os_string();
+	name.push(".tmp");
+	path.with_file_name(name)
+}
+
+/// Creates `path`, fills it with `write` and syncs it to disk
+pub(super) fn write_synced(
+	path: &Path,
+	write: impl FnOnce(&mut std::io::BufWriter<&fs::File>) -> Result<(), DvFileError>,
+) -> Result<(), DvFileError> {
+	let file = fs::File::create(path)?;
+	let mut writer = std::io::BufWriter::new(&file);
+	write(&mut writer)?;
+	writer.flush()?;
+	drop(writer);
+	file.sync_all()?;
+	Ok(())
+}
+
+/// Returns the path an existing file is moved to while it is replaced
+fn backup_path(path: &Path) -> PathBuf {
+	let mut name = path.file_name().unwrap_or_default().to_os_string();
+	name.push(".old");
+	path.with_file_name(name)
+}
+
+/// Renames the written PFT and DSK temporary files over their destinations
+///
+/// The old DSK is moved aside before the new one takes its place. If the PFT
+/// rename fails afterwards, the old DSK is moved back, so the destinations
+/// never pair a new DSK with an old PFT. If even that fails, the returned
+/// error names the mismatched DSK path.
+pub(super) fn replace_pair(
+	(pft_temp, pft_path): (&Path, &Path),
+	(dsk_temp, dsk_path): (&Path, &Path),
+) -> Result<(), DvFileError> {
+	let backup = backup_path(dsk_path);
+	let had_dsk = dsk_path.exists();
+	if had_dsk {
+		fs::rename(dsk_path, &backup)?;
+	}
+
+	if let Err(err) = fs::rename(dsk_temp, dsk_path) {
+		if had_dsk {
+			let _ = fs::rename(&backup, dsk_path);
+		}
+		return Err(err.into());
+	}
+
+	if let Err(err) = fs::rename(pft_temp, pft_path) {
+		let restored = if had_dsk {
+			fs::rename(&backup, dsk_path)
+		} else {
+			fs::remove_file(dsk_path)
+		};
+		if let Err(restore_err) = restored {
+			return Err(std::io::Error::new(
+				err.kind(),
+				format!(
+					"renaming {} failed ({}) and the old DSK could not be restored ({}), \
+					 {} does not match {}",
+					pft_temp.display(),
+					err,
+					restore_err,
+					dsk_path.display(),
+					pft_path.display()
+				),
+			)
+			.into());
+		}
+		return Err(err.into());
+	}
+
+	if had_dsk {
+		let _ = fs::remove_file(&backup);
+	}
+	Ok(())
+}
+
+/// Builds an in-memory archive from named entries, shared by the DSK tests
+#[cfg(test)]
+pub(crate) fn test_archive(entries: &[(&str, &[u8])]) -> super::DskMemory {
+	let mut builder = ArchiveBuilder::new();
+	for (name, data) in entries {
+		builder.add_entry(name, data.to_vec()).unwrap();
+	}
+	let (pft, data) = builder.to_bytes().unwrap();
+	super::File::from_bytes(data, pft).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::file::dsk;
+
+	#[test]
+	fn test_layout_follows_insertion_order() {
+		let mut builder = ArchiveBuilder::new();
+		builder.add_entry("ZETA", vec![1u8; DSK_BLOCK_SIZE]).unwrap();
+		builder.add_entry("EMPTY", Vec::new()).unwrap();
+		builder.add_entry("ALPHA", vec![2u8; 3]).unwrap();
+
+		let (pft, data) = builder.to_bytes().unwrap();
+		let entries = pft.entries();
+		assert_eq!(entries.len(), 3);
+		assert_eq!(
+			(entries[0].name(), entries[0].index, entries[0].actual_size),
+			("ZETA".into(), 0, 2048)
+		);
+		assert_eq!(
+			(entries[1].name(), entries[1].index, entries[1].actual_size),
+			("EMPTY".into(), 1, 0)
+		);
+		assert_eq!(
+			(entries[2].name(), entries[2].index, entries[2].actual_size),
+			("ALPHA".into(), 1, 3)
+		);
+		assert_eq!(data.len(), DSK_BLOCK_SIZE * 2);
+		assert!(data[DSK_BLOCK_SIZE + 3..].iter().all(|&b| b == 0));
+
+		let mut dsk = dsk::File::from_bytes(data, pft).unwrap();
+		dsk.validate().unwrap();
+		assert_eq!(dsk.extract_by_name("ALPHA").unwrap(), vec![2u8; 3]);
+	}
+
+	#[test]
+	fn test_rejects_invalid_names() {
+		let mut builder = ArchiveBuilder::new();
+		assert!(builder.add_entry("", vec![]).is_err());
+		assert!(builder.add_entry("TOOLONGNAME", vec![]).is_err());
+		assert!(builder.add_entry("BAD NAME", vec![]).is_err());
+		builder.add_entry("TITLE", vec![0]).unwrap();
+		assert!(matches!(
+			builder.add_entry("title", vec![1]),
+			Err(DvFileError::InvalidEntryName { .. })
+		));
+		assert_eq!(builder.remove_entry("Title"), Some(vec![0]));
+		assert_eq!(builder.entry_count(), 0);
+	}
+
+	#[test]
+	fn test_save_round_trip() {
+		let dir = std::env::temp_dir().join(format!("dvine_dsk_builder_{}", std::process::id()));
+		fs::create_dir_all(&dir).unwrap();
+
+		let mut builder = ArchiveBuilder::new();
+		builder.add_entry("ONE", b"first".to_vec()).unwrap();
+		builder.add_entry("TWO", vec![7u8; 5000]).unwrap();
+		let pft = builder.save(&dir, "TEST").unwrap();
+
+		assert!(!dir.join("TEST.PFT.tmp").exists());
+		assert!(!dir.join("TEST.DSK.tmp").exists());
+
+		let mut dsk = dsk::File::open(&dir, "TEST").unwrap();
+		assert_eq!(dsk.pft(), &pft);
+		assert_eq!(dsk.extract_by_name("ONE").unwrap(), b"first");
+		assert_eq!(dsk.extract_by_name("TWO").unwrap(), vec![7u8; 5000]);
+		assert_eq!(dsk.num_blocks().unwrap(), 4);
+
+		fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn test_failed_pft_rename_restores_old_dsk() {
+		let dir = std::env::temp_dir().join(format!("dvine_dsk_restore_{}", std::process::id()));
+		fs::create_dir_all(&dir).unwrap();
+
+		let mut builder = ArchiveBuilder::new();
+		builder.add_entry("OLD", b"old".to_vec()).unwrap();
+		builder.save(&dir, "TEST").unwrap();
+		let old_dsk = fs::read(dir.join("TEST.DSK")).unwrap();
+
+		// A non-empty directory in place of the PFT makes its rename fail
+		fs::remove_file(dir.join("TEST.PFT")).unwrap();
+		fs::create_dir_all(dir.join("TEST.PFT").join("BLOCKER")).unwrap();
+
+		builder.add_entry("NEW", vec![1u8; 5000]).unwrap();
+		assert!(builder.save(&dir, "TEST").is_err());
+		assert_eq!(fs::read(dir.join("TEST.DSK")).unwrap(), old_dsk);
+		assert!(!dir.join("TEST.DSK.old").exists());
+		assert!(!dir.join("TEST.DSK.tmp").exists());
+		assert!(!dir.join("TEST.PFT.tmp").exists());
+
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
@@ -0,0 +1,1112 @@
+//! `.dsk` file format support.
+//!
+//! DSK files are block-based container files that store multiple files.
+//! Each block is 2048 bytes (0x0800). The DSK file works in conjunction with
+//! a PFT file which contains the metadata (file names, indices, sizes).
+//!
+//! # Design Philosophy
+//!
+//! This implementation treats DSK as an abstract concept - a wrapper around any
+//! `Read + Seek` implementation. This allows for maximum flexibility:
+//! - Memory-backed: `Cursor<Vec<u8>>`
+//! - File-backed: `BufReader<File>`
+//! - Network-backed: Custom network readers
+//! - Any other seekable data source
+//!
+//! # Examples
+//!
+//! ## Reading from a file
+//!
+//! ```no_run
+//! use dvine_types::file::{pft, dsk};
+//! use std::fs::File;
+//! use std::io::BufReader;
+//!
+//! let pft = pft::File::open("data.pft").unwrap();
+//! let file = File::open("data.dsk").unwrap();
+//! let mut dsk = dsk::File::new(BufReader::new(file), pft);
+//!
+//! // Extract a file by name
+//! let data = dsk.extract_by_name("SPRITE.DAT").unwrap();
+//! ```
+//!
+//! ## Reading from memory
+//!
+//! ```no_run
+//! use dvine_types::file::{pft, dsk};
+//! use std::io::Cursor;
+//!
+//! let pft = pft::File::open("data.pft").unwrap();
+//! let data = std::fs::read("data.dsk").unwrap();
+//! let mut dsk = dsk::File::new(Cursor::new(data), pft);
+//!
+//! // Same API works for memory-backed version
+//! let file_data = dsk.extract_by_index(0).unwrap();
+//! ```
+//!
+//! ## Using convenience functions
+//!
+//! ```no_run
+//! use dvine_types::file::{pft, dsk};
+//!
+//! let pft = pft::File::open("data.pft").unwrap();
+//!
+//! // File-backed (automatically uses BufReader)
+//! let mut dsk = dsk::File::open("data", "DATA").unwrap();
+//!
+//! // Memory-backed (loads entire file)
+//! let mut dsk_mem = dsk::File::from_bytes(std::fs::read("data/DATA.dsk").unwrap(), pft).unwrap();
+//! ```
+//!
+//! ## Creating an archive
+//!
+//! ```no_run
+//! use dvine_types::file::dsk::ArchiveBuilder;
+//!
+//! let mut builder = ArchiveBuilder::new();
+//! builder.add_file("extracted/TITLE").unwrap();
+//! builder.save("data", "KG").unwrap();
+//! ```
+
+use std::{
+	fs,
+	io::{self, BufReader, Cursor, Read, Seek, SeekFrom, Write},
+	path::{Path, PathBuf},
+};
+
+use super::{DSK_BLOCK_SIZE, DvFileError, FileType, pft};
+
+mod analysis;
+mod builder;
+pub mod patch;
+pub mod thumb;
+
+pub use analysis::{Analysis, Gap, Overlap};
+pub use builder::{ArchiveBuilder, MAX_NAME_LEN};
+pub use patch::{Patch, PatchOp};
+
+/// DSK File abstraction over any seekable reader
+///
+/// This structure wraps any type implementing `Read + Seek` and provides
+/// block-based access to files stored in DSK format. File metadata is
+/// provided by the associated PFT file.
+///
+/// # Type Parameters
+///
+/// * `R` - Any type implementing `Read + Seek`
+///
+/// # Examples
+///
+/// ```no_run
+/// use dvine_types::file::{pft, dsk};
+/// use std::io::Cursor;
+///
+/// let pft = pft::File::empty();
+/// let data = vec![0u8; 2048 * 10]; // 10 blocks
+/// let mut dsk = dsk::File::new(Cursor::new(data), pft);
+/// ```
+pub struct File<R> {
+	/// The underlying reader
+	reader: R,
+
+	/// Associated PFT file containing metadata
+	pft: pft::File,
+
+	/// Optional name/description for debugging
+	name: Option<String>,
+
+	/// Optional path the PFT metadata is persisted to after modifications
+	pft_path: Option<PathBuf>,
+}
+
+impl<R: Read + Seek> File<R> {
+	/// Creates a new DSK file from a reader and PFT metadata
+	///
+	/// # Arguments
+	///
+	/// * `reader` - Any type implementing `Read + Seek`
+	/// * `pft` - Associated PFT file containing metadata
+	///
+	/// # Examples
+	///
+	/// ```no_run
+	/// use dvine_types::file::{pft, dsk};
+	/// use std::io::Cursor;
+	///
+	/// let pft = pft::File::empty();
+	/// let data = vec![0u8; 2048];
+	/// let dsk = dsk::File::new(Cursor::new(data), pft);
+	/// ```
+	pub fn new(reader: R, pft: pft::File) -> Self {
+		Self {
+			reader,
+			pft,
+			name: None,
+			pft_path: None,
+		}
+	}
+
+	/// Creates a new DSK file with a name for debugging
+	///
+	/// # Arguments
+	///
+	/// * `reader` - Any type implementing `Read + Seek`
+	/// * `pft` - Associated PFT file containing metadata
+	/// * `name` - Name or description for this DSK file
+	pub fn with_name(reader: R, pft: pft::File, name: impl Into<String>) -> Self {
+		Self {
+			reader,
+			pft,
+			name: Some(name.into()),
+			pft_path: None,
+		}
+	}
+
+	/// Returns a reference to the associated PFT file
+	pub fn pft(&self) -> &pft::File {
+		&self.pft
+	}
+
+	/// Returns the name/description if set
+	pub fn name(&self) -> Option<&str> {
+		self.name.as_deref()
+	}
+
+	/// Sets the path the PFT metadata is written to after entries are
+	/// replaced or appended
+	pub fn with_pft_path(mut self, path: impl Into<PathBuf>) -> Self {
+		self.pft_path = Some(path.into());
+		self
+	}
+
+	/// Returns the path the PFT metadata is persisted to, if set
+	pub fn pft_path(&self) -> Option<&Path> {
+		self.pft_path.as_deref()
+	}
+
+	/// Returns the total size of the DSK data in bytes
+	///
+	/// # Errors
+	///
+	/// Returns an error if seeking fails
+	pub fn size(&mut self) -> Result<u64, DvFileError> {
+		let current = self.reader.stream_position()?;
+		let size = self.reader.seek(SeekFrom::End(0))?;
+		self.reader.seek(SeekFrom::Start(current))?;
+		Ok(size)
+	}
+
+	/// Returns the total number of blocks in the DSK file
+	///
+	/// # Errors
+	///
+	/// Returns an error if determining the size fails
+	pub fn num_blocks(&mut self) -> Result<usize, DvFileError> {
+		Ok((self.size()? / DSK_BLOCK_SIZE as u64) as usize)
+	}
+
+	/// Returns the number of valid files in the container
+	/// (entries with non-zero size or non-empty name)
+	pub fn num_files(&self) -> usize {
+		self.pft.entries().iter().filter(|e| e.is_valid()).count()
+	}
+
+	/// Checks if a file with the given name exists
+	pub fn contains(&self, name: &str) -> bool {
+		self.pft.find_entry(name).is_some()
+	}
+
+	/// Returns an iterator over entries only (without extracting data)
+	///
+	/// This is efficient if you only need to inspect metadata.
+	/// Only returns valid entries (entries with non-zero size or non-empty name).
+	pub fn entries(&self) -> impl Iterator<Item = &pft::Entry> {
+		self.pft.entries().iter().filter(|e| e.is_valid())
+	}
+
+	/// Reads a single block by index
+	///
+	/// # Arguments
+	///
+	/// * `index` - Zero-based block index
+	///
+	/// # Errors
+	///
+	/// Returns an error if:
+	/// - The block index is out of range
+	/// - An I/O error occurs
+	pub fn read_block(&mut self, index: usize) -> Result<Vec<u8>, DvFileError> {
+		let offset = (index * DSK_BLOCK_SIZE) as u64;
+		let total_size = self.size()?;
+
+		if offset >= total_size {
+			return Err(DvFileError::BlockOutOfRange {
+				file_type: FileType::Dsk,
+				index: index as u32,
+				total: (total_size / DSK_BLOCK_SIZE as u64) as usize,
+			});
+		}
+
+		self.reader.seek(SeekFrom::Start(offset))?;
+
+		let mut buffer = vec![0u8; DSK_BLOCK_SIZE];
+		self.reader.read_exact(&mut buffer)?;
+
+		Ok(buffer)
+	}
+
+	/// Reads multiple consecutive blocks starting from the given index
+	///
+	/// # Arguments
+	///
+	/// * `start_index` - Starting block index
+	/// * `count` - Number of blocks to read
+	///
+	/// # Errors
+	///
+	/// Returns an error if:
+	/// - Any block index is out of range
+	/// - An I/O error occurs
+	pub fn read_blocks(
+		&mut self,
+		start_index: usize,
+		count: usize,
+	) -> Result<Vec<u8>, DvFileError> {
+		let offset = (start_index * DSK_BLOCK_SIZE) as u64;
+		let bytes_to_read = count * DSK_BLOCK_SIZE;
+		let end_offset = offset + bytes_to_read as u64;
+		let total_size = self.size()?;
+
+		if end_offset > total_size {
+			return Err(DvFileError::BlockOutOfRange {
+				file_type: FileType::Dsk,
+				index: (start_index + count - 1) as u32,
+				total: (total_size / DSK_BLOCK_SIZE as u64) as usize,
+			});
+		}
+
+		self.reader.seek(SeekFrom::Start(offset))?;
+
+		let mut buffer = vec![0u8; bytes_to_read];
+		self.reader.read_exact(&mut buffer)?;
+
+		Ok(buffer)
+	}
+
+	/// Extracts file data for a given PFT entry
+	///
+	/// This reads the blocks specified by the entry's index and returns
+	/// only the actual file data (trimming any padding in the last block).
+	///
+	/// # Arguments
+	///
+	/// * `entry` - PFT entry describing the file
+	///
+	/// # Errors
+	///
+	/// Returns an error if:
+	/// - The required blocks are out of range
+	/// - The actual size exceeds available data
+	/// - An I/O error occurs
+	pub fn extract(&mut self, entry: &pft::Entry) -> Result<Vec<u8>, DvFileError> {
+		let start_index = entry.index as usize;
+		let blocks_needed = entry.blocks_needed() as usize;
+		let actual_size = entry.actual_size as usize;
+
+		// Read all blocks for this entry
+		let block_data = self.read_blocks(start_index, blocks_needed)?;
+
+		// Validate actual size
+		if actual_size > block_data.len() {
+			return Err(DvFileError::InvalidExtraction {
+				file_type: FileType::Dsk,
+				required: actual_size,
+				available: block_data.len(),
+			});
+		}
+
+		// Return only the actual file data (trim padding)
+		Ok(block_data[..actual_size].to_vec())
+	}
+
+	/// Extracts file data by entry index
+	///
+	/// # Arguments
+	///
+	/// * `index` - Index of the entry in the PFT file
+	///
+	/// # Errors
+	///
+	/// Returns an error if:
+	/// - The index is out of range
+	/// - Extraction fails
+	/// - An I/O error occurs
+	pub fn extract_by_index(&mut self, index: usize) -> Result<Vec<u8>, DvFileError> {
+		// Get entry information before borrowing self mutably
+		let entry = *self.pft.get_entry(index).ok_or_else(|| DvFileError::InvalidExtraction {
+			file_type: FileType::Dsk,
+			required: index,
+			available: self.pft.num_entries(),
+		})?;
+
+		self.extract(&entry)
+	}
+
+	/// Extracts file data by file name
+	///
+	/// # Arguments
+	///
+	/// * `name` - Name of the file to extract
+	///
+	/// # Errors
+	///
+	/// Returns an error if:
+	/// - The file is not found
+	/// - Extraction fails
+	/// - An I/O error occurs
+	pub fn extract_by_name(&mut self, name: &str) -> Result<Vec<u8>, DvFileError> {
+		// Get entry information before borrowing self mutably
+		let entry = *self.pft.find_entry(name).ok_or(DvFileError::EntryNotFound {
+			file_type: FileType::Dsk,
+			message: format!("File '{}' not found in DSK container", name),
+		})?;
+
+		self.extract(&entry)
+	}
+
+	/// Opens a streaming reader over the data of a PFT entry
+	///
+	/// Unlike [`File::extract`], this does not load the entry into memory.
+	/// The returned reader implements `Read + Seek` and is clamped to the
+	/// entry's actual size, so it can be handed directly to any `from_reader`
+	/// constructor.
+	///
+	/// # Arguments
+	///
+	/// * `entry` - PFT entry describing the file
+	///
+	/// # Errors
+	///
+	/// Returns an error if:
+	/// - The required blocks are out of range
+	/// - An I/O error occurs
+	///
+	/// # Examples
+	///
+	/// ```no_run
+	/// use dvine_types::file::{dsk, kg};
+	///
+	/// let mut dsk = dsk::File::open("data", "KG").unwrap();
+	/// let entry = *dsk.pft().find_entry("TITLE").unwrap();
+	/// let mut reader = dsk.open_entry(&entry).unwrap();
+	/// let image = kg::File::from_reader(&mut reader).unwrap();
+	/// ```
+	pub fn open_entry(&mut self, entry: &pft::Entry) -> Result<EntryReader<'_, R>, DvFileError> {
+		let start = entry.index as u64 * DSK_BLOCK_SIZE as u64;
+		let blocks_needed = entry.blocks_needed() as usize;
+		let total_size = self.size()?;
+
+		if start + (blocks_needed * DSK_BLOCK_SIZE) as u64 > total_size {
+			return Err(DvFileError::BlockOutOfRange {
+				file_type: FileType::Dsk,
+				index: (entry.index as usize + blocks_needed.max(1) - 1) as u32,
+				total: (total_size / DSK_BLOCK_SIZE as u64) as usize,
+			});
+		}
+
+		self.reader.seek(SeekFrom::Start(start))?;
+
+		Ok(EntryReader {
+			reader: &mut self.reader,
+			start,
+			len: entry.actual_size as u64,
+			pos: 0,
+		})
+	}
+
+	/// Opens a streaming reader over a file by name
+	///
+	/// # Arguments
+	///
+	/// * `name` - Name of the file to open
+	///
+	/// # Errors
+	///
+	/// Returns an error if:
+	/// - The file is not found
+	/// - The required blocks are out of range
+	/// - An I/O error occurs
+	pub fn open_entry_by_name(&mut self, name: &str) -> Result<EntryReader<'_, R>, DvFileError> {
+		let entry = *self.pft.find_entry(name).ok_or(DvFileError::EntryNotFound {
+			file_type: FileType::Dsk,
+			message: format!("File '{}' not found in DSK container", name),
+		})?;
+
+		self.open_entry(&entry)
+	}
+
+	/// Returns an iterator over all entries and their extracted data
+	///
+	/// This is useful for processing all files in the DSK container.
+	///
+	/// # Note
+	///
+	/// The iterator will read files on-demand, so it requires mutable access.
+	/// Each file is loaded into memory as it's iterated.
+	pub fn iter(&mut self) -> DskIterator<'_, R> {
+		DskIterator {
+			dsk: self,
+			index: 0,
+		}
+	}
+
+	/// Validates the DSK file structure
+	///
+	/// Checks that:
+	/// - All entries reference valid blocks
+	/// - No entries exceed the available space
+	///
+	/// # Errors
+	///
+	/// Returns an error if:
+	/// - Any entry references out-of-range blocks
+	/// - An I/O error occurs while determining file size
+	pub fn validate(&mut self) -> Result<(), DvFileError> {
+		let total_blocks = self.num_blocks()?;
+
+		for entry in self.pft.entries() {
+			let start_index = entry.index as usize;
+			let blocks_needed = entry.blocks_needed() as usize;
+			let end_index = start_index + blocks_needed;
+
+			if end_index > total_blocks {
+				return Err(DvFileError::FileTooLarge {
+					file_type: FileType::Dsk,
+					size: entry.actual_size as usize,
+					blocks_needed,
+					blocks_available: total_blocks.saturating_sub(start_index),
+				});
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Consumes this DSK file and returns the underlying reader
+	pub fn into_inner(self) -> R {
+		self.reader
+	}
+
+	/// Returns a reference to the underlying reader
+	pub fn get_ref(&self) -> &R {
+		&self.reader
+	}
+
+	/// Returns a mutable reference to the underlying reader
+	pub fn get_mut(&mut self) -> &mut R {
+		&mut self.reader
+	}
+}
+
+// In-place modification for writable sources
+impl<R: Read + Write + Seek> File<R> {
+	/// Replaces the contents of an existing entry
+	///
+	/// If the new data needs no more blocks than the old data, and no other
+	/// entry shares those blocks, it is written in place. Otherwise the data
+	/// is appended after the last block and the entry is pointed at the new
+	/// location, leaving the old blocks unreferenced.
+	///
+	/// The PFT entry is updated, and written to the PFT path if one is set
+	/// (see [`File::with_pft_path`]); otherwise the caller is responsible for
+	/// saving [`File::pft`].
+	///
+	/// # Arguments
+	///
+	/// * `name` - Name of the entry to replace
+	/// * `data` - New contents
+	///
+	/// # Returns
+	///
+	/// The updated PFT entry
+	///
+	/// # Errors
+	///
+	/// Returns an error if:
+	/// - The entry is not found
+	/// - The data is larger than 4 GiB
+	/// - An I/O error occurs
+	///
+	/// # Examples
+	///
+	/// ```no_run
+	/// use dvine_types::file::dsk;
+	///
+	/// let mut dsk = dsk::File::open_rw("data", "KG").unwrap();
+	/// let image = std::fs::read("TITLE.kg").unwrap();
+	/// dsk.replace_entry("TITLE", &image).unwrap();
+	/// ```
+	pub fn replace_entry(&mut self, name: &str, data: &[u8]) -> Result<pft::Entry, DvFileError> {
+		let position = self.pft.find_entry_index(name).ok_or(DvFileError::EntryNotFound {
+			file_type: FileType::Dsk,
+			message: format!("File '{}' not found in DSK container", name),
+		})?;
+		let actual_size = entry_size(data)?;
+
+		let old = self.pft.entries()[position];
+		let new_blocks = actual_size.div_ceil(DSK_BLOCK_SIZE as u32);
+		let shared = self.pft.entries().iter().enumerate().any(|(i, other)| {
+			i != position
+				&& other.blocks_needed() > 0
+				&& other.index < old.index + old.blocks_needed()
+				&& old.index < other.index + other.blocks_needed()
+		});
+
+		let index = if new_blocks <= old.blocks_needed() && !shared {
+			old.index
+		} else {
+			self.next_free_block()?
+		};
+
+		self.write_entry_data(index, data)?;
+
+		let entry = pft::Entry {
+			raw_name: old.raw_name,
+			index,
+			actual_size,
+		};
+		if let Some(slot) = self.pft.get_entry_mut(position) {
+			*slot = entry;
+		}
+		self.persist_pft()?;
+
+		Ok(entry)
+	}
+
+	/// Appends a new entry after the last block
+	///
+	/// The PFT is updated and persisted the same way as in
+	/// [`File::replace_entry`].
+	///
+	/// # Errors
+	///
+	/// Returns an error if:
+	/// - The name is invalid or already used
+	/// - The data is larger than 4 GiB
+	/// - An I/O error occurs
+	pub fn append_entry(&mut self, name: &str, data: &[u8]) -> Result<pft::Entry, DvFileError> {
+		builder::validate_name(name)?;
+		if self.pft.find_entry(name).is_some() {
+			return Err(DvFileError::InvalidEntryName {
+				file_type: FileType::Dsk,
+				name: name.to_string(),
+				message: "duplicate entry name".to_string(),
+			});
+		}
+		let actual_size = entry_size(data)?;
+
+		let index = self.next_free_block()?;
+		self.write_entry_data(index, data)?;
+
+		let entry = pft::Entry::new(name, index, actual_size);
+		self.pft.add_entry(entry);
+		self.persist_pft()?;
+
+		Ok(entry)
+	}
+
+	/// Removes an entry from the PFT
+	///
+	/// The entry's blocks are left in place and become unreferenced; use
+	/// [`File::compact`] to reclaim them. The PFT is persisted the same way
+	/// as in [`File::replace_entry`].
+	///
+	/// # Errors
+	///
+	/// Returns an error if the entry is not found or persisting fails
+	pub fn remove_entry(&mut self, name: &str) -> Result<pft::Entry, DvFileError> {
+		let entry = self
+			.pft
+			.find_entry_index(name)
+			.and_then(|position| self.pft.remove_entry(position))
+			.ok_or(DvFileError::EntryNotFound {
+				file_type: FileType::Dsk,
+				message: format!("File '{}' not found in DSK container", name),
+			})?;
+		self.persist_pft()?;
+
+		Ok(entry)
+	}
+
+	/// Writes the PFT metadata to the PFT path, if one is set
+	///
+	/// The file is written to a temporary file first and then renamed into
+	/// place, so an interrupted write never leaves a truncated PFT behind.
+	///
+	/// # Errors
+	///
+	/// Returns an error if flushing the DSK data or writing the PFT fails
+	pub fn persist_pft(&mut self) -> Result<(), DvFileError> {
+		self.reader.flush()?;
+
+		let Some(path) = &self.pft_path else {
+			return Ok(());
+		};
+
+		let temp = builder::temp_path(path);
+		let bytes = self.pft.to_bytes();
+		let result = builder::write_synced(&temp, |file| Ok(file.write_all(&bytes)?))
+			.and_then(|()| Ok(fs::rename(&temp, path)?));
+
+		if result.is_err() {
+			let _ = fs::remove_file(&temp);
+		}
+		result
+	}
+
+	/// Returns the first block after the end of the DSK data
+	fn next_free_block(&mut self) -> Result<u32, DvFileError> {
+		let blocks = self.size()?.div_ceil(DSK_BLOCK_SIZE as u64);
+		u32::try_from(blocks).map_err(|_| DvFileError::FileTooLarge {
+			file_type: FileType::Dsk,
+			size: blocks as usize * DSK_BLOCK_SIZE,
+			blocks_needed: blocks as usize,
+			blocks_available: u32::MAX as usize,
+		})
+	}
+
+	/// Writes entry data starting at `index`, zero-padding the last block
+	fn write_entry_data(&mut self, index: u32, data: &[u8]) -> Result<(), DvFileError> {
+		let offset = index as u64 * DSK_BLOCK_SIZE as u64;
+		let size = self.size()?;
+
+		// Fill any unaligned tail so the entry starts on a block boundary
+		if offset > size {
+			self.reader.seek(SeekFrom::Start(size))?;
+			io::copy(&mut io::repeat(0).take(offset - size), &mut self.reader)?;
+		}
+
+		self.reader.seek(SeekFrom::Start(offset))?;
+		self.reader.write_all(data)?;
+
+		let padding = data.len().next_multiple_of(DSK_BLOCK_SIZE) - data.len();
+		self.reader.write_all(&[0u8; DSK_BLOCK_SIZE][..padding])?;
+
+		Ok(())
+	}
+}
+
+/// Converts an entry length to the PFT size field
+fn entry_size(data: &[u8]) -> Result<u32, DvFileError> {
+	u32::try_from(data.len()).map_err(|_| DvFileError::FileTooLarge {
+		file_type: FileType::Dsk,
+		size: data.len(),
+		blocks_needed: data.len().div_ceil(DSK_BLOCK_SIZE),
+		blocks_available: u32::MAX as usize / DSK_BLOCK_SIZE,
+	})
+}
+
+impl File<fs::File> {
+	/// Opens a DSK/PFT pair for reading and writing
+	///
+	/// The PFT path is remembered, so [`File::replace_entry`] and
+	/// [`File::append_entry`] persist the updated metadata automatically.
+	///
+	/// # Arguments
+	///
+	/// * `dir` - Directory containing the DSK and PFT files
+	/// * `name` - Base name of the files (without extension)
+	///
+	/// # Errors
+	///
+	/// Returns an error if either file cannot be opened
+	pub fn open_rw(dir: impl AsRef<Path>, name: &str) -> Result<Self, DvFileError> {
+		let pft_path = dir.as_ref().join(format!("{}.PFT", name));
+		let dsk_path = dir.as_ref().join(format!("{}.DSK", name));
+		Self::open_rw_with_paths(pft_path, dsk_path)
+	}
+
+	/// Opens a DSK/PFT pair for reading and writing from explicit paths
+	///
+	/// # Errors
+	///
+	/// Returns an error if either file cannot be opened
+	pub fn open_rw_with_paths(
+		pft_path: impl AsRef<Path>,
+		dsk_path: impl AsRef<Path>,
+	) -> Result<Self, DvFileError> {
+		let pft = pft::File::open(pft_path.as_ref())?;
+		let dsk_path = dsk_path.as_ref();
+		let file = fs::OpenOptions::new().read(true).write(true).open(dsk_path)?;
+
+		Ok(Self::with_name(file, pft, dsk_path.display().to_string())
+			.with_pft_path(pft_path.as_ref()))
+	}
+}
+
+// Convenience constructors for common types
+impl File<BufReader<fs::File>> {
+	/// Opens a DSK file from a path with buffered reading
+	///
+	/// # Arguments
+	///
+	/// * `path` - Path to the DSK file
+	/// * `pft` - Associated PFT file containing metadata
+	///
+	/// # Errors
+	///
+	/// Returns an error if the file cannot be opened
+	///
+	/// # Examples
+	///
+	/// ```no_run
+	/// use dvine_types::file::{pft, dsk};
+	///
+	/// let pft = pft::File::open("data.pft").unwrap();
+	/// let mut dsk = dsk::File::open_with_pft("data.dsk", pft).unwrap();
+	/// ```
+	pub fn open_with_pft(path: impl AsRef<Path>, pft: pft::File) -> io::Result<Self> {
+		let path_ref = path.as_ref();
+		let file = fs::File::open(path_ref)?;
+		let reader = BufReader::new(file);
+		Ok(Self::with_name(reader, pft, path_ref.display().to_string()))
+	}
+
+	/// Opens a DSK file and its associated PFT file from a directory and base name
+	///
+	/// # Arguments
+	///
+	/// * `dir` - Directory containing the DSK and PFT files
+	/// * `name` - Base name of the files (without extension)
+	///
+	/// # Errors
+	///
+	/// Returns an error if either file cannot be opened
+	///
+	/// # Examples
+	///
+	/// ```no_run
+	/// use dvine_types::file::{pft, dsk};
+	///
+	/// let mut dsk = dsk::File::open("data", "ANM").unwrap();
+	/// ```
+	///
+	/// This will open "data/ANM.DSK" and "data/ANM.PFT"
+	pub fn open(dir: impl AsRef<Path>, name: &str) -> Result<Self, DvFileError> {
+		let pft_name = format!("{}.PFT", name);
+		let pft_path = dir.as_ref().join(pft_name);
+		let pft = pft::File::open(&pft_path)?;
+
+		let dsk_name = format!("{}.DSK", name);
+		let dsk_path = dir.as_ref().join(dsk_name);
+		let dsk = Self::open_with_pft(&dsk_path, pft)?;
+
+		Ok(dsk)
+	}
+}
+
+impl File<Cursor<Vec<u8>>> {
+	/// Creates a DSK file from a byte vector (memory-backed)
+	///
+	/// # Arguments
+	///
+	/// * `data` - Raw block data
+	/// * `pft` - Associated PFT file containing metadata
+	///
+	/// # Examples
+	///
+	/// ```no_run
+	/// use dvine_types::file::{pft, dsk};
+	///
+	/// let pft = pft::File::empty();
+	/// let data = vec![0u8; 2048 * 5]; // 5 blocks
+	/// let mut dsk = dsk::File::from_bytes(data, pft).unwrap();
+	/// ```
+	pub fn from_bytes(data: Vec<u8>, pft: pft::File) -> io::Result<Self> {
+		Ok(Self::new(Cursor::new(data), pft))
+	}
+}
+
+impl<R> std::fmt::Display for File<R> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		if let Some(name) = &self.name {
+			writeln!(f, "DSK File: {}", name)?;
+		} else {
+			writeln!(f, "DSK File")?;
+		}
+		writeln!(f, "  Contains {} files:", self.pft.num_entries())?;
+		for entry in self.pft.entries() {
+			writeln!(f, "    {}", entry)?;
+		}
+		Ok(())
+	}
+}
+
+impl<R: std::fmt::Debug> std::fmt::Debug for File<R> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("File")
+			.field("reader", &self.reader)
+			.field("pft", &self.pft)
+			.field("name", &self.name)
+			.field("pft_path", &self.pft_path)
+			.finish()
+	}
+}
+
+/// Iterator over DSK file entries and their data
+///
+/// This iterator yields `Result<(pft::Entry, Vec<u8>), DskError>` for each file
+/// in the DSK container. Files are loaded on-demand as the iterator progresses.
+///
+/// Note: This returns owned `Entry` values to avoid lifetime issues.
+///
+/// # Type Parameters
+///
+/// * `R` - The reader type implementing `Read + Seek`
+pub struct DskIterator<'a, R> {
+	dsk: &'a mut File<R>,
+	index: usize,
+}
+
+impl<'a, R: Read + Seek> Iterator for DskIterator<'a, R> {
+	type Item = Result<(pft::Entry, Vec<u8>), DvFileError>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		// Skip invalid entries
+		loop {
+			if self.index >= self.dsk.pft.num_entries() {
+				return None;
+			}
+
+			// Copy the entry (it's a small Copy type)
+			let entry = *self.dsk.pft.get_entry(self.index)?;
+			self.index += 1;
+
+			// Skip invalid entries (zero size and empty name)
+			if !entry.is_valid() {
+				continue;
+			}
+
+			let result = self.dsk.extract(&entry);
+			return Some(result.map(|data| (entry, data)));
+		}
+	}
+
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		// We can't know exactly how many valid entries remain without iterating
+		// So we provide a conservative estimate
+		let remaining = self.dsk.pft.num_entries().saturating_sub(self.index);
+		(0, Some(remaining))
+	}
+}
+
+// Note: We can't implement ExactSizeIterator anymore because we filter invalid entries
+// and can't know the exact count without consuming the iterator
+
+/// Streaming reader over the data of a single DSK entry
+///
+/// Created by [`File::open_entry`]. Positions are relative to the start of
+/// the entry, and reads stop at the entry's actual size (the padding of the
+/// last block is never returned). Seeking past the end is allowed and
+/// behaves like end of file.
+///
+/// # Type Parameters
+///
+/// * `R` - The reader type implementing `Read + Seek`
+pub struct EntryReader<'a, R> {
+	/// Underlying DSK reader, positioned at `start + pos`
+	reader: &'a mut R,
+	/// Absolute offset of the entry in the DSK file
+	start: u64,
+	/// Actual size of the entry in bytes
+	len: u64,
+	/// Current position relative to `start`
+	pos: u64,
+}
+
+impl<R> EntryReader<'_, R> {
+	/// Returns the size of the entry in bytes
+	pub fn len(&self) -> u64 {
+		self.len
+	}
+
+	/// Returns true if the entry is empty
+	pub fn is_empty(&self) -> bool {
+		self.len == 0
+	}
+
+	/// Returns the current position relative to the start of the entry
+	pub fn position(&self) -> u64 {
+		self.pos
+	}
+}
+
+impl<R: Read + Seek> Read for EntryReader<'_, R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let remaining = self.len.saturating_sub(self.pos);
+		if remaining == 0 || buf.is_empty() {
+			return Ok(0);
+		}
+
+		let max = buf.len().min(remaining.try_into().unwrap_or(usize::MAX));
+		let read = self.reader.read(&mut buf[..max])?;
+		self.pos += read as u64;
+		Ok(read)
+	}
+}
+
+impl<R: Read + Seek> Seek for EntryReader<'_, R> {
+	fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+		let target = match pos {
+			SeekFrom::Start(offset) => Some(offset),
+			SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+			SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+		};
+
+		let Some(target) = target else {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				"invalid seek to a negative or overflowing position",
+			));
+		};
+
+		// Only the in-range part needs to be mirrored on the underlying reader,
+		// reads past the end never touch it
+		self.reader.seek(SeekFrom::Start(self.start + target.min(self.len)))?;
+		self.pos = target;
+		Ok(target)
+	}
+}
+
+impl<R> std::fmt::Debug for EntryReader<'_, R> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("EntryReader")
+			.field("start", &self.start)
+			.field("len", &self.len)
+			.field("pos", &self.pos)
+			.finish()
+	}
+}
+
+/// Type alias for file-backed DSK files with buffering
+pub type DskFile = File<BufReader<fs::File>>;
+
+/// Type alias for memory-backed DSK files
+pub type DskMemory = File<Cursor<Vec<u8>>>;
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn test_dsk() -> DskMemory {
+		let mut data = vec![0u8; DSK_BLOCK_SIZE * 3];
+		data[..5].copy_from_slice(b"HELLO");
+		for (i, byte) in data[DSK_BLOCK_SIZE..DSK_BLOCK_SIZE + 3000].iter_mut().enumerate() {
+			*byte = (i % 251) as u8;
+		}
+		let pft =
+			pft::File::new(vec![pft::Entry::new("SMALL", 0, 5), pft::Entry::new("LARGE", 1, 3000)]);
+		File::from_bytes(data, pft).unwrap()
+	}
+
+	#[test]
+	fn test_entry_reader_matches_extract() {
+		let mut dsk = test_dsk();
+		for name in ["SMALL", "LARGE"] {
+			let expected = dsk.extract_by_name(name).unwrap();
+			let mut reader = dsk.open_entry_by_name(name).unwrap();
+			assert_eq!(reader.len(), expected.len() as u64);
+
+			let mut actual = Vec::new();
+			reader.read_to_end(&mut actual).unwrap();
+			assert_eq!(actual, expected);
+		}
+	}
+
+	#[test]
+	fn test_entry_reader_seek() {
+		let mut dsk = test_dsk();
+		let mut reader = dsk.open_entry_by_name("LARGE").unwrap();
+
+		assert_eq!(reader.seek(SeekFrom::Start(2048)).unwrap(), 2048);
+		let mut buf = [0u8; 4];
+		reader.read_exact(&mut buf).unwrap();
+		assert_eq!(
+			buf,
+			[(2048 % 251) as u8, (2049 % 251) as u8, (2050 % 251) as u8, (2051 % 251) as u8]
+		);
+
+		assert_eq!(reader.seek(SeekFrom::End(-2)).unwrap(), 2998);
+		let mut rest = Vec::new();
+		reader.read_to_end(&mut rest).unwrap();
+		assert_eq!(rest, [(2998 % 251) as u8, (2999 % 251) as u8]);
+
+		// Past the end reads nothing, before the start is an error
+		assert_eq!(reader.seek(SeekFrom::Current(10)).unwrap(), 3010);
+		assert_eq!(reader.read(&mut buf).unwrap(), 0);
+		assert!(reader.seek(SeekFrom::Current(-4000)).is_err());
+		assert_eq!(reader.position(), 3010);
+	}
+
+	#[test]
+	fn test_replace_entry_in_place() {
+		let mut dsk = test_dsk();
+		let entry = dsk.replace_entry("large", &[9u8; 2049]).unwrap();
+		assert_eq!((entry.index, entry.actual_size), (1, 2049));
+		assert_eq!(dsk.pft().find_entry("LARGE"), Some(&entry));
+		assert_eq!(dsk.num_blocks().unwrap(), 3);
+		assert_eq!(dsk.extract_by_name("LARGE").unwrap(), vec![9u8; 2049]);
+		assert_eq!(dsk.extract_by_name("SMALL").unwrap(), b"HELLO");
+	}
+
+	#[test]
+	fn test_replace_entry_appends_when_growing() {
+		let mut dsk = test_dsk();
+		let entry = dsk.replace_entry("SMALL", &[5u8; 3000]).unwrap();
+		assert_eq!((entry.index, entry.actual_size), (3, 3000));
+		assert_eq!(dsk.num_blocks().unwrap(), 5);
+		assert_eq!(dsk.extract_by_name("SMALL").unwrap(), vec![5u8; 3000]);
+		assert_eq!(dsk.extract_by_name("LARGE").unwrap().len(), 3000);
+
+		let appended = dsk.append_entry("NEW", b"data").unwrap();
+		assert_eq!(appended.index, 5);
+		assert_eq!(dsk.extract_by_name("NEW").unwrap(), b"data");
+		assert!(dsk.append_entry("new", b"again").is_err());
+		assert!(dsk.replace_entry("MISSING", b"").is_err());
+	}
+
+	#[test]
+	fn test_replace_entry_persists_pft() {
+		let dir = std::env::temp_dir().join(format!("dvine_dsk_rw_{}", std::process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		let mut builder = ArchiveBuilder::new();
+		builder.add_entry("ONE", b"first".to_vec()).unwrap();
+		builder.add_entry("TWO", b"second".to_vec()).unwrap();
+		builder.save(&dir, "TEST").unwrap();
+
+		let mut dsk = File::open_rw(&dir, "TEST").unwrap();
+		dsk.replace_entry("ONE", &[1u8; 4000]).unwrap();
+		drop(dsk);
+
+		let mut dsk = File::open(&dir, "TEST").unwrap();
+		assert_eq!(dsk.pft().find_entry("ONE").unwrap().index, 2);
+		assert_eq!(dsk.extract_by_name("ONE").unwrap(), vec![1u8; 4000]);
+		assert_eq!(dsk.extract_by_name("TWO").unwrap(), b"second");
+
+		fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn test_entry_reader_out_of_range() {
+		let pft = pft::File::new(vec![pft::Entry::new("BROKEN", 2, 4096)]);
+		let mut dsk = File::from_bytes(vec![0u8; DSK_BLOCK_SIZE * 3], pft).unwrap();
+		assert!(matches!(
+			dsk.open_entry_by_name("BROKEN"),
+			Err(DvFileError::BlockOutOfRange { .. })
+		));
+		assert!(matches!(
+			dsk.open_entry_by_name("MISSING"),
+			Err(DvFileError::EntryNotFound { .. })
+		));
+	}
+}
@@ -0,0 +1,808 @@
+//! Binary patches for DSK/PFT archives.
+//!
+//! A [`Patch`] records the entries that differ between a base archive and a
+//! target archive, so translations and mods can ship only what changed.
+//! Every operation that touches an existing entry carries the MD5 hash of
+//! that entry in the base archive, and applying a patch is refused unless
+//! the base archive matches.
+//!
+//! # File Format
+//!
+//! All values are little-endian.
+//!
+//! ```text
+//! Header (16 bytes):
+//!   0x00  magic "DVPT"
+//!   0x04  version (u16, currently 1)
+//!   0x06  reserved (u16, zero)
+//!   0x08  operation count (u32)
+//!   0x0C  reserved (u32, zero)
+//!
+//! Operation (32-byte header + payload):
+//!   0x00  kind (u8): 1 = add, 2 = remove, 3 = replace
+//!   0x01  reserved (3 bytes, zero)
+//!   0x04  entry name (8 bytes, NUL-padded ASCII)
+//!   0x0C  MD5 of the entry in the base archive (16 bytes, zero for add)
+//!   0x1C  payload length (u32, zero for remove)
+//!   0x20  payload
+//! ```
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use dvine_types::file::dsk::{self, Patch};
+//!
+//! // Author side
+//! let mut original = dsk::File::open("original", "KG").unwrap();
+//! let mut translated = dsk::File::open("translated", "KG").unwrap();
+//! Patch::diff(&mut original, &mut translated).unwrap().save("KG.DVP").unwrap();
+//!
+//! // User side
+//! let patch = Patch::open("KG.DVP").unwrap();
+//! let mut installed = dsk::File::open_rw("game", "KG").unwrap();
+//! patch.apply_in_place(&mut installed).unwrap();
+//! ```
+
+use std::{
+	collections::HashSet,
+	io::{self, Read, Seek, Write},
+	path::Path,
+};
+
+use md5::{Digest, Md5};
+
+use crate::file::{DvFileError, FileType, ParseLimits, pft};
+
+use super::{ArchiveBuilder, File, builder::validate_name};
+
+/// Patch file constants
+pub mod constants {
+	/// Magic bytes at the start of a patch file
+	pub const MAGIC: [u8; 4] = *b"DVPT";
+
+	/// Current patch format version
+	pub const VERSION: u16 = 1;
+
+	/// Size of the patch header in bytes
+	pub const HEADER_SIZE: usize = 16;
+
+	/// Size of an operation header in bytes
+	pub const OP_HEADER_SIZE: usize = 32;
+
+	/// Operation kind: add a new entry
+	pub const OP_ADD: u8 = 1;
+
+	/// Operation kind: remove an entry
+	pub const OP_REMOVE: u8 = 2;
+
+	/// Operation kind: replace the data of an entry
+	pub const OP_REPLACE: u8 = 3;
+}
+
+/// MD5 hash of an entry
+pub type Md5Hash = [u8; 16];
+
+/// A single patch operation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchOp {
+	/// Adds an entry that does not exist in the base archive
+	Add {
+		/// Entry name
+		name: String,
+		/// Entry data
+		data: Vec<u8>,
+	},
+
+	/// Removes an entry from the base archive
+	Remove {
+		/// Entry name
+		name: String,
+		/// MD5 of the entry in the base archive
+		source_md5: Md5Hash,
+	},
+
+	/// Replaces the data of an entry in the base archive
+	Replace {
+		/// Entry name
+		name: String,
+		/// MD5 of the entry in the base archive
+		source_md5: Md5Hash,
+		/// New entry data
+		data: Vec<u8>,
+	},
+}
+
+impl PatchOp {
+	/// Returns the name of the entry this operation applies to
+	pub fn name(&self) -> &str {
+		match self {
+			PatchOp::Add {
+				name,
+				..
+			}
+			| PatchOp::Remove {
+				name,
+				..
+			}
+			| PatchOp::Replace {
+				name,
+				..
+			} => name,
+		}
+	}
+
+	/// Returns the expected MD5 of the entry in the base archive, if any
+	pub fn source_md5(&self) -> Option<&Md5Hash> {
+		match self {
+			PatchOp::Add {
+				..
+			} => None,
+			PatchOp::Remove {
+				source_md5,
+				..
+			}
+			| PatchOp::Replace {
+				source_md5,
+				..
+			} => Some(source_md5),
+		}
+	}
+
+	/// Returns the payload of this operation, if any
+	pub fn data(&self) -> Option<&[u8]> {
+		match self {
+			PatchOp::Add {
+				data,
+				..
+			}
+			| PatchOp::Replace {
+				data,
+				..
+			} => Some(data),
+			PatchOp::Remove {
+				..
+			} => None,
+		}
+	}
+
+	/// Returns the operation kind byte used in the file format
+	fn kind(&self) -> u8 {
+		match self {
+			PatchOp::Add {
+				..
+			} => constants::OP_ADD,
+			PatchOp::Remove {
+				..
+			} => constants::OP_REMOVE,
+			PatchOp::Replace {
+				..
+			} => constants::OP_REPLACE,
+		}
+	}
+}
+
+impl std::fmt::Display for PatchOp {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			PatchOp::Add {
+				name,
+				data,
+			} => write!(f, "+ {:<8} {} bytes", name, data.len()),
+			PatchOp::Remove {
+				name,
+				..
+			} => write!(f, "- {:<8}", name),
+			PatchOp::Replace {
+				name,
+				data,
+				..
+			} => write!(f, "~ {:<8} {} bytes", name, data.len()),
+		}
+	}
+}
+
+/// Set of changes between two DSK/PFT archives
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Patch {
+	/// Operations in application order
+	ops: Vec<PatchOp>,
+}
+
+impl Patch {
+	/// Creates an empty patch
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Computes the patch that turns `base` into `target`
+	///
+	/// Entries are matched by name. Removals and replacements are listed in
+	/// base archive order, followed by additions in target archive order.
+	///
+	/// # Errors
+	///
+	/// Returns an error if an entry of either archive cannot be read
+	pub fn diff<R1: Read + Seek, R2: Read + Seek>(
+		base: &mut File<R1>,
+		target: &mut File<R2>,
+	) -> Result<Self, DvFileError> {
+		let mut ops = Vec::new();
+
+		let base_entries: Vec<pft::Entry> = base.entries().copied().collect();
+		for entry in &base_entries {
+			let name = entry.name();
+			let source_md5 = entry_md5(base, entry)?;
+
+			match target.pft().find_entry(&name).copied() {
+				None => ops.push(PatchOp::Remove {
+					name,
+					source_md5,
+				}),
+				Some(target_entry) => {
+					if entry_md5(target, &target_entry)? != source_md5 {
+						ops.push(PatchOp::Replace {
+							name,
+							source_md5,
+							data: target.extract(&target_entry)?,
+						});
+					}
+				}
+			}
+		}
+
+		let target_entries: Vec<pft::Entry> = target.entries().copied().collect();
+		for entry in &target_entries {
+			let name = entry.name();
+			if base.pft().find_entry(&name).is_none() {
+				ops.push(PatchOp::Add {
+					data: target.extract(entry)?,
+					name,
+				});
+			}
+		}
+
+		Ok(Self {
+			ops,
+		})
+	}
+
+	/// Appends an operation
+	///
+	/// # Errors
+	///
+	/// Returns [`DvFileError::InvalidEntryName`] if the entry name does not
+	/// fit the 8-byte PFT name field
+	pub fn push(&mut self, op: PatchOp) -> Result<(), DvFileError> {
+		validate_name(op.name())?;
+		self.ops.push(op);
+		Ok(())
+	}
+
+	/// Returns the operations in application order
+	pub fn ops(&self) -> &[PatchOp] {
+		&self.ops
+	}
+
+	/// Returns the number of operations
+	pub fn len(&self) -> usize {
+		self.ops.len()
+	}
+
+	/// Returns true if the patch contains no operations
+	pub fn is_empty(&self) -> bool {
+		self.ops.is_empty()
+	}
+
+	/// Checks that the patch applies to `base`
+	///
+	/// Every entry name must be valid and appear in one operation only.
+	/// Every removed or replaced entry must exist with the recorded MD5 hash,
+	/// and no added entry may already exist.
+	///
+	/// # Errors
+	///
+	/// Returns [`DvFileError::InvalidEntryName`] for an invalid or repeated
+	/// name, [`DvFileError::BaseMismatch`] for the first operation that does
+	/// not match, or an error if an entry cannot be read
+	pub fn check<R: Read + Seek>(&self, base: &mut File<R>) -> Result<(), DvFileError> {
+		let mut names = HashSet::new();
+		for op in &self.ops {
+			validate_name(op.name())?;
+			if !names.insert(op.name().to_ascii_uppercase()) {
+				return Err(DvFileError::InvalidEntryName {
+					file_type: FileType::Patch,
+					name: op.name().to_string(),
+					message: "entry has more than one operation".to_string(),
+				});
+			}
+		}
+
+		for op in &self.ops {
+			let existing = base.pft().find_entry(op.name()).copied();
+
+			match (op.source_md5(), existing) {
+				(None, Some(_)) => {
+					return Err(mismatch(op.name(), "entry to add already exists"));
+				}
+				(Some(_), None) => {
+					return Err(mismatch(op.name(), "entry is missing"));
+				}
+				(Some(expected), Some(entry)) => {
+					if entry_md5(base, &entry)? != *expected {
+						return Err(mismatch(op.name(), "entry contents differ"));
+					}
+				}
+				(None, None) => {}
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Applies the patch, producing a new archive
+	///
+	/// The result keeps the base archive's entry order, with replaced entries
+	/// in their original position and added entries at the end. Unused
+	/// (empty) PFT slots of the base archive are not carried over.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the base archive does not match (see
+	/// [`Patch::check`]) or an entry cannot be read
+	pub fn apply<R: Read + Seek>(&self, base: &mut File<R>) -> Result<ArchiveBuilder, DvFileError> {
+		self.check(base)?;
+
+		let mut builder = ArchiveBuilder::new();
+		let base_entries: Vec<pft::Entry> = base.entries().copied().collect();
+
+		for entry in &base_entries {
+			let name = entry.name();
+			match self
+				.ops
+				.iter()
+				.find(|op| op.source_md5().is_some() && op.name().eq_ignore_ascii_case(&name))
+			{
+				Some(PatchOp::Remove {
+					..
+				}) => {}
+				Some(PatchOp::Replace {
+					data,
+					..
+				}) => builder.add_entry(&name, data.clone())?,
+				_ => builder.add_entry(&name, base.extract(entry)?)?,
+			}
+		}
+
+		for op in &self.ops {
+			if let PatchOp::Add {
+				name,
+				data,
+			} = op
+			{
+				builder.add_entry(name, data.clone())?;
+			}
+		}
+
+		Ok(builder)
+	}
+
+	/// Applies the patch to a writable archive in place
+	///
+	/// Uses [`File::remove_entry`], [`File::replace_entry`] and
+	/// [`File::append_entry`], so only changed entries are written. The base
+	/// archive is checked before anything is modified.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the base archive does not match or writing fails
+	pub fn apply_in_place<R: Read + Write + Seek>(
+		&self,
+		dsk: &mut File<R>,
+	) -> Result<(), DvFileError> {
+		self.check(dsk)?;
+
+		for op in &self.ops {
+			match op {
+				PatchOp::Add {
+					name,
+					data,
+				} => {
+					dsk.append_entry(name, data)?;
+				}
+				PatchOp::Remove {
+					name,
+					..
+				} => {
+					dsk.remove_entry(name)?;
+				}
+				PatchOp::Replace {
+					name,
+					data,
+					..
+				} => {
+					dsk.replace_entry(name, data)?;
+				}
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Opens a patch file from disk
+	///
+	/// # Errors
+	///
+	/// Returns an error if the file cannot be read or is not a valid patch
+	pub fn open(path: impl AsRef<Path>) -> Result<Self, DvFileError> {
+		let mut file = io::BufReader::new(std::fs::File::open(path)?);
+		Self::from_reader(&mut file)
+	}
+
+	/// Saves the patch to disk
+	///
+	/// # Errors
+	///
+	/// Returns an error if serialization or writing fails
+	pub fn save(&self, path: impl AsRef<Path>) -> Result<(), DvFileError> {
+		let mut file = io::BufWriter::new(std::fs::File::create(path)?);
+		self.write_to(&mut file)?;
+		file.flush()?;
+		Ok(())
+	}
+
+	/// Loads a patch from a byte slice
+	///
+	/// # Errors
+	///
+	/// Returns an error if the data is not a valid patch
+	pub fn from_bytes(data: &[u8]) -> Result<Self, DvFileError> {
+		Self::from_reader(&mut io::Cursor::new(data))
+	}
+
+	/// Loads a patch from a byte slice, rejecting patches over `limits`
+	///
+	/// # Errors
+	///
+	/// Returns an error if the data is not a valid patch or exceeds the limits
+	pub fn from_bytes_with_limits(data: &[u8], limits: &ParseLimits) -> Result<Self, DvFileError> {
+		Self::from_reader_with_limits(&mut io::Cursor::new(data), limits)
+	}
+
+	/// Loads a patch from any reader
+	///
+	/// # Errors
+	///
+	/// Returns an error if the data is not a valid patch
+	pub fn from_reader<R: Read>(reader: &mut R) -> Result<Self, DvFileError> {
+		Self::from_reader_with_limits(reader, &ParseLimits::default())
+	}
+
+	/// Loads a patch from any reader, rejecting patches over `limits`
+	///
+	/// The operation count is capped by [`ParseLimits::max_entries`] and the
+	/// payloads together by [`ParseLimits::max_allocation`].
+	///
+	/// # Errors
+	///
+	/// Returns an error if the data is not a valid patch or exceeds the limits
+	pub fn from_reader_with_limits<R: Read>(
+		reader: &mut R,
+		limits: &ParseLimits,
+	) -> Result<Self, DvFileError> {
+		let mut header = [0u8; constants::HEADER_SIZE];
+		read_exact(reader, &mut header, 0)?;
+
+		if header[0..4] != constants::MAGIC {
+			return Err(DvFileError::invalid_magic(
+				FileType::Patch,
+				&constants::MAGIC,
+				&header[0..4],
+			));
+		}
+
+		let version = u16::from_le_bytes([header[4], header[5]]);
+		if version != constants::VERSION {
+			return Err(DvFileError::InvalidHeader {
+				file_type: FileType::Patch,
+				message: format!("Unsupported patch version {}", version),
+			});
+		}
+
+		let op_count = u32::from_le_bytes(header[8..12].try_into()?);
+		limits.check_entries(FileType::Patch, op_count as usize)?;
+		let mut allocation = limits.allocation(FileType::Patch);
+		let mut ops = Vec::new();
+		let mut offset = constants::HEADER_SIZE;
+
+		for _ in 0..op_count {
+			let mut op_header = [0u8; constants::OP_HEADER_SIZE];
+			read_exact(reader, &mut op_header, offset)?;
+			offset += constants::OP_HEADER_SIZE;
+
+			let name =
+				String::from_utf8_lossy(&op_header[4..12]).trim_end_matches('\0').to_string();
+			let source_md5: Md5Hash = op_header[12..28].try_into()?;
+			let len = u32::from_le_bytes(op_header[28..32].try_into()?) as usize;
+			allocation.reserve(len)?;
+
+			// Read incrementally so a corrupt length can't trigger a huge allocation
+			let mut data = Vec::new();
+			let read = reader.by_ref().take(len as u64).read_to_end(&mut data)?;
+			if read != len {
+				return Err(DvFileError::insufficient_data(
+					FileType::Patch,
+					offset + len,
+					offset + read,
+				));
+			}
+			offset += len;
+
+			let op = match op_header[0] {
+				constants::OP_ADD => PatchOp::Add {
+					name,
+					data,
+				},
+				constants::OP_REMOVE => PatchOp::Remove {
+					name,
+					source_md5,
+				},
+				constants::OP_REPLACE => PatchOp::Replace {
+					name,
+					source_md5,
+					data,
+				},
+				kind => {
+					return Err(DvFileError::InvalidHeader {
+						file_type: FileType::Patch,
+						message: format!("Unknown operation kind {} for entry '{}'", kind, name),
+					});
+				}
+			};
+			ops.push(op);
+		}
+
+		Ok(Self {
+			ops,
+		})
+	}
+
+	/// Serializes the patch to bytes
+	///
+	/// # Errors
+	///
+	/// Returns an error if a payload is larger than 4 GiB
+	pub fn to_bytes(&self) -> Result<Vec<u8>, DvFileError> {
+		let mut buffer = Vec::new();
+		self.write_to(&mut buffer)?;
+		Ok(buffer)
+	}
+
+	/// Writes the patch to the given writer
+	///
+	/// # Errors
+	///
+	/// Returns an error if an entry name is invalid, a payload is larger than
+	/// 4 GiB or writing fails
+	pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), DvFileError> {
+		// Names are checked up front so no entry is written truncated
+		for op in &self.ops {
+			validate_name(op.name())?;
+		}
+
+		let mut header = [0u8; constants::HEADER_SIZE];
+		header[0..4].copy_from_slice(&constants::MAGIC);
+		header[4..6].copy_from_slice(&constants::VERSION.to_le_bytes());
+		header[8..12].copy_from_slice(&(self.ops.len() as u32).to_le_bytes());
+		writer.write_all(&header)?;
+
+		for op in &self.ops {
+			let data = op.data().unwrap_or_default();
+			let len = u32::try_from(data.len()).map_err(|_| DvFileError::FileTooLarge {
+				file_type: FileType::Patch,
+				size: data.len(),
+				blocks_needed: data.len(),
+				blocks_available: u32::MAX as usize,
+			})?;
+
+			let mut op_header = [0u8; constants::OP_HEADER_SIZE];
+			op_header[0] = op.kind();
+			op_header[4..12].copy_from_slice(&pft::Entry::new(op.name(), 0, 0).raw_name);
+			op_header[12..28].copy_from_slice(op.source_md5().unwrap_or(&[0u8; 16]));
+			op_header[28..32].copy_from_slice(&len.to_le_bytes());
+
+			writer.write_all(&op_header)?;
+			writer.write_all(data)?;
+		}
+
+		Ok(())
+	}
+}
+
+impl std::fmt::Display for Patch {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		writeln!(f, "Patch with {} operations:", self.ops.len())?;
+		for op in &self.ops {
+			writeln!(f, "  {}", op)?;
+		}
+		Ok(())
+	}
+}
+
+/// Computes the MD5 hash of an entry by streaming it from the archive
+fn entry_md5<R: Read + Seek>(
+	dsk: &mut File<R>,
+	entry: &pft::Entry,
+) -> Result<Md5Hash, DvFileError> {
+	let mut hasher = Md5::new();
+	io::copy(&mut dsk.open_entry(entry)?, &mut hasher)?;
+	Ok(hasher.finalize().into())
+}
+
+/// Creates a base mismatch error
+fn mismatch(name: &str, message: &str) -> DvFileError {
+	DvFileError::BaseMismatch {
+		file_type: FileType::Patch,
+		name: name.to_string(),
+		message: message.to_string(),
+	}
+}
+
+/// Reads exactly `buf.len()` bytes, reporting truncation as insufficient data
+fn read_exact<R: Read>(reader: &mut R, buf: &mut [u8], offset: usize) -> Result<(), DvFileError> {
+	reader.read_exact(buf).map_err(|err| match err.kind() {
+		io::ErrorKind::UnexpectedEof => {
+			DvFileError::insufficient_data(FileType::Patch, offset + buf.len(), offset)
+		}
+		_ => err.into(),
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::file::dsk::{DskMemory, builder::test_archive};
+
+	fn contents(dsk: &mut DskMemory) -> Vec<(String, Vec<u8>)> {
+		dsk.iter()
+			.map(|item| item.map(|(entry, data)| (entry.name(), data)))
+			.collect::<Result<_, _>>()
+			.unwrap()
+	}
+
+	#[test]
+	fn test_diff_and_apply() {
+		let mut base = test_archive(&[("KEEP", b"same"), ("GONE", b"old"), ("EDIT", b"before")]);
+		let mut target =
+			test_archive(&[("KEEP", b"same"), ("EDIT", &[7u8; 3000]), ("NEW", b"added")]);
+
+		let patch = Patch::diff(&mut base, &mut target).unwrap();
+		let kinds: Vec<_> =
+			patch.ops().iter().map(|op| (op.kind(), op.name().to_string())).collect();
+		assert_eq!(
+			kinds,
+			vec![
+				(constants::OP_REMOVE, "GONE".to_string()),
+				(constants::OP_REPLACE, "EDIT".to_string()),
+				(constants::OP_ADD, "NEW".to_string()),
+			]
+		);
+
+		let (pft, data) = patch.apply(&mut base).unwrap().to_bytes().unwrap();
+		let mut patched = File::from_bytes(data, pft).unwrap();
+		assert_eq!(contents(&mut patched), contents(&mut target));
+
+		patch.apply_in_place(&mut base).unwrap();
+		let mut in_place = contents(&mut base);
+		in_place.sort();
+		let mut expected = contents(&mut target);
+		expected.sort();
+		assert_eq!(in_place, expected);
+	}
+
+	#[test]
+	fn test_refuses_mismatching_base() {
+		let mut base = test_archive(&[("EDIT", b"before")]);
+		let mut target = test_archive(&[("EDIT", b"after"), ("NEW", b"added")]);
+		let patch = Patch::diff(&mut base, &mut target).unwrap();
+
+		let mut other = test_archive(&[("EDIT", b"different")]);
+		assert!(matches!(patch.apply(&mut other), Err(DvFileError::BaseMismatch { .. })));
+
+		let mut missing = test_archive(&[("OTHER", b"before")]);
+		assert!(matches!(patch.check(&mut missing), Err(DvFileError::BaseMismatch { .. })));
+
+		// Already applied: the entry to add exists and contents changed
+		assert!(patch.apply_in_place(&mut target).is_err());
+		assert_eq!(target.extract_by_name("EDIT").unwrap(), b"after");
+	}
+
+	#[test]
+	fn test_serialization_round_trip() {
+		let mut patch = Patch::new();
+		patch
+			.push(PatchOp::Add {
+				name: "NEW".to_string(),
+				data: vec![1, 2, 3],
+			})
+			.unwrap();
+		patch
+			.push(PatchOp::Remove {
+				name: "GONE".to_string(),
+				source_md5: [0xAB; 16],
+			})
+			.unwrap();
+		patch
+			.push(PatchOp::Replace {
+				name: "EDIT".to_string(),
+				source_md5: [0xCD; 16],
+				data: vec![9; 100],
+			})
+			.unwrap();
+
+		let bytes = patch.to_bytes().unwrap();
+		assert_eq!(bytes.len(), constants::HEADER_SIZE + 3 * constants::OP_HEADER_SIZE + 103);
+		assert_eq!(Patch::from_bytes(&bytes).unwrap(), patch);
+
+		assert!(Patch::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err().is_insufficient_data());
+		assert!(Patch::from_bytes(b"NOPE").is_err());
+		let mut bad_magic = bytes.clone();
+		bad_magic[0] = b'X';
+		assert!(Patch::from_bytes(&bad_magic).unwrap_err().is_invalid_magic());
+
+		let mut bad_version = bytes.clone();
+		bad_version[4] = 9;
+		assert!(Patch::from_bytes(&bad_version).unwrap_err().is_invalid_header());
+		let mut bad_kind = bytes.clone();
+		bad_kind[constants::HEADER_SIZE] = 0x7F;
+		let error = Patch::from_bytes(&bad_kind).unwrap_err();
+		assert!(error.is_invalid_header() && !error.is_decompression_error());
+	}
+
+	#[test]
+	fn test_invalid_patch_leaves_archive_untouched() {
+		let mut base = test_archive(&[("EDIT", b"before")]);
+		let mut target = test_archive(&[("EDIT", b"after")]);
+		let before = contents(&mut base);
+
+		// A valid replace followed by two adds of the same entry
+		let mut patch = Patch::diff(&mut base, &mut target).unwrap();
+		for name in ["NEW", "new"] {
+			patch
+				.push(PatchOp::Add {
+					name: name.to_string(),
+					data: b"added".to_vec(),
+				})
+				.unwrap();
+		}
+		let error = patch.apply_in_place(&mut base).unwrap_err();
+		assert!(matches!(error, DvFileError::InvalidEntryName { .. }), "{}", error);
+		assert_eq!(contents(&mut base), before);
+
+		// An add with a name that cannot be stored
+		let mut patch = Patch::diff(&mut base, &mut target).unwrap();
+		patch.ops.push(PatchOp::Add {
+			name: "NEW ENTRY".to_string(),
+			data: b"added".to_vec(),
+		});
+		assert!(patch.apply_in_place(&mut base).is_err());
+		assert!(patch.apply(&mut base).is_err());
+		assert_eq!(contents(&mut base), before);
+	}
+
+	#[test]
+	fn test_rejects_long_names() {
+		let long = PatchOp::Remove {
+			name: "TOOLONGNAME".to_string(),
+			source_md5: [0; 16],
+		};
+		let mut patch = Patch::new();
+		assert!(matches!(patch.push(long.clone()), Err(DvFileError::InvalidEntryName { .. })));
+		assert!(patch.is_empty());
+
+		// Never written truncated, even when built without push
+		let patch = Patch {
+			ops: vec![long],
+		};
+		assert!(matches!(patch.to_bytes(), Err(DvFileError::InvalidEntryName { .. })));
+	}
+}
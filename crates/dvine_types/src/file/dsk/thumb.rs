@@ -0,0 +1,469 @@
+//! Thumbnail regeneration for `THUMB.DSK`.
+//!
+//! `THUMB.DSK` holds small `.KG` previews of the CGs stored in `KG.DSK`. A
+//! thumbnail is paired with its CG by entry name, so replacing a CG leaves a
+//! stale preview behind. [`ThumbnailBuilder`] rebuilds every paired
+//! thumbnail from its CG:
+//!
+//! 1. Each thumbnail entry is mapped to the `KG.DSK` entry of the same name
+//!    (case-insensitive), or to the name returned by a custom mapping.
+//! 2. The CG is decoded and downsampled to the dimensions of the existing
+//!    thumbnail with a [`Filter`], so the output matches the shipped sizes.
+//! 3. The result is quantized to a 256-color palette and re-encoded as KG.
+//!
+//! Entries without a source CG, entries that are not KG images, and entries
+//! whose CG cannot be decoded (such as Type 2/3 CGs unless
+//! [`with_decode_options`](ThumbnailBuilder::with_decode_options) enables
+//! them) are copied unchanged. The regenerated entries keep the order of the original
+//! archive, so the new `THUMB.DSK`/`THUMB.PFT` pair is a drop-in replacement.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use dvine_types::file::dsk::{self, thumb::ThumbnailBuilder};
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut cgs = dsk::File::open("game", "KG")?;
+//! let mut thumbs = dsk::File::open("game", "THUMB")?;
+//!
+//! let (archive, report) = ThumbnailBuilder::new()
+//!     .with_new_entry("CG99", "CG99")
+//!     .regenerate(&mut cgs, &mut thumbs)?;
+//! for entry in &report {
+//!     println!("{}", entry);
+//! }
+//! archive.save("out", "THUMB")?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+	collections::HashMap,
+	fmt::Display,
+	io::{Read, Seek},
+};
+
+use crate::file::{
+	DvFileError, FileType, ParseLimits,
+	kg::{
+		self, Compression, CompressionLevel, DecodeOptions,
+		quantize::QuantizeOptions,
+		resample::{Filter, resize},
+	},
+	pft,
+};
+
+use super::{ArchiveBuilder, File};
+
+/// What happened to a thumbnail entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ThumbnailStatus {
+	/// Rebuilt from the source CG
+	Regenerated {
+		/// Name of the source entry in `KG.DSK`
+		source: String,
+	},
+
+	/// Added for a new CG
+	Added {
+		/// Name of the source entry in `KG.DSK`
+		source: String,
+	},
+
+	/// Copied unchanged, no source CG was found, it is not a KG image or it
+	/// cannot be decoded
+	Kept,
+}
+
+/// A thumbnail entry of the regenerated archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThumbnailEntry {
+	/// Entry name in `THUMB.DSK`
+	pub name: String,
+
+	/// Thumbnail width in pixels, `0` for entries that are not KG images
+	pub width: u16,
+
+	/// Thumbnail height in pixels, `0` for entries that are not KG images
+	pub height: u16,
+
+	/// How the entry was produced
+	pub status: ThumbnailStatus,
+}
+
+impl Display for ThumbnailEntry {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{:<8} {:>4}x{:<4} ", self.name, self.width, self.height)?;
+		match &self.status {
+			ThumbnailStatus::Regenerated {
+				source,
+			} => write!(f, "regenerated from {}", source),
+			ThumbnailStatus::Added {
+				source,
+			} => write!(f, "added from {}", source),
+			ThumbnailStatus::Kept => write!(f, "kept"),
+		}
+	}
+}
+
+/// Builder that regenerates `THUMB.DSK` from `KG.DSK`
+#[derive(Debug, Clone)]
+pub struct ThumbnailBuilder {
+	/// Resampling filter
+	filter: Filter,
+
+	/// KG compression level
+	level: CompressionLevel,
+
+	/// Palette reduction of the downsampled images
+	options: QuantizeOptions,
+
+	/// How source CGs are decoded
+	decode_options: DecodeOptions,
+
+	/// Size of added thumbnails, defaults to the most common existing size
+	size: Option<(u16, u16)>,
+
+	/// Maps a thumbnail name to its source name
+	source_name: fn(&str) -> String,
+
+	/// Thumbnails to add, as (thumbnail name, source name)
+	new_entries: Vec<(String, String)>,
+}
+
+impl Default for ThumbnailBuilder {
+	fn default() -> Self {
+		Self {
+			filter: Filter::default(),
+			level: CompressionLevel::default(),
+			options: QuantizeOptions::default(),
+			decode_options: DecodeOptions::default(),
+			size: None,
+			source_name: str::to_string,
+			new_entries: Vec::new(),
+		}
+	}
+}
+
+impl ThumbnailBuilder {
+	/// Creates a builder with the Lanczos filter and fast encoding
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Sets the resampling filter
+	pub fn with_filter(mut self, filter: Filter) -> Self {
+		self.filter = filter;
+		self
+	}
+
+	/// Sets the KG compression level
+	pub fn with_level(mut self, level: CompressionLevel) -> Self {
+		self.level = level;
+		self
+	}
+
+	/// Sets the palette reduction options, e.g. dithering
+	pub fn with_quantize_options(mut self, options: QuantizeOptions) -> Self {
+		self.options = options;
+		self
+	}
+
+	/// Sets how source CGs are decoded, e.g. to include Type 2/3 CGs
+	pub fn with_decode_options(mut self, options: DecodeOptions) -> Self {
+		self.decode_options = options;
+		self
+	}
+
+	/// Sets the size of added thumbnails
+	///
+	/// Existing thumbnails always keep their size.
+	pub fn with_size(mut self, width: u16, height: u16) -> Self {
+		self.size = Some((width, height));
+		self
+	}
+
+	/// Sets how thumbnail names map to source names (same name by default)
+	pub fn with_source_name(mut self, source_name: fn(&str) -> String) -> Self {
+		self.source_name = source_name;
+		self
+	}
+
+	/// Adds a thumbnail for a CG that has none yet
+	pub fn with_new_entry(mut self, name: impl Into<String>, source: impl Into<String>) -> Self {
+		self.new_entries.push((name.into(), source.into()));
+		self
+	}
+
+	/// Pairs every thumbnail with its source CG without encoding anything
+	///
+	/// Entries that will be kept unchanged have [`ThumbnailStatus::Kept`].
+	pub fn map<R1: Read + Seek, R2: Read + Seek>(
+		&self,
+		sources: &mut File<R1>,
+		thumbs: &mut File<R2>,
+	) -> Result<Vec<ThumbnailEntry>, DvFileError> {
+		let mut plan = Vec::new();
+		let mut sizes: HashMap<(u16, u16), usize> = HashMap::new();
+
+		let entries: Vec<pft::Entry> = thumbs.entries().copied().collect();
+		for existing in entries {
+			let name = existing.name();
+			let header = thumbs
+				.open_entry(&existing)
+				.and_then(|mut reader| kg::Header::from_reader(&mut reader))
+				.ok();
+
+			let source = (self.source_name)(&name);
+			let status = if header.is_some() && self.can_decode(sources, &source) {
+				ThumbnailStatus::Regenerated {
+					source,
+				}
+			} else {
+				ThumbnailStatus::Kept
+			};
+			let (width, height) = header.map_or((0, 0), |header| (header.width(), header.height()));
+			if header.is_some() {
+				*sizes.entry((width, height)).or_default() += 1;
+			}
+
+			plan.push(ThumbnailEntry {
+				name,
+				width,
+				height,
+				status,
+			});
+		}
+
+		if self.new_entries.is_empty() {
+			return Ok(plan);
+		}
+
+		// Most common size, ties broken towards the larger thumbnail
+		let common = sizes
+			.into_iter()
+			.max_by_key(|&((width, height), count)| (count, u32::from(width) * u32::from(height)))
+			.map(|(size, _)| size);
+		let Some((width, height)) = self.size.or(common) else {
+			return Err(DvFileError::CompressionError {
+				file_type: FileType::Kg,
+				message: "No thumbnail size given and no existing thumbnail to copy it from"
+					.to_string(),
+			});
+		};
+
+		for (name, source) in &self.new_entries {
+			if sources.pft().find_entry(source).is_none() {
+				return Err(DvFileError::EntryNotFound {
+					file_type: FileType::Dsk,
+					message: format!("source CG '{}' for thumbnail '{}'", source, name),
+				});
+			}
+			if !self.can_decode(sources, source) {
+				return Err(DvFileError::DecompressionError {
+					file_type: FileType::Kg,
+					message: format!(
+						"source CG '{}' for thumbnail '{}' cannot be decoded",
+						source, name
+					),
+				});
+			}
+			plan.push(ThumbnailEntry {
+				name: name.clone(),
+				width,
+				height,
+				status: ThumbnailStatus::Added {
+					source: source.clone(),
+				},
+			});
+		}
+
+		Ok(plan)
+	}
+
+	/// Regenerates the thumbnail archive
+	///
+	/// # Returns
+	///
+	/// An [`ArchiveBuilder`] holding the new `THUMB.DSK` contents, ready to
+	/// [`save`](ArchiveBuilder::save), and one [`ThumbnailEntry`] per entry.
+	///
+	/// # Errors
+	///
+	/// Returns an error if an archive cannot be read, a source CG cannot be
+	/// decoded, or the size of an added thumbnail cannot be determined
+	pub fn regenerate<R1: Read + Seek, R2: Read + Seek>(
+		&self,
+		sources: &mut File<R1>,
+		thumbs: &mut File<R2>,
+	) -> Result<(ArchiveBuilder, Vec<ThumbnailEntry>), DvFileError> {
+		let mut archive = ArchiveBuilder::new();
+		let plan = self.map(sources, thumbs)?;
+
+		for entry in &plan {
+			let data = match &entry.status {
+				ThumbnailStatus::Regenerated {
+					source,
+				}
+				| ThumbnailStatus::Added {
+					source,
+				} => self.thumbnail(&sources.extract_by_name(source)?, entry.width, entry.height)?,
+				ThumbnailStatus::Kept => thumbs.extract_by_name(&entry.name)?,
+			};
+			archive.add_entry(&entry.name, data)?;
+		}
+
+		Ok((archive, plan))
+	}
+
+	/// Returns whether the header of source `name` is a KG image this
+	/// builder decodes
+	fn can_decode<R: Read + Seek>(&self, sources: &mut File<R>, name: &str) -> bool {
+		let Ok(header) = sources
+			.open_entry_by_name(name)
+			.and_then(|mut reader| kg::Header::from_reader(&mut reader))
+		else {
+			return false;
+		};
+		header.compression_type() == Compression::BPP3
+			|| self.decode_options.experimental_true_color
+	}
+
+	/// Decodes, downsamples and re-encodes one CG
+	fn thumbnail(&self, cg: &[u8], width: u16, height: u16) -> Result<Vec<u8>, DvFileError> {
+		let file =
+			kg::File::from_bytes_with_options(cg, &ParseLimits::default(), &self.decode_options)?;
+		let header = file.header();
+		let pixels =
+			resize(file.pixels(), header.width(), header.height(), width, height, self.filter)?;
+		kg::compress_quantized(&pixels, width, height, &self.options, self.level)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::file::dsk::builder::test_archive;
+
+	/// A KG image with a few colors in vertical bands
+	fn cg(width: u16, height: u16, seed: u8) -> Vec<u8> {
+		let rgb: Vec<u8> = (0..width as usize * height as usize)
+			.flat_map(|i| {
+				let band = (i % width as usize) * 4 / width as usize;
+				[band as u8 * 60 + seed, 0x40, 0xC0 - band as u8 * 40]
+			})
+			.collect();
+		kg::compress(&rgb, width, height).unwrap()
+	}
+
+	#[test]
+	fn test_regenerate_keeps_layout_and_sizes() {
+		let mut cgs = test_archive(&[("CG01", &cg(64, 48, 0)), ("CG02", &cg(64, 48, 1))]);
+		let mut thumbs = test_archive(&[
+			("CG01", &cg(16, 12, 9)),
+			("README", b"not an image"),
+			("CG03", &cg(16, 12, 9)),
+		]);
+
+		let (builder, report) = ThumbnailBuilder::new()
+			.with_level(CompressionLevel::Fast)
+			.with_new_entry("CG02", "CG02")
+			.regenerate(&mut cgs, &mut thumbs)
+			.unwrap();
+
+		assert_eq!(builder.names().collect::<Vec<_>>(), ["CG01", "README", "CG03", "CG02"]);
+		assert_eq!(
+			report[0].status,
+			ThumbnailStatus::Regenerated {
+				source: "CG01".to_string()
+			}
+		);
+		assert_eq!(report[1].status, ThumbnailStatus::Kept);
+		assert_eq!(report[2].status, ThumbnailStatus::Kept);
+		assert_eq!((report[3].width, report[3].height), (16, 12));
+
+		let (pft, dsk) = builder.to_bytes().unwrap();
+		let mut regenerated = File::from_bytes(dsk, pft).unwrap();
+		assert_eq!(regenerated.extract_by_name("README").unwrap(), b"not an image");
+		assert_eq!(regenerated.extract_by_name("CG03").unwrap(), cg(16, 12, 9));
+
+		// The thumbnail shows the CG's bands, not the stale image
+		let thumb =
+			kg::File::from_reader(&mut regenerated.extract_by_name("CG01").unwrap().as_slice())
+				.unwrap();
+		assert_eq!((thumb.header().width(), thumb.header().height()), (16, 12));
+		let source =
+			kg::File::from_reader(&mut cgs.extract_by_name("CG01").unwrap().as_slice()).unwrap();
+		assert_eq!(&thumb.pixels()[..3], &source.pixels()[..3]);
+	}
+
+	#[test]
+	fn test_true_color_sources_are_opt_in() {
+		let rgb: Vec<u8> = (0..32 * 24 * 3).map(|i| (i * 7) as u8).collect();
+		let planar = kg::encode::experimental::compress_rgb24(
+			&rgb,
+			32,
+			24,
+			Compression::Planar,
+			CompressionLevel::Fast,
+		)
+		.unwrap();
+		let mut cgs = test_archive(&[("CG01", &planar), ("CG02", &cg(64, 48, 1))]);
+		let mut thumbs = test_archive(&[("CG01", &cg(16, 12, 9)), ("CG02", &cg(16, 12, 9))]);
+
+		// The Type 2 CG keeps its thumbnail, the rest is still rebuilt
+		let (builder, report) = ThumbnailBuilder::new().regenerate(&mut cgs, &mut thumbs).unwrap();
+		assert_eq!(report[0].status, ThumbnailStatus::Kept);
+		assert_eq!(
+			report[1].status,
+			ThumbnailStatus::Regenerated {
+				source: "CG02".to_string()
+			}
+		);
+		let (pft, dsk) = builder.to_bytes().unwrap();
+		let mut regenerated = File::from_bytes(dsk, pft).unwrap();
+		assert_eq!(regenerated.extract_by_name("CG01").unwrap(), cg(16, 12, 9));
+		assert!(
+			ThumbnailBuilder::new()
+				.with_new_entry("CG03", "CG01")
+				.map(&mut cgs, &mut thumbs)
+				.is_err()
+		);
+
+		let experimental = ThumbnailBuilder::new()
+			.with_decode_options(DecodeOptions::default().with_experimental_true_color(true));
+		let (_, report) = experimental.regenerate(&mut cgs, &mut thumbs).unwrap();
+		assert_eq!(
+			report[0].status,
+			ThumbnailStatus::Regenerated {
+				source: "CG01".to_string()
+			}
+		);
+	}
+
+	#[test]
+	fn test_source_name_mapping_and_missing_size() {
+		let mut cgs = test_archive(&[("CG01", &cg(32, 32, 0))]);
+		let mut thumbs = test_archive(&[("TCG01", &cg(8, 8, 0))]);
+
+		let builder = ThumbnailBuilder::new()
+			.with_source_name(|name| name.trim_start_matches('T').to_string());
+		let report = builder.map(&mut cgs, &mut thumbs).unwrap();
+		assert_eq!(
+			report[0].status,
+			ThumbnailStatus::Regenerated {
+				source: "CG01".to_string()
+			}
+		);
+		assert_eq!(report[0].to_string(), "TCG01       8x8    regenerated from CG01");
+
+		let mut empty = test_archive(&[]);
+		let new_entry = ThumbnailBuilder::new().with_new_entry("CG01", "CG01");
+		assert!(new_entry.map(&mut cgs, &mut empty).is_err());
+		let sized = new_entry.with_size(4, 4).map(&mut cgs, &mut empty).unwrap();
+		assert_eq!((sized[0].width, sized[0].height), (4, 4));
+		assert!(
+			ThumbnailBuilder::new().with_new_entry("X", "NOPE").map(&mut cgs, &mut thumbs).is_err()
+		);
+	}
+}
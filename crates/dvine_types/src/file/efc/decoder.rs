@@ -22,12 +22,14 @@ pub fn decode_ima_adpcm(
 	sample_count: u32,
 ) -> io::Result<Vec<i16>> {
 	let total_samples = sample_count as usize * channels as usize;
-	let mut pcm_data = Vec::with_capacity(total_samples);
 
 	if adpcm_data.len() < 4 {
 		return Err(io::Error::new(io::ErrorKind::InvalidData, "ADPCM data too short"));
 	}
 
+	// Each byte after the 4-byte prologue holds two samples
+	let mut pcm_data = Vec::with_capacity(total_samples.min(1 + (adpcm_data.len() - 4) * 2));
+
 	// Read initial predictor and step index (first 4 bytes)
 	let mut predictor = i16::from_le_bytes([adpcm_data[0], adpcm_data[1]]) as i32;
 	let mut step_index = adpcm_data[2] as usize;
@@ -5,7 +5,7 @@
 
 use std::io::{Read, Seek};
 
-use crate::file::{DvFileError, FileType};
+use crate::file::{DvFileError, FileType, ParseLimits};
 
 use super::constants::MAX_EFFECTS;
 use super::decoder;
@@ -20,11 +20,25 @@ pub struct File<R> {
 
 	/// Index table mapping effect IDs to file offsets
 	pub(super) index_table: [u32; MAX_EFFECTS],
+
+	/// Limits applied when extracting effects
+	pub(super) limits: ParseLimits,
 }
 
 impl<R: Read + Seek> File<R> {
 	/// Reads an `.EFC` file from the given reader
-	pub fn from_reader(mut reader: R) -> Result<Self, DvFileError> {
+	pub fn from_reader(reader: R) -> Result<Self, DvFileError> {
+		Self::from_reader_with_limits(reader, &ParseLimits::default())
+	}
+
+	/// Reads an `.EFC` file from the given reader
+	///
+	/// Effects are decoded lazily, so `limits` is kept and applied to every
+	/// [`extract`](Self::extract).
+	pub fn from_reader_with_limits(
+		mut reader: R,
+		limits: &ParseLimits,
+	) -> Result<Self, DvFileError> {
 		let mut index_table = [0u32; MAX_EFFECTS];
 		for entry in &mut index_table {
 			let mut buffer = [0u8; 4];
@@ -35,6 +49,7 @@ impl<R: Read + Seek> File<R> {
 		Ok(Self {
 			reader,
 			index_table,
+			limits: *limits,
 		})
 	}
 
@@ -98,16 +113,21 @@ impl<R: Read + Seek> File<R> {
 
 		// Calculate ADPCM data size
 		// Size = 4-byte header + ceil(sample_count * channels / 2) bytes of nibbles
-		let mut adpcm_size =
-			4 + (adpcm_header.sample_count as usize * adpcm_header.channels as usize).div_ceil(2);
+		let total_samples = adpcm_header.sample_count as usize * adpcm_header.channels as usize;
+		let mut adpcm_size = 4 + total_samples.div_ceil(2);
 
 		// Adjust size based on available data
 		// The size from offset calculation already accounts for the headers (4 + 0xC0 bytes)
 		// so we need to subtract those from the total size
 		let header_size = 4 + 0xC0;
-		if adpcm_size > size as usize - header_size {
-			adpcm_size = size as usize - header_size;
-		}
+		let available = (size as usize).checked_sub(header_size).ok_or_else(|| {
+			DvFileError::insufficient_data(FileType::Efc, header_size, size as usize)
+		})?;
+		adpcm_size = adpcm_size.min(available);
+
+		let mut allocation = self.limits.allocation(FileType::Efc);
+		allocation.reserve(adpcm_size)?;
+		allocation.reserve_items(total_samples, std::mem::size_of::<i16>())?;
 
 		// Read ADPCM data
 		let mut adpcm_data = vec![0u8; adpcm_size];
@@ -147,11 +147,16 @@ impl DecodedSound {
 		if sample_rate == 0 {
 			return 0;
 		}
-		(total_samples * 1000) / sample_rate
+		(u64::from(total_samples) * 1000 / u64::from(sample_rate)) as u32
 	}
 
 	/// Writes the decoded sound effect as a WAV file to the given writer
 	pub fn write<W: Write + Seek>(&self, writer: &mut W) -> Result<(), DvFileError> {
+		// hound divides by the channel count, reject corrupt headers first
+		if self.adpcm_header.channels == 0 {
+			return Err(hound::Error::Unsupported.into());
+		}
+
 		let spec = hound::WavSpec {
 			channels: self.adpcm_header.channels,
 			sample_rate: self.adpcm_header.sample_rate,
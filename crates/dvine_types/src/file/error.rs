@@ -50,6 +50,15 @@ pub enum DvFileError {
 		actual: Vec<u8>,
 	},
 
+	/// Invalid header field, e.g. an unknown version or record kind
+	#[error("{file_type} error: Invalid header: {message}")]
+	InvalidHeader {
+		/// File type that encountered the error
+		file_type: FileType,
+		/// Error message
+		message: String,
+	},
+
 	/// Unsupported compression type (KG files)
 	#[error("{file_type} error: Unsupported compression type {compression_type}")]
 	UnsupportedCompressionType {
@@ -191,6 +200,28 @@ pub enum DvFileError {
 		record_size: usize,
 	},
 
+	/// Invalid entry name (DSK/PFT files)
+	#[error("{file_type} error: Invalid entry name '{name}': {message}")]
+	InvalidEntryName {
+		/// File type that encountered the error
+		file_type: FileType,
+		/// Offending entry name
+		name: String,
+		/// Error message
+		message: String,
+	},
+
+	/// Patch does not apply to the base archive (patch files)
+	#[error("{file_type} error: Base archive mismatch for '{name}': {message}")]
+	BaseMismatch {
+		/// File type that encountered the error
+		file_type: FileType,
+		/// Name of the offending entry
+		name: String,
+		/// Error message
+		message: String,
+	},
+
 	/// Buffer underflow during decompression (KG files)
 	#[error("{file_type} error: Buffer underflow during decompression: {message}")]
 	UnderflowError {
@@ -236,6 +267,19 @@ pub enum DvFileError {
 		message: String,
 	},
 
+	/// Resource limit exceeded while parsing
+	#[error("{file_type} error: {limit} {value} exceeds the limit of {max}")]
+	LimitExceeded {
+		/// File type that encountered the error
+		file_type: FileType,
+		/// Name of the exceeded limit
+		limit: &'static str,
+		/// Requested value
+		value: u64,
+		/// Maximum allowed value
+		max: u64,
+	},
+
 	/// hound library error
 	#[error(transparent)]
 	HoundError(#[from] hound::Error),
@@ -261,6 +305,10 @@ impl DvFileError {
 				file_type,
 				..
 			}
+			| Self::InvalidHeader {
+				file_type,
+				..
+			}
 			| Self::UnsupportedCompressionType {
 				file_type,
 				..
@@ -313,6 +361,14 @@ impl DvFileError {
 				file_type,
 				..
 			}
+			| Self::InvalidEntryName {
+				file_type,
+				..
+			}
+			| Self::BaseMismatch {
+				file_type,
+				..
+			}
 			| Self::UnderflowError {
 				file_type,
 				..
@@ -332,6 +388,10 @@ impl DvFileError {
 			| Self::BadEncoding {
 				file_type,
 				..
+			}
+			| Self::LimitExceeded {
+				file_type,
+				..
 			} => Some(*file_type),
 			_ => None,
 		}
@@ -352,6 +412,16 @@ impl DvFileError {
 		matches!(self, Self::InvalidMagic { .. })
 	}
 
+	/// Returns true if this is an invalid header error
+	pub fn is_invalid_header(&self) -> bool {
+		matches!(self, Self::InvalidHeader { .. })
+	}
+
+	/// Returns true if a resource limit was exceeded
+	pub fn is_limit_exceeded(&self) -> bool {
+		matches!(self, Self::LimitExceeded { .. })
+	}
+
 	/// Returns true if this is a decompression-related error
 	pub fn is_decompression_error(&self) -> bool {
 		matches!(
@@ -405,6 +475,8 @@ pub enum FileType {
 	Spr,
 	/// ANM animation file
 	Anm,
+	/// DSK archive patch file
+	Patch,
 }
 
 impl FileType {
@@ -421,6 +493,7 @@ impl FileType {
 			FileType::Mfd => "MFD",
 			FileType::Spr => "SPR",
 			FileType::Anm => "ANM",
+			FileType::Patch => "DVP",
 		}
 	}
 
@@ -437,6 +510,7 @@ impl FileType {
 			FileType::Mfd => "Mouse cursor animation file",
 			FileType::Spr => "Sprite animation file",
 			FileType::Anm => "Animation file",
+			FileType::Patch => "Archive patch file",
 		}
 	}
 }
@@ -454,6 +528,7 @@ impl std::fmt::Display for FileType {
 			FileType::Mfd => write!(f, "MFD"),
 			FileType::Spr => write!(f, "SPR"),
 			FileType::Anm => write!(f, "ANM"),
+			FileType::Patch => write!(f, "PATCH"),
 		}
 	}
 }
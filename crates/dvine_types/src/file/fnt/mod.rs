@@ -68,7 +68,7 @@
 
 use std::{fmt::Display, io::Cursor};
 
-use crate::file::{DvFileError, FileType, fnt::glyph::Glyph};
+use crate::file::{DvFileError, FileType, ParseLimits, fnt::glyph::Glyph};
 
 pub mod glyph;
 
@@ -422,6 +422,13 @@ impl File {
 			// Overwrite existing glyph data
 			let start = (offset_multiplier as usize) * bytes_per_glyph;
 			let end = start + bytes_per_glyph;
+			if end > self.raw.len() {
+				return Err(DvFileError::InvalidExtraction {
+					file_type: FileType::Fnt,
+					required: end,
+					available: self.raw.len(),
+				});
+			}
 			self.raw[start..end].copy_from_slice(glyph.data());
 		} else {
 			// Insert new glyph at the end
@@ -474,12 +481,25 @@ impl File {
 
 	/// Loads a font file from a byte slice.
 	pub fn from_bytes(data: &[u8]) -> Result<Self, DvFileError> {
+		Self::from_bytes_with_limits(data, &ParseLimits::default())
+	}
+
+	/// Loads a font file from a byte slice, rejecting bitmap data over `limits`.
+	pub fn from_bytes_with_limits(data: &[u8], limits: &ParseLimits) -> Result<Self, DvFileError> {
 		let mut cursor = Cursor::new(data);
-		Self::from_reader(&mut cursor)
+		Self::from_reader_with_limits(&mut cursor, limits)
 	}
 
 	/// Loads a font file from any reader.
 	pub fn from_reader<R: std::io::Read>(reader: &mut R) -> Result<Self, DvFileError> {
+		Self::from_reader_with_limits(reader, &ParseLimits::default())
+	}
+
+	/// Loads a font file from any reader, rejecting bitmap data over `limits`.
+	pub fn from_reader_with_limits<R: std::io::Read>(
+		reader: &mut R,
+		limits: &ParseLimits,
+	) -> Result<Self, DvFileError> {
 		// Read font size from header
 		let mut buf = [0u8; constants::HEADER_SIZE];
 		reader.read_exact(&mut buf)?;
@@ -505,8 +525,7 @@ impl File {
 		}
 
 		// Read bitmap data
-		let mut raw = Vec::new();
-		reader.read_to_end(&mut raw)?;
+		let raw = limits.allocation(FileType::Fnt).read_to_end(reader)?;
 
 		Ok(Self {
 			font_size,
@@ -0,0 +1,441 @@
+//! Fuzz-style corpus test for every parser.
+//!
+//! Each format starts from a small valid file built with this crate's own
+//! writers. The corpus then adds every truncation, random byte flips, and
+//! boundary values (`0`, `0xFF`, `0xFFFF`, `0xFFFFFFFF`, ...) written over
+//! each header field. Every input must parse to a value or a [`DvFileError`]:
+//! a panic fails the test, and the limits keep allocation bombs from
+//! exhausting memory.
+
+use std::{
+	io::{Cursor, Read},
+	panic::{self, AssertUnwindSafe},
+};
+
+use super::{
+	DvFileError, FileType, ParseLimits, anm, dsk, efc, fnt, item, kg, mfd, pft, spr, startup_ini,
+};
+
+/// Random mutations per seed
+const MUTATIONS: usize = 400;
+
+/// Length of the header area that gets boundary values at every offset
+const HEADER_SWEEP: usize = 64;
+
+/// Values written over header fields
+const BOUNDARY_VALUES: [u32; 9] =
+	[0, 1, 0x7F, 0x80, 0xFF, 0x7FFF, 0xFFFF, 0x7FFF_FFFF, 0xFFFF_FFFF];
+
+/// Deterministic xorshift generator, so failures are reproducible
+struct Rng(u64);
+
+impl Rng {
+	fn next(&mut self) -> u64 {
+		self.0 ^= self.0 << 13;
+		self.0 ^= self.0 >> 7;
+		self.0 ^= self.0 << 17;
+		self.0
+	}
+
+	fn below(&mut self, n: usize) -> usize {
+		(self.next() % n.max(1) as u64) as usize
+	}
+}
+
+/// Builds the mutated inputs derived from one valid file
+fn corpus(seed: &[u8], rng: &mut Rng) -> Vec<Vec<u8>> {
+	let mut inputs = vec![seed.to_vec()];
+
+	// Truncations, dense near the start where the headers are
+	let mut len = 0;
+	while len < seed.len() {
+		inputs.push(seed[..len].to_vec());
+		len += if len < 256 {
+			1
+		} else {
+			1 + rng.below(64)
+		};
+	}
+
+	// Boundary values over every header field, in both widths
+	for offset in 0..HEADER_SWEEP.min(seed.len()) {
+		for value in BOUNDARY_VALUES {
+			for width in [2, 4] {
+				if offset + width <= seed.len() {
+					let mut input = seed.to_vec();
+					input[offset..offset + width].copy_from_slice(&value.to_le_bytes()[..width]);
+					inputs.push(input);
+				}
+			}
+		}
+	}
+
+	// Random flips and overwrites anywhere in the file
+	for _ in 0..MUTATIONS {
+		let mut input = seed.to_vec();
+		for _ in 0..1 + rng.below(4) {
+			let offset = rng.below(input.len());
+			match rng.below(3) {
+				0 => input[offset] ^= 1 << rng.below(8),
+				1 => input[offset] = rng.next() as u8,
+				_ => {
+					let value = BOUNDARY_VALUES[rng.below(BOUNDARY_VALUES.len())].to_le_bytes();
+					let end = (offset + 4).min(input.len());
+					input[offset..end].copy_from_slice(&value[..end - offset]);
+				}
+			}
+		}
+		inputs.push(input);
+	}
+
+	inputs
+}
+
+/// Runs `parse` over the corpus of `seed`, reporting the first input that panics
+fn fuzz(name: &str, seed: &[u8], parse: impl Fn(&[u8], &ParseLimits)) {
+	let mut rng = Rng(0x9E37_79B9_7F4A_7C15 ^ seed.len() as u64);
+	let strict = ParseLimits::strict();
+
+	for (index, input) in corpus(seed, &mut rng).iter().enumerate() {
+		for limits in [&strict, &ParseLimits::default()] {
+			if panic::catch_unwind(AssertUnwindSafe(|| parse(input, limits))).is_err() {
+				panic!("{} input #{} ({} bytes) panicked", name, index, input.len());
+			}
+		}
+	}
+}
+
+/// Test image with a few flat areas and gradients
+fn test_rgb(width: usize, height: usize) -> Vec<u8> {
+	(0..width * height)
+		.flat_map(|i| {
+			let (x, y) = (i % width, i / width);
+			[
+				(x * 32) as u8,
+				if (x + y) % 3 == 0 {
+					0xFF
+				} else {
+					0x10
+				},
+				(y * 40) as u8,
+			]
+		})
+		.collect()
+}
+
+#[test]
+fn test_fuzz_pft_and_dsk() {
+	let mut builder = dsk::ArchiveBuilder::new();
+	builder.add_entry("FIRST", vec![1; 100]).unwrap();
+	builder.add_entry("SECOND", vec![2; 3000]).unwrap();
+	let (pft_file, dsk_data) = builder.to_bytes().unwrap();
+	let pft_data = pft_file.to_bytes();
+	assert!(dsk::File::from_bytes(dsk_data.clone(), pft_file.clone()).unwrap().validate().is_ok());
+
+	fuzz("PFT", &pft_data, |data, limits| {
+		if let Ok(pft_file) = pft::File::from_bytes_with_limits(data, limits) {
+			let _ = pft_file.validate();
+			let _ = pft_file.find_entry("SECOND");
+			let _ = pft::File::from_reader_with_limits(&mut &data[..], limits);
+
+			let Ok(mut archive) = dsk::File::from_bytes(dsk_data.clone(), pft_file) else {
+				return;
+			};
+			let entries: Vec<pft::Entry> = archive.entries().copied().collect();
+			for entry in &entries {
+				let _ = archive.extract(entry);
+				if let Ok(mut reader) = archive.open_entry(entry) {
+					let _ = reader.read_to_end(&mut Vec::new());
+				}
+			}
+			let _ = archive.validate();
+			let _ = archive.analyze();
+		}
+	});
+
+	let pft_file = pft::File::from_bytes(&pft_data).unwrap();
+	fuzz("DSK", &dsk_data, |data, _| {
+		let Ok(mut archive) = dsk::File::from_bytes(data.to_vec(), pft_file.clone()) else {
+			return;
+		};
+		for name in ["FIRST", "SECOND"] {
+			let _ = archive.extract_by_name(name);
+		}
+	});
+}
+
+#[test]
+fn test_fuzz_kg() {
+	let rgb = test_rgb(12, 9);
+	let options = kg::quantize::QuantizeOptions::default();
+	let paletted =
+		kg::compress_quantized(&rgb, 12, 9, &options, kg::CompressionLevel::Fast).unwrap();
+	let planar = kg::encode::experimental::compress_rgb24(
+		&rgb,
+		12,
+		9,
+		kg::Compression::Planar,
+		kg::CompressionLevel::Fast,
+	)
+	.unwrap();
+	let interleaved = kg::encode::experimental::compress_rgb24(
+		&rgb,
+		12,
+		9,
+		kg::Compression::Interleaved,
+		kg::CompressionLevel::Best,
+	)
+	.unwrap();
+
+	let true_color = kg::DecodeOptions::default().with_experimental_true_color(true);
+	for seed in [&paletted, &planar, &interleaved] {
+		assert!(
+			kg::File::from_bytes_with_options(seed, &ParseLimits::strict(), &true_color).is_ok()
+		);
+		fuzz("KG", seed, |data, limits| {
+			if let Ok(file) = kg::File::from_bytes_with_limits(data, limits) {
+				let _ = file.to_rgba();
+				let _ = file.indices();
+			}
+			if let Ok(file) = kg::File::from_bytes_with_options(data, limits, &true_color) {
+				let _ = file.to_rgba();
+			}
+			let _ = kg::IndexedImage::from_bytes_with_limits(data, limits);
+			let _ = kg::File::from_reader_with_limits(&mut &data[..], limits);
+			if limits == &ParseLimits::strict() {
+				let _ = kg::trace_bitstream(data).map(|trace| trace.stats());
+				let _ =
+					kg::trace_bitstream_with_options(data, &true_color).map(|trace| trace.stats());
+			}
+		});
+	}
+}
+
+#[test]
+fn test_fuzz_spr() {
+	let mut file = spr::File::new();
+	for (width, height) in [(4u32, 3u32), (2, 5)] {
+		let count = (width * height) as usize;
+		let entry = spr::FrameEntry::new(0, 0, width, height, 1, 2);
+		let sprite = (0..count).map(|i| 176 + i as u8).collect();
+		file.add_frame(spr::Frame::new(entry, sprite, vec![0xFF; count])).unwrap();
+	}
+	let palette = spr::Palette::default();
+	assert_eq!(spr::File::from_bytes(&file.to_bytes()).unwrap().frame_count(), 2);
+
+	fuzz("SPR", &file.to_bytes(), |data, limits| {
+		if let Ok(file) = spr::File::from_bytes_with_limits(data, limits) {
+			for frame in file.iter() {
+				let _ = frame.apply_palette_with_mask(&palette);
+				let _ = frame.sprite_to_pgm();
+				let _ = frame.is_valid();
+			}
+			let _ = file.to_bytes();
+		}
+	});
+
+	fuzz("SPR palette", &palette.to_bytes(), |data, _| {
+		let _ = spr::Palette::from_bytes(data);
+	});
+}
+
+#[test]
+fn test_fuzz_mfd() {
+	let mut builder = mfd::FileBuilder::new();
+	builder.add_frame(mfd::Frame::new(3, 2, -1, 1, vec![0, 1, 0xFF, 0xFF, 1, 0])).unwrap();
+	builder.add_frame(mfd::Frame::blank(2, 2, 0, 0)).unwrap();
+	builder.animation_sequences(vec![0]);
+	builder.animation_index_table(vec![
+		mfd::AnimationEntry::new(0, 5),
+		mfd::AnimationEntry::new(1, 5),
+		mfd::AnimationEntry::loop_marker(0),
+	]);
+	let seed = builder.build().unwrap().to_bytes().unwrap();
+	assert_eq!(mfd::File::from_bytes(&seed).unwrap().frame_count(), 2);
+
+	fuzz("MFD", &seed, |data, limits| {
+		if let Ok(file) = mfd::File::from_bytes_with_limits(data, limits) {
+			for frame in file.iter() {
+				let _ = frame.to_rgba();
+			}
+			let _ = file.to_bytes();
+		}
+		let _ = mfd::File::from_reader_with_limits(&mut &data[..], limits);
+	});
+}
+
+#[test]
+fn test_fuzz_fnt() {
+	let mut file = fnt::File::new(fnt::FontSize::FS16x16);
+	for code in [0x41, 0x82A0] {
+		let glyph = fnt::glyph::Glyph::new(fnt::FontSize::FS16x16, code, vec![0x5A; 32]);
+		file.insert(&glyph, false).unwrap();
+	}
+	let glyph = fnt::glyph::Glyph::new(fnt::FontSize::FS16x16, 0x41, vec![0xA5; 32]);
+	assert!(fnt::File::from_bytes(&file.to_bytes()).is_ok());
+
+	fuzz("FNT", &file.to_bytes(), |data, limits| {
+		if let Ok(mut file) = fnt::File::from_bytes_with_limits(data, limits) {
+			let _ = file.iter().count();
+			let _ = file.lookup_from_stream(b"AB\x82\xa0");
+			if file.font_size() == fnt::FontSize::FS16x16 {
+				let _ = file.insert(&glyph, true);
+			}
+			let _ = file.to_bytes();
+		}
+	});
+}
+
+#[test]
+fn test_fuzz_item() {
+	let mut file = item::File::new();
+	let mut record = [0u8; 208];
+	record[2..6].copy_from_slice(b"ITEM");
+	file.add_item(record);
+	file.add_item([7; 208]);
+	assert_eq!(item::File::from_bytes(&file.to_bytes()).unwrap().iter().count(), 2);
+
+	fuzz("ITEM", &file.to_bytes(), |data, limits| {
+		if let Ok(file) = item::File::from_bytes_with_limits(data, limits) {
+			for record in file.iter() {
+				let _ = item::entry::ItemEntry::from(record).name();
+			}
+		}
+		let _ = item::entry::ItemEntry::try_from(data);
+	});
+}
+
+#[test]
+fn test_fuzz_efc() {
+	// One effect: sound header, ADPCM header, then a 4-byte prologue and nibbles
+	let mut seed = vec![0u8; efc::INDEX_TABLE_SIZE];
+	seed[..4].copy_from_slice(&(efc::INDEX_TABLE_SIZE as u32).to_le_bytes());
+	seed.extend_from_slice(&[1, 0, 10, 0]);
+	let mut adpcm = [0u8; efc::ADPCM_HEADER_SIZE];
+	adpcm[..4].copy_from_slice(&22050u32.to_le_bytes());
+	adpcm[4..6].copy_from_slice(&1u16.to_le_bytes());
+	for i in 0..efc::STEP_TABLE_ENTRIES {
+		adpcm[8 + i * 2..10 + i * 2].copy_from_slice(&(7 + i as i16 * 10).to_le_bytes());
+	}
+	adpcm[0xBC..0xC0].copy_from_slice(&16u32.to_le_bytes());
+	seed.extend_from_slice(&adpcm);
+	seed.extend_from_slice(&[0, 0, 0, 0, 0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC, 0xDE, 0xF0]);
+	let sound = efc::File::from_reader(Cursor::new(&seed)).unwrap().extract(0).unwrap();
+	assert_eq!(sound.pcm_data.len(), 16);
+
+	fuzz("EFC", &seed, |data, limits| {
+		let Ok(mut file) = efc::File::from_reader_with_limits(Cursor::new(data), limits) else {
+			return;
+		};
+		let _ = file.list_effects();
+		for sound in file.iter_sounds().flatten() {
+			let _ = sound.duration_ms();
+			let _ = sound.write(&mut Cursor::new(Vec::new()));
+		}
+	});
+}
+
+#[test]
+fn test_fuzz_anm() {
+	let mut file = anm::File::new();
+	let mut sequence = anm::AnimationSequence::new();
+	sequence.add_frame(anm::FrameDescriptor::frame(0, 10));
+	sequence.add_frame(anm::FrameDescriptor::frame(1, 5));
+	sequence.add_frame(anm::FrameDescriptor::jump(0));
+	file.set_sequence(0, sequence).unwrap();
+	let mut sequence = anm::AnimationSequence::new();
+	sequence.add_frame(anm::FrameDescriptor::frame(2, 1));
+	sequence.add_hold_marker();
+	file.set_sequence(3, sequence).unwrap();
+	assert_eq!(anm::File::from_bytes_raw(&file.to_bytes()).unwrap(), file);
+
+	fuzz("ANM", &file.to_bytes(), |data, limits| {
+		if let Ok(file) = anm::File::from_bytes_with_limits(data, limits) {
+			let _ = file.to_bytes();
+		}
+		if let Ok(file) = anm::File::from_bytes_raw(data) {
+			let _ = file.spr_filename();
+			let _ = file.to_bytes();
+		}
+	});
+}
+
+#[test]
+fn test_fuzz_patch_and_startup() {
+	let mut patch = dsk::Patch::new();
+	patch
+		.push(dsk::PatchOp::Add {
+			name: "NEW".to_string(),
+			data: vec![1, 2, 3],
+		})
+		.unwrap();
+	patch
+		.push(dsk::PatchOp::Remove {
+			name: "OLD".to_string(),
+			source_md5: [9; 16],
+		})
+		.unwrap();
+
+	fuzz("Patch", &patch.to_bytes().unwrap(), |data, limits| {
+		let _ = dsk::Patch::from_bytes_with_limits(data, limits);
+	});
+
+	fuzz("Startup.ini", &startup_ini::StartupIni::default().to_bytes(), |data, _| {
+		let _ = startup_ini::StartupIni::from_bytes(data);
+	});
+}
+
+#[test]
+fn test_detect_never_panics() {
+	let mut rng = Rng(7);
+	for len in 0..600 {
+		let data: Vec<u8> = (0..len).map(|_| rng.next() as u8).collect();
+		let _ = FileType::detect(&data);
+	}
+}
+
+#[test]
+fn test_limits_reject_allocation_bombs() {
+	// 0xFFFF x 0xFFFF Type 2 image: 12 GiB of RGB, rejected up front
+	let mut kg_data = kg::encode::experimental::compress_rgb24(
+		&test_rgb(4, 4),
+		4,
+		4,
+		kg::Compression::Planar,
+		kg::CompressionLevel::Fast,
+	)
+	.unwrap();
+	kg_data[4..8].copy_from_slice(&[0xFF; 4]);
+	let true_color = kg::DecodeOptions::default().with_experimental_true_color(true);
+	let error = kg::File::from_bytes_with_options(&kg_data, &ParseLimits::default(), &true_color)
+		.unwrap_err();
+	assert!(error.is_limit_exceeded(), "{}", error);
+	let unlimited = ParseLimits::unlimited().with_max_allocation(1 << 20);
+	assert!(matches!(
+		kg::File::from_bytes_with_options(&kg_data, &unlimited, &true_color),
+		Err(DvFileError::LimitExceeded {
+			limit: "allocation",
+			..
+		})
+	));
+
+	// Many MFD frames sharing one bitmap
+	let mut builder = mfd::FileBuilder::new();
+	for _ in 0..64 {
+		builder.add_frame(mfd::Frame::blank(32, 32, 0, 0)).unwrap();
+	}
+	let mfd_data = builder.build().unwrap().to_bytes().unwrap();
+	let limits = ParseLimits::default().with_max_allocation(32 * 32 * 63);
+	assert!(mfd::File::from_bytes_with_limits(&mfd_data, &limits).is_err());
+	assert!(
+		mfd::File::from_bytes_with_limits(&mfd_data, &limits.with_max_allocation(32 * 32 * 64))
+			.is_ok()
+	);
+
+	// PFT claiming four billion entries
+	let mut pft_data = pft::File::new(Vec::new()).to_bytes();
+	pft_data[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+	let error = pft::File::from_reader(&mut &pft_data[..]).unwrap_err();
+	assert_eq!(error.file_type(), Some(FileType::Pft));
+	assert!(error.is_limit_exceeded());
+}
@@ -0,0 +1,779 @@
+//! Conversions to and from the [`image`] crate
+//!
+//! Available with the `image` feature. Every pixel format of the game converts
+//! to an [`RgbaImage`] and a [`DynamicImage`], and back where the format can
+//! be rebuilt from pixels alone:
+//!
+//! | Type | To image | From image |
+//! |------|----------|------------|
+//! | [`kg::File`] | RGBA with the chroma key transparent, RGB as `DynamicImage`, PNG with `PLTE` when paletted | via [`kg::IndexedImage::compress_image`] |
+//! | [`kg::IndexedImage`] | RGBA with the chroma key transparent, RGB as `DynamicImage`, PNG with `PLTE` | quantized image, indexed PNG |
+//! | [`spr::Frame`] | RGBA with the mask as alpha, PNG with `PLTE` | image or PNG via [`spr::FrameImporter`] |
+//! | [`spr::atlas::AtlasPage`] | RGBA | - |
+//! | [`mfd::Frame`] | RGBA, PNG with `PLTE` and `tRNS` | alpha and brightness, indexed PNG |
+//! | [`GlyphBitmap`] | RGBA, black on transparent | dark opaque pixels |
+//!
+//! Indexed PNG output keeps the palette indices of the original data, so an
+//! image edited in a palette-aware editor converts back without quantization.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use dvine_types::file::{kg, spr};
+//! use image::RgbaImage;
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let background = kg::File::open("BG.KG")?;
+//! RgbaImage::from(&background).save("bg.png")?;
+//! std::fs::write("bg_indexed.png", background.to_png()?)?;
+//!
+//! let sprites = spr::File::open("CHAR.SPR")?;
+//! let palette = spr::Palette::from_file("SPR.PAL")?;
+//! if let Some(frame) = sprites.get_frame(0) {
+//!     frame.to_rgba_image(&palette).save("frame0.png")?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io::Cursor;
+
+use ::image::{DynamicImage, GrayImage, Luma, Rgba, RgbaImage};
+
+use crate::file::{
+	DvFileError, FileType, ParseLimits,
+	fnt::{FontSize, glyph::GlyphBitmap},
+	kg::{self, CompressionLevel, quantize::QuantizeOptions},
+	mfd, spr,
+};
+
+/// Alpha values below this count as transparent when converting from RGBA
+const ALPHA_THRESHOLD: u8 = 128;
+
+/// Luma values below this count as dark when converting from RGBA
+const LUMA_THRESHOLD: u8 = 128;
+
+/// A paletted PNG image
+struct IndexedPng {
+	width: u32,
+	height: u32,
+	/// RGBA palette entries, alpha from the `tRNS` chunk
+	palette: Vec<[u8; 4]>,
+	/// One palette index per pixel
+	indices: Vec<u8>,
+}
+
+impl IndexedPng {
+	/// Encodes the image as an 8-bit paletted PNG
+	///
+	/// A `tRNS` chunk is written only if an entry is not fully opaque.
+	fn encode(&self, file_type: FileType) -> Result<Vec<u8>, DvFileError> {
+		let error = |err: png::EncodingError| DvFileError::CompressionError {
+			file_type,
+			message: format!("PNG encoding failed: {}", err),
+		};
+
+		let colors: Vec<u8> = self.palette.iter().flat_map(|entry| entry[..3].to_vec()).collect();
+		let mut alpha: Vec<u8> = self.palette.iter().map(|entry| entry[3]).collect();
+		while alpha.last() == Some(&u8::MAX) {
+			alpha.pop();
+		}
+
+		let mut data = Vec::new();
+		let mut encoder = png::Encoder::new(&mut data, self.width, self.height);
+		encoder.set_color(png::ColorType::Indexed);
+		encoder.set_depth(png::BitDepth::Eight);
+		encoder.set_palette(colors);
+		if !alpha.is_empty() {
+			encoder.set_trns(alpha);
+		}
+		let mut writer = encoder.write_header().map_err(error)?;
+		writer.write_image_data(&self.indices).map_err(error)?;
+		writer.finish().map_err(error)?;
+
+		Ok(data)
+	}
+
+	/// Decodes a paletted PNG of any bit depth
+	///
+	/// The header size is checked against `limits` before the pixel buffers
+	/// are allocated.
+	fn decode(data: &[u8], file_type: FileType, limits: &ParseLimits) -> Result<Self, DvFileError> {
+		let error = |message: String| DvFileError::DecompressionError {
+			file_type,
+			message,
+		};
+
+		let png_limits = png::Limits {
+			bytes: limits.max_allocation,
+		};
+		let mut reader = png::Decoder::new_with_limits(Cursor::new(data), png_limits)
+			.read_info()
+			.map_err(|err| error(format!("PNG decoding failed: {}", err)))?;
+		let info = reader.info();
+		if info.color_type != png::ColorType::Indexed {
+			return Err(error(format!("PNG is {:?}, expected an indexed image", info.color_type)));
+		}
+		limits.check_dimensions(file_type, info.width, info.height)?;
+		let pixel_count = info.width as usize * info.height as usize;
+		let buffer_size = reader
+			.output_buffer_size()
+			.ok_or_else(|| error("PNG image size overflows".to_string()))?;
+		let mut allocation = limits.allocation(file_type);
+		allocation.reserve(buffer_size)?;
+		allocation.reserve(pixel_count)?;
+
+		let info = reader.info();
+		let depth = info.bit_depth as usize;
+		let palette_bytes = info.palette.as_deref().unwrap_or_default();
+		let trns = info.trns.as_deref().unwrap_or_default();
+		let palette = palette_bytes
+			.chunks_exact(3)
+			.enumerate()
+			.map(|(i, rgb)| [rgb[0], rgb[1], rgb[2], trns.get(i).copied().unwrap_or(u8::MAX)])
+			.collect();
+
+		let mut buffer = vec![0; buffer_size];
+		let frame = reader
+			.next_frame(&mut buffer)
+			.map_err(|err| error(format!("PNG decoding failed: {}", err)))?;
+
+		// Unpack 1, 2 and 4-bit indices, most significant bits first
+		let (width, height) = (frame.width as usize, frame.height as usize);
+		let mut indices = Vec::with_capacity(pixel_count);
+		for row in buffer.chunks_exact(frame.line_size).take(height) {
+			indices.extend((0..width).map(|x| {
+				let bit = x * depth;
+				let shift = 8 - depth - bit % 8;
+				(row[bit / 8] >> shift) & (u8::MAX >> (8 - depth))
+			}));
+		}
+
+		Ok(Self {
+			width: frame.width,
+			height: frame.height,
+			palette,
+			indices,
+		})
+	}
+}
+
+/// Checks that an image fits the `u16` dimensions of the game formats
+fn dimensions_u16(width: u32, height: u32, file_type: FileType) -> Result<(u16, u16), DvFileError> {
+	match (u16::try_from(width), u16::try_from(height)) {
+		(Ok(width), Ok(height)) => Ok((width, height)),
+		_ => Err(DvFileError::CompressionError {
+			file_type,
+			message: format!("Image size {}x{} exceeds 65535x65535", width, height),
+		}),
+	}
+}
+
+/// Perceived brightness of a color
+fn luma(pixel: &Rgba<u8>) -> u8 {
+	let [r, g, b, _] = pixel.0;
+	((u32::from(r) * 299 + u32::from(g) * 587 + u32::from(b) * 114) / 1000) as u8
+}
+
+// ============================================================================
+// KG
+// ============================================================================
+
+/// Pixels of the default chroma key are transparent, like
+/// [`kg::File::to_rgba`]
+impl From<&kg::File> for RgbaImage {
+	fn from(file: &kg::File) -> Self {
+		file.to_rgba_image_with_key(kg::constants::DEFAULT_CHROMA_KEY)
+	}
+}
+
+/// Opaque RGB; convert to [`RgbaImage`] for chroma-key transparency
+impl From<&kg::File> for DynamicImage {
+	fn from(file: &kg::File) -> Self {
+		let header = file.header();
+		let rgb = ::image::RgbImage::from_vec(
+			u32::from(header.width()),
+			u32::from(header.height()),
+			file.pixels().to_vec(),
+		)
+		.expect("KG pixel buffer matches its header");
+		DynamicImage::ImageRgb8(rgb)
+	}
+}
+
+impl kg::File {
+	/// Converts the image to RGBA with pixels of color `key` transparent,
+	/// e.g. [`DEFAULT_CHROMA_KEY`](kg::constants::DEFAULT_CHROMA_KEY)
+	pub fn to_rgba_image_with_key(&self, key: [u8; 3]) -> RgbaImage {
+		let header = self.header();
+		RgbaImage::from_vec(
+			u32::from(header.width()),
+			u32::from(header.height()),
+			self.to_rgba_with_key(key),
+		)
+		.expect("KG pixel buffer matches its header")
+	}
+
+	/// Encodes the image as PNG
+	///
+	/// Paletted images are written as indexed PNG with their palette in the
+	/// `PLTE` chunk, true-color images as RGB PNG.
+	pub fn to_png(&self) -> Result<Vec<u8>, DvFileError> {
+		match kg::IndexedImage::try_from(self) {
+			Ok(indexed) => indexed.to_png(),
+			Err(_) => {
+				let mut data = Vec::new();
+				DynamicImage::from(self)
+					.write_to(&mut Cursor::new(&mut data), ::image::ImageFormat::Png)
+					.map_err(|err| DvFileError::CompressionError {
+						file_type: FileType::Kg,
+						message: format!("PNG encoding failed: {}", err),
+					})?;
+				Ok(data)
+			}
+		}
+	}
+}
+
+/// Palette entries of the default chroma key are transparent, like
+/// [`kg::File::to_rgba`]
+impl From<&kg::IndexedImage> for RgbaImage {
+	fn from(image: &kg::IndexedImage) -> Self {
+		RgbaImage::from_vec(
+			u32::from(image.width()),
+			u32::from(image.height()),
+			image.to_rgba_with_key(kg::constants::DEFAULT_CHROMA_KEY),
+		)
+		.expect("index buffer matches the image size")
+	}
+}
+
+/// Opaque RGB; convert to [`RgbaImage`] for chroma-key transparency
+impl From<&kg::IndexedImage> for DynamicImage {
+	fn from(image: &kg::IndexedImage) -> Self {
+		let rgb = ::image::RgbImage::from_vec(
+			u32::from(image.width()),
+			u32::from(image.height()),
+			image.to_rgb(),
+		)
+		.expect("index buffer matches the image size");
+		DynamicImage::ImageRgb8(rgb)
+	}
+}
+
+impl kg::IndexedImage {
+	/// Quantizes an image to at most `options.max_colors` palette entries
+	///
+	/// The alpha channel is ignored; use [`compress_image`](Self::compress_image)
+	/// to keep transparency.
+	pub fn from_image(
+		image: &DynamicImage,
+		options: &QuantizeOptions,
+	) -> Result<Self, DvFileError> {
+		let rgb = image.to_rgb8();
+		let (width, height) = dimensions_u16(rgb.width(), rgb.height(), FileType::Kg)?;
+		let (palette, indices) = kg::quantize::quantize(rgb.as_raw(), width, height, options)?;
+		Self::new(width, height, palette, indices)
+	}
+
+	/// Encodes the image as indexed PNG, with all 256 palette entries in the
+	/// `PLTE` chunk
+	pub fn to_png(&self) -> Result<Vec<u8>, DvFileError> {
+		IndexedPng {
+			width: u32::from(self.width()),
+			height: u32::from(self.height()),
+			palette: self.palette().iter().map(|&[r, g, b, _]| [r, g, b, u8::MAX]).collect(),
+			indices: self.indices().to_vec(),
+		}
+		.encode(FileType::Kg)
+	}
+
+	/// Decodes an indexed PNG, keeping its palette and indices
+	///
+	/// Palettes shorter than 256 entries are padded with black; transparency
+	/// is ignored.
+	pub fn from_png(data: &[u8]) -> Result<Self, DvFileError> {
+		Self::from_png_with_limits(data, &ParseLimits::default())
+	}
+
+	/// Decodes an indexed PNG, rejecting images over `limits`
+	pub fn from_png_with_limits(data: &[u8], limits: &ParseLimits) -> Result<Self, DvFileError> {
+		let png = IndexedPng::decode(data, FileType::Kg, limits)?;
+		let (width, height) = dimensions_u16(png.width, png.height, FileType::Kg)?;
+
+		let mut palette = [[0u8; 4]; 256];
+		for (entry, &[r, g, b, _]) in palette.iter_mut().zip(&png.palette) {
+			*entry = [r, g, b, 0];
+		}
+		Self::new(width, height, palette, png.indices)
+	}
+
+	/// Quantizes and encodes an image as a `.KG` file
+	///
+	/// Images with an alpha channel store transparency as the chroma key,
+	/// see [`kg::compress_rgba`].
+	pub fn compress_image(
+		image: &DynamicImage,
+		options: &QuantizeOptions,
+		level: CompressionLevel,
+	) -> Result<Vec<u8>, DvFileError> {
+		if !image.color().has_alpha() {
+			return Self::from_image(image, options)?.to_bytes_with_level(level);
+		}
+
+		let rgba = image.to_rgba8();
+		let (width, height) = dimensions_u16(rgba.width(), rgba.height(), FileType::Kg)?;
+		kg::compress_rgba(rgba.as_raw(), width, height, options, level)
+	}
+}
+
+// ============================================================================
+// SPR
+// ============================================================================
+
+impl From<&spr::atlas::AtlasPage> for RgbaImage {
+	fn from(page: &spr::atlas::AtlasPage) -> Self {
+		RgbaImage::from_vec(page.width, page.height, page.rgba.clone())
+			.expect("page buffer matches the page size")
+	}
+}
+
+impl spr::Frame {
+	/// Renders the frame with `palette`, using the mask as alpha channel
+	pub fn to_rgba_image(&self, palette: &spr::Palette) -> RgbaImage {
+		RgbaImage::from_vec(self.width(), self.height(), self.apply_palette_with_mask(palette))
+			.expect("sprite buffer matches the frame size")
+	}
+
+	/// Renders the frame with `palette` as a [`DynamicImage`]
+	pub fn to_dynamic_image(&self, palette: &spr::Palette) -> DynamicImage {
+		DynamicImage::ImageRgba8(self.to_rgba_image(palette))
+	}
+
+	/// Returns the mask as a grayscale image (`0x00` opaque, `0xFF`
+	/// transparent)
+	pub fn mask_image(&self) -> GrayImage {
+		GrayImage::from_vec(self.width(), self.height(), self.mask_pixels().to_vec())
+			.expect("mask buffer matches the frame size")
+	}
+
+	/// Encodes the sprite as indexed PNG with the raw pixel values as indices
+	///
+	/// `PLTE` entries 176-255 hold the 80 palette colors, lower entries are
+	/// black. The mask is not included, see [`mask_image`](Self::mask_image).
+	pub fn to_indexed_png(&self, palette: &spr::Palette) -> Result<Vec<u8>, DvFileError> {
+		let mut entries = vec![[0, 0, 0, u8::MAX]; 256];
+		for (index, entry) in entries.iter_mut().skip(176).enumerate() {
+			let (r, g, b, _) = palette.get(index as u8);
+			*entry = [r, g, b, u8::MAX];
+		}
+
+		IndexedPng {
+			width: self.width(),
+			height: self.height(),
+			palette: entries,
+			indices: self.sprite_pixels().to_vec(),
+		}
+		.encode(FileType::Spr)
+	}
+}
+
+impl spr::FrameImporter {
+	/// Builds a frame from an image of any color type
+	///
+	/// # Errors
+	///
+	/// Returns an error if the image cannot be converted
+	pub fn import_image(
+		&self,
+		image: &DynamicImage,
+		hotspot_x: u32,
+		hotspot_y: u32,
+	) -> Result<spr::Frame, DvFileError> {
+		let rgba = image.to_rgba8();
+		self.import(rgba.as_raw(), rgba.width(), rgba.height(), hotspot_x, hotspot_y)
+	}
+
+	/// Builds a frame from PNG data
+	///
+	/// # Errors
+	///
+	/// Returns an error if the data is not a valid PNG
+	pub fn import_png(
+		&self,
+		data: &[u8],
+		hotspot_x: u32,
+		hotspot_y: u32,
+	) -> Result<spr::Frame, DvFileError> {
+		self.import_png_with_limits(data, hotspot_x, hotspot_y, &ParseLimits::default())
+	}
+
+	/// Builds a frame from PNG data, rejecting images over `limits`
+	///
+	/// # Errors
+	///
+	/// Returns an error if the data is not a valid PNG or exceeds `limits`
+	pub fn import_png_with_limits(
+		&self,
+		data: &[u8],
+		hotspot_x: u32,
+		hotspot_y: u32,
+		limits: &ParseLimits,
+	) -> Result<spr::Frame, DvFileError> {
+		let error = |err: ::image::ImageError| DvFileError::DecompressionError {
+			file_type: FileType::Spr,
+			message: format!("PNG decoding failed: {}", err),
+		};
+
+		let reader =
+			|| ::image::ImageReader::with_format(Cursor::new(data), ::image::ImageFormat::Png);
+		let (width, height) = reader().into_dimensions().map_err(error)?;
+		limits.check_dimensions(FileType::Spr, width, height)?;
+
+		// RGBA output plus the frame's sprite and mask planes
+		let pixel_count = width as usize * height as usize;
+		limits.allocation(FileType::Spr).reserve_items(pixel_count, 6)?;
+
+		let mut image_limits = ::image::Limits::default();
+		image_limits.max_alloc = Some(limits.max_allocation as u64);
+		let mut reader = reader();
+		reader.limits(image_limits);
+		let image = reader.decode().map_err(error)?;
+		self.import_image(&image, hotspot_x, hotspot_y)
+	}
+}
+
+// ============================================================================
+// MFD
+// ============================================================================
+
+impl From<&mfd::Frame> for RgbaImage {
+	fn from(frame: &mfd::Frame) -> Self {
+		frame.to_rgba_image_with_palette(&mfd::DEFAULT_RGBA_PALETTE)
+	}
+}
+
+impl From<&mfd::Frame> for DynamicImage {
+	fn from(frame: &mfd::Frame) -> Self {
+		DynamicImage::ImageRgba8(RgbaImage::from(frame))
+	}
+}
+
+impl mfd::Frame {
+	/// Renders the frame with a `[transparent, outline, fill]` palette
+	pub fn to_rgba_image_with_palette(&self, palette: &[[u8; 4]; 3]) -> RgbaImage {
+		RgbaImage::from_vec(
+			u32::from(self.width()),
+			u32::from(self.height()),
+			self.to_rgba_with_palette(palette),
+		)
+		.expect("pixel buffer matches the frame size")
+	}
+
+	/// Builds a frame from an RGBA image
+	///
+	/// Transparent pixels become `0`, dark opaque pixels outline (`1`) and
+	/// light opaque pixels fill (`0xFF`), matching
+	/// [`DEFAULT_RGBA_PALETTE`](mfd::DEFAULT_RGBA_PALETTE).
+	pub fn from_rgba_image(
+		image: &RgbaImage,
+		x_offset: i16,
+		y_offset: i16,
+	) -> Result<Self, DvFileError> {
+		let (width, height) = dimensions_u16(image.width(), image.height(), FileType::Mfd)?;
+		let pixels = image
+			.pixels()
+			.map(|pixel| {
+				if pixel.0[3] < ALPHA_THRESHOLD {
+					0
+				} else if luma(pixel) < LUMA_THRESHOLD {
+					1
+				} else {
+					0xFF
+				}
+			})
+			.collect();
+		Ok(Self::new(width, height, x_offset, y_offset, pixels))
+	}
+
+	/// Encodes the frame as indexed PNG
+	///
+	/// The `PLTE` chunk holds [`DEFAULT_RGBA_PALETTE`](mfd::DEFAULT_RGBA_PALETTE)
+	/// with index 0 transparent; fill pixels are written as index 2.
+	pub fn to_indexed_png(&self) -> Result<Vec<u8>, DvFileError> {
+		IndexedPng {
+			width: u32::from(self.width()),
+			height: u32::from(self.height()),
+			palette: mfd::DEFAULT_RGBA_PALETTE.to_vec(),
+			indices: self.pixels().iter().map(|&pixel| pixel.min(2)).collect(),
+		}
+		.encode(FileType::Mfd)
+	}
+
+	/// Decodes an indexed PNG, mapping index 0 to transparent, 1 to outline
+	/// and all others to fill (`0xFF`)
+	pub fn from_indexed_png(
+		data: &[u8],
+		x_offset: i16,
+		y_offset: i16,
+	) -> Result<Self, DvFileError> {
+		Self::from_indexed_png_with_limits(data, x_offset, y_offset, &ParseLimits::default())
+	}
+
+	/// Decodes an indexed PNG like [`from_indexed_png`](Self::from_indexed_png),
+	/// rejecting images over `limits`
+	pub fn from_indexed_png_with_limits(
+		data: &[u8],
+		x_offset: i16,
+		y_offset: i16,
+		limits: &ParseLimits,
+	) -> Result<Self, DvFileError> {
+		let png = IndexedPng::decode(data, FileType::Mfd, limits)?;
+		let (width, height) = dimensions_u16(png.width, png.height, FileType::Mfd)?;
+		let pixels = png
+			.indices
+			.into_iter()
+			.map(|index| match index {
+				0 | 1 => index,
+				_ => 0xFF,
+			})
+			.collect();
+		Ok(Self::new(width, height, x_offset, y_offset, pixels))
+	}
+}
+
+// ============================================================================
+// FNT
+// ============================================================================
+
+impl From<&GlyphBitmap> for RgbaImage {
+	fn from(bitmap: &GlyphBitmap) -> Self {
+		let n = bitmap.font_size() as u32;
+		RgbaImage::from_fn(n, n, |x, y| {
+			if bitmap.pixels()[(y * n + x) as usize] {
+				Rgba([0, 0, 0, u8::MAX])
+			} else {
+				Rgba([0, 0, 0, 0])
+			}
+		})
+	}
+}
+
+impl From<&GlyphBitmap> for DynamicImage {
+	fn from(bitmap: &GlyphBitmap) -> Self {
+		DynamicImage::ImageRgba8(RgbaImage::from(bitmap))
+	}
+}
+
+impl GlyphBitmap {
+	/// Renders the glyph as a grayscale image, black on white
+	pub fn to_gray_image(&self) -> GrayImage {
+		let n = self.font_size() as u32;
+		GrayImage::from_fn(n, n, |x, y| {
+			if self.pixels()[(y * n + x) as usize] {
+				Luma([0])
+			} else {
+				Luma([u8::MAX])
+			}
+		})
+	}
+
+	/// Builds a glyph from a square 8, 16 or 24 pixel image
+	///
+	/// Opaque dark pixels are set, so both black-on-white and
+	/// black-on-transparent images work.
+	pub fn from_image(code: u16, image: &DynamicImage) -> Result<Self, DvFileError> {
+		let rgba = image.to_rgba8();
+		let size = match (rgba.width(), rgba.height()) {
+			(8, 8) => FontSize::FS8x8,
+			(16, 16) => FontSize::FS16x16,
+			(24, 24) => FontSize::FS24x24,
+			(width, _) => {
+				return Err(DvFileError::InvalidFontSize {
+					file_type: FileType::Fnt,
+					value: width,
+				});
+			}
+		};
+
+		let pixels = rgba
+			.pixels()
+			.map(|pixel| pixel.0[3] >= ALPHA_THRESHOLD && luma(pixel) < LUMA_THRESHOLD)
+			.collect();
+		Ok(Self::new(size, code, pixels))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_kg_indexed_png_roundtrip() {
+		let mut palette = [[0u8; 4]; 256];
+		palette[1] = [255, 0, 0, 0];
+		palette[2] = [0, 128, 255, 0];
+		palette[200] = [255, 0, 0, 0];
+		let indices = (0..60).map(|i| [0, 1, 2, 200][i % 4]).collect();
+		let image = kg::IndexedImage::new(10, 6, palette, indices).unwrap();
+
+		let png = image.to_png().unwrap();
+		assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+		assert!(png.windows(4).any(|chunk| chunk == b"PLTE"));
+		assert_eq!(kg::IndexedImage::from_png(&png).unwrap(), image);
+
+		// The generic decoder sees the same colors
+		let decoded = ::image::load_from_memory(&png).unwrap().to_rgba8();
+		assert_eq!(decoded, RgbaImage::from(&image));
+
+		let bytes = image.to_bytes().unwrap();
+		let file = kg::File::from_reader(&mut bytes.as_slice()).unwrap();
+		assert_eq!(RgbaImage::from(&file), decoded);
+		assert_eq!(DynamicImage::from(&file), DynamicImage::from(&image));
+		assert_eq!(kg::IndexedImage::from_png(&file.to_png().unwrap()).unwrap(), image);
+	}
+
+	#[test]
+	fn test_kg_from_image() {
+		let rgb = ::image::RgbImage::from_fn(12, 5, |x, y| {
+			::image::Rgb([(x * 20) as u8, (y * 50) as u8, 7])
+		});
+		let image = DynamicImage::ImageRgb8(rgb);
+		let options = QuantizeOptions::default();
+		let data =
+			kg::IndexedImage::compress_image(&image, &options, CompressionLevel::Fast).unwrap();
+		let file = kg::File::from_reader(&mut data.as_slice()).unwrap();
+		assert_eq!(DynamicImage::from(&file).to_rgb8(), image.to_rgb8());
+
+		// Transparency survives through the chroma key
+		let mut rgba = image.to_rgba8();
+		rgba.get_pixel_mut(3, 2).0[3] = 0;
+		let data = kg::IndexedImage::compress_image(
+			&DynamicImage::ImageRgba8(rgba.clone()),
+			&options,
+			CompressionLevel::Fast,
+		)
+		.unwrap();
+		let file = kg::File::from_reader(&mut data.as_slice()).unwrap();
+		let keyed = file.to_rgba_image_with_key(kg::constants::DEFAULT_CHROMA_KEY);
+		assert_eq!(keyed.get_pixel(3, 2).0[3], 0);
+		assert_eq!(RgbaImage::from(&file).into_raw(), file.to_rgba());
+		let indexed = kg::IndexedImage::try_from(&file).unwrap();
+		assert_eq!(RgbaImage::from(&indexed), keyed);
+		assert_eq!(DynamicImage::from(&file).to_rgba8().get_pixel(3, 2).0[3], 255);
+		rgba.put_pixel(3, 2, *keyed.get_pixel(3, 2));
+		assert_eq!(keyed, rgba);
+	}
+
+	#[test]
+	fn test_spr_frame_roundtrip() {
+		let palette = spr::palette::test_palette();
+		let sprite = (0..12u8).map(|i| 176 + i * 6).collect();
+		let mask = (0..12u8)
+			.map(|i| {
+				if i % 3 == 0 {
+					0xFF
+				} else {
+					0
+				}
+			})
+			.collect();
+		let entry = spr::FrameEntry::new(0, 0, 4, 3, 1, 2);
+		let frame = spr::Frame::new(entry, sprite, mask);
+
+		let image = frame.to_rgba_image(&palette);
+		assert_eq!(image.get_pixel(0, 0).0[3], 0);
+		assert_eq!(image.get_pixel(1, 0).0[3], 255);
+
+		assert_eq!(frame.mask_image().as_raw(), frame.mask_pixels());
+
+		let png = frame.to_indexed_png(&palette).unwrap();
+		let decoded = IndexedPng::decode(&png, FileType::Spr, &ParseLimits::default()).unwrap();
+		assert_eq!(decoded.indices, frame.sprite_pixels());
+		assert_eq!(decoded.palette[176 + 5], [15, 245, 2, 255]);
+
+		// PNG import: transparent pixels get palette index 0
+		let mut rgba_png = Vec::new();
+		image.write_to(&mut Cursor::new(&mut rgba_png), ::image::ImageFormat::Png).unwrap();
+		let imported = spr::FrameImporter::new(&palette).import_png(&rgba_png, 1, 2).unwrap();
+		assert_eq!(imported.mask_pixels(), frame.mask_pixels());
+		for (i, &pixel) in imported.sprite_pixels().iter().enumerate() {
+			let expected = if i % 3 == 0 {
+				176
+			} else {
+				frame.sprite_pixels()[i]
+			};
+			assert_eq!(pixel, expected);
+		}
+		assert!(spr::FrameImporter::new(&palette).import_png(b"not a png", 0, 0).is_err());
+	}
+
+	#[test]
+	fn test_mfd_frame_roundtrip() {
+		let pixels = (0..20).map(|i| [0, 1, 0xFF][i % 3]).collect();
+		let frame = mfd::Frame::new(5, 4, -1, 3, pixels);
+
+		let image = RgbaImage::from(&frame);
+		assert_eq!(mfd::Frame::from_rgba_image(&image, -1, 3).unwrap(), frame);
+
+		let png = frame.to_indexed_png().unwrap();
+		assert!(png.windows(4).any(|chunk| chunk == b"tRNS"));
+		assert_eq!(mfd::Frame::from_indexed_png(&png, -1, 3).unwrap(), frame);
+		assert_eq!(::image::load_from_memory(&png).unwrap().to_rgba8(), image);
+	}
+
+	#[test]
+	fn test_glyph_roundtrip() {
+		let pixels = (0..256).map(|i| (i * 7) % 5 == 0).collect();
+		let bitmap = GlyphBitmap::new(FontSize::FS16x16, 0x8260, pixels);
+
+		let rgba = DynamicImage::from(&bitmap);
+		assert_eq!(GlyphBitmap::from_image(0x8260, &rgba).unwrap(), bitmap);
+		let gray = DynamicImage::ImageLuma8(bitmap.to_gray_image());
+		assert_eq!(GlyphBitmap::from_image(0x8260, &gray).unwrap(), bitmap);
+
+		let wrong = DynamicImage::new_rgba8(10, 10);
+		assert!(GlyphBitmap::from_image(0, &wrong).is_err());
+	}
+
+	#[test]
+	fn test_png_decoding_respects_limits() {
+		let frame = mfd::Frame::new(8, 2, 0, 0, vec![1; 16]);
+		let png = frame.to_indexed_png().unwrap();
+
+		let narrow = ParseLimits::default().with_max_dimensions(4, 4);
+		let result = mfd::Frame::from_indexed_png_with_limits(&png, 0, 0, &narrow);
+		assert!(result.unwrap_err().is_limit_exceeded());
+		let small = ParseLimits::default().with_max_allocation(20);
+		assert!(
+			kg::IndexedImage::from_png_with_limits(&png, &small).unwrap_err().is_limit_exceeded()
+		);
+		assert!(kg::IndexedImage::from_png_with_limits(&png, &ParseLimits::strict()).is_ok());
+
+		let rgba = RgbaImage::from(&frame);
+		let mut rgba_png = Vec::new();
+		rgba.write_to(&mut Cursor::new(&mut rgba_png), ::image::ImageFormat::Png).unwrap();
+		let importer = spr::FrameImporter::new(&spr::Palette::new());
+		let result = importer.import_png_with_limits(&rgba_png, 0, 0, &narrow);
+		assert!(result.unwrap_err().is_limit_exceeded());
+		assert!(importer.import_png_with_limits(&rgba_png, 0, 0, &ParseLimits::strict()).is_ok());
+	}
+
+	#[test]
+	fn test_decode_low_bit_depth_png() {
+		// 2-bit paletted PNG as written by many editors
+		let mut data = Vec::new();
+		let mut encoder = png::Encoder::new(&mut data, 5, 2);
+		encoder.set_color(png::ColorType::Indexed);
+		encoder.set_depth(png::BitDepth::Two);
+		encoder.set_palette(vec![0, 0, 0, 255, 255, 255, 9, 9, 9]);
+		let mut writer = encoder.write_header().unwrap();
+		// Rows: 0 1 2 1 0 | 2 2 0 0 1
+		writer.write_image_data(&[0b0001_1001, 0b0000_0000, 0b1010_0000, 0b0100_0000]).unwrap();
+		writer.finish().unwrap();
+
+		let png = IndexedPng::decode(&data, FileType::Mfd, &ParseLimits::default()).unwrap();
+		assert_eq!(png.indices, [0, 1, 2, 1, 0, 2, 2, 0, 0, 1]);
+		assert_eq!(png.palette.len(), 3);
+	}
+}
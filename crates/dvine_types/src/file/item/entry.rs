@@ -2,6 +2,9 @@
 
 use encoding_rs::SHIFT_JIS;
 
+use super::ItemRaw;
+use crate::file::{DvFileError, FileType};
+
 /// An entry in the ITEM.dat file, representing a single item.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ItemEntry {
@@ -18,6 +21,9 @@ pub struct ItemEntry {
 }
 
 impl ItemEntry {
+	/// Number of bytes of an item record the entry is read from
+	pub const SIZE: usize = 38;
+
 	/// Returns the raw name bytes in Shift-JIS encoding.
 	pub fn raw_name(&self) -> &[u8; 20] {
 		&self.raw_name
@@ -46,8 +52,8 @@ impl ItemEntry {
 	}
 }
 
-impl From<&[u8]> for ItemEntry {
-	fn from(data: &[u8]) -> Self {
+impl From<&ItemRaw> for ItemEntry {
+	fn from(data: &ItemRaw) -> Self {
 		let id = u16::from_le_bytes([data[0], data[1]]);
 		let mut raw_name = [0u8; 20];
 		raw_name.copy_from_slice(&data[2..22]);
@@ -62,3 +68,19 @@ impl From<&[u8]> for ItemEntry {
 		}
 	}
 }
+
+impl TryFrom<&[u8]> for ItemEntry {
+	type Error = DvFileError;
+
+	fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+		if data.len() < Self::SIZE {
+			return Err(DvFileError::insufficient_data(FileType::Item, Self::SIZE, data.len()));
+		}
+
+		Ok(Self {
+			id: u16::from_le_bytes([data[0], data[1]]),
+			raw_name: data[2..22].try_into()?,
+			extra: data[22..38].try_into()?,
+		})
+	}
+}
@@ -64,7 +64,7 @@
 //! # }
 //! ```
 
-use crate::file::{DvFileError, FileType};
+use crate::file::{DvFileError, FileType, ParseLimits};
 
 pub mod entry;
 
@@ -193,14 +193,32 @@ impl File {
 	/// - The data is too small
 	/// - The checksum validation fails
 	/// - The data size is not a multiple of the item size
-	pub fn from_reader<R: std::io::Read>(mut reader: R) -> Result<Self, DvFileError> {
+	pub fn from_reader<R: std::io::Read>(reader: R) -> Result<Self, DvFileError> {
+		Self::from_reader_with_limits(reader, &ParseLimits::default())
+	}
+
+	/// Parses item data from a reader, rejecting item counts over `limits`.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the data is invalid, as for [`from_reader`], or
+	/// the item count exceeds the limits.
+	///
+	/// [`from_reader`]: Self::from_reader
+	pub fn from_reader_with_limits<R: std::io::Read>(
+		mut reader: R,
+		limits: &ParseLimits,
+	) -> Result<Self, DvFileError> {
 		// Read item count (2 bytes, little-endian)
 		let mut item_count_buf = [0u8; constants::ITEM_COUNT_SIZE];
 		reader.read_exact(&mut item_count_buf)?;
 		let item_count = u16::from_le_bytes(item_count_buf);
+		limits.check_entries(FileType::Item, item_count as usize)?;
 
 		// Calculate expected data size
 		let data_size = (item_count as usize) * constants::ITEM_SIZE;
+		// Covers both the encrypted and the decrypted buffer
+		limits.allocation(FileType::Item).reserve(data_size.saturating_mul(2))?;
 
 		// Read encrypted data section
 		let mut encrypted_data = vec![0u8; data_size];
@@ -276,8 +294,20 @@ impl File {
 	/// - The checksum validation fails
 	/// - The data size is not a multiple of the item size
 	pub fn from_bytes(data: &[u8]) -> Result<Self, DvFileError> {
+		Self::from_bytes_with_limits(data, &ParseLimits::default())
+	}
+
+	/// Parses item data from a byte slice, rejecting item counts over `limits`.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the data is invalid, as for [`from_bytes`], or
+	/// the item count exceeds the limits.
+	///
+	/// [`from_bytes`]: Self::from_bytes
+	pub fn from_bytes_with_limits(data: &[u8], limits: &ParseLimits) -> Result<Self, DvFileError> {
 		use std::io::Cursor;
-		Self::from_reader(Cursor::new(data))
+		Self::from_reader_with_limits(Cursor::new(data), limits)
 	}
 
 	/// Returns the number of items in the file.
@@ -32,7 +32,8 @@
 //!   - Byte 1: Green
 //!   - Byte 2: Red
 //!   - Byte 3: Alpha (usually 0)
-//! - **Note**: Index 0 is typically transparent (0x00, 0xFF, 0xFF, 0x00) for chroma-keying
+//! - **Note**: Index 0 is typically transparent (0x00, 0xFF, 0xFF, 0x00) for chroma-keying,
+//!   see [`File::to_rgba`](super::File::to_rgba)
 //!
 //! ### Compressed Data
 //!
@@ -56,10 +57,12 @@
 //!
 //! - `output_buffer`: Decompressed pixel data
 //! - `write_position`: Current write offset (in bytes)
-//! - `bit_buffer`: 8-bit buffer for bitstream reading
-//! - `bits_remaining`: Number of valid bits in buffer
-//! - `lru_cache`: 256×8 LRU cache for color indices
-//! - `current_color`: Reference color for LRU cache updates
+//! - `reader`: Bit reader with a 64-bit buffer, refilled a word at a time
+//! - `lru_cache`: 256×8 LRU cache for color indices, keyed by the previous
+//!   pixel's color
+//!
+//! Opcodes and short variable-length counts are decoded with lookup tables
+//! over the next few bits, and copies within a plane are done in bulk.
 //!
 //! ### Opcodes
 //!
@@ -67,9 +70,9 @@
 //! |--------|---------|----------------------------------------|
 //! | 0      | "0"     | Dictionary lookup (direct or LRU)      |
 //! | 2      | "10"    | Copy from previous pixel               |
-//! | 12     | "110"   | Copy from one line up                  |
-//! | 13     | "1100"  | Copy diagonal up-right                 |
-//! | 14     | "1101"  | Copy diagonal up-left                  |
+//! | 12     | "1100"  | Copy from one line up                  |
+//! | 13     | "1101"  | Copy diagonal up-right                 |
+//! | 14     | "1110"  | Copy diagonal up-left                  |
 //! | 15     | "1111"  | Copy from two pixels back              |
 //!
 //! ### Decompression Steps
@@ -92,6 +95,9 @@
 //!    - Read variable-length count
 //!    - Copy pixels from computed source offset
 //!
+//! Truncated bitstreams and copies that reach outside the image are reported
+//! as [`DvFileError::DecompressionError`].
+//!
 //! ### Variable-Length Integer Encoding
 //!
 //! Progressive bit reading until non-zero value:
@@ -101,26 +107,190 @@
 //! 4. Try 16 bits → if non-zero, return value
 //! 5. Read 32 bits (16-bit high + 16-bit low)
 //!
+//! ## True-Color Images (Types 2 and 3, experimental)
+//!
+//! No Type 2 or Type 3 file is known among the game's assets, so the layout
+//! below is inferred from the branches of the decoder that Type 1 never takes
+//! and has not been checked against real data. The decoders report both types
+//! as [`DvFileError::UnsupportedCompressionType`] unless
+//! [`DecodeOptions::experimental_true_color`] is set.
+//!
+//! Types 2 and 3 carry no palette. The decoder produces 24-bit pixels in BGR
+//! byte order (the same order as palette entries), which are converted to RGB
+//! for [`File::pixels`](super::File::pixels).
+//!
+//! - **Type 2 (planar)**: The bitstream holds three consecutive Type 1 passes,
+//!   one per color plane (blue, green, red). Each pass starts at the plane's
+//!   byte offset and steps by 3 bytes, which is what the strided copy path in
+//!   the decoder is for. The LRU cache and the 2-byte prologue restart with
+//!   each pass; only the bit reader carries over.
+//! - **Type 3 (interleaved)**: A single Type 1 pass over the BGR bytes, treated
+//!   as an 8-bit image that is three times as wide. References such as
+//!   "previous pixel" and "one line up" therefore address bytes, not pixels.
+//!
 
-use crate::file::{DvFileError, FileType};
+use std::sync::OnceLock;
+
+use crate::file::{DvFileError, FileType, ParseLimits, limits::Allocation};
+
+use super::{
+	Compression, Header, opcodes,
+	trace::{Trace, TraceOp, TraceRecord},
+};
+
+/// Initial LRU cache - each entry must be initialized to {0, 1, 2, 3, 4, 5, 6, 7}
+///
+/// This is critical! Initializing to all zeros causes 37% error rate in small files.
+const INITIAL_LRU_CACHE: [[u8; 8]; 256] = [[0, 1, 2, 3, 4, 5, 6, 7]; 256];
+
+/// Opcode prefix table, indexed by the next 4 bits: (opcode, prefix length)
+///
+/// Prefixes: 0="0", 2="10", {12,13,14,15}="11"+`2bit_index`
+const OPCODE_TABLE: [(u8, u32); 16] = {
+	let mut table = [(0u8, 0u32); 16];
+	let mut i = 0;
+	while i < 16 {
+		table[i] = if i < 8 {
+			(opcodes::OP_DICT_LOOKUP, 1)
+		} else if i < 12 {
+			(opcodes::OP_COPY_PREV_PIXEL, 2)
+		} else {
+			(opcodes::OP_COPY_PREV_LINE + (i as u8 & 3), 4)
+		};
+		i += 1;
+	}
+	table
+};
+
+/// Short variable-length count table, indexed by the next 6 bits:
+/// (value, code length), or a length of 0 for counts of 8 bits or more
+const COUNT_TABLE: [(u32, u32); 64] = {
+	let mut table = [(0u32, 0u32); 64];
+	let mut i = 0;
+	while i < 64 {
+		let high = (i >> 4) as u32;
+		let low = (i & 15) as u32;
+		table[i] = if high != 0 {
+			(high, 2)
+		} else if low != 0 {
+			(low + 3, 6)
+		} else {
+			(0, 0)
+		};
+		i += 1;
+	}
+	table
+};
+
+/// Big-endian bit reader over the compressed bitstream
+///
+/// Keeps up to 64 bits left-aligned in `buffer` and refills a whole word at a
+/// time, so most reads are a shift. Reading past the end yields zero bits and
+/// marks the reader as exhausted.
+#[derive(Debug)]
+struct BitReader {
+	data: Vec<u8>,
+	/// Next byte to load into the buffer
+	position: usize,
+	buffer: u64,
+	/// Number of valid bits at the top of `buffer`
+	count: u32,
+	/// Number of zero bits appended past the end of `data`
+	padding: u32,
+}
 
-use super::{Compression, Header, opcodes};
+impl BitReader {
+	fn new(data: Vec<u8>) -> Self {
+		Self {
+			data,
+			position: 0,
+			buffer: 0,
+			count: 0,
+			padding: 0,
+		}
+	}
+
+	/// Tops up the buffer to at least 57 bits
+	#[inline(always)]
+	fn refill(&mut self) {
+		if let Some(bytes) = self.data.get(self.position..self.position + 8) {
+			// Bits already in the buffer are reloaded unchanged, so OR is safe
+			let word = u64::from_be_bytes(bytes.try_into().unwrap());
+			self.buffer |= word >> self.count;
+			let loaded = (63 - self.count) >> 3;
+			self.position += loaded as usize;
+			self.count += loaded * 8;
+		} else {
+			while self.count <= 56 {
+				if let Some(&byte) = self.data.get(self.position) {
+					self.buffer |= u64::from(byte) << (56 - self.count);
+					self.position += 1;
+				} else {
+					self.padding = self.padding.saturating_add(8);
+				}
+				self.count += 8;
+			}
+		}
+	}
+
+	/// Returns the next `num_bits` (1-32) bits without consuming them
+	#[inline(always)]
+	fn peek(&mut self, num_bits: u32) -> u32 {
+		if self.count < num_bits {
+			self.refill();
+		}
+		(self.buffer >> (64 - num_bits)) as u32
+	}
+
+	/// Consumes `num_bits` bits that were peeked
+	#[inline(always)]
+	fn consume(&mut self, num_bits: u32) {
+		self.buffer <<= num_bits;
+		self.count -= num_bits;
+	}
+
+	/// Reads `num_bits` (1-32) bits
+	#[inline(always)]
+	fn read_bits(&mut self, num_bits: u32) -> u32 {
+		let value = self.peek(num_bits);
+		self.consume(num_bits);
+		value
+	}
+
+	/// Returns `true` once bits past the end of the data have been consumed
+	fn is_exhausted(&self) -> bool {
+		self.count < self.padding
+	}
+
+	/// Returns the number of bits consumed so far
+	fn bit_offset(&self) -> u64 {
+		self.position as u64 * 8 + u64::from(self.padding) - u64::from(self.count)
+	}
+}
+
+/// Decoder state captured before a traced operation
+#[derive(Debug, Clone, Copy)]
+struct TraceStart {
+	bit_offset: u64,
+	output_offset: usize,
+	reference: u8,
+	lru: [u8; 8],
+}
 
 /// State structure for the decompressor
 #[derive(Debug)]
 struct DecompressorState {
 	output_buffer: Vec<u8>,
-	compressed_data: Vec<u8>,
-	read_offset: usize,
+	reader: BitReader,
 	write_position: usize,
 	total_size: usize,
 	line_bytes: usize,
 	bytes_per_pixel: usize,
-	current_color: u8,
-	bit_buffer: u8,
-	bits_remaining: u32,
 	lru_cache: [[u8; 8]; 256],
-	palette: [[u8; 4]; 256],
+	/// Current pass (color plane for Type 2)
+	pass: u8,
+	/// Operations decoded so far, when tracing
+	trace: Option<Vec<TraceRecord>>,
 }
 
 impl DecompressorState {
@@ -128,123 +298,127 @@ impl DecompressorState {
 		let total_size = width * height * bytes_per_pixel;
 		let line_bytes = width * bytes_per_pixel;
 
-		// Initialize LRU cache - each entry must be initialized to {0, 1, 2, 3, 4, 5, 6, 7}
-		// This is critical! Initializing to all zeros causes 37% error rate in small files.
-		let lru_cache = [[0u8, 1, 2, 3, 4, 5, 6, 7]; 256];
-
 		Self {
 			output_buffer: vec![0; total_size],
-			compressed_data,
-			read_offset: 0,
+			reader: BitReader::new(compressed_data),
 			write_position: 0,
 			total_size,
 			line_bytes,
 			bytes_per_pixel,
-			current_color: 0,
-			bit_buffer: 0,
-			bits_remaining: 0,
-			lru_cache,
-			palette: [[0; 4]; 256],
+			lru_cache: INITIAL_LRU_CACHE,
+			pass: 0,
+			trace: None,
 		}
 	}
 
-	/// Reads specified number of bits from the compressed bitstream
-	///
-	/// Uses big-endian bit ordering (high bits first) and maintains an 8-bit buffer
-	/// for efficient reading of partial bytes.
-	fn read_bits(&mut self, num_bits: u32) -> u32 {
-		let mut edx = u32::from(self.bit_buffer);
-		let mut ebx = self.bits_remaining;
-		let mut eax = num_bits;
-
-		if ebx < num_bits {
-			loop {
-				let shift_bits = ebx;
-				edx <<= shift_bits;
-				eax -= ebx;
-
-				let new_byte = self.compressed_data[self.read_offset];
-				self.read_offset += 1;
-				edx = (edx & 0xFFFF_FF00) | u32::from(new_byte);
-				ebx = 8;
-
-				let ecx_loop = eax;
-				if ebx >= ecx_loop {
-					edx <<= ecx_loop;
-					break;
-				}
+	/// Returns how far back a copy opcode reads
+	fn copy_distance(&self, opcode: u8) -> usize {
+		let bpp = self.bytes_per_pixel;
+		match opcode {
+			opcodes::OP_COPY_PREV_PIXEL => bpp,
+			opcodes::OP_COPY_PREV_LINE => self.line_bytes,
+			// Diagonal up-right: one line up, one pixel right
+			opcodes::OP_COPY_DIAGONAL_1 => self.line_bytes - bpp,
+			// Diagonal up-left: one line up, one pixel left
+			opcodes::OP_COPY_DIAGONAL_2 => self.line_bytes + bpp,
+			_ => bpp * 2,
+		}
+	}
+
+	/// Captures the state needed to trace the next operation
+	fn trace_start(&self) -> TraceStart {
+		let reference = self.output_buffer[self.write_position - self.bytes_per_pixel];
+		TraceStart {
+			bit_offset: self.reader.bit_offset(),
+			output_offset: self.write_position,
+			reference,
+			lru: self.lru_cache[reference as usize],
+		}
+	}
+
+	/// Records an operation that started at `start`
+	fn trace_op(&mut self, start: TraceStart, opcode: u8) {
+		let bits = (self.reader.bit_offset() - start.bit_offset) as u32;
+		let count = ((self.write_position - start.output_offset) / self.bytes_per_pixel) as u32;
+		let op = if opcode == opcodes::OP_DICT_LOOKUP {
+			let value = self.output_buffer[start.output_offset];
+			// Cached lookups take 5 bits, direct ones 10
+			let cache_index = if bits == 5 {
+				start.lru.iter().position(|&color| color == value).map(|index| index as u8)
+			} else {
+				None
+			};
+			TraceOp::Lookup {
+				value,
+				cache_index,
+				reference: start.reference,
+				lru: start.lru,
 			}
-			ebx -= eax;
 		} else {
-			edx <<= num_bits;
-			ebx -= num_bits;
+			TraceOp::Copy {
+				opcode,
+				source_offset: start.output_offset - self.copy_distance(opcode),
+			}
+		};
+
+		let record = TraceRecord {
+			pass: self.pass,
+			bit_offset: start.bit_offset,
+			bits,
+			output_offset: start.output_offset,
+			count,
+			op,
+		};
+		if let Some(trace) = &mut self.trace {
+			trace.push(record);
 		}
+	}
 
-		self.bit_buffer = edx as u8;
-		edx >>= 8;
-		self.bits_remaining = ebx;
-
-		edx
+	/// Builds a decompression error at the current position
+	fn error(&self, message: &str) -> DvFileError {
+		DvFileError::DecompressionError {
+			file_type: FileType::Kg,
+			message: format!("{} at output offset {}", message, self.write_position),
+		}
 	}
 
 	/// Reads a variable-length integer from the bitstream
 	///
-	/// Progressive encoding: tries 2, 4, 8, 16, then 32 bits until non-zero value found.
+	/// Progressive encoding: tries 2, 4, 8, 16, then 32 bits until non-zero
+	/// value found. The 2- and 4-bit forms are decoded with one table lookup.
+	#[inline(always)]
 	fn read_variable_length(&mut self) -> u32 {
-		let value = self.read_bits(2);
-		if value != 0 {
+		let (value, length) = COUNT_TABLE[self.reader.peek(6) as usize];
+		if length != 0 {
+			self.reader.consume(length);
 			return value;
 		}
+		self.reader.consume(6);
 
-		let value = self.read_bits(4);
-		if value != 0 {
-			return value + 3;
-		}
-
-		let value = self.read_bits(8);
+		let value = self.reader.read_bits(8);
 		if value != 0 {
 			return value;
 		}
 
-		let value = self.read_bits(16);
+		let value = self.reader.read_bits(16);
 		if value != 0 {
 			return value;
 		}
 
-		let high = self.read_bits(16);
-		let low = self.read_bits(16);
+		let high = self.reader.read_bits(16);
+		let low = self.reader.read_bits(16);
 		(high << 16) | low
 	}
 
-	/// Reads an opcode from the bitstream using variable-length prefix encoding
-	///
-	/// Encoding: 0="0", 2="10", {12,13,14,15}="11"+`2bit_index`
-	fn read_opcode(&mut self) -> u8 {
-		const OPCODE_TABLE: [u8; 4] = [12, 13, 14, 15];
-
-		let bit1 = self.read_bits(1);
-		if bit1 == 0 {
-			return 0;
-		}
-
-		let bit2 = self.read_bits(1);
-		if bit2 == 0 {
-			return 2;
-		}
-
-		let index = self.read_bits(2) as usize;
-		OPCODE_TABLE[index]
-	}
-
 	/// Updates the LRU cache by moving a color to the front
 	///
-	/// CRITICAL: Uses `self.current_color` as index, NOT the `reference_color` parameter!
-	/// This matches the assembly code which uses a global variable.
+	/// The cache entry is selected by the reference color (the previous
+	/// pixel), matching the assembly which keeps it in a global variable.
 	///
 	/// Note: Manual loop is 2× faster than `copy_within` for small arrays (benchmark verified)
 	#[inline(always)]
-	fn update_lru_cache(&mut self, _reference_color: u8, new_color: u8) {
-		let cache_entry = &mut self.lru_cache[self.current_color as usize];
+	fn update_lru_cache(&mut self, reference_color: u8, new_color: u8) {
+		let cache_entry = &mut self.lru_cache[reference_color as usize];
 
 		// Find position of new_color in cache, if it exists
 		let mut position = cache_entry.iter().position(|&color| color == new_color).unwrap_or(8);
@@ -259,158 +433,157 @@ impl DecompressorState {
 			position = 7;
 		}
 
-		// Manual shift is faster than copy_within for small arrays (8 elements)
-		// Benchmark: manual loop ~1.06 µs vs copy_within ~2.08 µs per 1000 operations
 		for i in (1..=position).rev() {
 			cache_entry[i] = cache_entry[i - 1];
 		}
 		cache_entry[0] = new_color;
 	}
 
-	/// Reads a color index either directly (8 bits) or from LRU cache (3 bits)
-	///
-	/// 1-bit flag determines mode: 1=direct read, 0=LRU cache lookup
-	#[inline(always)]
-	fn read_color_index(&mut self) -> u8 {
-		let flag = self.read_bits(1);
-
-		if flag == 1 {
-			self.read_bits(8) as u8
-		} else {
-			// Direct calculation without boundary checks (matches assembly)
-			let ref_pos = self.write_position - self.bytes_per_pixel;
-			let reference_color = self.output_buffer[ref_pos];
-			let cache_index = self.read_bits(3) as usize;
-			self.lru_cache[reference_color as usize][cache_index]
-		}
-	}
-
-	/// Writes a single pixel value to the output buffer at current position
-	fn write_pixel(&mut self, value: u8) {
-		self.output_buffer[self.write_position] = value;
-	}
-
-	/// Copies pixel data from source to destination offset
-	///
-	/// For BPP=1: Simple byte copy
-	/// For BPP>1: Per-pixel copy with stride (planar format)
-	fn copy_data(&mut self, dst_offset: usize, src_offset: usize, length: usize) {
-		if self.bytes_per_pixel == 1 {
-			for i in 0..length {
-				self.output_buffer[dst_offset + i] = self.output_buffer[src_offset + i];
-			}
-			self.write_position += length;
-		} else {
-			let total_bytes = self.bytes_per_pixel * length;
-			self.write_position += total_bytes;
-
-			let mut ecx = dst_offset;
-			let mut esi = src_offset;
-			let mut edi = length;
-
-			while edi > 0 {
-				let dl = self.output_buffer[esi];
-				self.output_buffer[ecx] = dl;
-				ecx += self.bytes_per_pixel;
-				esi += self.bytes_per_pixel;
-				edi -= 1;
-			}
-		}
-	}
-
 	/// Opcode 0: Dictionary lookup with LRU cache
 	///
-	/// Reads color index (direct or from cache), writes pixel, and updates LRU
+	/// A 1-bit flag selects the mode: 1 = direct 8-bit color, 0 = 3-bit LRU
+	/// cache index for the previous pixel's color. Both forms are decoded from
+	/// a single 9-bit peek.
 	#[inline(always)]
 	fn opcode_0_dictionary_lookup(&mut self) {
-		// Read color index from bitstream or LRU cache
-		let color_index = self.read_color_index();
-
-		// Write the pixel value
-		self.write_pixel(color_index);
+		let reference_color = self.output_buffer[self.write_position - self.bytes_per_pixel];
 
-		// Get reference color (direct calculation without boundary checks)
-		let ref_pos = self.write_position - self.bytes_per_pixel;
-		let reference_color = self.output_buffer[ref_pos];
-
-		// Set current_color - this is used by update_lru_cache
-		self.current_color = reference_color;
+		let bits = self.reader.peek(9);
+		let color_index = if bits & 0x100 != 0 {
+			self.reader.consume(9);
+			bits as u8
+		} else {
+			self.reader.consume(4);
+			self.lru_cache[reference_color as usize][(bits >> 5) as usize]
+		};
 
-		// Update LRU cache
+		self.output_buffer[self.write_position] = color_index;
 		self.update_lru_cache(reference_color, color_index);
-
-		// Advance write position
 		self.write_position += self.bytes_per_pixel;
 	}
 
-	/// Opcode 2: Copy from previous pixel (left or up depending on position)
+	/// Copy opcodes: copies `length` pixels from `distance` bytes back
 	///
-	/// Reads variable-length count and copies from `write_position - bytes_per_pixel`
-	fn opcode_2_copy_previous_pixel(&mut self) {
-		let length = self.read_variable_length() as usize;
-		// Direct calculation without boundary checks (matches assembly)
-		let src = self.write_position - self.bytes_per_pixel;
-		self.copy_data(self.write_position, src, length);
-	}
-
-	/// Opcode 12: Copy from one line up (same horizontal position)
-	fn opcode_12_copy_up_1_line(&mut self) {
+	/// For BPP=1 the copy is done in bulk, replicating the source pattern when
+	/// it overlaps the destination. For BPP>1 (planar passes) it steps through
+	/// the plane.
+	#[inline(always)]
+	fn copy_run(&mut self, distance: usize) -> Result<(), DvFileError> {
 		let length = self.read_variable_length() as usize;
-		// Direct calculation without boundary checks (matches assembly)
-		let src = self.write_position - self.line_bytes;
-		self.copy_data(self.write_position, src, length);
-	}
+		let bpp = self.bytes_per_pixel;
+		let dst = self.write_position;
 
-	/// Opcode 13: Copy from diagonal up-right (one line up, one pixel right)
-	fn opcode_13_copy_up_right(&mut self) {
-		let length = self.read_variable_length() as usize;
-		// Direct calculation without boundary checks (matches assembly)
-		let src = self.write_position - self.line_bytes + self.bytes_per_pixel;
-		self.copy_data(self.write_position, src, length);
-	}
+		if length == 0 {
+			return Err(self.error("Zero-length copy"));
+		}
+		if distance > dst {
+			return Err(self.error("Copy source before start of image"));
+		}
+		if (length - 1).saturating_mul(bpp).saturating_add(dst) >= self.total_size {
+			return Err(self.error("Copy past end of image"));
+		}
 
-	/// Opcode 14: Copy from diagonal up-left (one line up, one pixel left)
-	fn opcode_14_copy_up_left(&mut self) {
-		let length = self.read_variable_length() as usize;
-		// Direct calculation without boundary checks (matches assembly)
-		let src = self.write_position - self.line_bytes - self.bytes_per_pixel;
-		self.copy_data(self.write_position, src, length);
-	}
+		let output = &mut self.output_buffer;
+		if bpp == 1 {
+			// A distance of 0 (diagonal copy in a one-pixel-wide image) copies
+			// the destination onto itself
+			if distance == 0 || distance >= length {
+				output.copy_within(dst - distance..dst - distance + length, dst);
+			} else if distance == 1 {
+				let value = output[dst - 1];
+				output[dst..dst + length].fill(value);
+			} else {
+				// Overlapping: the output repeats with period `distance`, so each
+				// chunk can copy everything written so far from the pattern start
+				let src = dst - distance;
+				let mut copied = 0;
+				while copied < length {
+					let chunk = (copied + distance).min(length - copied);
+					output.copy_within(src..src + chunk, dst + copied);
+					copied += chunk;
+				}
+			}
+		} else {
+			for i in 0..length {
+				let position = dst + i * bpp;
+				output[position] = output[position - distance];
+			}
+		}
 
-	/// Opcode 15: Copy from two pixels back (double BPP offset)
-	fn opcode_15_copy_up_double(&mut self) {
-		let length = self.read_variable_length() as usize;
-		// Direct calculation without boundary checks (matches assembly)
-		let src = self.write_position - self.bytes_per_pixel * 2;
-		self.copy_data(self.write_position, src, length);
+		self.write_position += length * bpp;
+		Ok(())
 	}
 
 	/// Main decompression routine for Type 1 compression
 	///
 	/// 1. Initialize by writing first 2 bytes directly
 	/// 2. Loop reading opcodes and executing operations until complete
-	fn decompress_type1(&mut self) {
+	fn decompress_type1(&mut self) -> Result<(), DvFileError> {
+		let bpp = self.bytes_per_pixel;
+
 		// Initialize: Read first 2 bytes directly from bitstream
 		for _ in 0..2 {
-			let byte_val = self.read_bits(8) as u8;
-			self.write_pixel(byte_val);
-			self.write_position += self.bytes_per_pixel;
+			if self.write_position >= self.total_size {
+				break;
+			}
+			let bit_offset = self.reader.bit_offset();
+			let value = self.reader.read_bits(8) as u8;
+			if let Some(trace) = &mut self.trace {
+				trace.push(TraceRecord {
+					pass: self.pass,
+					bit_offset,
+					bits: 8,
+					output_offset: self.write_position,
+					count: 1,
+					op: TraceOp::Prologue {
+						value,
+					},
+				});
+			}
+			self.output_buffer[self.write_position] = value;
+			self.write_position += bpp;
 		}
 
 		// Main decompression loop
 		while self.write_position < self.total_size {
-			let opcode = self.read_opcode();
+			if self.reader.is_exhausted() {
+				return Err(self.error("Unexpected end of bitstream"));
+			}
+
+			let start = self.trace.is_some().then(|| self.trace_start());
+
+			let (opcode, length) = OPCODE_TABLE[self.reader.peek(4) as usize];
+			self.reader.consume(length);
 
 			match opcode {
 				opcodes::OP_DICT_LOOKUP => self.opcode_0_dictionary_lookup(),
-				opcodes::OP_COPY_PREV_PIXEL => self.opcode_2_copy_previous_pixel(),
-				opcodes::OP_COPY_PREV_LINE => self.opcode_12_copy_up_1_line(),
-				opcodes::OP_COPY_DIAGONAL_1 => self.opcode_13_copy_up_right(),
-				opcodes::OP_COPY_DIAGONAL_2 => self.opcode_14_copy_up_left(),
-				opcodes::OP_COPY_DOUBLE_BPP => self.opcode_15_copy_up_double(),
-				_ => {}
+				_ => self.copy_run(self.copy_distance(opcode))?,
+			}
+
+			if let Some(start) = start {
+				self.trace_op(start, opcode);
 			}
 		}
+
+		if self.reader.is_exhausted() {
+			return Err(self.error("Unexpected end of bitstream"));
+		}
+		Ok(())
+	}
+
+	/// Main decompression routine for Type 2 compression
+	///
+	/// Runs one Type 1 pass per color plane, each starting at the plane's
+	/// byte offset and stepping by `bytes_per_pixel`. Only the bit reader
+	/// carries over between passes.
+	fn decompress_planar(&mut self) -> Result<(), DvFileError> {
+		for plane in 0..self.bytes_per_pixel {
+			self.pass = plane as u8;
+			self.write_position = plane;
+			self.lru_cache = INITIAL_LRU_CACHE;
+			self.decompress_type1()?;
+		}
+		Ok(())
 	}
 }
 
@@ -441,78 +614,363 @@ fn load_palette(data: &[u8], header: &Header) -> Option<[[u8; 4]; 256]> {
 	Some(palette)
 }
 
-fn apply_palette(indexed_data: &[u8], palette: Option<&[[u8; 4]; 256]>) -> Vec<u8> {
+/// Expands palette indices to RGB, or copies them without a palette
+pub(super) fn apply_palette(indexed_data: &[u8], palette: Option<&[[u8; 4]; 256]>) -> Vec<u8> {
 	let Some(pal) = palette else {
 		return indexed_data.to_vec();
 	};
 
-	let mut rgb_data = Vec::with_capacity(indexed_data.len() * 3);
-	for &index in indexed_data {
-		let color = &pal[index as usize];
-		rgb_data.push(color[0]);
-		rgb_data.push(color[1]);
-		rgb_data.push(color[2]);
+	let mut rgb_data = vec![0u8; indexed_data.len() * 3];
+	for (pixel, &index) in rgb_data.chunks_exact_mut(3).zip(indexed_data) {
+		pixel.copy_from_slice(&pal[index as usize][..3]);
 	}
 
 	rgb_data
 }
 
+/// Converts BGR pixel data to RGB in place
+fn bgr_to_rgb(mut data: Vec<u8>) -> Vec<u8> {
+	for pixel in data.chunks_exact_mut(3) {
+		pixel.swap(0, 2);
+	}
+	data
+}
+
+/// Decoded contents of a KG file before palette expansion
+struct Decoded {
+	header: Header,
+	padding: Option<Vec<u8>>,
+	palette: Option<[[u8; 4]; 256]>,
+	/// Palette indices for Type 1, RGB pixels for Types 2 and 3
+	pixels: Vec<u8>,
+	/// Decoded operations, when tracing
+	trace: Option<Vec<TraceRecord>>,
+}
+
+/// Options for decoding KG files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DecodeOptions {
+	/// Decode Type 2 and Type 3 files with the inferred, unverified layout
+	/// instead of reporting [`DvFileError::UnsupportedCompressionType`]
+	pub experimental_true_color: bool,
+}
+
+impl DecodeOptions {
+	/// Enables the experimental Type 2/3 decoder
+	pub fn with_experimental_true_color(mut self, enabled: bool) -> Self {
+		self.experimental_true_color = enabled;
+		self
+	}
+}
+
+/// What [`decode`] produces besides the pixels
+#[derive(Debug, Clone, Copy, Default)]
+struct DecodeMode {
+	/// Caller options
+	options: DecodeOptions,
+	/// Record every operation of the bitstream
+	trace: bool,
+}
+
 /// Decompress KG format data from a byte slice
 /// Returns (Header, RGB data)
 pub fn decompress(data: &[u8]) -> Result<super::File, DvFileError> {
-	let header = Header::from_bytes(data)?;
-
-	let compression_type = header.compression_type();
-
-	let padding =
-		header.padding_size().map(|size| data[Header::SIZE..Header::SIZE + size].to_vec());
+	decompress_with_limits(data, &ParseLimits::default())
+}
 
-	let palette = load_palette(data, &header);
-	let has_palette = palette.is_some();
+/// Decompress KG format data, rejecting images over `limits`
+pub fn decompress_with_limits(
+	data: &[u8],
+	limits: &ParseLimits,
+) -> Result<super::File, DvFileError> {
+	decompress_with_options(data, limits, &DecodeOptions::default())
+}
 
-	let output_bpp = if compression_type == Compression::BPP3 && has_palette {
-		1
-	} else if compression_type == Compression::BPP3 {
-		3
-	} else {
-		1
+/// Decompress KG format data with `options`, rejecting images over `limits`
+pub fn decompress_with_options(
+	data: &[u8],
+	limits: &ParseLimits,
+	options: &DecodeOptions,
+) -> Result<super::File, DvFileError> {
+	let mode = DecodeMode {
+		options: *options,
+		trace: false,
+	};
+	let mut allocation = limits.allocation(FileType::Kg);
+	let decoded = decode(data, mode, limits, &mut allocation)?;
+
+	// Paletted images keep their indices and expand to RGB when first read
+	let (pixels, indices) = match decoded.palette {
+		Some(_) => {
+			allocation.reserve_items(decoded.pixels.len(), 3)?;
+			(OnceLock::new(), Some(decoded.pixels))
+		}
+		None => (OnceLock::from(decoded.pixels), None),
 	};
 
-	let width = header.width as usize;
-	let height = header.height as usize;
-	let data_offset = header.data_offset as usize;
+	Ok(super::File {
+		header: decoded.header,
+		padding: decoded.padding,
+		palette: decoded.palette,
+		indices,
+		pixels,
+	})
+}
 
-	let compressed_data = data[data_offset..].to_vec();
+/// Decompress a paletted KG file without expanding it to RGB
+pub fn decompress_indexed(data: &[u8]) -> Result<super::IndexedImage, DvFileError> {
+	decompress_indexed_with_limits(data, &ParseLimits::default())
+}
 
-	let mut state = DecompressorState::new(width, height, output_bpp, compressed_data);
-	if let Some(pal) = palette {
-		state.palette = pal;
-	} else {
+/// Decompress a paletted KG file, rejecting images over `limits`
+pub fn decompress_indexed_with_limits(
+	data: &[u8],
+	limits: &ParseLimits,
+) -> Result<super::IndexedImage, DvFileError> {
+	let decoded =
+		decode(data, DecodeMode::default(), limits, &mut limits.allocation(FileType::Kg))?;
+	let Some(palette) = decoded.palette else {
 		return Err(DvFileError::DecompressionError {
 			file_type: FileType::Kg,
-			message: "Missing palette".to_string(),
+			message: format!("{} image has no palette to index", decoded.header.compression_type()),
 		});
-	}
+	};
 
-	if compression_type == Compression::BPP3 {
-		state.decompress_type1();
-	} else {
+	super::IndexedImage::new(decoded.header.width, decoded.header.height, palette, decoded.pixels)
+}
+
+/// Decodes a KG file and records every operation of its bitstream
+pub fn trace_bitstream(data: &[u8]) -> Result<Trace, DvFileError> {
+	trace_bitstream_with_options(data, &DecodeOptions::default())
+}
+
+/// Decodes a KG file with `options` and records every operation of its
+/// bitstream
+pub fn trace_bitstream_with_options(
+	data: &[u8],
+	options: &DecodeOptions,
+) -> Result<Trace, DvFileError> {
+	let mode = DecodeMode {
+		options: *options,
+		trace: true,
+	};
+	let limits = ParseLimits::default();
+	let decoded = decode(data, mode, &limits, &mut limits.allocation(FileType::Kg))?;
+	Ok(Trace::new(decoded.header, decoded.trace.unwrap_or_default()))
+}
+
+/// Decodes the header, palette and bitstream of a KG file
+///
+/// Types 2 and 3 are rejected unless the options enable them.
+fn decode(
+	data: &[u8],
+	mode: DecodeMode,
+	limits: &ParseLimits,
+	allocation: &mut Allocation,
+) -> Result<Decoded, DvFileError> {
+	let header = Header::from_bytes(data)?;
+	let compression_type = header.compression_type();
+	let true_color = matches!(compression_type, Compression::Planar | Compression::Interleaved);
+	if true_color && !mode.options.experimental_true_color {
 		return Err(DvFileError::UnsupportedCompressionType {
 			file_type: FileType::Kg,
 			compression_type: compression_type as u8,
 		});
 	}
+	limits.check_dimensions(FileType::Kg, header.width.into(), header.height.into())?;
+
+	let padding = match header.padding_size() {
+		Some(size) => {
+			let end = Header::SIZE + size;
+			let padding = data
+				.get(Header::SIZE..end)
+				.ok_or_else(|| DvFileError::insufficient_data(FileType::Kg, end, data.len()))?;
+			allocation.reserve(size)?;
+			Some(padding.to_vec())
+		}
+		None => None,
+	};
+
+	let palette = load_palette(data, &header);
+
+	let width = header.width as usize;
+	let height = header.height as usize;
+	let data_offset = header.data_offset as usize;
+
+	let compressed_data = data
+		.get(data_offset..)
+		.ok_or_else(|| DvFileError::insufficient_data(FileType::Kg, data_offset, data.len()))?
+		.to_vec();
+	allocation.reserve(compressed_data.len())?;
+
+	let bytes_per_pixel = if compression_type == Compression::BPP3 {
+		1
+	} else {
+		3
+	};
+	let total_size = width * height * bytes_per_pixel;
+	allocation.reserve(total_size)?;
+	if mode.trace {
+		// Every operation writes at least one value
+		allocation.reserve_items(total_size, std::mem::size_of::<TraceRecord>())?;
+	}
+
+	let mut state = match compression_type {
+		Compression::BPP3 => {
+			if palette.is_none() {
+				return Err(DvFileError::DecompressionError {
+					file_type: FileType::Kg,
+					message: "Missing palette".to_string(),
+				});
+			}
+			DecompressorState::new(width, height, 1, compressed_data)
+		}
+		Compression::Planar => DecompressorState::new(width, height, 3, compressed_data),
+		// One byte per "pixel", three times as wide
+		Compression::Interleaved => DecompressorState::new(width * 3, height, 1, compressed_data),
+		Compression::Unsupported => {
+			return Err(DvFileError::UnsupportedCompressionType {
+				file_type: FileType::Kg,
+				compression_type: compression_type as u8,
+			});
+		}
+	};
 
-	let final_data = if has_palette {
-		apply_palette(&state.output_buffer, Some(&state.palette))
+	state.trace = mode.trace.then(Vec::new);
+	if compression_type == Compression::Planar {
+		state.decompress_planar()?;
 	} else {
+		state.decompress_type1()?;
+	}
+
+	let final_data = if compression_type == Compression::BPP3 {
 		state.output_buffer
+	} else {
+		bgr_to_rgb(state.output_buffer)
 	};
 
-	Ok(super::File {
+	Ok(Decoded {
 		header,
 		padding,
 		palette,
 		pixels: final_data,
+		trace: state.trace,
 	})
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Builds a true-color KG file around a raw bitstream
+	fn true_color_file(compression: u8, width: u16, height: u16, bitstream: &[u8]) -> Vec<u8> {
+		let mut data = vec![0u8; Header::SIZE];
+		data[0..2].copy_from_slice(b"KG");
+		data[2] = 0x02;
+		data[3] = compression;
+		data[4..6].copy_from_slice(&width.to_le_bytes());
+		data[6..8].copy_from_slice(&height.to_le_bytes());
+		data[16..20].copy_from_slice(&(Header::SIZE as u32).to_le_bytes());
+		data[20..24].copy_from_slice(&((Header::SIZE + bitstream.len()) as u32).to_le_bytes());
+		data.extend_from_slice(bitstream);
+		data
+	}
+
+	/// Decodes with the experimental Type 2/3 support enabled
+	fn decompress_true_color(data: &[u8]) -> Result<crate::file::kg::File, DvFileError> {
+		let options = DecodeOptions::default().with_experimental_true_color(true);
+		decompress_with_options(data, &ParseLimits::default(), &options)
+	}
+
+	#[test]
+	fn test_decompress_planar() {
+		// 2x1 image: each plane is just the 2-byte prologue (B0 B1, G0 G1, R0 R1)
+		let data = true_color_file(2, 2, 1, &[0x10, 0x11, 0x20, 0x21, 0x30, 0x31]);
+		let file = decompress_true_color(&data).unwrap();
+
+		assert_eq!(file.header().compression_type(), Compression::Planar);
+		assert!(file.palette().is_none());
+		assert_eq!(file.pixels(), &[0x30, 0x20, 0x10, 0x31, 0x21, 0x11]);
+	}
+
+	#[test]
+	fn test_decompress_interleaved() {
+		// 1x1 image: prologue B G, then opcode 0 with a direct 8-bit R
+		// 0x10 0x20 | 0 1 0011_0000 (+ padding)
+		let data = true_color_file(3, 1, 1, &[0x10, 0x20, 0b0100_1100, 0b0000_0000]);
+		let file = decompress_true_color(&data).unwrap();
+
+		assert_eq!(file.header().compression_type(), Compression::Interleaved);
+		assert_eq!(file.pixels(), &[0x30, 0x20, 0x10]);
+	}
+
+	#[test]
+	fn test_decompress_unsupported_type() {
+		let data = true_color_file(4, 1, 1, &[0; 4]);
+		assert!(matches!(
+			decompress(&data),
+			Err(DvFileError::UnsupportedCompressionType {
+				compression_type: 4,
+				..
+			})
+		));
+
+		// The inferred Type 2/3 layouts are opt-in
+		for compression in [2, 3] {
+			let data = true_color_file(compression, 2, 1, &[0x10, 0x11, 0x20, 0x21, 0x30, 0x31]);
+			assert!(matches!(
+				decompress(&data),
+				Err(DvFileError::UnsupportedCompressionType {
+					compression_type,
+					..
+				}) if compression_type == compression
+			));
+			assert!(trace_bitstream(&data).is_err());
+		}
+	}
+
+	#[test]
+	fn test_decompress_truncated_stream() {
+		// 4x1 image: prologue, then a copy whose count never arrives
+		let data = true_color_file(3, 4, 1, &[0x10, 0x20]);
+		assert!(matches!(
+			decompress_true_color(&data),
+			Err(DvFileError::DecompressionError { .. })
+		));
+
+		// Copy that would run past the end of the image: opcode 2, count 3
+		// 0x10 0x20 | 10 11 (+ padding)
+		let data = true_color_file(3, 1, 1, &[0x10, 0x20, 0b1011_0000]);
+		assert!(matches!(
+			decompress_true_color(&data),
+			Err(DvFileError::DecompressionError { .. })
+		));
+	}
+
+	#[test]
+	fn test_decompress_matches_reference_output() {
+		use md5::{Digest, Md5};
+
+		// MD5 of the RGB and index buffers produced by the original
+		// bit-at-a-time decoder
+		let reference = [
+			("BLACK", "13673718fb38f2049ffa8e23cb5b9d82", "e9c2d33b2d6b533909bf0d95f6782209"),
+			("BUHIN", "d136c7737e9bb95ee98cd13b118a8425", "0f0f9ee3af9ea060d72dd263a5840151"),
+			("VYADOY01", "d590320ffd7856f05a292002ed0857ca", "ca49dd52be3033f35d91a81290348f5a"),
+		];
+
+		for (name, pixels_md5, indices_md5) in reference {
+			let path = format!("{}/../../benches/test_data/{}", env!("CARGO_MANIFEST_DIR"), name);
+			let data = std::fs::read(path).unwrap();
+			let file = decompress(&data).unwrap();
+
+			assert_eq!(format!("{:x}", Md5::digest(file.pixels())), pixels_md5, "{}", name);
+			assert_eq!(
+				format!("{:x}", Md5::digest(file.indices().unwrap())),
+				indices_md5,
+				"{}",
+				name
+			);
+		}
+	}
+}
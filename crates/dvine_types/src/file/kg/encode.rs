@@ -25,10 +25,46 @@
 //!    - Choose the most efficient encoding
 //! 4. Output compressed bitstream
 //!
+//! ## Compression Levels
+//!
+//! [`CompressionLevel::Fast`] (the default) picks the longest copy at every
+//! position. [`CompressionLevel::Best`] searches for the sequence of
+//! operations with the fewest bits, which typically matches or beats the
+//! files shipped with the game, but takes a few hundred times longer; pass it
+//! to the `*_with_level` functions to opt in.
+//!
 
 use crate::file::{DvFileError, FileType};
 
-use super::{Compression, Header, opcodes};
+use super::{
+	Compression, Header,
+	constants::DEFAULT_CHROMA_KEY,
+	opcodes,
+	parse::{self, Op, Pass},
+	quantize::{Palette, QuantizeOptions, quantize},
+};
+
+/// Alpha values below this are stored as the chroma key
+const ALPHA_THRESHOLD: u8 = 128;
+
+/// How hard the encoder works to make the output small
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CompressionLevel {
+	/// Greedy longest-match encoding
+	#[default]
+	Fast,
+	/// Optimal parse over all opcode choices, never larger than `Fast`
+	Best,
+}
+
+impl std::fmt::Display for CompressionLevel {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			CompressionLevel::Fast => write!(f, "fast"),
+			CompressionLevel::Best => write!(f, "best"),
+		}
+	}
+}
 
 /// Bit writer for encoding the compressed bitstream
 #[derive(Debug)]
@@ -156,10 +192,17 @@ struct CompressorState {
 	line_bytes: usize,
 	lru_cache: [[u8; 8]; 256],
 	current_color: u8,
+	level: CompressionLevel,
 }
 
 impl CompressorState {
-	fn new(indexed_data: Vec<u8>, width: usize, height: usize, bytes_per_pixel: usize) -> Self {
+	fn new(
+		indexed_data: Vec<u8>,
+		width: usize,
+		height: usize,
+		bytes_per_pixel: usize,
+		level: CompressionLevel,
+	) -> Self {
 		let line_bytes = width * bytes_per_pixel;
 		let lru_cache = [[0u8, 1, 2, 3, 4, 5, 6, 7]; 256];
 
@@ -173,6 +216,7 @@ impl CompressorState {
 			line_bytes,
 			lru_cache,
 			current_color: 0,
+			level,
 		}
 	}
 
@@ -333,9 +377,31 @@ impl CompressorState {
 		self.read_position += length * self.bytes_per_pixel;
 	}
 
+	/// Collects the operations the greedy encoder would emit from the
+	/// current position, without writing anything
+	fn greedy_parse(&mut self) -> Vec<Op> {
+		let start = self.read_position;
+		let total_size = self.indexed_data.len();
+		let mut ops = Vec::new();
+
+		while self.read_position < total_size {
+			if let Some((opcode, length)) = self.find_best_copy_operation() {
+				ops.push(Op::Copy(opcode, length));
+				self.read_position += length * self.bytes_per_pixel;
+			} else {
+				ops.push(Op::Literal);
+				self.read_position += self.bytes_per_pixel;
+			}
+		}
+
+		self.read_position = start;
+		ops
+	}
+
 	/// Main compression routine for Type 1 compression
 	fn compress_type1(&mut self) {
 		let total_size = self.indexed_data.len();
+		let start = self.read_position;
 
 		// Write first 2 bytes directly
 		for _ in 0..2 {
@@ -347,17 +413,38 @@ impl CompressorState {
 			self.read_position += self.bytes_per_pixel;
 		}
 
-		// Main compression loop
-		while self.read_position < total_size {
-			// Try to find a good copy operation
-			if let Some((opcode, length)) = self.find_best_copy_operation() {
-				self.encode_copy_operation(opcode, length);
-			} else {
-				// No good match, use dictionary lookup
-				self.encode_dictionary_lookup();
+		// Choose the operations, then emit them
+		let mut ops = self.greedy_parse();
+		if self.level == CompressionLevel::Best {
+			let pass = Pass {
+				data: &self.indexed_data,
+				start,
+				bytes_per_pixel: self.bytes_per_pixel,
+				line_bytes: self.line_bytes,
+			};
+			ops = parse::optimize(&pass, ops);
+		}
+
+		for op in ops {
+			match op {
+				Op::Copy(opcode, length) => self.encode_copy_operation(opcode, length),
+				Op::Literal => self.encode_dictionary_lookup(),
 			}
 		}
 	}
+
+	/// Main compression routine for Type 2 compression
+	///
+	/// Encodes one Type 1 pass per color plane, restarting the LRU cache for
+	/// each pass to match the decoder.
+	fn compress_planar(&mut self) {
+		for plane in 0..self.bytes_per_pixel {
+			self.read_position = plane;
+			self.current_color = 0;
+			self.lru_cache = [[0u8, 1, 2, 3, 4, 5, 6, 7]; 256];
+			self.compress_type1();
+		}
+	}
 }
 
 /// Builds a palette from RGB image data
@@ -428,6 +515,18 @@ fn palette_to_bgr(palette: &[[u8; 4]; 256]) -> Vec<u8> {
 ///
 /// A complete KG file as a byte vector
 pub fn compress(rgb_data: &[u8], width: u16, height: u16) -> Result<Vec<u8>, DvFileError> {
+	compress_with_level(rgb_data, width, height, CompressionLevel::default())
+}
+
+/// Compresses RGB image data into KG format at the given compression level
+///
+/// See [`compress`] for the arguments.
+pub fn compress_with_level(
+	rgb_data: &[u8],
+	width: u16,
+	height: u16,
+	level: CompressionLevel,
+) -> Result<Vec<u8>, DvFileError> {
 	// Validate input
 	let expected_size = (width as usize) * (height as usize) * 3;
 	if rgb_data.len() != expected_size {
@@ -446,12 +545,160 @@ pub fn compress(rgb_data: &[u8], width: u16, height: u16) -> Result<Vec<u8>, DvF
 	// Build palette and convert to indexed color
 	let (palette, indexed_data) = build_palette(rgb_data)?;
 
+	Ok(encode_indexed(&palette, indexed_data, width, height, level))
+}
+
+/// Compresses RGB image data into KG format, reducing it to a palette first
+///
+/// Unlike [`compress`], this accepts images with any number of colors. Images
+/// that already fit in the palette are encoded losslessly.
+///
+/// # Arguments
+///
+/// * `rgb_data` - Raw RGB pixel data (width * height * 3 bytes)
+/// * `width` - Image width in pixels
+/// * `height` - Image height in pixels
+/// * `options` - Palette size, dithering and chroma key, see [`quantize`]
+/// * `level` - Compression level
+///
+/// # Returns
+///
+/// A complete KG file as a byte vector
+pub fn compress_quantized(
+	rgb_data: &[u8],
+	width: u16,
+	height: u16,
+	options: &QuantizeOptions,
+	level: CompressionLevel,
+) -> Result<Vec<u8>, DvFileError> {
+	let (palette, indexed_data) = quantize(rgb_data, width, height, options)?;
+	Ok(encode_indexed(&palette, indexed_data, width, height, level))
+}
+
+/// Compresses RGBA image data into KG format, storing transparency as the
+/// chroma key
+///
+/// Pixels with alpha below 128 become the chroma key, which is pinned to
+/// palette index 0. The key is `options.chroma_key`, or
+/// [`DEFAULT_CHROMA_KEY`] if none is set. Opaque pixels that happen to have
+/// the key color are nudged by one step in the blue channel so they stay
+/// visible. Decode with [`File::to_rgba_with_key`](super::File::to_rgba_with_key)
+/// using the same key to get the alpha channel back.
+///
+/// # Arguments
+///
+/// * `rgba_data` - Raw RGBA pixel data (width * height * 4 bytes)
+/// * `width` - Image width in pixels
+/// * `height` - Image height in pixels
+/// * `options` - Palette size, dithering and chroma key, see [`quantize`]
+/// * `level` - Compression level
+///
+/// # Returns
+///
+/// A complete KG file as a byte vector
+pub fn compress_rgba(
+	rgba_data: &[u8],
+	width: u16,
+	height: u16,
+	options: &QuantizeOptions,
+	level: CompressionLevel,
+) -> Result<Vec<u8>, DvFileError> {
+	let expected_size = width as usize * height as usize * 4;
+	if rgba_data.len() != expected_size {
+		return Err(DvFileError::CompressionError {
+			file_type: FileType::Kg,
+			message: format!(
+				"Invalid RGBA data size: expected {} bytes ({}x{} * 4), got {} bytes",
+				expected_size,
+				width,
+				height,
+				rgba_data.len()
+			),
+		});
+	}
+
+	let key = options.chroma_key.unwrap_or(DEFAULT_CHROMA_KEY);
+	let mut rgb_data = Vec::with_capacity(rgba_data.len() / 4 * 3);
+	for pixel in rgba_data.chunks_exact(4) {
+		let mut rgb = [pixel[0], pixel[1], pixel[2]];
+		if pixel[3] < ALPHA_THRESHOLD {
+			rgb = key;
+		} else if rgb == key {
+			rgb[2] ^= 1;
+		}
+		rgb_data.extend_from_slice(&rgb);
+	}
+
+	compress_quantized(&rgb_data, width, height, &options.with_chroma_key(key), level)
+}
+
+/// Compresses palette indices into KG format, keeping the palette as given
+///
+/// Use this when the palette order matters, e.g. for images that share a
+/// palette or use index 0 as the transparent color. Both the indices and the
+/// palette are stored unchanged.
+///
+/// # Arguments
+///
+/// * `indexed_data` - One palette index per pixel (width * height bytes)
+/// * `palette` - RGB palette entries, the fourth byte of each is reserved
+/// * `width` - Image width in pixels
+/// * `height` - Image height in pixels
+///
+/// # Returns
+///
+/// A complete KG file as a byte vector
+pub fn compress_indexed(
+	indexed_data: &[u8],
+	palette: &Palette,
+	width: u16,
+	height: u16,
+) -> Result<Vec<u8>, DvFileError> {
+	compress_indexed_with_level(indexed_data, palette, width, height, CompressionLevel::default())
+}
+
+/// Compresses palette indices into KG format at the given compression level
+///
+/// See [`compress_indexed`] for the arguments.
+pub fn compress_indexed_with_level(
+	indexed_data: &[u8],
+	palette: &Palette,
+	width: u16,
+	height: u16,
+	level: CompressionLevel,
+) -> Result<Vec<u8>, DvFileError> {
+	let expected_size = (width as usize) * (height as usize);
+	if indexed_data.len() != expected_size {
+		return Err(DvFileError::CompressionError {
+			file_type: FileType::Kg,
+			message: format!(
+				"Invalid indexed data size: expected {} bytes ({}x{}), got {} bytes",
+				expected_size,
+				width,
+				height,
+				indexed_data.len()
+			),
+		});
+	}
+
+	Ok(encode_indexed(palette, indexed_data.to_vec(), width, height, level))
+}
+
+/// Encodes palette indices as a complete Type 1 KG file
+fn encode_indexed(
+	palette: &Palette,
+	indexed_data: Vec<u8>,
+	width: u16,
+	height: u16,
+	level: CompressionLevel,
+) -> Vec<u8> {
 	// Compress the indexed data
 	let mut state = CompressorState::new(
 		indexed_data,
 		width as usize,
 		height as usize,
 		1, // bytes_per_pixel for indexed color
+		level,
 	);
 	state.compress_type1();
 	let compressed_data = state.writer.into_data();
@@ -465,7 +712,7 @@ pub fn compress(rgb_data: &[u8], width: u16, height: u16) -> Result<Vec<u8>, DvF
 	};
 	let padding = temp_header.create_default_padding();
 	let palette_offset = (Header::SIZE + padding.len()) as u32;
-	let palette_bytes = palette_to_bgr(&palette);
+	let palette_bytes = palette_to_bgr(palette);
 	let data_offset = palette_offset + palette_bytes.len() as u32;
 	let file_size = data_offset + compressed_data.len() as u32;
 
@@ -488,23 +735,143 @@ pub fn compress(rgb_data: &[u8], width: u16, height: u16) -> Result<Vec<u8>, DvF
 	output.extend_from_slice(&palette_bytes);
 	output.extend_from_slice(&compressed_data);
 
-	Ok(output)
+	output
 }
 
 /// Compresses a `File` structure back into KG format bytes
 ///
 /// This is useful when you've loaded a KG file and want to save it again,
-/// possibly after modifications.
+/// possibly after modifications. Paletted files keep their palette as is.
+/// True-color (Type 2/3) files are re-encoded as paletted Type 1, which
+/// fails if they use more than 256 colors; reduce them with
+/// [`compress_quantized`] first. Type 2/3 output is only written through
+/// [`experimental::compress_rgb24`].
 pub fn compress_file(file: &super::File) -> Result<Vec<u8>, DvFileError> {
+	compress_file_with_level(file, CompressionLevel::default())
+}
+
+/// Compresses a `File` structure back into KG format bytes at the given
+/// compression level
+pub fn compress_file_with_level(
+	file: &super::File,
+	level: CompressionLevel,
+) -> Result<Vec<u8>, DvFileError> {
 	let width = file.header().width();
 	let height = file.header().height();
-	compress(file.pixels(), width, height)
+	match (file.palette(), file.indices()) {
+		(Some(palette), Some(indices)) => {
+			compress_indexed_with_level(indices, palette, width, height, level)
+		}
+		_ => compress_with_level(file.pixels(), width, height, level),
+	}
+}
+
+/// Experimental true-color encoding
+///
+/// The Type 2/3 layout is inferred from the decoder and has not been checked
+/// against game data, so files written here are only known to decode with
+/// this crate, with [`DecodeOptions::experimental_true_color`] set. Nothing
+/// outside this module writes Type 2 or Type 3 files.
+///
+/// [`DecodeOptions::experimental_true_color`]: super::DecodeOptions::experimental_true_color
+pub mod experimental {
+	use super::*;
+
+	/// Compresses RGB image data into a true-color KG file without palette
+	///
+	/// # Arguments
+	///
+	/// * `rgb_data` - Raw RGB pixel data (width * height * 3 bytes)
+	/// * `width` - Image width in pixels
+	/// * `height` - Image height in pixels
+	/// * `compression` - [`Compression::Planar`] or [`Compression::Interleaved`]
+	/// * `level` - Compression level
+	///
+	/// # Returns
+	///
+	/// A complete KG file as a byte vector
+	pub fn compress_rgb24(
+		rgb_data: &[u8],
+		width: u16,
+		height: u16,
+		compression: Compression,
+		level: CompressionLevel,
+	) -> Result<Vec<u8>, DvFileError> {
+		let expected_size = (width as usize) * (height as usize) * 3;
+		if rgb_data.len() != expected_size {
+			return Err(DvFileError::CompressionError {
+				file_type: FileType::Kg,
+				message: format!(
+					"Invalid RGB data size: expected {} bytes ({}x{} * 3), got {} bytes",
+					expected_size,
+					width,
+					height,
+					rgb_data.len()
+				),
+			});
+		}
+
+		// The bitstream stores BGR, like the palette entries
+		let mut bgr_data = rgb_data.to_vec();
+		for pixel in bgr_data.chunks_exact_mut(3) {
+			pixel.swap(0, 2);
+		}
+
+		let compressed_data = match compression {
+			Compression::Planar => {
+				let mut state =
+					CompressorState::new(bgr_data, width as usize, height as usize, 3, level);
+				state.compress_planar();
+				state.writer.into_data()
+			}
+			Compression::Interleaved => {
+				let mut state =
+					CompressorState::new(bgr_data, width as usize * 3, height as usize, 1, level);
+				state.compress_type1();
+				state.writer.into_data()
+			}
+			_ => {
+				return Err(DvFileError::CompressionError {
+					file_type: FileType::Kg,
+					message: format!("{} is not a true-color compression type", compression),
+				});
+			}
+		};
+
+		let data_offset = Header::SIZE as u32;
+		let file_size = data_offset + compressed_data.len() as u32;
+		let header = Header {
+			width,
+			height,
+			palette_offset: 0,
+			data_offset,
+			file_size,
+			compression_type: compression,
+			version: 0x02,
+			..Header::default()
+		};
+
+		let mut output = Vec::with_capacity(file_size as usize);
+		output.extend_from_slice(&header.to_bytes());
+		output.extend_from_slice(&compressed_data);
+
+		Ok(output)
+	}
 }
 
 #[cfg(test)]
 mod tests {
-	use super::*;
-	use crate::file::kg::decode;
+	use super::{experimental::compress_rgb24, *};
+	use crate::file::{
+		ParseLimits,
+		kg::{DecodeOptions, File, decode},
+	};
+
+	/// Decodes with the experimental Type 2/3 support enabled
+	fn decompress_true_color(data: &[u8]) -> Result<File, DvFileError> {
+		let options = DecodeOptions::default().with_experimental_true_color(true);
+		File::from_bytes_with_options(data, &ParseLimits::default(), &options)
+	}
 
 	#[test]
 	fn test_bit_writer() {
@@ -656,6 +1023,227 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn test_true_color_roundtrip() {
+		// More colors than Type 1 can hold, with runs for the copy opcodes
+		let (width, height) = (24u16, 16u16);
+		let mut rgb_data = Vec::new();
+		for y in 0..height as usize {
+			for x in 0..width as usize {
+				rgb_data.extend_from_slice(&[(x * 10) as u8, (y * 15) as u8, ((x / 4) * 40) as u8]);
+			}
+		}
+
+		for compression in [Compression::Planar, Compression::Interleaved] {
+			let compressed =
+				compress_rgb24(&rgb_data, width, height, compression, CompressionLevel::Best)
+					.unwrap();
+			let file = decompress_true_color(&compressed).unwrap();
+
+			assert_eq!(file.header().compression_type(), compression);
+			assert_eq!(file.pixels(), &rgb_data);
+
+			// Re-encoding never writes Type 2/3, and Type 1 can't hold every color
+			assert!(compress_file(&file).is_err());
+		}
+
+		// True-color files with few colors come back as Type 1
+		let small: Vec<u8> = rgb_data[..width as usize * 3].repeat(height as usize);
+		let compressed =
+			compress_rgb24(&small, width, height, Compression::Planar, CompressionLevel::Fast)
+				.unwrap();
+		let file = decompress_true_color(&compressed).unwrap();
+		let reencoded = decode::decompress(&compress_file(&file).unwrap()).unwrap();
+		assert_eq!(reencoded.header().compression_type(), Compression::BPP3);
+		assert_eq!(reencoded.pixels(), &small);
+
+		assert!(
+			compress_rgb24(&rgb_data, width, height, Compression::BPP3, CompressionLevel::Best)
+				.is_err()
+		);
+	}
+
+	#[test]
+	fn test_quantized_compression() {
+		let (width, height) = (32u16, 32u16);
+		let mut rgb_data = Vec::new();
+		for i in 0..(width as usize * height as usize) {
+			rgb_data.extend_from_slice(&[(i % 256) as u8, (i / 4) as u8, 0x40]);
+		}
+		assert!(compress(&rgb_data, width, height).is_err());
+
+		let options = QuantizeOptions::default().with_chroma_key([0, 0, 0]);
+		let compressed =
+			compress_quantized(&rgb_data, width, height, &options, CompressionLevel::Best).unwrap();
+		let file = decode::decompress(&compressed).unwrap();
+		assert_eq!(file.palette().unwrap()[0], [0, 0, 0, 0]);
+		assert_eq!(file.pixels().len(), rgb_data.len());
+
+		// Few colors: lossless, same as the exact encoder
+		let small = [255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 0, 0];
+		let compressed = compress_quantized(
+			&small,
+			2,
+			2,
+			&QuantizeOptions::default(),
+			CompressionLevel::default(),
+		)
+		.unwrap();
+		assert_eq!(compressed, compress(&small, 2, 2).unwrap());
+	}
+
+	#[test]
+	fn test_indexed_roundtrip_keeps_palette() {
+		// Index 0 is a transparent magenta that the image never uses, and the
+		// palette is deliberately not in first-seen order
+		let mut palette = [[0u8; 4]; 256];
+		palette[0] = [255, 0, 255, 0];
+		palette[7] = [10, 20, 30, 0];
+		palette[3] = [200, 100, 50, 0];
+		palette[200] = [1, 2, 3, 0];
+
+		let (width, height) = (8u16, 4u16);
+		let indexed_data: Vec<u8> = (0..32).map(|i| [7, 3, 200, 3][(i + i / 8) % 4]).collect();
+
+		let compressed = compress_indexed(&indexed_data, &palette, width, height).unwrap();
+		let file = decode::decompress(&compressed).unwrap();
+		assert_eq!(file.palette(), Some(&palette));
+		let expected: Vec<u8> =
+			indexed_data.iter().flat_map(|&i| palette[i as usize][..3].to_vec()).collect();
+		assert_eq!(file.pixels(), &expected);
+
+		// Re-encoding a decoded file keeps its palette
+		let reencoded = decode::decompress(&compress_file(&file).unwrap()).unwrap();
+		assert_eq!(reencoded.palette(), Some(&palette));
+		assert_eq!(reencoded.pixels(), file.pixels());
+
+		assert!(compress_indexed(&indexed_data[1..], &palette, width, height).is_err());
+	}
+
+	#[test]
+	fn test_rgba_roundtrip_through_chroma_key() {
+		let key = super::super::constants::DEFAULT_CHROMA_KEY;
+		let (width, height) = (6u16, 4u16);
+		let mut rgba_data = Vec::new();
+		for i in 0..(width as usize * height as usize) {
+			let pixel = match i % 4 {
+				0 => [90, 12, 200, 0],              // transparent, color discarded
+				1 => [key[0], key[1], key[2], 255], // opaque key color
+				_ => [(i * 10) as u8, 40, 80, 255],
+			};
+			rgba_data.extend_from_slice(&pixel);
+		}
+
+		let options = QuantizeOptions::default();
+		let compressed =
+			compress_rgba(&rgba_data, width, height, &options, CompressionLevel::Fast).unwrap();
+		let file = decode::decompress(&compressed).unwrap();
+		assert_eq!(file.palette().unwrap()[0], [key[0], key[1], key[2], 0]);
+		assert_eq!(file.indices().unwrap()[0], 0);
+
+		let rgba = file.to_rgba();
+		for (i, (pixel, original)) in rgba.chunks(4).zip(rgba_data.chunks(4)).enumerate() {
+			match i % 4 {
+				0 => assert_eq!(pixel, [key[0], key[1], key[2], 0]),
+				1 => assert_eq!(pixel, [0x00, 0xFF, 0xFE, 255]),
+				_ => assert_eq!(pixel, original),
+			}
+		}
+
+		// A custom key replaces the default
+		let magenta = [0xFF, 0x00, 0xFF];
+		let compressed = compress_rgba(
+			&rgba_data,
+			width,
+			height,
+			&options.with_chroma_key(magenta),
+			CompressionLevel::Fast,
+		)
+		.unwrap();
+		let file = decode::decompress(&compressed).unwrap();
+		let rgba = file.to_rgba_with_key(magenta);
+		assert_eq!(rgba[3], 0);
+		assert_eq!(&rgba[4..8], &[key[0], key[1], key[2], 255]);
+
+		assert!(
+			compress_rgba(&rgba_data[4..], width, height, &options, CompressionLevel::Fast)
+				.is_err()
+		);
+	}
+
+	#[test]
+	fn test_rgba_reduced_palette_never_rebuilds_key() {
+		// Both colors sit next to the key and average out to it
+		let rgba_data: Vec<u8> = (0..16)
+			.flat_map(|i| {
+				if i % 2 == 0 {
+					[0x00, 0xFF, 0xFE, 255]
+				} else {
+					[0x00, 0xFE, 0xFF, 255]
+				}
+			})
+			.collect();
+		let options = QuantizeOptions::default().with_max_colors(2);
+		let compressed = compress_rgba(&rgba_data, 4, 4, &options, CompressionLevel::Fast).unwrap();
+
+		let file = decode::decompress(&compressed).unwrap();
+		assert_ne!(file.palette().unwrap()[1][..3], DEFAULT_CHROMA_KEY);
+		assert!(file.to_rgba().chunks(4).all(|pixel| pixel[3] == 255));
+	}
+
+	#[test]
+	fn test_best_level_never_larger() {
+		// Stripes and a gradient: plenty of competing copy choices
+		let (width, height) = (40u16, 24u16);
+		let colors = [[0, 0, 0], [255, 0, 0], [0, 255, 0], [0, 0, 255], [255, 255, 255]];
+		let mut rgb_data = Vec::new();
+		for y in 0..height as usize {
+			for x in 0..width as usize {
+				let index = if y % 6 < 3 {
+					(x / 3 + y) % colors.len()
+				} else {
+					(x * x / 7) % colors.len()
+				};
+				rgb_data.extend_from_slice(&colors[index]);
+			}
+		}
+
+		let fast = compress_with_level(&rgb_data, width, height, CompressionLevel::Fast).unwrap();
+		let best = compress_with_level(&rgb_data, width, height, CompressionLevel::Best).unwrap();
+		assert!(best.len() <= fast.len(), "{} > {}", best.len(), fast.len());
+		assert_eq!(decode::decompress(&best).unwrap().pixels(), &rgb_data);
+		// The plain functions keep the fast encoder
+		assert_eq!(compress(&rgb_data, width, height).unwrap(), fast);
+
+		for compression in [Compression::Planar, Compression::Interleaved] {
+			let fast =
+				compress_rgb24(&rgb_data, width, height, compression, CompressionLevel::Fast)
+					.unwrap();
+			let best =
+				compress_rgb24(&rgb_data, width, height, compression, CompressionLevel::Best)
+					.unwrap();
+			assert!(best.len() <= fast.len());
+			assert_eq!(decompress_true_color(&best).unwrap().pixels(), &rgb_data);
+		}
+	}
+
+	#[test]
+	fn test_best_level_matches_shipped_files() {
+		for name in ["BLACK", "BUHIN", "VYADOY01"] {
+			let path = format!("{}/../../benches/test_data/{}", env!("CARGO_MANIFEST_DIR"), name);
+			let original = std::fs::read(path).unwrap();
+			let file = decode::decompress(&original).unwrap();
+
+			let best = compress_file_with_level(&file, CompressionLevel::Best).unwrap();
+			assert!(best.len() <= original.len(), "{}: {} > {}", name, best.len(), original.len());
+
+			let reencoded = decode::decompress(&best).unwrap();
+			assert_eq!(reencoded.palette(), file.palette(), "{}", name);
+			assert_eq!(reencoded.indices(), file.indices(), "{}", name);
+			assert_eq!(reencoded.pixels(), file.pixels(), "{}", name);
+		}
+	}
+
 	#[test]
 	fn test_encode_invalid_dimensions() {
 		// Test with invalid data size
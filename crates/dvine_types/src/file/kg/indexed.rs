@@ -0,0 +1,279 @@
+//! Palette-indexed KG images
+//!
+//! [`File`](super::File) exposes decoded images as RGB, which loses the
+//! palette index of every pixel. [`IndexedImage`] keeps the raw indices next
+//! to the palette, so they can be edited directly, re-encoded losslessly with
+//! [`compress_indexed`](super::compress_indexed), or rendered with a different
+//! palette to emulate palette animation.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use dvine_types::file::kg::IndexedImage;
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut image = IndexedImage::open("input.kg")?;
+//!
+//! // Cycle palette entries 16..32 by one step
+//! image.palette_mut()[16..32].rotate_left(1);
+//! let frame = image.to_rgb();
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{collections::HashMap, io::Read};
+
+use crate::file::{DvFileError, FileType, ParseLimits};
+
+use super::{CompressionLevel, decode, encode, quantize::Palette};
+
+/// A decoded paletted image: one palette index per pixel
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexedImage {
+	width: u16,
+	height: u16,
+	palette: Palette,
+	indices: Vec<u8>,
+}
+
+impl IndexedImage {
+	/// Creates an indexed image from its parts
+	///
+	/// Returns an error if `indices` does not hold exactly `width * height`
+	/// entries.
+	pub fn new(
+		width: u16,
+		height: u16,
+		palette: Palette,
+		indices: Vec<u8>,
+	) -> Result<Self, DvFileError> {
+		let expected_size = width as usize * height as usize;
+		if indices.len() != expected_size {
+			return Err(DvFileError::CompressionError {
+				file_type: FileType::Kg,
+				message: format!(
+					"Invalid index buffer size: expected {} bytes ({}x{}), got {} bytes",
+					expected_size,
+					width,
+					height,
+					indices.len()
+				),
+			});
+		}
+
+		Ok(Self {
+			width,
+			height,
+			palette,
+			indices,
+		})
+	}
+
+	/// Converts RGB pixels to indices of `palette`
+	///
+	/// Colors that appear more than once in the palette map to their first
+	/// entry. Returns an error if a pixel's color is not in the palette.
+	pub fn from_rgb(
+		rgb_data: &[u8],
+		width: u16,
+		height: u16,
+		palette: Palette,
+	) -> Result<Self, DvFileError> {
+		let mut lookup = HashMap::new();
+		for (index, color) in palette.iter().enumerate().rev() {
+			lookup.insert([color[0], color[1], color[2]], index as u8);
+		}
+
+		let indices = rgb_data
+			.chunks_exact(3)
+			.enumerate()
+			.map(|(i, pixel)| {
+				lookup.get(pixel).copied().ok_or_else(|| DvFileError::CompressionError {
+					file_type: FileType::Kg,
+					message: format!(
+						"Pixel {} color #{:02X}{:02X}{:02X} is not in the palette",
+						i, pixel[0], pixel[1], pixel[2]
+					),
+				})
+			})
+			.collect::<Result<Vec<_>, _>>()?;
+
+		Self::new(width, height, palette, indices)
+	}
+
+	/// Opens a paletted `.KG` file without expanding it to RGB
+	pub fn open(path: impl AsRef<std::path::Path>) -> Result<Self, DvFileError> {
+		let data = std::fs::read(path)?;
+		decode::decompress_indexed(&data)
+	}
+
+	/// Decodes a paletted `.KG` file from bytes
+	///
+	/// Returns an error for true-color (Type 2 and 3) images.
+	pub fn from_bytes(data: &[u8]) -> Result<Self, DvFileError> {
+		decode::decompress_indexed(data)
+	}
+
+	/// Decodes a paletted `.KG` file from bytes, rejecting images over `limits`
+	pub fn from_bytes_with_limits(data: &[u8], limits: &ParseLimits) -> Result<Self, DvFileError> {
+		decode::decompress_indexed_with_limits(data, limits)
+	}
+
+	/// Decodes a paletted `.KG` file from any reader
+	pub fn from_reader<R: Read>(reader: &mut R) -> Result<Self, DvFileError> {
+		Self::from_reader_with_limits(reader, &ParseLimits::default())
+	}
+
+	/// Decodes a paletted `.KG` file from any reader, rejecting images over
+	/// `limits`
+	pub fn from_reader_with_limits<R: Read>(
+		reader: &mut R,
+		limits: &ParseLimits,
+	) -> Result<Self, DvFileError> {
+		let data = limits.allocation(FileType::Kg).read_to_end(reader)?;
+		decode::decompress_indexed_with_limits(&data, &limits.remaining_after(data.len()))
+	}
+
+	/// Returns the image width in pixels
+	pub fn width(&self) -> u16 {
+		self.width
+	}
+
+	/// Returns the image height in pixels
+	pub fn height(&self) -> u16 {
+		self.height
+	}
+
+	/// Returns the palette
+	pub fn palette(&self) -> &Palette {
+		&self.palette
+	}
+
+	/// Returns the palette for editing
+	pub fn palette_mut(&mut self) -> &mut Palette {
+		&mut self.palette
+	}
+
+	/// Returns the palette indices, row by row
+	pub fn indices(&self) -> &[u8] {
+		&self.indices
+	}
+
+	/// Returns the palette indices for editing
+	pub fn indices_mut(&mut self) -> &mut [u8] {
+		&mut self.indices
+	}
+
+	/// Returns the palette index at the given pixel, if it is in bounds
+	pub fn index_at(&self, x: u16, y: u16) -> Option<u8> {
+		if x >= self.width || y >= self.height {
+			return None;
+		}
+		Some(self.indices[y as usize * self.width as usize + x as usize])
+	}
+
+	/// Expands the indices to RGB pixels with the current palette
+	pub fn to_rgb(&self) -> Vec<u8> {
+		self.to_rgb_with(&self.palette)
+	}
+
+	/// Expands the indices to RGB pixels with another palette
+	pub fn to_rgb_with(&self, palette: &Palette) -> Vec<u8> {
+		self.indices.iter().flat_map(|&index| palette[index as usize][..3].to_vec()).collect()
+	}
+
+	/// Expands the indices to RGBA pixels, with palette entries of color `key`
+	/// transparent
+	pub fn to_rgba_with_key(&self, key: [u8; 3]) -> Vec<u8> {
+		super::rgb_to_rgba(&self.to_rgb(), key)
+	}
+
+	/// Encodes the image as a `.KG` file, keeping indices and palette
+	pub fn to_bytes(&self) -> Result<Vec<u8>, DvFileError> {
+		self.to_bytes_with_level(CompressionLevel::default())
+	}
+
+	/// Encodes the image as a `.KG` file at the given compression level
+	pub fn to_bytes_with_level(&self, level: CompressionLevel) -> Result<Vec<u8>, DvFileError> {
+		encode::compress_indexed_with_level(
+			&self.indices,
+			&self.palette,
+			self.width,
+			self.height,
+			level,
+		)
+	}
+
+	/// Saves the image as a `.KG` file
+	pub fn save(&self, path: impl AsRef<std::path::Path>) -> Result<(), DvFileError> {
+		std::fs::write(path, self.to_bytes()?)?;
+		Ok(())
+	}
+}
+
+impl TryFrom<&super::File> for IndexedImage {
+	type Error = DvFileError;
+
+	fn try_from(file: &super::File) -> Result<Self, Self::Error> {
+		let (Some(palette), Some(indices)) = (file.palette(), file.indices()) else {
+			return Err(DvFileError::DecompressionError {
+				file_type: FileType::Kg,
+				message: format!(
+					"{} image has no palette to index",
+					file.header().compression_type()
+				),
+			});
+		};
+		Self::new(file.header().width(), file.header().height(), *palette, indices.to_vec())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn sample() -> IndexedImage {
+		let mut palette = [[0u8; 4]; 256];
+		palette[1] = [255, 0, 0, 0];
+		palette[2] = [0, 255, 0, 0];
+		palette[9] = [255, 0, 0, 0]; // duplicate of 1
+		let indices = (0..48).map(|i| [0, 1, 2, 9][(i / 3 + i / 8) % 4]).collect();
+		IndexedImage::new(8, 6, palette, indices).unwrap()
+	}
+
+	#[test]
+	fn test_indexed_roundtrip() {
+		let image = sample();
+		let bytes = image.to_bytes().unwrap();
+
+		// Indices survive, including the duplicate palette entry
+		let decoded = IndexedImage::from_bytes(&bytes).unwrap();
+		assert_eq!(decoded, image);
+
+		// The RGB view agrees and converts back to a File
+		let file = super::super::File::from_reader(&mut bytes.as_slice()).unwrap();
+		assert_eq!(file.pixels(), image.to_rgb());
+		assert_eq!(IndexedImage::try_from(&file).unwrap(), image);
+	}
+
+	#[test]
+	fn test_from_rgb_and_palette_swap() {
+		let image = sample();
+		let rgb = image.to_rgb();
+
+		let converted = IndexedImage::from_rgb(&rgb, 8, 6, *image.palette()).unwrap();
+		assert_eq!(converted.to_rgb(), rgb);
+		assert!(!converted.indices().contains(&9));
+		assert_eq!(image.index_at(7, 5), Some(image.indices()[47]));
+		assert_eq!(image.index_at(8, 0), None);
+
+		let mut swapped = *image.palette();
+		swapped.swap(1, 2);
+		let recolored = image.to_rgb_with(&swapped);
+		assert_eq!(image.indices()[3], 1);
+		assert_eq!(&recolored[9..12], &[0, 255, 0]);
+
+		assert!(IndexedImage::from_rgb(&[1, 2, 3], 1, 1, *image.palette()).is_err());
+		assert!(IndexedImage::new(2, 2, *image.palette(), vec![0; 3]).is_err());
+	}
+}
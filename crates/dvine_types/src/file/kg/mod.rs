@@ -5,9 +5,13 @@
 //!
 //! # Features
 //!
-//! - **Decompression**: Full support for Type 1 (BPP3) compression
+//! - **Decompression**: Type 1 (BPP3) paletted images; Type 2/3 true-color
+//!   images are experimental and opt-in through [`DecodeOptions`], see
+//!   [Limitations](#limitations)
 //! - **Compression**: Efficient encoding with LRU cache and copy operations
 //! - **Palette Support**: 256-color indexed images with BGRA palette
+//! - **Transparency**: RGBA output and input through the chroma-key color
+//!   (by default cyan, [`constants::DEFAULT_CHROMA_KEY`])
 //!
 //! # Examples
 //!
@@ -76,25 +80,48 @@
 //!
 //! # Limitations
 //!
-//! - Maximum 256 unique colors (8-bit indexed color)
-//! - Only Type 1 (BPP3) compression is currently supported
-//! - Images with more than 256 colors will fail to compress
+//! - Type 1 (BPP3) images are limited to 256 unique colors (8-bit indexed color);
+//!   use [`compress_quantized`] to reduce true-color input to a palette
+//! - Type 2 and Type 3 support is experimental. Their layout is inferred from
+//!   the decoder's code paths; no game file or reference output has been
+//!   checked against it. [`File::open`] and the other default decoders report
+//!   them as [`DvFileError::UnsupportedCompressionType`]; decoding them takes
+//!   [`DecodeOptions::experimental_true_color`]. Files written by the
+//!   [`encode::experimental`] encoder may not load in the game, and
+//!   [`compress_file`] re-encodes true-color files as Type 1
+//! - [`compress`] builds the palette in first-seen color order; use
+//!   [`compress_indexed`] to keep an existing palette and its indices
 
 mod decode;
 pub mod encode;
+pub mod indexed;
+mod parse;
+pub mod quantize;
+pub mod resample;
+pub mod trace;
 
-pub use encode::{compress, compress_file};
+pub use decode::{DecodeOptions, trace_bitstream, trace_bitstream_with_options};
+pub use encode::{
+	CompressionLevel, compress, compress_file, compress_file_with_level, compress_indexed,
+	compress_indexed_with_level, compress_quantized, compress_rgba, compress_with_level,
+};
+pub use indexed::IndexedImage;
 
-use std::{fmt::Display, io::Read};
+use std::{fmt::Display, io::Read, sync::OnceLock};
 
-use crate::file::{DvFileError, FileType, kg::constants::MAGIC};
+use crate::file::{DvFileError, FileType, ParseLimits, kg::constants::MAGIC};
 
-mod constants {
+/// Constants for `.KG` files
+pub mod constants {
 	/// Magic bytes for `.KG` files
 	pub const MAGIC: [u8; 2] = [0x4B, 0x47]; // "KG"
 
 	/// Header size for `.KG` files
 	pub const HEADER_SIZE: usize = 32;
+
+	/// Chroma-key color conventionally stored at palette index 0 and drawn
+	/// as transparent (cyan)
+	pub const DEFAULT_CHROMA_KEY: [u8; 3] = [0x00, 0xFF, 0xFF];
 }
 
 // Opcode definitions for the KG decompression algorithm
@@ -124,6 +151,18 @@ pub enum Compression {
 	/// Image is encoded using dictionary lookup with LRU cache and various copy operations
 	/// Uses 1 or 3 bytes per pixel depending on whether a palette is present
 	BPP3 = 1,
+
+	/// 24-bit image without palette, encoded as three consecutive color planes
+	/// (blue, green, red) with the Type 1 algorithm
+	///
+	/// Experimental: the layout is inferred, not verified against game data.
+	Planar = 2,
+
+	/// 24-bit image without palette, encoded as interleaved BGR bytes with the
+	/// Type 1 algorithm
+	///
+	/// Experimental: the layout is inferred, not verified against game data.
+	Interleaved = 3,
 }
 
 impl Display for Compression {
@@ -131,6 +170,8 @@ impl Display for Compression {
 		match self {
 			Compression::Unsupported => write!(f, "Unsupported"),
 			Compression::BPP3 => write!(f, "BPP3"),
+			Compression::Planar => write!(f, "Planar"),
+			Compression::Interleaved => write!(f, "Interleaved"),
 		}
 	}
 }
@@ -193,11 +234,10 @@ impl Header {
 
 	/// Returns the size of the padding in bytes, if any.
 	pub fn padding_size(&self) -> Option<usize> {
-		if self.palette_offset > 0 && self.palette_offset - constants::HEADER_SIZE as u32 > 0 {
-			Some((self.palette_offset - constants::HEADER_SIZE as u32) as usize)
-		} else {
-			None
-		}
+		self.palette_offset
+			.checked_sub(constants::HEADER_SIZE as u32)
+			.filter(|&size| size > 0)
+			.map(|size| size as usize)
 	}
 
 	/// Returns true if the image has padding
@@ -268,6 +308,8 @@ impl Header {
 		let version = data[2];
 		let compression_type = match data[3] {
 			1 => Compression::BPP3,
+			2 => Compression::Planar,
+			3 => Compression::Interleaved,
 			_ => {
 				return Err(DvFileError::UnsupportedCompressionType {
 					file_type: FileType::Kg,
@@ -377,6 +419,21 @@ impl Display for Header {
 
 type Plalette = [[u8; 4]; 256];
 
+/// Expands RGB pixels to RGBA, making pixels of color `key` transparent
+fn rgb_to_rgba(rgb_data: &[u8], key: [u8; 3]) -> Vec<u8> {
+	rgb_data
+		.chunks_exact(3)
+		.flat_map(|rgb| {
+			let alpha = if rgb == key {
+				0
+			} else {
+				u8::MAX
+			};
+			[rgb[0], rgb[1], rgb[2], alpha]
+		})
+		.collect()
+}
+
 /// Representation of a decoded `.KG` file
 #[derive(Debug)]
 pub struct File {
@@ -389,8 +446,12 @@ pub struct File {
 	/// Palette data, if present
 	palette: Option<Plalette>,
 
-	/// Pixel data of the `.KG` file, in RGB format
-	pixels: Vec<u8>,
+	/// Palette indices of paletted images, one per pixel
+	indices: Option<Vec<u8>>,
+
+	/// Pixel data of the `.KG` file, in RGB format, expanded from the
+	/// indices on first access for paletted images
+	pixels: OnceLock<Vec<u8>>,
 }
 
 impl File {
@@ -411,8 +472,34 @@ impl File {
 	}
 
 	/// Returns a reference to the pixel data of the `.KG` file
+	///
+	/// Paletted images are expanded to RGB on the first call.
 	pub fn pixels(&self) -> &[u8] {
-		&self.pixels
+		self.pixels.get_or_init(|| {
+			decode::apply_palette(self.indices().unwrap_or_default(), self.palette())
+		})
+	}
+
+	/// Returns the pixel data as RGBA, with the default chroma key transparent
+	///
+	/// See [`to_rgba_with_key`](Self::to_rgba_with_key).
+	pub fn to_rgba(&self) -> Vec<u8> {
+		self.to_rgba_with_key(constants::DEFAULT_CHROMA_KEY)
+	}
+
+	/// Returns the pixel data as RGBA, with pixels of color `key` transparent
+	///
+	/// Transparent pixels keep their color and get alpha 0, every other pixel
+	/// is opaque.
+	pub fn to_rgba_with_key(&self, key: [u8; 3]) -> Vec<u8> {
+		rgb_to_rgba(self.pixels(), key)
+	}
+
+	/// Returns the palette index of every pixel, if the file has a palette
+	///
+	/// See [`IndexedImage`] for editing indices.
+	pub fn indices(&self) -> Option<&[u8]> {
+		self.indices.as_deref()
 	}
 
 	/// Opens and parses a `.KG` file from the specified path
@@ -421,15 +508,52 @@ impl File {
 		decode::decompress(&data)
 	}
 
+	/// Parses a `.KG` file from bytes
+	pub fn from_bytes(data: &[u8]) -> Result<Self, DvFileError> {
+		decode::decompress(data)
+	}
+
+	/// Parses a `.KG` file from bytes, rejecting images over `limits`
+	pub fn from_bytes_with_limits(data: &[u8], limits: &ParseLimits) -> Result<Self, DvFileError> {
+		decode::decompress_with_limits(data, limits)
+	}
+
+	/// Parses a `.KG` file from bytes with `options`, rejecting images over
+	/// `limits`
+	pub fn from_bytes_with_options(
+		data: &[u8],
+		limits: &ParseLimits,
+		options: &DecodeOptions,
+	) -> Result<Self, DvFileError> {
+		decode::decompress_with_options(data, limits, options)
+	}
+
 	/// Creates a `.KG` file from any reader
 	///
 	/// Note: This reads the entire file into memory before decompression.
 	/// The KG decompression algorithm requires random access to the data,
 	/// so streaming decompression is not supported.
 	pub fn from_reader<R: Read>(reader: &mut R) -> Result<Self, DvFileError> {
-		let mut data = Vec::new();
-		reader.read_to_end(&mut data)?;
-		decode::decompress(&data)
+		Self::from_reader_with_limits(reader, &ParseLimits::default())
+	}
+
+	/// Creates a `.KG` file from any reader, rejecting images over `limits`
+	pub fn from_reader_with_limits<R: Read>(
+		reader: &mut R,
+		limits: &ParseLimits,
+	) -> Result<Self, DvFileError> {
+		Self::from_reader_with_options(reader, limits, &DecodeOptions::default())
+	}
+
+	/// Creates a `.KG` file from any reader with `options`, rejecting images
+	/// over `limits`
+	pub fn from_reader_with_options<R: Read>(
+		reader: &mut R,
+		limits: &ParseLimits,
+		options: &DecodeOptions,
+	) -> Result<Self, DvFileError> {
+		let data = limits.allocation(FileType::Kg).read_to_end(reader)?;
+		decode::decompress_with_options(&data, &limits.remaining_after(data.len()), options)
 	}
 
 	/// Saves the `.KG` file to the specified path
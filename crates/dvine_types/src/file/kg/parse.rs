@@ -0,0 +1,309 @@
+//! Optimal parsing for the KG encoder.
+//!
+//! The greedy encoder picks the longest copy at each position, which is not
+//! the cheapest sequence overall: a shorter copy can line up a much longer
+//! one, and the variable-length counts make some lengths disproportionately
+//! expensive. This module finds the bit-cheapest sequence of operations with
+//! a shortest-path search over pixel positions.
+//!
+//! The cost of a dictionary lookup depends on the LRU cache, which in turn
+//! depends on the lookups chosen before it. The search therefore estimates
+//! lookup costs from a reference parse, re-parses with those estimates, and
+//! repeats a few times, keeping whichever parse is actually cheapest.
+
+use super::opcodes;
+
+/// Lengths up to this value are all considered for every copy
+const NICE_LENGTH: usize = 32;
+
+/// Lengths at which the variable-length count grows
+const COUNT_BOUNDARIES: [usize; 4] = [3, 18, 255, 65535];
+
+/// Number of re-parses with refined lookup costs
+const ITERATIONS: usize = 3;
+
+/// Copy opcodes, in the order the greedy encoder tries them
+const COPY_OPCODES: [u8; 5] = [
+	opcodes::OP_COPY_PREV_PIXEL,
+	opcodes::OP_COPY_PREV_LINE,
+	opcodes::OP_COPY_DIAGONAL_1,
+	opcodes::OP_COPY_DIAGONAL_2,
+	opcodes::OP_COPY_DOUBLE_BPP,
+];
+
+/// A single encoder operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Op {
+	/// Opcode 0: dictionary lookup for one pixel
+	Literal,
+	/// Copy opcode with its length in pixels
+	Copy(u8, usize),
+}
+
+/// One encoder pass over the data: every `bytes_per_pixel`-th byte from
+/// `start`
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Pass<'a> {
+	/// Complete (indexed or BGR) image data
+	pub data: &'a [u8],
+	/// Offset of the first byte of the pass
+	pub start: usize,
+	/// Distance between consecutive bytes of the pass
+	pub bytes_per_pixel: usize,
+	/// Distance between rows
+	pub line_bytes: usize,
+}
+
+impl Pass<'_> {
+	/// Number of pixels in the pass
+	fn len(&self) -> usize {
+		(self.data.len().saturating_sub(self.start)).div_ceil(self.bytes_per_pixel)
+	}
+
+	/// Byte position of pixel `k`
+	fn position(&self, k: usize) -> usize {
+		self.start + k * self.bytes_per_pixel
+	}
+
+	/// Returns how far back a copy opcode reads at `position`, if it is
+	/// available there (same conditions as the greedy encoder)
+	fn copy_distance(&self, opcode: u8, position: usize) -> Option<usize> {
+		let (bpp, line) = (self.bytes_per_pixel, self.line_bytes);
+		match opcode {
+			opcodes::OP_COPY_PREV_PIXEL if position >= bpp => Some(bpp),
+			opcodes::OP_COPY_PREV_LINE if position >= line => Some(line),
+			opcodes::OP_COPY_DIAGONAL_1 if position >= line && line > bpp => Some(line - bpp),
+			opcodes::OP_COPY_DIAGONAL_2 if position >= line + bpp => Some(line + bpp),
+			opcodes::OP_COPY_DOUBLE_BPP if position >= bpp * 2 => Some(bpp * 2),
+			_ => None,
+		}
+	}
+
+	/// Computes, for every copy opcode and pixel, the longest run that
+	/// opcode could copy starting there
+	fn run_lengths(&self) -> Vec<Vec<u32>> {
+		let n = self.len();
+		COPY_OPCODES
+			.iter()
+			.map(|&opcode| {
+				let mut runs = vec![0u32; n + 1];
+				for k in (0..n).rev() {
+					let position = self.position(k);
+					let Some(distance) = self.copy_distance(opcode, position) else {
+						continue;
+					};
+					if self.data[position] == self.data[position - distance] {
+						runs[k] = runs[k + 1] + 1;
+					}
+				}
+				runs
+			})
+			.collect()
+	}
+}
+
+/// Bits needed for an opcode
+fn opcode_bits(opcode: u8) -> u32 {
+	match opcode {
+		opcodes::OP_DICT_LOOKUP => 1,
+		opcodes::OP_COPY_PREV_PIXEL => 2,
+		_ => 4,
+	}
+}
+
+/// Bits needed for a variable-length count
+pub(super) fn count_bits(value: usize) -> u32 {
+	match value {
+		1..=3 => 2,
+		4..=18 => 6,
+		19..=255 => 14,
+		256..=65535 => 30,
+		_ => 62,
+	}
+}
+
+/// Moves `color` to the front of an LRU cache entry, like the decoder
+fn update_lru(entry: &mut [u8; 8], color: u8) {
+	let position = entry.iter().position(|&c| c == color).unwrap_or(7);
+	entry.copy_within(0..position, 1);
+	entry[0] = color;
+}
+
+/// Replays a parse through the LRU cache
+///
+/// Returns the exact number of bits the parse encodes to, and for every
+/// pixel whether a dictionary lookup there would have hit the cache.
+pub(super) fn simulate(pass: &Pass<'_>, ops: &[Op]) -> (u64, Vec<bool>) {
+	let n = pass.len();
+	let prologue = n.min(2);
+	let mut lru = [[0u8, 1, 2, 3, 4, 5, 6, 7]; 256];
+	let mut hits = vec![false; n];
+	let mut bits = prologue as u64 * 8;
+
+	let mut k = prologue;
+	let mut ops = ops.iter();
+	while k < n {
+		// Cache state before pixel k is decided
+		let position = pass.position(k);
+		let reference = pass.data[position - pass.bytes_per_pixel];
+		hits[k] = lru[reference as usize].contains(&pass.data[position]);
+
+		match ops.next() {
+			Some(Op::Copy(opcode, length)) => {
+				bits += u64::from(opcode_bits(*opcode) + count_bits(*length));
+				// Pixels inside the copy never become lookups, but their cache
+				// hits are still useful estimates for the next parse
+				let end = (k + length).min(n);
+				for (j, hit) in hits.iter_mut().enumerate().take(end).skip(k + 1) {
+					let position = pass.position(j);
+					let reference = pass.data[position - pass.bytes_per_pixel];
+					*hit = lru[reference as usize].contains(&pass.data[position]);
+				}
+				k += length;
+			}
+			_ => {
+				let color = pass.data[position];
+				bits += if hits[k] {
+					5
+				} else {
+					10
+				};
+				update_lru(&mut lru[reference as usize], color);
+				k += 1;
+			}
+		}
+	}
+
+	(bits, hits)
+}
+
+/// Finds the cheapest parse given per-pixel dictionary lookup cache hits
+fn shortest_path(pass: &Pass<'_>, runs: &[Vec<u32>], hits: &[bool]) -> Vec<Op> {
+	let n = pass.len();
+	let prologue = n.min(2);
+	let mut cost = vec![u64::MAX; n + 1];
+	let mut step = vec![Op::Literal; n + 1];
+	cost[prologue] = 0;
+
+	for k in prologue..n {
+		let base = cost[k];
+		if base == u64::MAX {
+			continue;
+		}
+
+		let literal = base
+			+ if hits[k] {
+				5
+			} else {
+				10
+			};
+		if literal < cost[k + 1] {
+			cost[k + 1] = literal;
+			step[k + 1] = Op::Literal;
+		}
+
+		for (&opcode, runs) in COPY_OPCODES.iter().zip(runs) {
+			let run = runs[k] as usize;
+			if run == 0 {
+				continue;
+			}
+
+			let mut relax = |length: usize| {
+				let next = base + u64::from(opcode_bits(opcode) + count_bits(length));
+				if next < cost[k + length] {
+					cost[k + length] = next;
+					step[k + length] = Op::Copy(opcode, length);
+				}
+			};
+			for length in 1..=run.min(NICE_LENGTH) {
+				relax(length);
+			}
+			for length in COUNT_BOUNDARIES {
+				if length > NICE_LENGTH && length < run {
+					relax(length);
+				}
+			}
+			if run > NICE_LENGTH {
+				relax(run);
+			}
+		}
+	}
+
+	// Walk back from the end
+	let mut ops = Vec::new();
+	let mut k = n;
+	while k > prologue {
+		let op = step[k];
+		ops.push(op);
+		k -= match op {
+			Op::Literal => 1,
+			Op::Copy(_, length) => length,
+		};
+	}
+	ops.reverse();
+	ops
+}
+
+/// Improves on a reference parse by iterated shortest-path search
+///
+/// The result never encodes to more bits than `reference`.
+pub(super) fn optimize(pass: &Pass<'_>, reference: Vec<Op>) -> Vec<Op> {
+	let runs = pass.run_lengths();
+	let (mut best_bits, mut hits) = simulate(pass, &reference);
+	let mut best = reference;
+
+	for _ in 0..ITERATIONS {
+		let ops = shortest_path(pass, &runs, &hits);
+		let (bits, next_hits) = simulate(pass, &ops);
+		if bits < best_bits {
+			best_bits = bits;
+			best = ops;
+		}
+		hits = next_hits;
+	}
+
+	best
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_count_bits_match_writer_ranges() {
+		assert_eq!(count_bits(1), 2);
+		assert_eq!(count_bits(3), 2);
+		assert_eq!(count_bits(4), 6);
+		assert_eq!(count_bits(18), 6);
+		assert_eq!(count_bits(19), 14);
+		assert_eq!(count_bits(65535), 30);
+		assert_eq!(count_bits(65536), 62);
+	}
+
+	#[test]
+	fn test_optimize_covers_pass() {
+		// A short repeating pattern over two rows: copies beat lookups
+		let data: Vec<u8> = (0..64).map(|i| [1, 2, 1, 2, 3, 3, 3, 3][i % 8]).collect();
+		let pass = Pass {
+			data: &data,
+			start: 0,
+			bytes_per_pixel: 1,
+			line_bytes: 32,
+		};
+
+		let literals = vec![Op::Literal; data.len() - 2];
+		let (literal_bits, _) = simulate(&pass, &literals);
+		let ops = optimize(&pass, literals);
+		let (bits, _) = simulate(&pass, &ops);
+
+		assert!(bits * 2 < literal_bits, "{} vs {}", bits, literal_bits);
+		let covered: usize = ops
+			.iter()
+			.map(|op| match op {
+				Op::Literal => 1,
+				Op::Copy(_, length) => *length,
+			})
+			.sum();
+		assert_eq!(covered, data.len() - 2);
+	}
+}
@@ -0,0 +1,477 @@
+//! Color quantization for KG encoding.
+//!
+//! Type 1 KG images are limited to a 256-color palette. This module reduces
+//! true-color RGB input to such a palette using median cut, with optional
+//! Floyd–Steinberg dithering. Images that already fit are mapped exactly,
+//! with palette entries in order of first appearance.
+//!
+//! Palette index 0 can be pinned to a chroma-key color. Only pixels that
+//! match the key exactly map to index 0; every other pixel (including
+//! anti-aliased edges close to the key) is mapped to the remaining entries,
+//! so edits never turn visible pixels transparent. A reduced palette entry
+//! that averages out to the key is nudged by one step in the blue channel.
+//!
+//! # Examples
+//!
+//! ```
+//! use dvine_types::file::kg::quantize::{Dither, QuantizeOptions, quantize};
+//!
+//! // A gradient with 4096 distinct colors
+//! let mut rgb = Vec::new();
+//! for i in 0..4096u32 {
+//!     rgb.extend_from_slice(&[(i % 256) as u8, (i / 16) as u8, 0x80]);
+//! }
+//!
+//! let options = QuantizeOptions::default()
+//!     .with_dither(Dither::FloydSteinberg)
+//!     .with_chroma_key([0x00, 0xFF, 0x00]);
+//! let (palette, indices) = quantize(&rgb, 64, 64, &options).unwrap();
+//!
+//! assert_eq!(indices.len(), 4096);
+//! assert_eq!(palette[0], [0x00, 0xFF, 0x00, 0]);
+//! assert!(indices.iter().all(|&index| index != 0));
+//! ```
+
+use std::collections::HashMap;
+
+use crate::file::{DvFileError, FileType};
+
+/// RGB palette with 256 entries, the fourth byte of each entry is unused
+pub type Palette = [[u8; 4]; 256];
+
+/// Dithering applied when mapping pixels to a reduced palette
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Dither {
+	/// Map every pixel to its nearest palette color
+	#[default]
+	None,
+
+	/// Diffuse the mapping error to neighboring pixels (Floyd–Steinberg)
+	FloydSteinberg,
+}
+
+/// Options for [`quantize`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuantizeOptions {
+	/// Maximum number of palette entries to use (1-256), including the
+	/// chroma key if one is pinned
+	pub max_colors: usize,
+
+	/// Dithering used when the image has more colors than the palette
+	pub dither: Dither,
+
+	/// Color pinned to palette index 0, if any
+	pub chroma_key: Option<[u8; 3]>,
+}
+
+impl Default for QuantizeOptions {
+	fn default() -> Self {
+		Self {
+			max_colors: 256,
+			dither: Dither::None,
+			chroma_key: None,
+		}
+	}
+}
+
+impl QuantizeOptions {
+	/// Sets the maximum number of palette entries
+	pub fn with_max_colors(mut self, max_colors: usize) -> Self {
+		self.max_colors = max_colors;
+		self
+	}
+
+	/// Sets the dithering mode
+	pub fn with_dither(mut self, dither: Dither) -> Self {
+		self.dither = dither;
+		self
+	}
+
+	/// Pins palette index 0 to the given RGB color
+	pub fn with_chroma_key(mut self, chroma_key: [u8; 3]) -> Self {
+		self.chroma_key = Some(chroma_key);
+		self
+	}
+}
+
+/// Reduces RGB image data to a palette and per-pixel palette indices
+///
+/// # Arguments
+///
+/// * `rgb_data` - Raw RGB pixel data (width * height * 3 bytes)
+/// * `width` - Image width in pixels
+/// * `height` - Image height in pixels
+/// * `options` - Palette size, dithering and chroma key
+///
+/// # Returns
+///
+/// The palette (unused entries are zero) and one palette index per pixel
+///
+/// # Errors
+///
+/// Returns an error if the data size does not match the dimensions or
+/// `max_colors` leaves no room for the image colors
+pub fn quantize(
+	rgb_data: &[u8],
+	width: u16,
+	height: u16,
+	options: &QuantizeOptions,
+) -> Result<(Palette, Vec<u8>), DvFileError> {
+	let expected_size = width as usize * height as usize * 3;
+	if rgb_data.len() != expected_size {
+		return Err(quantize_error(format!(
+			"Invalid RGB data size: expected {} bytes ({}x{} * 3), got {} bytes",
+			expected_size,
+			width,
+			height,
+			rgb_data.len()
+		)));
+	}
+	if options.max_colors == 0 || options.max_colors > 256 {
+		return Err(quantize_error(format!(
+			"Palette size must be between 1 and 256, got {}",
+			options.max_colors
+		)));
+	}
+
+	let key = options.chroma_key;
+	let first_free = usize::from(key.is_some());
+	let available = options.max_colors - first_free;
+
+	// Distinct non-key colors, in order of first appearance
+	let mut counts: HashMap<[u8; 3], u32> = HashMap::new();
+	let mut order = Vec::new();
+	for pixel in rgb_data.chunks_exact(3) {
+		let color = [pixel[0], pixel[1], pixel[2]];
+		if Some(color) == key {
+			continue;
+		}
+		*counts.entry(color).or_insert_with(|| {
+			order.push(color);
+			0
+		}) += 1;
+	}
+
+	if !order.is_empty() && available == 0 {
+		return Err(quantize_error("No palette entries left besides the chroma key".to_string()));
+	}
+
+	let exact = order.len() <= available;
+	let mut colors = if exact {
+		order
+	} else {
+		let histogram: Vec<([u8; 3], u32)> = order.iter().map(|c| (*c, counts[c])).collect();
+		median_cut(histogram, available)
+	};
+
+	// Averaging colors next to the key can rebuild the key itself, which
+	// would make every pixel mapped to that entry decode as transparent
+	if let Some(key) = key {
+		for color in colors.iter_mut().filter(|color| **color == key) {
+			color[2] ^= 1;
+		}
+	}
+
+	let mut palette = [[0u8; 4]; 256];
+	if let Some([r, g, b]) = key {
+		palette[0] = [r, g, b, 0];
+	}
+	for (entry, [r, g, b]) in palette[first_free..].iter_mut().zip(&colors) {
+		*entry = [*r, *g, *b, 0];
+	}
+
+	let mut mapper = Mapper {
+		colors: &colors,
+		offset: first_free as u8,
+		cache: HashMap::new(),
+	};
+
+	let indices = if exact || options.dither == Dither::None {
+		rgb_data
+			.chunks_exact(3)
+			.map(|pixel| {
+				let color = [pixel[0], pixel[1], pixel[2]];
+				if Some(color) == key {
+					0
+				} else {
+					mapper.nearest(color)
+				}
+			})
+			.collect()
+	} else {
+		floyd_steinberg(rgb_data, width as usize, key, &mut mapper)
+	};
+
+	Ok((palette, indices))
+}
+
+/// Maps colors to their nearest palette index, memoizing the results
+struct Mapper<'a> {
+	/// Palette colors, excluding the chroma key
+	colors: &'a [[u8; 3]],
+	/// Palette index of `colors[0]`
+	offset: u8,
+	/// Memoized lookups
+	cache: HashMap<[u8; 3], u8>,
+}
+
+impl Mapper<'_> {
+	/// Returns the palette index closest to `color`
+	fn nearest(&mut self, color: [u8; 3]) -> u8 {
+		if let Some(&index) = self.cache.get(&color) {
+			return index;
+		}
+
+		let best = self
+			.colors
+			.iter()
+			.enumerate()
+			.min_by_key(|(_, candidate)| distance(color, **candidate))
+			.map_or(0, |(i, _)| i);
+		let index = self.offset + best as u8;
+		self.cache.insert(color, index);
+		index
+	}
+}
+
+/// Squared Euclidean distance between two colors
+fn distance(a: [u8; 3], b: [u8; 3]) -> u32 {
+	a.iter()
+		.zip(&b)
+		.map(|(&x, &y)| {
+			let d = x as i32 - y as i32;
+			(d * d) as u32
+		})
+		.sum()
+}
+
+/// Splits the color histogram into `target` boxes and returns the weighted
+/// mean color of each box
+fn median_cut(histogram: Vec<([u8; 3], u32)>, target: usize) -> Vec<[u8; 3]> {
+	let mut boxes = vec![histogram];
+
+	while boxes.len() < target {
+		// Split the box with the widest channel range
+		let Some((index, channel, _)) = boxes
+			.iter()
+			.enumerate()
+			.filter(|(_, colors)| colors.len() > 1)
+			.map(|(i, colors)| {
+				let (channel, range) = widest_channel(colors);
+				(i, channel, range)
+			})
+			.max_by_key(|&(i, _, range)| (range, std::cmp::Reverse(i)))
+		else {
+			break;
+		};
+
+		let mut colors = boxes.swap_remove(index);
+		colors.sort_by_key(|(color, _)| color[channel]);
+
+		// Weighted median, keeping at least one color on each side
+		let total: u64 = colors.iter().map(|&(_, count)| count as u64).sum();
+		let mut running = 0;
+		let mut split = 1;
+		for (i, &(_, count)) in colors.iter().enumerate() {
+			running += count as u64;
+			if running * 2 >= total {
+				split = i + 1;
+				break;
+			}
+		}
+		let split = split.clamp(1, colors.len() - 1);
+
+		let upper = colors.split_off(split);
+		boxes.push(colors);
+		boxes.push(upper);
+	}
+
+	boxes.iter().map(|colors| mean_color(colors)).collect()
+}
+
+/// Returns the channel with the largest value range and that range
+fn widest_channel(colors: &[([u8; 3], u32)]) -> (usize, u8) {
+	(0..3)
+		.map(|channel| {
+			let min = colors.iter().map(|(c, _)| c[channel]).min().unwrap_or(0);
+			let max = colors.iter().map(|(c, _)| c[channel]).max().unwrap_or(0);
+			(channel, max - min)
+		})
+		.max_by_key(|&(channel, range)| (range, std::cmp::Reverse(channel)))
+		.unwrap_or((0, 0))
+}
+
+/// Weighted mean of the colors in a box
+fn mean_color(colors: &[([u8; 3], u32)]) -> [u8; 3] {
+	let total: u64 = colors.iter().map(|&(_, count)| count as u64).sum::<u64>().max(1);
+	let mut mean = [0u8; 3];
+	for (channel, value) in mean.iter_mut().enumerate() {
+		let sum: u64 = colors.iter().map(|(c, count)| c[channel] as u64 * *count as u64).sum();
+		*value = ((sum + total / 2) / total) as u8;
+	}
+	mean
+}
+
+/// Maps pixels with Floyd–Steinberg error diffusion
+///
+/// Chroma-key pixels always map to index 0 and neither receive nor spread
+/// error, so the transparent area stays exact.
+fn floyd_steinberg(
+	rgb_data: &[u8],
+	width: usize,
+	key: Option<[u8; 3]>,
+	mapper: &mut Mapper<'_>,
+) -> Vec<u8> {
+	let pixel_count = rgb_data.len() / 3;
+	let is_key = |i: usize| key.is_some_and(|k| rgb_data[i * 3..i * 3 + 3] == k);
+
+	let mut error = vec![[0i32; 3]; pixel_count];
+	let mut indices = vec![0u8; pixel_count];
+
+	for i in 0..pixel_count {
+		if is_key(i) {
+			continue;
+		}
+
+		let mut color = [0u8; 3];
+		for channel in 0..3 {
+			let value = rgb_data[i * 3 + channel] as i32 + error[i][channel] / 16;
+			color[channel] = value.clamp(0, 255) as u8;
+		}
+
+		let index = mapper.nearest(color);
+		indices[i] = index;
+
+		let mapped = mapper.colors[(index - mapper.offset) as usize];
+		let diff = [
+			color[0] as i32 - mapped[0] as i32,
+			color[1] as i32 - mapped[1] as i32,
+			color[2] as i32 - mapped[2] as i32,
+		];
+
+		let x = i % width;
+		let mut spread = |target: usize, weight: i32| {
+			if !is_key(target) {
+				for channel in 0..3 {
+					error[target][channel] += diff[channel] * weight;
+				}
+			}
+		};
+		if x + 1 < width {
+			spread(i + 1, 7);
+		}
+		if i + width < pixel_count {
+			if x > 0 {
+				spread(i + width - 1, 3);
+			}
+			spread(i + width, 5);
+			if x + 1 < width {
+				spread(i + width + 1, 1);
+			}
+		}
+	}
+
+	indices
+}
+
+/// Creates a compression error for the quantizer
+fn quantize_error(message: String) -> DvFileError {
+	DvFileError::CompressionError {
+		file_type: FileType::Kg,
+		message,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn gradient(width: u16, height: u16) -> Vec<u8> {
+		let mut rgb = Vec::new();
+		for y in 0..height as usize {
+			for x in 0..width as usize {
+				rgb.extend_from_slice(&[(x * 4) as u8, (y * 4) as u8, ((x + y) * 2) as u8]);
+			}
+		}
+		rgb
+	}
+
+	#[test]
+	fn test_exact_when_colors_fit() {
+		let rgb = [10, 20, 30, 40, 50, 60, 10, 20, 30, 70, 80, 90];
+		let (palette, indices) = quantize(&rgb, 2, 2, &QuantizeOptions::default()).unwrap();
+
+		assert_eq!(indices, vec![0, 1, 0, 2]);
+		assert_eq!(palette[1], [40, 50, 60, 0]);
+	}
+
+	#[test]
+	fn test_reduces_to_max_colors() {
+		let rgb = gradient(64, 64);
+
+		// Squared error of the mean color of each 8x8 block
+		let block_error = |palette: &Palette, indices: &[u8]| -> u64 {
+			let mut total = 0;
+			for block in 0..64 {
+				let (bx, by) = (block % 8 * 8, block / 8 * 8);
+				let mut sums = [[0u64; 3]; 2];
+				for y in by..by + 8 {
+					for x in bx..bx + 8 {
+						let i = y * 64 + x;
+						let c = palette[indices[i] as usize];
+						for channel in 0..3 {
+							sums[0][channel] += rgb[i * 3 + channel] as u64;
+							sums[1][channel] += c[channel] as u64;
+						}
+					}
+				}
+				total += (0..3).map(|c| sums[0][c].abs_diff(sums[1][c]).pow(2) / 4096).sum::<u64>();
+			}
+			total / 64
+		};
+
+		let mut errors = Vec::new();
+		for dither in [Dither::None, Dither::FloydSteinberg] {
+			let options = QuantizeOptions::default().with_max_colors(32).with_dither(dither);
+			let (palette, indices) = quantize(&rgb, 64, 64, &options).unwrap();
+
+			assert!(indices.iter().all(|&i| i < 32));
+			assert!(palette[32..].iter().all(|c| *c == [0; 4]));
+			errors.push(block_error(&palette, &indices));
+		}
+
+		// Dithering keeps local averages closer to the original
+		assert!(errors[1] < errors[0], "block errors {:?}", errors);
+		assert!(errors[0] < 800, "block errors {:?}", errors);
+	}
+
+	#[test]
+	fn test_chroma_key_pinned() {
+		let key = [0x00, 0xFF, 0x00];
+		let mut rgb = gradient(32, 32);
+		rgb[0..3].copy_from_slice(&key);
+		// Near-key pixel must not become transparent
+		rgb[3..6].copy_from_slice(&[0x02, 0xFD, 0x01]);
+
+		let options = QuantizeOptions::default()
+			.with_max_colors(16)
+			.with_dither(Dither::FloydSteinberg)
+			.with_chroma_key(key);
+		let (palette, indices) = quantize(&rgb, 32, 32, &options).unwrap();
+
+		assert_eq!(palette[0], [0x00, 0xFF, 0x00, 0]);
+		assert_eq!(indices[0], 0);
+		assert!(indices[1..].iter().all(|&i| (1..16).contains(&i)));
+	}
+
+	#[test]
+	fn test_invalid_options() {
+		let rgb = gradient(4, 4);
+		let zero = QuantizeOptions::default().with_max_colors(0);
+		assert!(quantize(&rgb, 4, 4, &zero).is_err());
+
+		let only_key = QuantizeOptions::default().with_max_colors(1).with_chroma_key([0, 0, 0]);
+		assert!(quantize(&rgb, 4, 4, &only_key).is_err());
+		assert!(quantize(&rgb, 4, 5, &QuantizeOptions::default()).is_err());
+	}
+}
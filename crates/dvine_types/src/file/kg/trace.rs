@@ -0,0 +1,392 @@
+//! KG bitstream disassembly and statistics
+//!
+//! [`trace_bitstream`](super::trace_bitstream) decodes a `.KG` file and
+//! records every operation of the bitstream: where it starts, how many bits
+//! it takes, what it writes and, for dictionary lookups, the LRU cache entry
+//! it consulted. The resulting [`Trace`] prints as a disassembly listing, and
+//! [`Trace::stats`] summarizes how the bits are spent per operation, which
+//! makes it easy to compare two encoders on the same image.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use dvine_types::file::kg;
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let data = std::fs::read("input.kg")?;
+//! let trace = kg::trace_bitstream(&data)?;
+//! println!("{}", trace.stats());
+//! for record in trace.records().iter().take(20) {
+//!     println!("{}", record);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt::Display;
+
+use serde::Serialize;
+
+use super::{Header, opcodes};
+
+/// What a traced operation did
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TraceOp {
+	/// Raw 8-bit value of the 2-byte prologue that starts each pass
+	Prologue {
+		/// Value written
+		value: u8,
+	},
+	/// Opcode 0: dictionary lookup
+	Lookup {
+		/// Value written
+		value: u8,
+		/// LRU cache slot the value came from, or `None` for a direct 8-bit value
+		cache_index: Option<u8>,
+		/// Previous pixel's value, which selects the LRU cache entry
+		reference: u8,
+		/// The LRU cache entry for `reference` before the lookup
+		lru: [u8; 8],
+	},
+	/// Opcodes 2 and 12-15: copy earlier output
+	Copy {
+		/// Copy opcode
+		opcode: u8,
+		/// Output byte offset of the first source byte
+		source_offset: usize,
+	},
+}
+
+impl TraceOp {
+	/// Returns the opcode, or `None` for prologue bytes
+	pub fn opcode(&self) -> Option<u8> {
+		match self {
+			TraceOp::Prologue {
+				..
+			} => None,
+			TraceOp::Lookup {
+				..
+			} => Some(opcodes::OP_DICT_LOOKUP),
+			TraceOp::Copy {
+				opcode,
+				..
+			} => Some(*opcode),
+		}
+	}
+
+	/// Short mnemonic of the operation
+	pub fn mnemonic(&self) -> &'static str {
+		match self {
+			TraceOp::Prologue {
+				..
+			} => "RAW",
+			TraceOp::Lookup {
+				cache_index: Some(_),
+				..
+			} => "LRU",
+			TraceOp::Lookup {
+				cache_index: None,
+				..
+			} => "LIT",
+			TraceOp::Copy {
+				opcode,
+				..
+			} => match *opcode {
+				opcodes::OP_COPY_PREV_PIXEL => "PREV",
+				opcodes::OP_COPY_PREV_LINE => "UP",
+				opcodes::OP_COPY_DIAGONAL_1 => "UPR",
+				opcodes::OP_COPY_DIAGONAL_2 => "UPL",
+				_ => "PREV2",
+			},
+		}
+	}
+}
+
+/// One operation of the bitstream
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TraceRecord {
+	/// Pass the operation belongs to (the color plane for Type 2, else 0)
+	pub pass: u8,
+	/// Offset of the operation's first bit in the bitstream
+	pub bit_offset: u64,
+	/// Number of bits the operation takes, opcode included
+	pub bits: u32,
+	/// Output byte offset of the first value written
+	pub output_offset: usize,
+	/// Number of values written (pixels, or bytes of a plane)
+	pub count: u32,
+	/// What the operation did
+	#[serde(flatten)]
+	pub op: TraceOp,
+}
+
+impl Display for TraceRecord {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f,
+			"{:>9} {:>3}b  p{} @{:<8} {:<5}",
+			self.bit_offset,
+			self.bits,
+			self.pass,
+			self.output_offset,
+			self.op.mnemonic()
+		)?;
+		match self.op {
+			TraceOp::Prologue {
+				value,
+			} => write!(f, " {:#04x}", value),
+			TraceOp::Lookup {
+				value,
+				cache_index,
+				reference,
+				lru,
+			} => {
+				write!(f, " {:#04x}", value)?;
+				if let Some(index) = cache_index {
+					write!(f, " [{}]", index)?;
+				}
+				write!(f, " ref {:#04x} lru {:02x?}", reference, lru)
+			}
+			TraceOp::Copy {
+				source_offset,
+				..
+			} => write!(f, " x{} from @{}", self.count, source_offset),
+		}
+	}
+}
+
+/// Totals for one kind of operation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct OpStats {
+	/// Number of operations
+	pub ops: u64,
+	/// Number of values written
+	pub values: u64,
+	/// Number of bits spent
+	pub bits: u64,
+}
+
+impl OpStats {
+	/// Average number of bits per value written
+	pub fn bits_per_value(&self) -> f64 {
+		if self.values == 0 {
+			0.0
+		} else {
+			self.bits as f64 / self.values as f64
+		}
+	}
+}
+
+/// Operation histogram and bit usage of a trace
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TraceStats {
+	/// Totals per mnemonic, in the order `RAW`, `LRU`, `LIT`, `PREV`, `UP`,
+	/// `UPR`, `UPL`, `PREV2`
+	pub ops: Vec<(&'static str, OpStats)>,
+	/// Histogram of copy counts: index `n` counts copies of `n` values, the
+	/// last bucket collects all longer copies
+	pub copy_lengths: Vec<u64>,
+	/// Totals over all operations
+	pub total: OpStats,
+}
+
+/// Number of buckets in [`TraceStats::copy_lengths`]
+const LENGTH_BUCKETS: usize = 33;
+
+/// Mnemonics in display order
+const MNEMONICS: [&str; 8] = ["RAW", "LRU", "LIT", "PREV", "UP", "UPR", "UPL", "PREV2"];
+
+impl Display for TraceStats {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		writeln!(
+			f,
+			"{:<6} {:>9} {:>9} {:>10} {:>6} {:>9}",
+			"op", "count", "values", "bits", "bits%", "bits/val"
+		)?;
+		for (name, stats) in &self.ops {
+			if stats.ops == 0 {
+				continue;
+			}
+			writeln!(
+				f,
+				"{:<6} {:>9} {:>9} {:>10} {:>5.1}% {:>9.3}",
+				name,
+				stats.ops,
+				stats.values,
+				stats.bits,
+				stats.bits as f64 * 100.0 / self.total.bits.max(1) as f64,
+				stats.bits_per_value()
+			)?;
+		}
+		write!(
+			f,
+			"{:<6} {:>9} {:>9} {:>10} {:>5.1}% {:>9.3}",
+			"total",
+			self.total.ops,
+			self.total.values,
+			self.total.bits,
+			100.0,
+			self.total.bits_per_value()
+		)
+	}
+}
+
+/// Every operation of a decoded `.KG` bitstream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trace {
+	header: Header,
+	records: Vec<TraceRecord>,
+}
+
+impl Trace {
+	pub(super) fn new(header: Header, records: Vec<TraceRecord>) -> Self {
+		Self {
+			header,
+			records,
+		}
+	}
+
+	/// Returns the header of the traced file
+	pub fn header(&self) -> &Header {
+		&self.header
+	}
+
+	/// Returns the operations in bitstream order
+	pub fn records(&self) -> &[TraceRecord] {
+		&self.records
+	}
+
+	/// Summarizes the trace per operation
+	pub fn stats(&self) -> TraceStats {
+		let mut ops: Vec<(&'static str, OpStats)> =
+			MNEMONICS.iter().map(|&name| (name, OpStats::default())).collect();
+		let mut copy_lengths = vec![0u64; LENGTH_BUCKETS];
+		let mut total = OpStats::default();
+
+		for record in &self.records {
+			let mnemonic = record.op.mnemonic();
+			let slot = ops.iter_mut().find(|(name, _)| *name == mnemonic).map(|(_, stats)| stats);
+			for stats in slot.into_iter().chain(std::iter::once(&mut total)) {
+				stats.ops += 1;
+				stats.values += u64::from(record.count);
+				stats.bits += u64::from(record.bits);
+			}
+
+			if let TraceOp::Copy {
+				..
+			} = record.op
+			{
+				copy_lengths[(record.count as usize).min(LENGTH_BUCKETS - 1)] += 1;
+			}
+		}
+
+		TraceStats {
+			ops,
+			copy_lengths,
+			total,
+		}
+	}
+}
+
+impl Display for Trace {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		writeln!(f, "{}", self.header)?;
+		writeln!(f, "{:>9} {:>4}  {:<2} {:<9} op", "bit", "len", "ps", "output")?;
+		for record in &self.records {
+			writeln!(f, "{}", record)?;
+		}
+		write!(f, "{}", self.stats())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::file::kg::{
+		Compression, CompressionLevel, DecodeOptions, compress_with_level,
+		encode::experimental::compress_rgb24, trace_bitstream, trace_bitstream_with_options,
+	};
+
+	#[test]
+	fn test_trace_accounts_for_every_bit_and_pixel() {
+		let (width, height) = (16u16, 8u16);
+		let mut rgb_data = Vec::new();
+		for y in 0..height as usize {
+			for x in 0..width as usize {
+				let shade = if (x / 4 + y / 2) % 2 == 0 {
+					0x20
+				} else {
+					0xC0
+				};
+				rgb_data.extend_from_slice(&[shade, (x * 16) as u8, 0x40]);
+			}
+		}
+
+		let data = compress_with_level(&rgb_data, width, height, CompressionLevel::Fast).unwrap();
+		let trace = trace_bitstream(&data).unwrap();
+		let records = trace.records();
+
+		// Records tile the bitstream and the output without gaps
+		for pair in records.windows(2) {
+			assert_eq!(pair[0].bit_offset + u64::from(pair[0].bits), pair[1].bit_offset);
+			assert_eq!(pair[0].output_offset + pair[0].count as usize, pair[1].output_offset);
+		}
+		let stats = trace.stats();
+		assert_eq!(stats.total.values, u64::from(width) * u64::from(height));
+		let data_bits = (data.len() - trace.header().data_offset() as usize) as u64 * 8;
+		assert!(stats.total.bits <= data_bits && stats.total.bits + 8 > data_bits);
+
+		// Two prologue bytes, then operations with consistent opcodes
+		assert_eq!(
+			stats.ops[0],
+			(
+				"RAW",
+				OpStats {
+					ops: 2,
+					values: 2,
+					bits: 16
+				}
+			)
+		);
+		for record in &records[2..] {
+			match record.op {
+				TraceOp::Lookup {
+					value,
+					cache_index: Some(index),
+					lru,
+					..
+				} => {
+					assert_eq!(lru[index as usize], value);
+					assert_eq!(record.bits, 5);
+				}
+				TraceOp::Lookup {
+					cache_index: None,
+					..
+				} => assert_eq!(record.bits, 10),
+				TraceOp::Copy {
+					source_offset,
+					..
+				} => assert!(source_offset < record.output_offset),
+				TraceOp::Prologue {
+					..
+				} => panic!("prologue after start"),
+			}
+		}
+		assert!(records[2].to_string().contains(records[2].op.mnemonic()));
+	}
+
+	#[test]
+	fn test_trace_planar_passes() {
+		let rgb_data: Vec<u8> = (0..4 * 4 * 3).map(|i| (i * 7) as u8).collect();
+		let data =
+			compress_rgb24(&rgb_data, 4, 4, Compression::Planar, CompressionLevel::Fast).unwrap();
+		let options = DecodeOptions::default().with_experimental_true_color(true);
+		let trace = trace_bitstream_with_options(&data, &options).unwrap();
+
+		let passes: Vec<u8> = trace.records().iter().map(|record| record.pass).collect();
+		assert!(passes.windows(2).all(|pair| pair[0] <= pair[1]));
+		assert_eq!(passes.last(), Some(&2));
+		assert_eq!(trace.stats().total.values, 4 * 4 * 3);
+	}
+}
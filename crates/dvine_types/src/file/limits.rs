@@ -0,0 +1,289 @@
+//! Resource limits for parsing untrusted files.
+//!
+//! Dimensions, frame counts and offsets in the game's formats come straight
+//! from header fields, so a corrupt or malicious file can ask for a
+//! `65535x65535` image or four billion frames. [`ParseLimits`] caps what a
+//! single parse may request; anything beyond the caps is rejected with
+//! [`DvFileError::LimitExceeded`] before the memory is allocated.
+//!
+//! Every parser that allocates from header fields has a `*_with_limits`
+//! variant of its `from_bytes`/`from_reader` constructors. The plain
+//! constructors use [`ParseLimits::default()`], which is generous enough for
+//! every file shipped with the game.
+//!
+//! # Covered entry points
+//!
+//! The `*_with_limits` variants exist for [`anm::File`](super::anm::File),
+//! [`efc::File`](super::efc::File), [`fnt::File`](super::fnt::File),
+//! [`item::File`](super::item::File), [`kg::File`](super::kg::File),
+//! [`mfd::File`](super::mfd::File), [`pft::File`](super::pft::File),
+//! [`spr::File`](super::spr::File), indexed KG images, DSK patches and, with
+//! the `image` feature, PNG imports.
+//!
+//! The remaining parsers take no limits because their size does not come
+//! from the input:
+//!
+//! - [`pft::Header`](super::pft::Header), [`spr::Palette`](super::spr::Palette),
+//!   [`startup_ini::StartupIni`](super::startup_ini::StartupIni) and the EFC
+//!   [`SoundDataHeader`](super::efc::SoundDataHeader) and
+//!   [`AdpcmDataHeader`](super::efc::AdpcmDataHeader) read fixed-size
+//!   records. The entry count of a PFT header is only checked when a
+//!   [`pft::File`](super::pft::File) is parsed.
+//! - [`anm::AnimationSequence::from_bytes`](super::anm::AnimationSequence::from_bytes)
+//!   stops after [`ParseConfig::max_iterations`](super::anm::ParseConfig)
+//!   frames, and `from_bytes_raw` reads at most one frame per input word.
+//! - [`ManifestEntry::from_reader`](super::manifest::ManifestEntry::from_reader)
+//!   hashes the input as a stream and keeps none of it.
+//!
+//! # Examples
+//!
+//! ```
+//! use dvine_types::file::{DvFileError, ParseLimits, spr};
+//!
+//! // Header of an SPR file claiming 0x10000000 frames
+//! let mut data = vec![0u8; 16];
+//! data[..4].copy_from_slice(&0x1000_0000u32.to_le_bytes());
+//!
+//! let limits = ParseLimits::strict();
+//! let result = spr::File::from_bytes_with_limits(&data, &limits);
+//! assert!(matches!(result, Err(DvFileError::LimitExceeded { .. })));
+//! ```
+
+use std::io::Read;
+
+use super::{DvFileError, FileType};
+
+/// Caps on the resources a single parse may use.
+///
+/// # Presets
+///
+/// - `default()`: 8192x8192 pixels, 65536 frames and entries, 512 MiB
+/// - `strict()`: 2048x2048 pixels, 4096 frames and entries, 32 MiB
+/// - `unlimited()`: no caps, for trusted files only
+///
+/// # Examples
+///
+/// ```
+/// use dvine_types::file::ParseLimits;
+///
+/// let limits = ParseLimits::strict().with_max_allocation(64 << 20);
+/// assert_eq!(limits.max_width, 2048);
+/// assert_eq!(limits.max_allocation, 64 << 20);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ParseLimits {
+	/// Maximum width of an image or frame in pixels
+	pub max_width: u32,
+	/// Maximum height of an image or frame in pixels
+	pub max_height: u32,
+	/// Maximum number of frames in a sprite, cursor or animation file
+	pub max_frames: usize,
+	/// Maximum number of archive entries, item records or patch operations
+	pub max_entries: usize,
+	/// Maximum number of bytes a single parse may allocate, buffered input
+	/// included
+	pub max_allocation: usize,
+}
+
+impl Default for ParseLimits {
+	fn default() -> Self {
+		Self {
+			max_width: 8192,
+			max_height: 8192,
+			max_frames: 65536,
+			max_entries: 65536,
+			max_allocation: 512 << 20,
+		}
+	}
+}
+
+impl ParseLimits {
+	/// Creates limits with lower caps, for files from unknown sources.
+	pub fn strict() -> Self {
+		Self {
+			max_width: 2048,
+			max_height: 2048,
+			max_frames: 4096,
+			max_entries: 4096,
+			max_allocation: 32 << 20,
+		}
+	}
+
+	/// Creates limits that never reject a file.
+	///
+	/// Only use this for trusted files: a malformed header can then make a
+	/// parser allocate gigabytes.
+	pub fn unlimited() -> Self {
+		Self {
+			max_width: u32::MAX,
+			max_height: u32::MAX,
+			max_frames: usize::MAX,
+			max_entries: usize::MAX,
+			max_allocation: usize::MAX,
+		}
+	}
+
+	/// Sets the maximum image or frame dimensions
+	pub fn with_max_dimensions(mut self, width: u32, height: u32) -> Self {
+		self.max_width = width;
+		self.max_height = height;
+		self
+	}
+
+	/// Sets the maximum number of frames
+	pub fn with_max_frames(mut self, max_frames: usize) -> Self {
+		self.max_frames = max_frames;
+		self
+	}
+
+	/// Sets the maximum number of entries
+	pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+		self.max_entries = max_entries;
+		self
+	}
+
+	/// Sets the maximum number of bytes a parse may allocate
+	pub fn with_max_allocation(mut self, max_allocation: usize) -> Self {
+		self.max_allocation = max_allocation;
+		self
+	}
+
+	/// Checks image or frame dimensions against the limits
+	pub(crate) fn check_dimensions(
+		&self,
+		file_type: FileType,
+		width: u32,
+		height: u32,
+	) -> Result<(), DvFileError> {
+		check(file_type, "width", width as u64, self.max_width as u64)?;
+		check(file_type, "height", height as u64, self.max_height as u64)
+	}
+
+	/// Checks a frame count against the limits
+	pub(crate) fn check_frames(
+		&self,
+		file_type: FileType,
+		count: usize,
+	) -> Result<(), DvFileError> {
+		check(file_type, "frame count", count as u64, self.max_frames as u64)
+	}
+
+	/// Checks an entry count against the limits
+	pub(crate) fn check_entries(
+		&self,
+		file_type: FileType,
+		count: usize,
+	) -> Result<(), DvFileError> {
+		check(file_type, "entry count", count as u64, self.max_entries as u64)
+	}
+
+	/// Returns the limits left after `used` bytes have been allocated
+	pub(crate) fn remaining_after(&self, used: usize) -> Self {
+		Self {
+			max_allocation: self.max_allocation.saturating_sub(used),
+			..*self
+		}
+	}
+
+	/// Starts tracking the allocations of one parse
+	pub(crate) fn allocation(&self, file_type: FileType) -> Allocation {
+		Allocation {
+			file_type,
+			used: 0,
+			max: self.max_allocation,
+		}
+	}
+}
+
+/// Returns a [`DvFileError::LimitExceeded`] if `value` is over `max`
+fn check(
+	file_type: FileType,
+	limit: &'static str,
+	value: u64,
+	max: u64,
+) -> Result<(), DvFileError> {
+	if value > max {
+		return Err(DvFileError::LimitExceeded {
+			file_type,
+			limit,
+			value,
+			max,
+		});
+	}
+	Ok(())
+}
+
+/// Running total of the bytes a parse has allocated
+#[derive(Debug)]
+pub(crate) struct Allocation {
+	file_type: FileType,
+	used: usize,
+	max: usize,
+}
+
+impl Allocation {
+	/// Accounts for `bytes` more, failing if the total goes over the limit
+	pub(crate) fn reserve(&mut self, bytes: usize) -> Result<(), DvFileError> {
+		let used = self.used.saturating_add(bytes);
+		check(self.file_type, "allocation", used as u64, self.max as u64)?;
+		self.used = used;
+		Ok(())
+	}
+
+	/// Accounts for `count` items of `size` bytes each
+	pub(crate) fn reserve_items(&mut self, count: usize, size: usize) -> Result<(), DvFileError> {
+		self.reserve(count.saturating_mul(size))
+	}
+
+	/// Reads a reader to the end without buffering more than the limit allows
+	pub(crate) fn read_to_end<R: Read + ?Sized>(
+		&mut self,
+		reader: &mut R,
+	) -> Result<Vec<u8>, DvFileError> {
+		let remaining = self.max - self.used;
+		let mut data = Vec::new();
+		reader.take((remaining as u64).saturating_add(1)).read_to_end(&mut data)?;
+		self.reserve(data.len())?;
+		Ok(data)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_checks_reject_values_over_the_limits() {
+		let limits = ParseLimits::strict();
+		assert!(limits.check_dimensions(FileType::Kg, 2048, 2048).is_ok());
+		assert!(limits.check_dimensions(FileType::Kg, 2049, 1).is_err());
+		assert!(limits.check_frames(FileType::Spr, 4097).is_err());
+
+		let error = limits.check_entries(FileType::Pft, 5000).unwrap_err();
+		assert_eq!(error.to_string(), "PFT error: entry count 5000 exceeds the limit of 4096");
+		assert!(error.is_limit_exceeded());
+
+		let unlimited = ParseLimits::unlimited();
+		assert!(unlimited.check_dimensions(FileType::Kg, u32::MAX, u32::MAX).is_ok());
+		assert!(unlimited.allocation(FileType::Kg).reserve_items(usize::MAX, 2).is_ok());
+	}
+
+	#[test]
+	fn test_allocation_tracks_running_total() {
+		let limits = ParseLimits::default().with_max_allocation(100);
+		let mut allocation = limits.allocation(FileType::Mfd);
+		allocation.reserve(60).unwrap();
+		allocation.reserve_items(10, 4).unwrap();
+		assert!(allocation.reserve(1).is_err());
+
+		// Reading stops one byte past the limit instead of buffering everything
+		let mut allocation = limits.allocation(FileType::Spr);
+		allocation.reserve(90).unwrap();
+		let mut reader = std::io::repeat(0xAA);
+		assert!(allocation.read_to_end(&mut reader).is_err());
+
+		let mut allocation = limits.allocation(FileType::Spr);
+		let data = allocation.read_to_end(&mut &[1u8, 2, 3][..]).unwrap();
+		assert_eq!(data, [1, 2, 3]);
+	}
+}
@@ -0,0 +1,413 @@
+//! Content hash manifests.
+//!
+//! A [`Manifest`] maps paths to the size and MD5 hash of their contents. It can
+//! be built from the entries of a DSK archive or from a directory of loose
+//! files, and serialized with any `serde` format (JSON in practice).
+//!
+//! Comparing the manifest of an installation against a known-good reference
+//! manifest with [`Manifest::verify`] tells which assets are missing, extra or
+//! modified, which is how damaged or mixed-version installs are diagnosed.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use dvine_types::file::{dsk, manifest::Manifest};
+//!
+//! let mut kg = dsk::File::open("game", "KG").unwrap();
+//! let mut actual = Manifest::from_dsk(&mut kg, "KG").unwrap();
+//! actual.extend(Manifest::from_dir("game/extras").unwrap());
+//!
+//! let json = std::fs::read_to_string("reference.json").unwrap();
+//! let reference: Manifest = serde_json::from_str(&json).unwrap();
+//!
+//! let report = reference.verify(&actual);
+//! for path in &report.modified {
+//!     println!("modified: {}", path);
+//! }
+//! ```
+
+use std::{
+	collections::BTreeMap,
+	fs,
+	io::{self, Read, Seek},
+	path::Path,
+};
+
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+
+use super::{DvFileError, dsk};
+
+/// Size and MD5 hash of a single file
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ManifestEntry {
+	/// Size in bytes
+	pub size: u64,
+
+	/// MD5 hash as lowercase hex string
+	pub md5: String,
+}
+
+impl ManifestEntry {
+	/// Hashes an in-memory buffer
+	pub fn from_bytes(data: &[u8]) -> Self {
+		Self {
+			size: data.len() as u64,
+			md5: to_hex(&Md5::digest(data)),
+		}
+	}
+
+	/// Hashes everything a reader yields
+	///
+	/// # Errors
+	///
+	/// Returns an error if reading fails
+	pub fn from_reader<R: Read>(reader: &mut R) -> Result<Self, DvFileError> {
+		let mut hasher = Md5::new();
+		let size = io::copy(reader, &mut hasher)?;
+
+		Ok(Self {
+			size,
+			md5: to_hex(&hasher.finalize()),
+		})
+	}
+}
+
+impl std::fmt::Display for ManifestEntry {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{} ({} bytes)", self.md5, self.size)
+	}
+}
+
+/// Manifest of per-path content hashes
+///
+/// Paths are stored upper-cased with `/` separators, so manifests taken on
+/// case-insensitive and case-sensitive file systems compare equal. Paths of
+/// deserialized manifests are normalized the same way.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "RawManifest")]
+pub struct Manifest {
+	/// Entries keyed by normalized path
+	entries: BTreeMap<String, ManifestEntry>,
+}
+
+/// Serialized form of a [`Manifest`], with paths as written
+#[derive(Deserialize)]
+struct RawManifest {
+	entries: BTreeMap<String, ManifestEntry>,
+}
+
+impl From<RawManifest> for Manifest {
+	fn from(raw: RawManifest) -> Self {
+		let mut manifest = Self::new();
+		for (path, entry) in raw.entries {
+			manifest.insert_entry(&path, entry);
+		}
+		manifest
+	}
+}
+
+impl Manifest {
+	/// Creates an empty manifest
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Builds a manifest of every valid entry in a DSK archive
+	///
+	/// Entries are streamed through [`dsk::File::open_entry`], so the archive
+	/// is never loaded into memory as a whole.
+	///
+	/// # Arguments
+	///
+	/// * `dsk` - Archive to hash
+	/// * `prefix` - Prefix for the entry paths, e.g. `KG` for `KG/TITLE`;
+	///   an empty prefix uses the bare entry names
+	///
+	/// # Errors
+	///
+	/// Returns an error if an entry cannot be read
+	pub fn from_dsk<R: Read + Seek>(
+		dsk: &mut dsk::File<R>,
+		prefix: &str,
+	) -> Result<Self, DvFileError> {
+		let mut manifest = Self::new();
+		let entries: Vec<_> = dsk.entries().copied().collect();
+
+		for entry in entries {
+			let hash = ManifestEntry::from_reader(&mut dsk.open_entry(&entry)?)?;
+			let path = if prefix.is_empty() {
+				entry.name()
+			} else {
+				format!("{}/{}", prefix, entry.name())
+			};
+			manifest.insert_entry(&path, hash);
+		}
+
+		Ok(manifest)
+	}
+
+	/// Builds a manifest of every file below a directory, recursively
+	///
+	/// Paths are relative to `dir`.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the directory or a file cannot be read
+	pub fn from_dir(dir: impl AsRef<Path>) -> Result<Self, DvFileError> {
+		let mut manifest = Self::new();
+		manifest.add_dir(dir.as_ref(), "")?;
+		Ok(manifest)
+	}
+
+	/// Hashes `data` and records it under `path`
+	pub fn insert(&mut self, path: &str, data: &[u8]) {
+		self.insert_entry(path, ManifestEntry::from_bytes(data));
+	}
+
+	/// Records an already computed entry under `path`
+	pub fn insert_entry(&mut self, path: &str, entry: ManifestEntry) {
+		self.entries.insert(normalize(path), entry);
+	}
+
+	/// Returns the entry recorded for `path`
+	pub fn get(&self, path: &str) -> Option<&ManifestEntry> {
+		self.entries.get(&normalize(path))
+	}
+
+	/// Adds all entries of another manifest, replacing existing ones
+	pub fn extend(&mut self, other: Manifest) {
+		self.entries.extend(other.entries);
+	}
+
+	/// Returns the number of entries
+	pub fn len(&self) -> usize {
+		self.entries.len()
+	}
+
+	/// Returns true if the manifest has no entries
+	pub fn is_empty(&self) -> bool {
+		self.entries.is_empty()
+	}
+
+	/// Returns an iterator over paths and entries, sorted by path
+	pub fn iter(&self) -> impl Iterator<Item = (&str, &ManifestEntry)> {
+		self.entries.iter().map(|(path, entry)| (path.as_str(), entry))
+	}
+
+	/// Compares an installation against this (reference) manifest
+	///
+	/// # Arguments
+	///
+	/// * `actual` - Manifest of the installation being checked
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use dvine_types::file::manifest::Manifest;
+	///
+	/// let mut reference = Manifest::new();
+	/// reference.insert("KG/TITLE", b"title");
+	/// reference.insert("KG/BLACK", b"black");
+	///
+	/// let mut actual = Manifest::new();
+	/// actual.insert("kg/title", b"patched");
+	/// actual.insert("KG/EXTRA", b"extra");
+	///
+	/// let report = reference.verify(&actual);
+	/// assert_eq!(report.missing, vec!["KG/BLACK"]);
+	/// assert_eq!(report.extra, vec!["KG/EXTRA"]);
+	/// assert_eq!(report.modified, vec!["KG/TITLE"]);
+	/// ```
+	pub fn verify(&self, actual: &Manifest) -> VerifyReport {
+		let mut report = VerifyReport::default();
+
+		for (path, expected) in &self.entries {
+			match actual.entries.get(path) {
+				None => report.missing.push(path.clone()),
+				Some(found) if found != expected => report.modified.push(path.clone()),
+				Some(_) => report.matched += 1,
+			}
+		}
+
+		report.extra = actual
+			.entries
+			.keys()
+			.filter(|path| !self.entries.contains_key(*path))
+			.cloned()
+			.collect();
+
+		report
+	}
+
+	/// Recursively hashes the files below `dir`
+	fn add_dir(&mut self, dir: &Path, relative: &str) -> Result<(), DvFileError> {
+		for entry in fs::read_dir(dir)? {
+			let entry = entry?;
+			let name = entry.file_name().to_string_lossy().into_owned();
+			let path = if relative.is_empty() {
+				name
+			} else {
+				format!("{}/{}", relative, name)
+			};
+
+			let file_type = entry.file_type()?;
+			if file_type.is_dir() {
+				self.add_dir(&entry.path(), &path)?;
+			} else if file_type.is_file() {
+				let mut file = fs::File::open(entry.path())?;
+				self.insert_entry(&path, ManifestEntry::from_reader(&mut file)?);
+			}
+		}
+
+		Ok(())
+	}
+}
+
+impl std::fmt::Display for Manifest {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		for (path, entry) in &self.entries {
+			writeln!(f, "{}  {:>10}  {}", entry.md5, entry.size, path)?;
+		}
+		Ok(())
+	}
+}
+
+/// Result of comparing an installation against a reference manifest
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifyReport {
+	/// Paths in the reference that the installation lacks
+	pub missing: Vec<String>,
+
+	/// Paths in the installation that the reference lacks
+	pub extra: Vec<String>,
+
+	/// Paths whose size or hash differs from the reference
+	pub modified: Vec<String>,
+
+	/// Number of paths that match the reference
+	pub matched: usize,
+}
+
+impl VerifyReport {
+	/// Returns true if the installation matches the reference exactly
+	pub fn is_ok(&self) -> bool {
+		self.missing.is_empty() && self.extra.is_empty() && self.modified.is_empty()
+	}
+}
+
+impl std::fmt::Display for VerifyReport {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		writeln!(
+			f,
+			"{} matched, {} missing, {} extra, {} modified",
+			self.matched,
+			self.missing.len(),
+			self.extra.len(),
+			self.modified.len()
+		)?;
+		for path in &self.missing {
+			writeln!(f, "  missing:  {}", path)?;
+		}
+		for path in &self.extra {
+			writeln!(f, "  extra:    {}", path)?;
+		}
+		for path in &self.modified {
+			writeln!(f, "  modified: {}", path)?;
+		}
+		Ok(())
+	}
+}
+
+impl<R: Read + Seek> dsk::File<R> {
+	/// Builds a manifest of every valid entry, keyed by bare entry name
+	///
+	/// See [`Manifest::from_dsk`] to key the entries by a prefix.
+	///
+	/// # Errors
+	///
+	/// Returns an error if an entry cannot be read
+	pub fn manifest(&mut self) -> Result<Manifest, DvFileError> {
+		Manifest::from_dsk(self, "")
+	}
+}
+
+/// Normalizes a manifest path: `/` separators, upper-case
+fn normalize(path: &str) -> String {
+	path.split(['/', '\\'])
+		.filter(|c| !c.is_empty())
+		.map(str::to_ascii_uppercase)
+		.collect::<Vec<_>>()
+		.join("/")
+}
+
+/// Formats a digest as lowercase hex
+fn to_hex(bytes: &[u8]) -> String {
+	bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::file::dsk::ArchiveBuilder;
+
+	#[test]
+	fn test_known_hash() {
+		let entry = ManifestEntry::from_bytes(b"");
+		assert_eq!(entry.md5, "d41d8cd98f00b204e9800998ecf8427e");
+
+		let entry = ManifestEntry::from_reader(&mut &b"abc"[..]).unwrap();
+		assert_eq!(entry.md5, "900150983cd24fb0d6963f7d28e17f72");
+		assert_eq!(entry.size, 3);
+	}
+
+	#[test]
+	fn test_from_dsk_matches_extracted_data() {
+		let mut builder = ArchiveBuilder::new();
+		builder.add_entry("TITLE", b"title".to_vec()).unwrap();
+		builder.add_entry("BIG", vec![3u8; 5000]).unwrap();
+		let (pft, data) = builder.to_bytes().unwrap();
+		let mut dsk = dsk::File::from_bytes(data, pft).unwrap();
+
+		let manifest = Manifest::from_dsk(&mut dsk, "KG").unwrap();
+		assert_eq!(manifest.len(), 2);
+		assert_eq!(manifest.get("kg/big"), Some(&ManifestEntry::from_bytes(&[3u8; 5000])));
+		assert_eq!(dsk.manifest().unwrap().get("TITLE"), manifest.get("KG/TITLE"));
+	}
+
+	#[test]
+	fn test_json_round_trip_and_verify() {
+		let mut reference = Manifest::new();
+		reference.insert("SPR.PAL", &[1, 2, 3]);
+		reference.insert("KG/TITLE", b"title");
+
+		let json = serde_json::to_string(&reference).unwrap();
+		let loaded: Manifest = serde_json::from_str(&json).unwrap();
+		assert_eq!(loaded, reference);
+		assert!(reference.verify(&loaded).is_ok());
+
+		let mut actual = loaded.clone();
+		actual.insert("SPR.PAL", &[1, 2, 3, 4]);
+		let report = reference.verify(&actual);
+		assert!(!report.is_ok());
+		assert_eq!(report.modified, vec!["SPR.PAL"]);
+		assert_eq!(report.matched, 1);
+	}
+
+	#[test]
+	fn test_deserialize_normalizes_paths() {
+		let entry = ManifestEntry::from_bytes(b"title");
+		let json = format!(
+			r#"{{"entries":{{"kg/title":{{"size":{},"md5":"{}"}}}}}}"#,
+			entry.size, entry.md5
+		);
+		let reference: Manifest = serde_json::from_str(&json).unwrap();
+		assert_eq!(reference.iter().next().map(|(path, _)| path), Some("KG/TITLE"));
+
+		let mut actual = Manifest::new();
+		actual.insert("KG/TITLE", b"title");
+		let report = reference.verify(&actual);
+		assert!(report.is_ok(), "{:?}", report);
+		assert_eq!(report.matched, 1);
+	}
+}
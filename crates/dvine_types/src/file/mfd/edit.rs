@@ -0,0 +1,222 @@
+//! Editing operations on MFD cursor frames.
+//!
+//! MFD offsets place a frame's top-left corner relative to the cursor
+//! position, so the click point is the pixel at `(-x_offset, -y_offset)`
+//! inside the frame. Every operation moves the offsets with the pixels,
+//! keeping the click point on the same pixel:
+//!
+//! - Flips mirror the frame around the click point
+//! - Crops and pads shift the offsets by the removed or added margins
+//! - Resizing scales the click point with the frame
+//!
+//! # Examples
+//!
+//! ```
+//! use dvine_types::file::mfd::Frame;
+//!
+//! // 4x2 cursor with the click point at its top-left pixel
+//! let frame = Frame::new(4, 2, 0, 0, vec![1, 2, 2, 0, 1, 1, 0, 0]);
+//!
+//! // Left-handed copy clicking at the top-right corner instead
+//! let mirrored = frame.flipped_horizontal();
+//! assert_eq!(mirrored.pixels(), &[0, 2, 2, 1, 0, 0, 1, 1]);
+//! assert_eq!(mirrored.x_offset(), -3);
+//! ```
+
+use crate::file::raster;
+
+use super::Frame;
+
+/// Pixel value of transparent pixels
+const TRANSPARENT: u8 = 0;
+
+/// Mirrors an offset so its click pixel maps to `size - 1 - click`, saturating
+/// at the `i16` range
+fn mirrored(offset: i16, size: u16) -> i16 {
+	let click = -i64::from(offset);
+	let mirrored = -(i64::from(size) - 1 - click);
+	mirrored.clamp(i16::MIN.into(), i16::MAX.into()) as i16
+}
+
+impl Frame {
+	/// Returns a copy mirrored left to right around the click point
+	///
+	/// The click point moves from column `c` to `width - 1 - c`. Offsets
+	/// saturate at the `i16` range.
+	pub fn flipped_horizontal(&self) -> Self {
+		Self::new(
+			self.width(),
+			self.height(),
+			mirrored(self.x_offset(), self.width()),
+			self.y_offset(),
+			raster::flip_horizontal(self.pixels(), self.width() as usize),
+		)
+	}
+
+	/// Returns a copy mirrored top to bottom around the click point
+	///
+	/// The click point moves from row `r` to `height - 1 - r`. Offsets
+	/// saturate at the `i16` range.
+	pub fn flipped_vertical(&self) -> Self {
+		Self::new(
+			self.width(),
+			self.height(),
+			self.x_offset(),
+			mirrored(self.y_offset(), self.height()),
+			raster::flip_vertical(self.pixels(), self.width() as usize),
+		)
+	}
+
+	/// Returns the `width` x `height` region at (`x`, `y`)
+	///
+	/// # Returns
+	///
+	/// `None` if the region exceeds the frame or the offsets overflow.
+	pub fn cropped(&self, x: u16, y: u16, width: u16, height: u16) -> Option<Self> {
+		if x.checked_add(width)? > self.width() || y.checked_add(height)? > self.height() {
+			return None;
+		}
+
+		let x_offset = i16::try_from(i64::from(self.x_offset()) + i64::from(x)).ok()?;
+		let y_offset = i16::try_from(i64::from(self.y_offset()) + i64::from(y)).ok()?;
+		let pixels = raster::crop(
+			self.pixels(),
+			self.width() as usize,
+			(x as usize, y as usize),
+			(width as usize, height as usize),
+		);
+		Some(Self::new(width, height, x_offset, y_offset, pixels))
+	}
+
+	/// Returns a copy with transparent margins added on each side
+	///
+	/// # Returns
+	///
+	/// `None` if the padded size or the offsets overflow.
+	pub fn padded(&self, left: u16, top: u16, right: u16, bottom: u16) -> Option<Self> {
+		let width = self.width().checked_add(left)?.checked_add(right)?;
+		let height = self.height().checked_add(top)?.checked_add(bottom)?;
+		let x_offset = i16::try_from(i64::from(self.x_offset()) - i64::from(left)).ok()?;
+		let y_offset = i16::try_from(i64::from(self.y_offset()) - i64::from(top)).ok()?;
+
+		let pixels = raster::pad(
+			self.pixels(),
+			(self.width() as usize, self.height() as usize),
+			(left as usize, top as usize, right as usize, bottom as usize),
+			TRANSPARENT,
+		);
+		Some(Self::new(width, height, x_offset, y_offset, pixels))
+	}
+
+	/// Returns a copy scaled to `width` x `height` with nearest neighbor sampling
+	///
+	/// The click point is scaled by the same factors, rounded to the nearest
+	/// pixel.
+	///
+	/// # Returns
+	///
+	/// `None` if this frame or the requested size is empty, or the offsets
+	/// overflow.
+	pub fn resized(&self, width: u16, height: u16) -> Option<Self> {
+		if self.pixel_count() == 0 || width == 0 || height == 0 {
+			return None;
+		}
+
+		let size = (self.width() as usize, self.height() as usize);
+		let new_size = (width as usize, height as usize);
+		let click_x = raster::scale_coordinate(-i64::from(self.x_offset()), size.0, new_size.0);
+		let click_y = raster::scale_coordinate(-i64::from(self.y_offset()), size.1, new_size.1);
+		Some(Self::new(
+			width,
+			height,
+			i16::try_from(-click_x).ok()?,
+			i16::try_from(-click_y).ok()?,
+			raster::resize_nearest(self.pixels(), size, new_size),
+		))
+	}
+
+	/// Draws the non-transparent pixels of `source` with its top-left corner
+	/// at (`x`, `y`) of this frame
+	///
+	/// Pixels outside this frame are clipped, and the offsets are unchanged.
+	/// To line up both click points, draw at
+	/// `x = i64::from(source.x_offset()) - i64::from(self.x_offset())`, and
+	/// likewise for `y`.
+	pub fn blit(&mut self, source: &Frame, x: i64, y: i64) {
+		let target_size = (self.width() as usize, self.height() as usize);
+		let source_size = (source.width() as usize, source.height() as usize);
+		let pixels = self.pixels_mut();
+		raster::blit(target_size, source_size, (x, y), |target, index| {
+			let pixel = source.pixels()[index];
+			if pixel != TRANSPARENT {
+				pixels[target] = pixel;
+			}
+		});
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// 3x2 cursor clicking at its center-right pixel
+	fn frame() -> Frame {
+		Frame::new(3, 2, -2, -1, vec![1, 2, 0, 1, 2, 2])
+	}
+
+	/// Click point position in frame pixels
+	fn click_point(frame: &Frame) -> (i16, i16) {
+		(-frame.x_offset(), -frame.y_offset())
+	}
+
+	#[test]
+	fn test_flips_mirror_around_click_point() {
+		let frame = frame();
+
+		let flipped = frame.flipped_horizontal();
+		assert_eq!(flipped.pixels(), &[0, 2, 1, 2, 2, 1]);
+		assert_eq!(click_point(&flipped), (0, 1));
+		assert_eq!(flipped.flipped_horizontal(), frame);
+
+		let flipped = frame.flipped_vertical();
+		assert_eq!(flipped.pixels(), &[1, 2, 2, 1, 2, 0]);
+		assert_eq!(click_point(&flipped), (2, 0));
+		assert_eq!(flipped.flipped_vertical(), frame);
+
+		let wide = Frame::blank(u16::MAX, 1, i16::MAX, 0);
+		assert_eq!(wide.flipped_horizontal().x_offset(), i16::MIN);
+	}
+
+	#[test]
+	fn test_crop_pad_and_resize_keep_click_point() {
+		let frame = frame();
+
+		let cropped = frame.cropped(1, 1, 2, 1).unwrap();
+		assert_eq!(cropped.pixels(), &[2, 2]);
+		assert_eq!(click_point(&cropped), (1, 0));
+		assert!(frame.cropped(2, 0, 2, 1).is_none());
+		assert!(Frame::blank(2, 2, i16::MAX, 0).cropped(1, 0, 1, 1).is_none());
+
+		let padded = frame.padded(2, 1, 1, 0).unwrap();
+		assert_eq!((padded.width(), padded.height()), (6, 3));
+		assert_eq!(click_point(&padded), (4, 2));
+		assert_eq!(padded.cropped(2, 1, 3, 2).unwrap(), frame);
+		assert!(frame.padded(u16::MAX, 0, 0, 0).is_none());
+
+		let doubled = frame.resized(6, 4).unwrap();
+		assert_eq!(doubled.pixels()[..6], [1, 1, 2, 2, 0, 0]);
+		assert_eq!(click_point(&doubled), (4, 2));
+		assert_eq!(doubled.resized(3, 2).unwrap(), frame);
+		assert!(frame.resized(3, 0).is_none());
+	}
+
+	#[test]
+	fn test_blit_skips_transparent_pixels() {
+		let mut target = Frame::blank(3, 3, 0, 0);
+		target.fill(1);
+		target.blit(&frame(), 1, 2);
+		target.blit(&frame(), 5, 0);
+		assert_eq!(target.pixels(), &[1, 1, 1, 1, 1, 1, 1, 1, 2]);
+		assert_eq!((target.x_offset(), target.y_offset()), (0, 0));
+	}
+}
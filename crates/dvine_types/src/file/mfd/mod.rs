@@ -224,9 +224,10 @@
 //! # }
 //! ```
 
-use crate::file::{DvFileError, FileType};
+use crate::file::{DvFileError, FileType, ParseLimits};
 use serde::{Deserialize, Serialize};
 
+mod edit;
 pub mod frame;
 
 pub use frame::{DEFAULT_RGBA_PALETTE, Frame, FrameRowIterator};
@@ -754,6 +755,19 @@ impl File {
 	/// - The glyph table offset is beyond the file size
 	/// - Frame entries are invalid
 	pub fn from_bytes(data: &[u8]) -> Result<Self, DvFileError> {
+		Self::from_bytes_with_limits(data, &ParseLimits::default())
+	}
+
+	/// Loads an MFD file from a byte slice, rejecting files over `limits`.
+	///
+	/// Frames may share bitmap data in the file, so the allocation limit
+	/// applies to the sum of all frame sizes rather than the file size.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the file structure is invalid or the frame count,
+	/// a frame size or the total frame data exceeds the limits.
+	pub fn from_bytes_with_limits(data: &[u8], limits: &ParseLimits) -> Result<Self, DvFileError> {
 		// Validate minimum file size (header)
 		if data.len() < constants::HEADER_SIZE {
 			return Err(DvFileError::insufficient_data(
@@ -802,6 +816,8 @@ impl File {
 		}
 
 		// Parse glyph table and extract frames
+		limits.check_frames(FileType::Mfd, frame_count as usize)?;
+		let mut allocation = limits.allocation(FileType::Mfd);
 		let mut frames = Vec::with_capacity(frame_count as usize);
 		for i in 0..frame_count as usize {
 			let glyph_offset = glyph_table_offset + i * constants::GLYPH_ENTRY_SIZE;
@@ -828,8 +844,10 @@ impl File {
 			]);
 
 			// Calculate absolute bitmap offset and extract pixels
+			limits.check_dimensions(FileType::Mfd, width.into(), height.into())?;
 			let bitmap_start = constants::BITMAP_DATA_START + bitmap_offset as usize;
 			let pixel_count = width as usize * height as usize;
+			allocation.reserve(pixel_count)?;
 			let bitmap_end = bitmap_start + pixel_count;
 
 			// Validate bitmap range
@@ -933,9 +951,21 @@ impl File {
 	/// - Not enough data can be read
 	/// - The file structure is invalid
 	pub fn from_reader<R: std::io::Read>(reader: &mut R) -> Result<Self, DvFileError> {
-		let mut data = Vec::new();
-		reader.read_to_end(&mut data)?;
-		Self::from_bytes(&data)
+		Self::from_reader_with_limits(reader, &ParseLimits::default())
+	}
+
+	/// Loads an MFD file from any reader, rejecting files over `limits`.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the file structure is invalid or exceeds the
+	/// limits.
+	pub fn from_reader_with_limits<R: std::io::Read>(
+		reader: &mut R,
+		limits: &ParseLimits,
+	) -> Result<Self, DvFileError> {
+		let data = limits.allocation(FileType::Mfd).read_to_end(reader)?;
+		Self::from_bytes_with_limits(&data, &limits.remaining_after(data.len()))
 	}
 }
 
@@ -1,30 +1,41 @@
 //! File type support for `dvine-rs` project.
 
+mod detect;
 mod error;
+mod limits;
+mod raster;
 
 pub mod anm;
 pub mod dsk;
 pub mod efc;
 pub mod fnt;
+#[cfg(feature = "image")]
+pub mod image;
 pub mod item;
 pub mod kg;
+pub mod manifest;
 pub mod mfd;
 pub mod pft;
 pub mod spr;
 pub mod startup_ini;
 
+#[cfg(test)]
+mod fuzz;
+
 /// Block size used in DSK files (2048 bytes / 0x0800)
 pub const DSK_BLOCK_SIZE: usize = 0x0800;
 
 // Re-export unified error type
+pub use detect::DETECT_PREFIX_LEN;
 pub use error::{DvFileError, FileType};
+pub use limits::ParseLimits;
 
 // Re-export main file types
 pub use anm::{
 	AnimationSequence as AnmAnimationSequence, File as AnmFile,
 	FrameDescriptor as AnmFrameDescriptor,
 };
-pub use dsk::File as DskFile;
+pub use dsk::{ArchiveBuilder as DskArchiveBuilder, File as DskFile};
 pub use efc::{
 	AdpcmDataHeader, DecodedSound, EffectInfo, File as EfcFile, FileBuilder as EfcFileBuilder,
 	SoundDataHeader,
@@ -41,9 +52,11 @@ pub use mfd::{
 };
 pub use pft::{Entry, File as PftFile, Header as PftHeader};
 pub use spr::{
-	ColorRowIterator as SprColorRowIterator, File as SprFile, Frame as SprFrame,
-	FrameEntry as SprFrameEntry, FrameIterator as SprFrameIterator,
-	FrameRowIterator as SprFrameRowIterator, Palette as SprPalette,
+	ColorRowIterator as SprColorRowIterator, CompositePalette as SprCompositePalette,
+	File as SprFile, Frame as SprFrame, FrameEntry as SprFrameEntry,
+	FrameImporter as SprFrameImporter, FrameIterator as SprFrameIterator,
+	FrameRowIterator as SprFrameRowIterator, OptimizeStats as SprOptimizeStats,
+	Optimizer as SprOptimizer, Palette as SprPalette,
 };
 pub use startup_ini::{
 	OpeningMode as StartupOpeningMode, RenderMode as StartupRenderMode, StartupIni,
@@ -8,7 +8,7 @@ use std::{
 	io::{self, Read},
 };
 
-use super::{DSK_BLOCK_SIZE, DvFileError, FileType};
+use super::{DSK_BLOCK_SIZE, DvFileError, FileType, ParseLimits};
 
 mod constants {
 	/// Magic number for PFT files
@@ -407,18 +407,44 @@ impl File {
 		self.entries.iter().find(|e| e.name().eq_ignore_ascii_case(name))
 	}
 
+	/// Gets a mutable entry by index
+	pub fn get_entry_mut(&mut self, index: usize) -> Option<&mut Entry> {
+		self.entries.get_mut(index)
+	}
+
+	/// Finds the index of an entry by name
+	pub fn find_entry_index(&self, name: &str) -> Option<usize> {
+		self.entries.iter().position(|e| e.name().eq_ignore_ascii_case(name))
+	}
+
 	/// Adds an entry to the file
 	pub fn add_entry(&mut self, entry: Entry) {
 		self.entries.push(entry);
 		self.header.num_entries = self.entries.len() as u32;
 	}
 
+	/// Removes the entry at the given index
+	pub fn remove_entry(&mut self, index: usize) -> Option<Entry> {
+		if index >= self.entries.len() {
+			return None;
+		}
+		let entry = self.entries.remove(index);
+		self.header.num_entries = self.entries.len() as u32;
+		Some(entry)
+	}
+
 	/// Loads file from a byte slice
 	pub fn from_bytes(data: &[u8]) -> Result<Self, DvFileError> {
+		Self::from_bytes_with_limits(data, &ParseLimits::default())
+	}
+
+	/// Loads file from a byte slice, rejecting entry counts over `limits`
+	pub fn from_bytes_with_limits(data: &[u8], limits: &ParseLimits) -> Result<Self, DvFileError> {
 		// Parse header
 		let header = Header::from_bytes(data)?;
 
 		let expected_entries = header.num_entries as usize;
+		limits.check_entries(FileType::Pft, expected_entries)?;
 		let header_size = Header::size();
 		let required_size = header_size + expected_entries * Entry::size();
 
@@ -444,8 +470,17 @@ impl File {
 
 	/// Loads file from any reader
 	pub fn from_reader<R: Read>(reader: &mut R) -> Result<Self, DvFileError> {
+		Self::from_reader_with_limits(reader, &ParseLimits::default())
+	}
+
+	/// Loads file from any reader, rejecting entry counts over `limits`
+	pub fn from_reader_with_limits<R: Read>(
+		reader: &mut R,
+		limits: &ParseLimits,
+	) -> Result<Self, DvFileError> {
 		// Read header
 		let header = Header::from_reader(reader)?;
+		limits.check_entries(FileType::Pft, header.num_entries as usize)?;
 
 		// Read entries
 		let mut entries = Vec::with_capacity(header.num_entries as usize);
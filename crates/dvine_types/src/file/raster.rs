@@ -0,0 +1,103 @@
+//! Pixel plane operations shared by the frame editing methods.
+//!
+//! A plane is one byte per pixel in row-major order. The functions here only
+//! move bytes around; callers check bounds and keep hotspots in sync.
+
+/// Mirrors every row left to right
+pub(crate) fn flip_horizontal(pixels: &[u8], width: usize) -> Vec<u8> {
+	if width == 0 {
+		return pixels.to_vec();
+	}
+	pixels.chunks_exact(width).flat_map(|row| row.iter().rev()).copied().collect()
+}
+
+/// Mirrors the row order top to bottom
+pub(crate) fn flip_vertical(pixels: &[u8], width: usize) -> Vec<u8> {
+	if width == 0 {
+		return pixels.to_vec();
+	}
+	pixels.chunks_exact(width).rev().flatten().copied().collect()
+}
+
+/// Copies the `crop_width` x `crop_height` region at (`x`, `y`)
+pub(crate) fn crop(
+	pixels: &[u8],
+	width: usize,
+	(x, y): (usize, usize),
+	(crop_width, crop_height): (usize, usize),
+) -> Vec<u8> {
+	if width == 0 {
+		return Vec::new();
+	}
+	pixels
+		.chunks_exact(width)
+		.skip(y)
+		.take(crop_height)
+		.flat_map(|row| &row[x..x + crop_width])
+		.copied()
+		.collect()
+}
+
+/// Surrounds a plane with `fill` pixels, margins given as (left, top, right, bottom)
+pub(crate) fn pad(
+	pixels: &[u8],
+	(width, height): (usize, usize),
+	(left, top, right, bottom): (usize, usize, usize, usize),
+	fill: u8,
+) -> Vec<u8> {
+	let padded_width = left + width + right;
+	let mut padded = vec![fill; padded_width * (top + height + bottom)];
+	if width > 0 {
+		for (row, source) in pixels.chunks_exact(width).enumerate() {
+			let start = (top + row) * padded_width + left;
+			padded[start..start + width].copy_from_slice(source);
+		}
+	}
+	padded
+}
+
+/// Scales a plane with nearest neighbor sampling at pixel centers
+pub(crate) fn resize_nearest(
+	pixels: &[u8],
+	(width, height): (usize, usize),
+	(new_width, new_height): (usize, usize),
+) -> Vec<u8> {
+	let columns: Vec<usize> =
+		(0..new_width).map(|x| (2 * x + 1) * width / (2 * new_width)).collect();
+	(0..new_height)
+		.flat_map(|y| {
+			let row = (2 * y + 1) * height / (2 * new_height) * width;
+			columns.iter().map(move |&x| pixels[row + x])
+		})
+		.collect()
+}
+
+/// Scales a coordinate by `to / from`, rounding half up
+pub(crate) fn scale_coordinate(value: i64, from: usize, to: usize) -> i64 {
+	let (from, to) = (from as i64, to as i64);
+	(2 * value * to + from).div_euclid(2 * from)
+}
+
+/// Calls `put(target_index, source_index)` for every source pixel that lands
+/// inside the target when the source's top-left corner is placed at (`x`, `y`)
+pub(crate) fn blit(
+	(target_width, target_height): (usize, usize),
+	(source_width, source_height): (usize, usize),
+	(x, y): (i64, i64),
+	mut put: impl FnMut(usize, usize),
+) {
+	let clip = |offset: i64, source: usize, target: usize| {
+		let start = (-offset).clamp(0, source as i64) as usize;
+		let end = (target as i64 - offset).clamp(0, source as i64) as usize;
+		start..end.max(start)
+	};
+
+	let columns = clip(x, source_width, target_width);
+	for row in clip(y, source_height, target_height) {
+		let target_row = (y + row as i64) as usize * target_width;
+		for column in columns.clone() {
+			let target_index = target_row + (x + column as i64) as usize;
+			put(target_index, row * source_width + column);
+		}
+	}
+}
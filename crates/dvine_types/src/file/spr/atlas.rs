@@ -0,0 +1,587 @@
+//! Texture atlas packing for SPR sprite sheets.
+//!
+//! [`AtlasBuilder`] places the frames of one or more SPR files onto RGBA
+//! pages whose sides are powers of two, as GPUs prefer. Frames are rendered
+//! with [`Frame::apply_palette_with_mask`] and packed with the `MaxRects`
+//! algorithm (best short side fit), largest frames first.
+//!
+//! Next to the pages, the builder produces an [`AtlasDescriptor`] with the
+//! page size and rectangle of every frame, its original hotspot, and the
+//! source file and frame index it came from. The descriptor implements
+//! `serde` traits, so it can be written as JSON, RON or any other format.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use dvine_types::file::spr::{File, Palette, atlas::AtlasBuilder};
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let palette = Palette::from_file("SPR.PAL")?;
+//! let katia = File::open("KATIA.SPR")?;
+//! let ag = File::open("AG.SPR")?;
+//!
+//! let atlas = AtlasBuilder::new()
+//!     .with_max_size(1024)
+//!     .with_page_prefix("chars_")
+//!     .add_file("KATIA.SPR", &katia)
+//!     .add_file("AG.SPR", &ag)
+//!     .build(&palette)?;
+//!
+//! for (page, info) in atlas.pages.iter().zip(&atlas.descriptor.pages) {
+//!     println!("{}: {}x{}, {} bytes", info.image, page.width, page.height, page.rgba.len());
+//! }
+//! std::fs::write("chars.json", serde_json::to_string_pretty(&atlas.descriptor)?)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+use crate::file::{DvFileError, FileType};
+
+use super::{File, Frame, Palette};
+
+/// Rectangle in page pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rect {
+	x: u32,
+	y: u32,
+	width: u32,
+	height: u32,
+}
+
+impl Rect {
+	fn right(&self) -> u32 {
+		self.x + self.width
+	}
+
+	fn bottom(&self) -> u32 {
+		self.y + self.height
+	}
+
+	fn intersects(&self, other: &Rect) -> bool {
+		self.x < other.right()
+			&& other.x < self.right()
+			&& self.y < other.bottom()
+			&& other.y < self.bottom()
+	}
+
+	fn contains(&self, other: &Rect) -> bool {
+		self.x <= other.x
+			&& self.y <= other.y
+			&& other.right() <= self.right()
+			&& other.bottom() <= self.bottom()
+	}
+}
+
+/// `MaxRects` bin tracking the maximal free rectangles of one page
+#[derive(Debug)]
+struct MaxRects {
+	free: Vec<Rect>,
+}
+
+impl MaxRects {
+	fn new(width: u32, height: u32) -> Self {
+		Self {
+			free: vec![Rect {
+				x: 0,
+				y: 0,
+				width,
+				height,
+			}],
+		}
+	}
+
+	/// Places a rectangle at the free spot leaving the shortest side over
+	fn insert(&mut self, width: u32, height: u32) -> Option<Rect> {
+		let placed = self
+			.free
+			.iter()
+			.filter(|free| width <= free.width && height <= free.height)
+			.min_by_key(|free| {
+				let (dx, dy) = (free.width - width, free.height - height);
+				(dx.min(dy), dx.max(dy), free.y, free.x)
+			})
+			.map(|free| Rect {
+				x: free.x,
+				y: free.y,
+				width,
+				height,
+			})?;
+
+		// Split every free rectangle the placement overlaps
+		let mut free = Vec::with_capacity(self.free.len() + 4);
+		for rect in &self.free {
+			if !rect.intersects(&placed) {
+				free.push(*rect);
+				continue;
+			}
+			if placed.x > rect.x {
+				free.push(Rect {
+					width: placed.x - rect.x,
+					..*rect
+				});
+			}
+			if placed.right() < rect.right() {
+				free.push(Rect {
+					x: placed.right(),
+					width: rect.right() - placed.right(),
+					..*rect
+				});
+			}
+			if placed.y > rect.y {
+				free.push(Rect {
+					height: placed.y - rect.y,
+					..*rect
+				});
+			}
+			if placed.bottom() < rect.bottom() {
+				free.push(Rect {
+					y: placed.bottom(),
+					height: rect.bottom() - placed.bottom(),
+					..*rect
+				});
+			}
+		}
+
+		// Drop free rectangles contained in another one
+		let mut index = 0;
+		while index < free.len() {
+			let rect = free[index];
+			let redundant = free.iter().enumerate().any(|(other, outer)| {
+				other != index && outer.contains(&rect) && (outer != &rect || other < index)
+			});
+			if redundant {
+				free.swap_remove(index);
+			} else {
+				index += 1;
+			}
+		}
+		self.free = free;
+
+		Some(placed)
+	}
+}
+
+/// An RGBA atlas page
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AtlasPage {
+	/// Page width in pixels, a power of two
+	pub width: u32,
+
+	/// Page height in pixels, a power of two
+	pub height: u32,
+
+	/// RGBA pixels in row-major order, transparent where no frame is placed
+	pub rgba: Vec<u8>,
+}
+
+/// Size and image name of an atlas page
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct AtlasPageInfo {
+	/// Image file name of the page, e.g. `atlas_0.png`
+	pub image: String,
+
+	/// Page width in pixels
+	pub width: u32,
+
+	/// Page height in pixels
+	pub height: u32,
+}
+
+/// Placement of one SPR frame in the atlas
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct AtlasFrame {
+	/// Name of the source SPR file, as given to [`AtlasBuilder::add_file`]
+	pub source: String,
+
+	/// Frame index in the source file
+	pub frame: usize,
+
+	/// Index of the page holding the frame
+	pub page: usize,
+
+	/// Left edge on the page
+	pub x: u32,
+
+	/// Top edge on the page
+	pub y: u32,
+
+	/// Frame width in pixels
+	pub width: u32,
+
+	/// Frame height in pixels
+	pub height: u32,
+
+	/// Original hotspot X coordinate, relative to the frame
+	pub hotspot_x: u32,
+
+	/// Original hotspot Y coordinate, relative to the frame
+	pub hotspot_y: u32,
+}
+
+/// Layout of a packed atlas
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct AtlasDescriptor {
+	/// Atlas pages in order
+	pub pages: Vec<AtlasPageInfo>,
+
+	/// Frames in the order the files and frames were added
+	pub frames: Vec<AtlasFrame>,
+}
+
+impl AtlasDescriptor {
+	/// Finds the placement of a frame of a source file
+	pub fn find(&self, source: &str, frame: usize) -> Option<&AtlasFrame> {
+		self.frames.iter().find(|entry| entry.source == source && entry.frame == frame)
+	}
+}
+
+/// Packed atlas pages and their descriptor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Atlas {
+	/// Rendered pages, in the order of [`AtlasDescriptor::pages`]
+	pub pages: Vec<AtlasPage>,
+
+	/// Page sizes and frame placements
+	pub descriptor: AtlasDescriptor,
+}
+
+/// Builder that packs SPR frames into atlas pages
+#[derive(Debug, Clone)]
+pub struct AtlasBuilder<'a> {
+	/// Maximum page width and height, a power of two
+	max_size: u32,
+
+	/// Transparent pixels between frames
+	padding: u32,
+
+	/// Prefix of the page image names
+	page_prefix: String,
+
+	/// Source files with their names
+	files: Vec<(String, &'a File)>,
+}
+
+impl Default for AtlasBuilder<'_> {
+	fn default() -> Self {
+		Self {
+			max_size: 2048,
+			padding: 1,
+			page_prefix: "atlas_".to_string(),
+			files: Vec::new(),
+		}
+	}
+}
+
+impl<'a> AtlasBuilder<'a> {
+	/// Creates a builder for 2048x2048 pages with 1 pixel of padding
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Sets the maximum page size, rounded up to a power of two
+	pub fn with_max_size(mut self, max_size: u32) -> Self {
+		self.max_size = max_size.max(1).checked_next_power_of_two().unwrap_or(1 << 31);
+		self
+	}
+
+	/// Sets the number of transparent pixels between frames
+	pub fn with_padding(mut self, padding: u32) -> Self {
+		self.padding = padding;
+		self
+	}
+
+	/// Sets the prefix of the page image names, `atlas_` by default
+	pub fn with_page_prefix(mut self, page_prefix: impl Into<String>) -> Self {
+		self.page_prefix = page_prefix.into();
+		self
+	}
+
+	/// Adds every frame of an SPR file
+	///
+	/// Frames without pixels (zero width or height) are skipped.
+	pub fn add_file(mut self, name: impl Into<String>, file: &'a File) -> Self {
+		self.files.push((name.into(), file));
+		self
+	}
+
+	/// Packs and renders the atlas
+	///
+	/// # Errors
+	///
+	/// Returns an error if a frame is larger than the maximum page size
+	pub fn build(&self, palette: &Palette) -> Result<Atlas, DvFileError> {
+		let mut frames: Vec<(&str, usize, Frame)> = Vec::new();
+		for (name, file) in &self.files {
+			for (index, frame) in file.iter().enumerate() {
+				if frame.is_valid() {
+					frames.push((name, index, frame));
+				}
+			}
+		}
+
+		// Largest first packs tighter; the sort is stable for equal sizes
+		let mut order: Vec<usize> = (0..frames.len()).collect();
+		order.sort_by_key(|&i| {
+			let frame = &frames[i].2;
+			std::cmp::Reverse((frame.width().max(frame.height()), frame.entry().pixel_count()))
+		});
+
+		for (source, index, frame) in &frames {
+			if frame.width() > self.max_size || frame.height() > self.max_size {
+				return Err(DvFileError::CompressionError {
+					file_type: FileType::Spr,
+					message: format!(
+						"Frame {} of {} is {}x{}, larger than the {}x{} atlas page",
+						index,
+						source,
+						frame.width(),
+						frame.height(),
+						self.max_size,
+						self.max_size
+					),
+				});
+			}
+		}
+
+		// Padding goes right of and below every frame, so the bins get one
+		// padding of slack to keep frames at the page edge
+		let padded: Vec<(u32, u32)> = frames
+			.iter()
+			.map(|(_, _, frame)| (frame.width() + self.padding, frame.height() + self.padding))
+			.collect();
+		let mut placements = vec![
+			(
+				0,
+				Rect {
+					x: 0,
+					y: 0,
+					width: 0,
+					height: 0,
+				}
+			);
+			frames.len()
+		];
+		let mut sizes = Vec::new();
+		let mut remaining = order;
+
+		while !remaining.is_empty() {
+			// Take the smallest page holding every remaining frame, or fill a
+			// full page and carry the rest over
+			let area: u64 =
+				remaining.iter().map(|&i| u64::from(padded[i].0) * u64::from(padded[i].1)).sum();
+			let (placed, left) = self
+				.page_sizes()
+				.into_iter()
+				.filter(|&(width, height)| {
+					u64::from(width + self.padding) * u64::from(height + self.padding) >= area
+				})
+				.map(|(width, height)| self.pack(&padded, &remaining, width, height))
+				.find(|(_, left)| left.is_empty())
+				.unwrap_or_else(|| self.pack(&padded, &remaining, self.max_size, self.max_size));
+
+			// Shrink the page to the power of two covering its frames
+			let page = sizes.len();
+			let (mut width, mut height) = (1u32, 1u32);
+			for (i, rect) in placed {
+				width = width.max((rect.x + frames[i].2.width()).next_power_of_two());
+				height = height.max((rect.y + frames[i].2.height()).next_power_of_two());
+				placements[i] = (page, rect);
+			}
+			sizes.push((width, height));
+			remaining = left;
+		}
+
+		let mut pages: Vec<AtlasPage> = sizes
+			.iter()
+			.map(|&(width, height)| AtlasPage {
+				width,
+				height,
+				rgba: vec![0; width as usize * height as usize * 4],
+			})
+			.collect();
+		let mut descriptor = AtlasDescriptor {
+			pages: pages
+				.iter()
+				.enumerate()
+				.map(|(index, page)| AtlasPageInfo {
+					image: format!("{}{}.png", self.page_prefix, index),
+					width: page.width,
+					height: page.height,
+				})
+				.collect(),
+			frames: Vec::with_capacity(frames.len()),
+		};
+
+		for ((source, index, frame), (page, rect)) in frames.iter().zip(&placements) {
+			let target = &mut pages[*page];
+			let row_len = frame.width() as usize * 4;
+			let rgba = frame.apply_palette_with_mask(palette);
+			for (row, pixels) in rgba.chunks_exact(row_len).enumerate() {
+				let start = ((rect.y as usize + row) * target.width as usize + rect.x as usize) * 4;
+				target.rgba[start..start + row_len].copy_from_slice(pixels);
+			}
+
+			descriptor.frames.push(AtlasFrame {
+				source: source.to_string(),
+				frame: *index,
+				page: *page,
+				x: rect.x,
+				y: rect.y,
+				width: frame.width(),
+				height: frame.height(),
+				hotspot_x: frame.hotspot_x(),
+				hotspot_y: frame.hotspot_y(),
+			});
+		}
+
+		Ok(Atlas {
+			pages,
+			descriptor,
+		})
+	}
+
+	/// Power-of-two page sizes up to the maximum, smallest and squarest first
+	fn page_sizes(&self) -> Vec<(u32, u32)> {
+		let sides: Vec<u32> =
+			(0..32).map(|shift| 1u32 << shift).take_while(|&side| side <= self.max_size).collect();
+		let mut sizes: Vec<(u32, u32)> = sides
+			.iter()
+			.flat_map(|&width| sides.iter().map(move |&height| (width, height)))
+			.collect();
+		sizes.sort_by_key(|&(width, height)| {
+			(u64::from(width) * u64::from(height), width.max(height), width)
+		});
+		sizes
+	}
+
+	/// Packs frames onto one page, returning placements and frames left over
+	fn pack(
+		&self,
+		padded: &[(u32, u32)],
+		order: &[usize],
+		width: u32,
+		height: u32,
+	) -> (Vec<(usize, Rect)>, Vec<usize>) {
+		let mut bin =
+			MaxRects::new(width.saturating_add(self.padding), height.saturating_add(self.padding));
+		let mut placed = Vec::new();
+		let mut left = Vec::new();
+		for &i in order {
+			match bin.insert(padded[i].0, padded[i].1) {
+				Some(rect) => placed.push((i, rect)),
+				None => left.push(i),
+			}
+		}
+		(placed, left)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::file::spr::{FrameEntry, palette::test_palette};
+
+	/// SPR file with solid frames of the given sizes, colored by index
+	fn sprites(sizes: &[(u32, u32)]) -> File {
+		let mut file = File::new();
+		for (index, &(width, height)) in sizes.iter().enumerate() {
+			let count = (width * height) as usize;
+			let entry = FrameEntry::new(0, 0, width, height, width / 2, height);
+			let frame = Frame::new(entry, vec![176 + index as u8; count], vec![0; count]);
+			file.add_frame(frame).unwrap();
+		}
+		file
+	}
+
+	#[test]
+	fn test_frames_do_not_overlap_and_match_source() {
+		let a = sprites(&[(30, 20), (7, 50), (64, 64), (1, 1), (0, 5)]);
+		let b = sprites(&[(16, 16), (16, 16), (40, 3)]);
+		let palette = test_palette();
+		let atlas = AtlasBuilder::new()
+			.with_max_size(100)
+			.with_padding(2)
+			.add_file("A.SPR", &a)
+			.add_file("B.SPR", &b)
+			.build(&palette)
+			.unwrap();
+
+		// The empty frame is skipped, the rest keeps its insertion order
+		let frames = &atlas.descriptor.frames;
+		assert_eq!(frames.len(), 7);
+		assert_eq!((frames[4].source.as_str(), frames[4].frame), ("B.SPR", 0));
+		assert_eq!(atlas.descriptor.find("A.SPR", 1).unwrap().hotspot_y, 50);
+		assert!(atlas.descriptor.find("A.SPR", 4).is_none());
+
+		for page in &atlas.pages {
+			assert!(page.width.is_power_of_two() && page.width <= 128);
+			assert!(page.height.is_power_of_two() && page.height <= 128);
+		}
+
+		for (i, placed) in frames.iter().enumerate() {
+			let rect = Rect {
+				x: placed.x,
+				y: placed.y,
+				width: placed.width + 2,
+				height: placed.height + 2,
+			};
+			for other in &frames[i + 1..] {
+				let other_rect = Rect {
+					x: other.x,
+					y: other.y,
+					width: other.width + 2,
+					height: other.height + 2,
+				};
+				assert!(placed.page != other.page || !rect.intersects(&other_rect));
+			}
+
+			let source = if placed.source == "A.SPR" {
+				&a
+			} else {
+				&b
+			};
+			let rgba = source.get_frame(placed.frame).unwrap().apply_palette_with_mask(&palette);
+			let page = &atlas.pages[placed.page];
+			let last = (placed.width - 1, placed.height - 1);
+			for (x, y) in [(0, 0), last] {
+				let offset = (((placed.y + y) * page.width + placed.x + x) * 4) as usize;
+				let pixel = ((y * placed.width + x) * 4) as usize;
+				assert_eq!(page.rgba[offset..offset + 4], rgba[pixel..pixel + 4]);
+			}
+		}
+	}
+
+	#[test]
+	fn test_pages_overflow_and_descriptor_serializes() {
+		let file = sprites(&[(60, 60), (60, 60), (60, 60)]);
+		let atlas = AtlasBuilder::new()
+			.with_max_size(64)
+			.with_padding(0)
+			.add_file("X", &file)
+			.build(&test_palette())
+			.unwrap();
+		assert_eq!(atlas.pages.len(), 3);
+		assert_eq!((atlas.pages[0].width, atlas.pages[0].height), (64, 64));
+		assert_eq!(atlas.descriptor.pages[2].image, "atlas_2.png");
+
+		let json = serde_json::to_string(&atlas.descriptor).unwrap();
+		assert_eq!(serde_json::from_str::<AtlasDescriptor>(&json).unwrap(), atlas.descriptor);
+
+		let error =
+			AtlasBuilder::new().with_max_size(32).add_file("X", &file).build(&test_palette());
+		assert!(error.is_err());
+	}
+
+	#[test]
+	fn test_max_rects_fills_a_page_exactly() {
+		let mut bin = MaxRects::new(64, 64);
+		let mut rects = Vec::new();
+		for _ in 0..16 {
+			rects.push(bin.insert(16, 16).unwrap());
+		}
+		assert!(bin.insert(1, 1).is_none());
+		assert!(rects.iter().all(|rect| rect.x % 16 == 0 && rect.y % 16 == 0));
+	}
+}
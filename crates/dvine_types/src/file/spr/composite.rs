@@ -0,0 +1,240 @@
+//! Full 256-color screen palette of scenes with sprites.
+//!
+//! The game runs in an 8-bit display mode with a single hardware palette.
+//! Scene images (`.KG`) own the lower part of it, while the 80 colors of
+//! SPR.PAL are loaded at [`SPR_PAL_OFFSET`], which is why sprite pixels are
+//! stored as raw values 176-255. [`CompositePalette`] rebuilds that palette:
+//!
+//! - Sprite frames render with their raw values against the whole palette, so
+//!   values below 176 show the scene colors they show in the game, instead of
+//!   falling back to index 0 like [`Frame::apply_palette_with_mask`]
+//! - Palette swaps and tints apply to scene and sprite colors at once, and
+//!   [`scene_palette`](CompositePalette::scene_palette) and
+//!   [`sprite_palette`](CompositePalette::sprite_palette) split the result
+//!   again for the KG and SPR APIs
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use dvine_types::file::kg::IndexedImage;
+//! use dvine_types::file::spr::{CompositePalette, File, Palette};
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let scene = IndexedImage::open("BG01.KG")?;
+//! let sprites = Palette::from_file("SPR.PAL")?;
+//! let spr = File::open("KATIA.SPR")?;
+//!
+//! // Night version of both the background and the sprite
+//! let night = CompositePalette::new(scene.palette(), &sprites).multiplied([96, 104, 160]);
+//! let background = scene.to_rgb_with(&night.scene_palette());
+//! let katia = night.render_frame(&spr.get_frame(0).unwrap());
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::file::kg::quantize::Palette as KgPalette;
+
+use super::palette::{SPR_PAL_COLOR_COUNT, SPR_PAL_OFFSET};
+use super::{Frame, Palette};
+
+/// A 256-color screen palette: scene colors with SPR.PAL on top
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompositePalette {
+	/// RGB colors, the fourth byte of each entry is unused
+	colors: KgPalette,
+}
+
+impl CompositePalette {
+	/// Merges SPR.PAL colors into a KG scene palette
+	///
+	/// Entries from [`SPR_PAL_OFFSET`] on are replaced by the 80 sprite
+	/// colors, the scene colors below stay as they are.
+	pub fn new(scene: &KgPalette, sprites: &Palette) -> Self {
+		let mut colors = *scene;
+		for (index, color) in colors.iter_mut().enumerate().skip(SPR_PAL_OFFSET) {
+			let (r, g, b, _) = sprites.get((index - SPR_PAL_OFFSET) as u8);
+			*color = [r, g, b, 0];
+		}
+
+		Self {
+			colors,
+		}
+	}
+
+	/// Creates a palette with SPR.PAL colors on a black scene
+	pub fn from_sprite_palette(sprites: &Palette) -> Self {
+		Self::new(&[[0; 4]; 256], sprites)
+	}
+
+	/// Returns the RGB color of a palette entry
+	#[inline]
+	pub fn get(&self, index: u8) -> [u8; 3] {
+		let [r, g, b, _] = self.colors[index as usize];
+		[r, g, b]
+	}
+
+	/// Sets the RGB color of a palette entry
+	#[inline]
+	pub fn set(&mut self, index: u8, color: [u8; 3]) {
+		self.colors[index as usize] = [color[0], color[1], color[2], 0];
+	}
+
+	/// Returns all 256 entries
+	#[inline]
+	pub fn colors(&self) -> &KgPalette {
+		&self.colors
+	}
+
+	/// Returns all 256 entries mutably
+	#[inline]
+	pub fn colors_mut(&mut self) -> &mut KgPalette {
+		&mut self.colors
+	}
+
+	/// Returns the whole palette for rendering KG images
+	///
+	/// Scene images rarely use the sprite range, but it is kept so the
+	/// result stays the actual screen palette.
+	pub fn scene_palette(&self) -> KgPalette {
+		self.colors
+	}
+
+	/// Returns the sprite colors as an SPR.PAL palette
+	pub fn sprite_palette(&self) -> Palette {
+		let mut palette = Palette::new();
+		for index in 0..SPR_PAL_COLOR_COUNT {
+			let [r, g, b] = self.get((SPR_PAL_OFFSET + index) as u8);
+			palette.set(index as u8, (r, g, b, 0));
+		}
+		palette
+	}
+
+	/// Returns a copy with every color passed through `map`
+	///
+	/// This is the general palette swap; [`multiplied`](Self::multiplied)
+	/// and [`blended`](Self::blended) cover the usual tints. Like every
+	/// operation here it also changes the KG chroma key at index 0, so
+	/// render transparent scene images with the original key color.
+	pub fn mapped(&self, mut map: impl FnMut(u8, [u8; 3]) -> [u8; 3]) -> Self {
+		let mut result = self.clone();
+		for index in 0..=u8::MAX {
+			result.set(index, map(index, self.get(index)));
+		}
+		result
+	}
+
+	/// Returns a copy with every channel scaled by `factor / 255`
+	///
+	/// `[96, 104, 160]` gives a blue night tint, `[255, 255, 255]` leaves
+	/// the colors unchanged.
+	pub fn multiplied(&self, factor: [u8; 3]) -> Self {
+		self.mapped(|_, color| {
+			std::array::from_fn(|channel| {
+				(u16::from(color[channel]) * u16::from(factor[channel]) / 255) as u8
+			})
+		})
+	}
+
+	/// Returns a copy with every color moved toward `target` by `amount / 255`
+	///
+	/// `amount` 0 leaves the colors unchanged, 255 replaces them by `target`.
+	pub fn blended(&self, target: [u8; 3], amount: u8) -> Self {
+		let amount = u16::from(amount);
+		self.mapped(|_, color| {
+			std::array::from_fn(|channel| {
+				let blended = u16::from(color[channel]) * (255 - amount)
+					+ u16::from(target[channel]) * amount;
+				((blended + 127) / 255) as u8
+			})
+		})
+	}
+
+	/// Renders a sprite frame to RGBA against the full palette
+	///
+	/// Raw sprite values index the palette directly and alpha comes from the
+	/// mask (0x00 = opaque, 0xFF = transparent), matching
+	/// [`Frame::apply_palette_with_mask`] for values 176-255.
+	pub fn render_frame(&self, frame: &Frame) -> Vec<u8> {
+		frame
+			.sprite_pixels()
+			.iter()
+			.zip(frame.mask_pixels())
+			.flat_map(|(&raw, &mask)| {
+				let [r, g, b] = self.get(raw);
+				[r, g, b, u8::MAX - mask]
+			})
+			.collect()
+	}
+}
+
+impl From<&Palette> for CompositePalette {
+	fn from(sprites: &Palette) -> Self {
+		Self::from_sprite_palette(sprites)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::file::spr::{FrameEntry, palette::test_palette};
+
+	fn scene() -> KgPalette {
+		std::array::from_fn(|index| [index as u8, 255 - index as u8, 7, 0])
+	}
+
+	#[test]
+	fn test_composite_merges_at_offset() {
+		let composite = CompositePalette::new(&scene(), &test_palette());
+
+		assert_eq!(composite.get(0), [0, 255, 7]);
+		assert_eq!(composite.get(175), [175, 80, 7]);
+		assert_eq!(composite.get(176), [0, 255, 0]);
+		assert_eq!(composite.get(255), [237, 97, 39]);
+		assert!(composite.colors().iter().all(|color| color[3] == 0));
+		assert_eq!(composite.scene_palette()[..176], scene()[..176]);
+
+		// The sprite range round-trips, apart from the unused fourth byte
+		let sprite_palette = composite.sprite_palette();
+		for index in 0..80u8 {
+			let (r, g, b, _) = test_palette().get(index);
+			assert_eq!(sprite_palette.get(index), (r, g, b, 0));
+		}
+		assert_eq!(CompositePalette::from(&test_palette()).get(3), [0, 0, 0]);
+	}
+
+	#[test]
+	fn test_render_frame_uses_scene_colors() {
+		let composite = CompositePalette::new(&scene(), &test_palette());
+		let entry = FrameEntry::new(0, 0, 3, 1, 0, 0);
+		let frame = Frame::new(entry, vec![177, 255, 5], vec![0x00, 0xFF, 0x00]);
+
+		let rgba = composite.render_frame(&frame);
+		assert_eq!(rgba[..8], frame.apply_palette_with_mask(&test_palette())[..8]);
+		assert_eq!(rgba[8..], [5, 250, 7, 255]);
+	}
+
+	#[test]
+	fn test_tints_apply_to_scene_and_sprites() {
+		let composite = CompositePalette::new(&scene(), &test_palette());
+
+		let night = composite.multiplied([128, 255, 0]);
+		assert_eq!(night.get(100), [50, 155, 0]);
+		assert_eq!(night.get(200), [36, 207, 0]);
+		assert_eq!(composite.multiplied([255; 3]), composite);
+
+		let dusk = composite.blended([255, 0, 0], 255);
+		assert!((0..=255).all(|index| dusk.get(index) == [255, 0, 0]));
+		assert_eq!(composite.blended([255, 0, 0], 0), composite);
+		assert_eq!(composite.blended([255, 255, 255], 128).get(176), [128, 255, 128]);
+
+		// Swapping two entries is a mapping over indices
+		let swapped = composite.mapped(|index, color| match index {
+			176 => composite.get(177),
+			177 => composite.get(176),
+			_ => color,
+		});
+		assert_eq!(swapped.get(176), composite.get(177));
+		let (r, g, b, _) = test_palette().get(0);
+		assert_eq!(swapped.sprite_palette().get(1), (r, g, b, 0));
+	}
+}
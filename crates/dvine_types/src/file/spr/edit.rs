@@ -0,0 +1,275 @@
+//! Editing operations on SPR frames.
+//!
+//! Every operation keeps the hotspot on the same pixels, so an edited frame
+//! still lines up with the rest of its animation:
+//!
+//! - Flips mirror the frame around its hotspot
+//! - Crops and pads move the hotspot with the frame's top-left corner
+//! - Resizing scales the hotspot with the frame
+//!
+//! The results carry zero data offsets; [`File::add_frame`](super::File::add_frame)
+//! and [`File::replace_frame`](super::File::replace_frame) assign them.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use dvine_types::file::spr::File;
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut spr = File::open("KATIA.SPR")?;
+//!
+//! // Append right-facing copies of all frames
+//! let mirrored: Vec<_> = spr.iter().filter_map(|frame| frame.flipped_horizontal()).collect();
+//! for frame in mirrored {
+//!     spr.add_frame(frame)?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::file::raster;
+
+use super::{Frame, FrameEntry, frame::MASK_TRANSPARENT};
+
+/// Sprite value of padding pixels, palette index 0
+const PAD_SPRITE: u8 = 176;
+
+/// Mirrors a hotspot coordinate within `size` pixels, `None` if it would
+/// become negative
+fn mirrored(coordinate: u32, size: u32) -> Option<u32> {
+	size.checked_sub(1)?.checked_sub(coordinate)
+}
+
+impl Frame {
+	/// Builds a frame with new pixels and hotspot, dropping the data offsets
+	fn edited(
+		(width, height): (u32, u32),
+		(hotspot_x, hotspot_y): (u32, u32),
+		sprite_pixels: Vec<u8>,
+		mask_pixels: Vec<u8>,
+	) -> Self {
+		let entry = FrameEntry::new(0, 0, width, height, hotspot_x, hotspot_y);
+		Self::new(entry, sprite_pixels, mask_pixels)
+	}
+
+	/// Returns a copy mirrored left to right around the hotspot
+	///
+	/// The hotspot names a pixel, so it moves from column `x` to
+	/// `width - 1 - x` and that pixel stays in place on screen.
+	///
+	/// # Returns
+	///
+	/// `None` if the hotspot lies right of the frame, as the mirrored hotspot
+	/// would be negative.
+	pub fn flipped_horizontal(&self) -> Option<Self> {
+		let width = self.width() as usize;
+		Some(Self::edited(
+			(self.width(), self.height()),
+			(mirrored(self.hotspot_x(), self.width())?, self.hotspot_y()),
+			raster::flip_horizontal(self.sprite_pixels(), width),
+			raster::flip_horizontal(self.mask_pixels(), width),
+		))
+	}
+
+	/// Returns a copy mirrored top to bottom around the hotspot
+	///
+	/// The hotspot moves from row `y` to `height - 1 - y`.
+	///
+	/// # Returns
+	///
+	/// `None` if the hotspot lies below the frame, such as a hotspot at the
+	/// feet with `hotspot_y == height`, as the mirrored hotspot would be
+	/// negative.
+	pub fn flipped_vertical(&self) -> Option<Self> {
+		let width = self.width() as usize;
+		Some(Self::edited(
+			(self.width(), self.height()),
+			(self.hotspot_x(), mirrored(self.hotspot_y(), self.height())?),
+			raster::flip_vertical(self.sprite_pixels(), width),
+			raster::flip_vertical(self.mask_pixels(), width),
+		))
+	}
+
+	/// Returns the `width` x `height` region at (`x`, `y`)
+	///
+	/// # Returns
+	///
+	/// `None` if the region exceeds the frame, or if the hotspot lies left of
+	/// or above it, as hotspots cannot be negative.
+	pub fn cropped(&self, x: u32, y: u32, width: u32, height: u32) -> Option<Self> {
+		if x.checked_add(width)? > self.width()
+			|| y.checked_add(height)? > self.height()
+			|| x > self.hotspot_x()
+			|| y > self.hotspot_y()
+		{
+			return None;
+		}
+
+		let frame_width = self.width() as usize;
+		let origin = (x as usize, y as usize);
+		let size = (width as usize, height as usize);
+		Some(Self::edited(
+			(width, height),
+			(self.hotspot_x() - x, self.hotspot_y() - y),
+			raster::crop(self.sprite_pixels(), frame_width, origin, size),
+			raster::crop(self.mask_pixels(), frame_width, origin, size),
+		))
+	}
+
+	/// Returns a copy with transparent margins added on each side
+	///
+	/// # Returns
+	///
+	/// `None` if the padded size or hotspot overflows.
+	pub fn padded(&self, left: u32, top: u32, right: u32, bottom: u32) -> Option<Self> {
+		let width = self.width().checked_add(left)?.checked_add(right)?;
+		let height = self.height().checked_add(top)?.checked_add(bottom)?;
+		let hotspot = (self.hotspot_x().checked_add(left)?, self.hotspot_y().checked_add(top)?);
+
+		let size = (self.width() as usize, self.height() as usize);
+		let margins = (left as usize, top as usize, right as usize, bottom as usize);
+		Some(Self::edited(
+			(width, height),
+			hotspot,
+			raster::pad(self.sprite_pixels(), size, margins, PAD_SPRITE),
+			raster::pad(self.mask_pixels(), size, margins, MASK_TRANSPARENT),
+		))
+	}
+
+	/// Returns a copy scaled to `width` x `height` with nearest neighbor sampling
+	///
+	/// The hotspot is scaled by the same factors, rounded to the nearest pixel.
+	///
+	/// # Returns
+	///
+	/// `None` if this frame or the requested size is empty.
+	pub fn resized(&self, width: u32, height: u32) -> Option<Self> {
+		if self.is_empty() || width == 0 || height == 0 {
+			return None;
+		}
+
+		let size = (self.width() as usize, self.height() as usize);
+		let new_size = (width as usize, height as usize);
+		let hotspot_x = raster::scale_coordinate(self.hotspot_x().into(), size.0, new_size.0);
+		let hotspot_y = raster::scale_coordinate(self.hotspot_y().into(), size.1, new_size.1);
+		Some(Self::edited(
+			(width, height),
+			(u32::try_from(hotspot_x).ok()?, u32::try_from(hotspot_y).ok()?),
+			raster::resize_nearest(self.sprite_pixels(), size, new_size),
+			raster::resize_nearest(self.mask_pixels(), size, new_size),
+		))
+	}
+
+	/// Draws `source` with its top-left corner at (`x`, `y`) of this frame
+	///
+	/// Only source pixels that are not fully transparent are drawn; each
+	/// keeps the more opaque of both mask values. Pixels outside this frame
+	/// are clipped, and the hotspot is unchanged. To line up both hotspots,
+	/// draw at `x = i64::from(self.hotspot_x()) - i64::from(source.hotspot_x())`,
+	/// and likewise for `y`.
+	pub fn blit(&mut self, source: &Frame, x: i64, y: i64) {
+		let target_size = (self.width() as usize, self.height() as usize);
+		let source_size = (source.width() as usize, source.height() as usize);
+		let sprite_pixels = self.sprite_pixels_mut();
+		raster::blit(target_size, source_size, (x, y), |target, index| {
+			if source.mask_pixels()[index] != MASK_TRANSPARENT {
+				sprite_pixels[target] = source.sprite_pixels()[index];
+			}
+		});
+
+		let mask_pixels = self.mask_pixels_mut();
+		raster::blit(target_size, source_size, (x, y), |target, index| {
+			mask_pixels[target] = mask_pixels[target].min(source.mask_pixels()[index]);
+		});
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// 3x2 frame with distinct pixels and a transparent corner
+	fn frame() -> Frame {
+		let entry = FrameEntry::new(8, 14, 3, 2, 1, 2);
+		Frame::new(entry, vec![176, 177, 178, 179, 180, 181], vec![0, 0, 0, 0, 0, 0xFF])
+	}
+
+	#[test]
+	fn test_flips_mirror_around_hotspot() {
+		let frame = frame();
+
+		let flipped = frame.flipped_horizontal().unwrap();
+		assert_eq!(flipped.sprite_pixels(), &[178, 177, 176, 181, 180, 179]);
+		assert_eq!(flipped.mask_pixels(), &[0, 0, 0, 0xFF, 0, 0]);
+		assert_eq!((flipped.hotspot_x(), flipped.hotspot_y()), (1, 2));
+		assert_eq!((flipped.entry().color_offset(), flipped.entry().mask_offset()), (0, 0));
+		let restored = flipped.flipped_horizontal().unwrap();
+		assert_eq!(restored.sprite_pixels(), frame.sprite_pixels());
+		assert_eq!(
+			(restored.hotspot_x(), restored.hotspot_y()),
+			(frame.hotspot_x(), frame.hotspot_y())
+		);
+
+		// A hotspot at the feet, below the last row, has no mirror image
+		assert!(frame.flipped_vertical().is_none());
+		let sprite = frame.sprite_pixels().to_vec();
+		let right = Frame::edited((3, 2), (3, 1), sprite.clone(), frame.mask_pixels().to_vec());
+		assert!(right.flipped_horizontal().is_none());
+
+		// A hotspot inside the frame stays on its pixel
+		let inside = Frame::edited((3, 2), (2, 1), sprite, frame.mask_pixels().to_vec());
+		let flipped = inside.flipped_vertical().unwrap();
+		assert_eq!(flipped.sprite_pixels(), &[179, 180, 181, 176, 177, 178]);
+		assert_eq!((flipped.hotspot_x(), flipped.hotspot_y()), (2, 0));
+		let flipped = flipped.flipped_horizontal().unwrap();
+		assert_eq!((flipped.hotspot_x(), flipped.hotspot_y()), (0, 0));
+		assert_eq!(flipped.get_sprite_pixel(0, 0), Some(181));
+		let restored = flipped.flipped_vertical().unwrap().flipped_horizontal().unwrap();
+		assert_eq!((restored.hotspot_x(), restored.hotspot_y()), (2, 1));
+		assert_eq!(restored, inside);
+	}
+
+	#[test]
+	fn test_crop_and_pad_move_hotspot() {
+		let frame = frame();
+
+		let cropped = frame.cropped(1, 0, 2, 2).unwrap();
+		assert_eq!(cropped.sprite_pixels(), &[177, 178, 180, 181]);
+		assert_eq!((cropped.hotspot_x(), cropped.hotspot_y()), (0, 2));
+		assert!(frame.cropped(2, 0, 2, 2).is_none());
+		assert!(frame.cropped(0, 0, 3, 3).is_none());
+		assert!(frame.cropped(u32::MAX, 0, 2, 1).is_none());
+
+		let padded = frame.padded(1, 2, 0, 1).unwrap();
+		assert_eq!((padded.width(), padded.height()), (4, 5));
+		assert_eq!((padded.hotspot_x(), padded.hotspot_y()), (2, 4));
+		assert_eq!(padded.get_sprite_pixel(1, 2), Some(176));
+		assert_eq!(padded.get_sprite_pixel(3, 3), Some(181));
+		assert_eq!(padded.get_mask_pixel(0, 0), Some(MASK_TRANSPARENT));
+		assert_eq!(padded.cropped(1, 2, 3, 2).unwrap(), frame.padded(0, 0, 0, 0).unwrap());
+		assert!(frame.padded(u32::MAX, 0, 0, 0).is_none());
+	}
+
+	#[test]
+	fn test_resize_and_blit() {
+		let frame = frame();
+
+		let doubled = frame.resized(6, 4).unwrap();
+		assert_eq!(doubled.sprite_pixels()[..6], [176, 176, 177, 177, 178, 178]);
+		assert_eq!(doubled.sprite_pixels()[18..], [179, 179, 180, 180, 181, 181]);
+		assert_eq!((doubled.hotspot_x(), doubled.hotspot_y()), (2, 4));
+		assert_eq!(doubled.resized(3, 2).unwrap(), frame.padded(0, 0, 0, 0).unwrap());
+		assert!(frame.resized(0, 2).is_none());
+
+		let mut target = Frame::edited((4, 3), (0, 0), vec![200; 12], vec![MASK_TRANSPARENT; 12]);
+		target.blit(&frame, 2, 1);
+		target.blit(&frame, -10, 0);
+		assert_eq!(
+			target.sprite_pixels(),
+			&[200, 200, 200, 200, 200, 200, 176, 177, 200, 200, 179, 180]
+		);
+		assert_eq!(target.mask_pixels()[6..8], [0, 0]);
+		assert_eq!(target.mask_pixels()[..6], [MASK_TRANSPARENT; 6]);
+		assert_eq!((target.hotspot_x(), target.hotspot_y()), (0, 0));
+	}
+}
@@ -7,6 +7,12 @@ use std::fmt;
 
 use super::Palette;
 
+/// Mask value of an opaque pixel, drawn with alpha 255
+pub const MASK_OPAQUE: u8 = 0x00;
+
+/// Mask value of a transparent pixel, drawn with alpha 0
+pub const MASK_TRANSPARENT: u8 = 0xFF;
+
 /// SPR frame descriptor entry (24 bytes).
 ///
 /// This structure describes a single frame's metadata, including offsets to both
@@ -448,7 +454,7 @@ impl Frame {
 
 	/// Converts the mask data to ASCII art with a default character mapping.
 	///
-	/// Uses ' ' for transparent (0x00) and '#' for opaque (0xFF).
+	/// Uses ' ' for opaque (0x00) and '#' for transparent (0xFF).
 	pub fn mask_to_ascii_art_default(&self) -> String {
 		self.mask_to_ascii_art(|pixel| {
 			if pixel < 128 {
@@ -553,7 +559,8 @@ impl Frame {
 	///
 	/// A vector of RGBA bytes (width × height × 4 bytes).
 	/// Pixels are in row-major order, with each pixel as [R, G, B, A].
-	/// Alpha is taken from the mask data (0x00 = opaque, 0xFF = transparent).
+	/// Alpha is `255 - mask`, so [`MASK_OPAQUE`] is opaque and
+	/// [`MASK_TRANSPARENT`] is transparent.
 	pub fn apply_palette_with_mask(&self, palette: &Palette) -> Vec<u8> {
 		let pixel_count = self.entry.pixel_count();
 		let mut rgba_data = Vec::with_capacity(pixel_count * 4);
@@ -695,11 +702,7 @@ impl<'a> FrameRowIterator<'a> {
 	/// * `pixels` - Pixel data to iterate over
 	/// * `width` - Width of each row
 	pub fn new(pixels: &'a [u8], width: usize) -> Self {
-		let total_rows = if width > 0 {
-			pixels.len() / width
-		} else {
-			0
-		};
+		let total_rows = pixels.len().checked_div(width).unwrap_or(0);
 
 		Self {
 			pixels,
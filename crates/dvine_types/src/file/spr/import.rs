@@ -0,0 +1,203 @@
+//! Importing SPR frames from RGBA images.
+//!
+//! SPR frames store palette indices in the 176-255 range plus a separate
+//! mask. [`FrameImporter`] builds both from plain RGBA pixels, so sprites can
+//! be drawn in any image editor:
+//!
+//! - Each opaque pixel maps to the nearest of the 80 colors of an SPR.PAL
+//!   palette and is encoded with [`Frame::encode_sprite_pixel`].
+//! - The mask comes from the alpha channel: pixels with alpha at or above the
+//!   threshold are opaque (`0x00`), all others transparent (`0xFF`).
+//! - Transparent pixels get a fixed palette index, so identical shapes encode
+//!   to identical bytes whatever color the editor left under them.
+//!
+//! # Examples
+//!
+//! ```
+//! use dvine_types::file::spr::{File, FrameImporter, Palette};
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut palette = Palette::new();
+//! palette.set(1, (255, 0, 0, 0));
+//!
+//! // 2x1 image: a reddish opaque pixel and a transparent one
+//! let rgba = [250, 10, 5, 255, 0, 0, 0, 0];
+//! let frame = FrameImporter::new(&palette).import(&rgba, 2, 1, 1, 0)?;
+//! assert_eq!(frame.sprite_pixels(), &[177, 176]);
+//! assert_eq!(frame.mask_pixels(), &[0x00, 0xFF]);
+//!
+//! let mut spr = File::new();
+//! spr.add_frame(frame)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+
+use crate::file::{DvFileError, FileType};
+
+use super::{
+	Frame, FrameEntry, Palette,
+	frame::{MASK_OPAQUE, MASK_TRANSPARENT},
+};
+
+/// Builds SPR frames from RGBA pixels
+#[derive(Debug, Clone)]
+pub struct FrameImporter {
+	/// Palette the colors are mapped to
+	palette: Palette,
+
+	/// Alpha values at or above this are opaque
+	alpha_threshold: u8,
+
+	/// Palette index stored under transparent pixels
+	transparent_index: u8,
+}
+
+impl FrameImporter {
+	/// Creates an importer mapping colors to `palette`
+	///
+	/// Pixels with alpha of 128 or more are opaque, transparent pixels use
+	/// palette index 0.
+	pub fn new(palette: &Palette) -> Self {
+		Self {
+			palette: palette.clone(),
+			alpha_threshold: 128,
+			transparent_index: 0,
+		}
+	}
+
+	/// Sets the alpha value from which a pixel counts as opaque
+	///
+	/// `0` makes every pixel opaque.
+	pub fn with_alpha_threshold(mut self, alpha_threshold: u8) -> Self {
+		self.alpha_threshold = alpha_threshold;
+		self
+	}
+
+	/// Sets the palette index (0-79) stored under transparent pixels
+	pub fn with_transparent_index(mut self, transparent_index: u8) -> Self {
+		self.transparent_index = transparent_index.min(79);
+		self
+	}
+
+	/// Builds a frame from RGBA pixels
+	///
+	/// # Arguments
+	///
+	/// * `rgba` - Pixel data, `width * height * 4` bytes in row-major order
+	/// * `width` - Frame width in pixels
+	/// * `height` - Frame height in pixels
+	/// * `hotspot_x` - Hotspot X coordinate
+	/// * `hotspot_y` - Hotspot Y coordinate
+	///
+	/// # Returns
+	///
+	/// A frame ready for [`File::add_frame`](super::File::add_frame), which
+	/// assigns its data offsets.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the data size does not match the dimensions
+	pub fn import(
+		&self,
+		rgba: &[u8],
+		width: u32,
+		height: u32,
+		hotspot_x: u32,
+		hotspot_y: u32,
+	) -> Result<Frame, DvFileError> {
+		let pixel_count = (width as usize).checked_mul(height as usize);
+		if pixel_count.and_then(|count| count.checked_mul(4)) != Some(rgba.len()) {
+			return Err(DvFileError::CompressionError {
+				file_type: FileType::Spr,
+				message: format!(
+					"Invalid RGBA data size: expected {}x{} * 4 bytes, got {} bytes",
+					width,
+					height,
+					rgba.len()
+				),
+			});
+		}
+
+		let transparent = Frame::encode_sprite_pixel(self.transparent_index);
+		let mut nearest: HashMap<[u8; 3], u8> = HashMap::new();
+		let mut sprite_pixels = Vec::with_capacity(rgba.len() / 4);
+		let mut mask_pixels = Vec::with_capacity(rgba.len() / 4);
+
+		for pixel in rgba.chunks_exact(4) {
+			if pixel[3] < self.alpha_threshold {
+				sprite_pixels.push(transparent);
+				mask_pixels.push(MASK_TRANSPARENT);
+				continue;
+			}
+
+			let index = *nearest
+				.entry([pixel[0], pixel[1], pixel[2]])
+				.or_insert_with(|| self.palette.nearest_index(pixel[0], pixel[1], pixel[2]));
+			sprite_pixels.push(Frame::encode_sprite_pixel(index));
+			mask_pixels.push(MASK_OPAQUE);
+		}
+
+		let entry = FrameEntry::new(0, 0, width, height, hotspot_x, hotspot_y);
+		Ok(Frame::new(entry, sprite_pixels, mask_pixels))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::file::spr::palette::test_palette;
+
+	#[test]
+	fn test_import_roundtrips_rendered_frames() {
+		let palette = test_palette();
+		let sprite: Vec<u8> = (0..12u8).map(|i| 176 + i * 6).collect();
+		let mask: Vec<u8> = (0..12u8)
+			.map(|i| {
+				if i % 4 == 0 {
+					MASK_TRANSPARENT
+				} else {
+					MASK_OPAQUE
+				}
+			})
+			.collect();
+		let entry = FrameEntry::new(0, 0, 4, 3, 2, 3);
+		let frame = Frame::new(entry, sprite, mask);
+
+		let rgba = frame.apply_palette_with_mask(&palette);
+		let imported = FrameImporter::new(&palette).import(&rgba, 4, 3, 2, 3).unwrap();
+
+		assert_eq!(imported.mask_pixels(), frame.mask_pixels());
+		assert_eq!(imported.entry(), frame.entry());
+		for (i, (&imported, &original)) in
+			imported.sprite_pixels().iter().zip(frame.sprite_pixels()).enumerate()
+		{
+			let expected = if frame.mask_pixels()[i] == MASK_TRANSPARENT {
+				176
+			} else {
+				original
+			};
+			assert_eq!(imported, expected, "pixel {}", i);
+		}
+	}
+
+	#[test]
+	fn test_import_maps_colors_and_alpha() {
+		let palette = test_palette();
+
+		// Off-palette color next to index 10, half transparent pixels
+		let rgba = [31, 236, 4, 200, 9, 9, 9, 100, 0, 255, 0, 128];
+		let importer = FrameImporter::new(&palette).with_transparent_index(5);
+		let frame = importer.import(&rgba, 3, 1, 0, 0).unwrap();
+		assert_eq!(frame.sprite_pixels(), &[186, 181, 176]);
+		assert_eq!(frame.mask_pixels(), &[MASK_OPAQUE, MASK_TRANSPARENT, MASK_OPAQUE]);
+
+		let opaque = importer.with_alpha_threshold(0).import(&rgba, 3, 1, 0, 0).unwrap();
+		assert!(opaque.mask_pixels().iter().all(|&mask| mask == MASK_OPAQUE));
+
+		assert!(FrameImporter::new(&palette).import(&rgba, 2, 2, 0, 0).is_err());
+		assert!(FrameImporter::new(&palette).import(&[], u32::MAX, u32::MAX, 0, 0).is_err());
+		assert_eq!(palette.nearest_index(31, 236, 4), 10);
+	}
+}
@@ -24,7 +24,7 @@
 //! # Pixel Format
 //!
 //! - **Sprite pixels**: Indexed color values (176-255 range maps to palette indices 0-79)
-//! - **Mask pixels**: Binary transparency values (0x00 = transparent, 0xFF = opaque)
+//! - **Mask pixels**: Binary transparency values (0x00 = opaque, 0xFF = transparent)
 //!
 //! # Usage Examples
 //!
@@ -96,7 +96,7 @@
 //! // Create a simple 10x10 frame
 //! let entry = FrameEntry::new(0, 100, 10, 10, 5, 5);
 //! let sprite_pixels = vec![176; 100]; // Palette index 0
-//! let mask_pixels = vec![0xFF; 100];  // Fully opaque
+//! let mask_pixels = vec![0x00; 100];  // Fully opaque
 //! let frame = Frame::new(entry, sprite_pixels, mask_pixels);
 //!
 //! spr.add_frame(frame)?;
@@ -104,15 +104,40 @@
 //! # Ok(())
 //! # }
 //! ```
+//!
+//! ## Importing frames from RGBA images
+//!
+//! ```no_run
+//! use dvine_types::file::spr::{File, FrameImporter, Palette};
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let palette = Palette::from_file("SPR.PAL")?;
+//! let importer = FrameImporter::new(&palette);
+//!
+//! // 16x16 RGBA pixels drawn in any editor, hotspot at the feet
+//! let rgba = vec![0u8; 16 * 16 * 4];
+//! let mut spr = File::new();
+//! spr.add_frame(importer.import(&rgba, 16, 16, 8, 15)?)?;
+//! # Ok(())
+//! # }
+//! ```
 
 use std::io::Cursor;
 
-use crate::file::{DvFileError, FileType};
+use crate::file::{DvFileError, FileType, ParseLimits};
 
+pub mod atlas;
+pub mod composite;
+mod edit;
 pub mod frame;
+pub mod import;
+pub mod optimize;
 pub mod palette;
 
+pub use composite::CompositePalette;
 pub use frame::{ColorRowIterator, Frame, FrameEntry, FrameRowIterator};
+pub use import::FrameImporter;
+pub use optimize::{OptimizeStats, Optimizer};
 pub use palette::Palette;
 
 /// SPR file constants.
@@ -226,8 +251,8 @@ impl File {
 		let mask_start = data_start + entry.mask_offset as usize;
 
 		let pixel_count = entry.pixel_count();
-		let sprite_end = sprite_start + pixel_count;
-		let mask_end = mask_start + pixel_count;
+		let sprite_end = sprite_start.checked_add(pixel_count)?;
+		let mask_end = mask_start.checked_add(pixel_count)?;
 
 		// Validate ranges
 		if sprite_end > self.raw.len() || mask_end > self.raw.len() {
@@ -260,6 +285,8 @@ impl File {
 	/// Updates a frame's pixel data in the file.
 	///
 	/// This method updates both sprite and mask data for a specific frame.
+	/// Pixel data shared with other frames is not overwritten, see
+	/// [`replace_frame`](Self::replace_frame).
 	///
 	/// # Arguments
 	///
@@ -281,29 +308,15 @@ impl File {
 			return false;
 		}
 
-		let data_start = self.data_area_start();
-
-		// Update sprite data
-		let sprite_start = data_start + entry.color_offset as usize;
-		let sprite_end = sprite_start + expected_size;
-		if sprite_end > self.raw.len() {
-			return false;
-		}
-		self.raw[sprite_start..sprite_end].copy_from_slice(sprite_pixels);
-
-		// Update mask data
-		let mask_start = data_start + entry.mask_offset as usize;
-		let mask_end = mask_start + expected_size;
-		if mask_end > self.raw.len() {
-			return false;
-		}
-		self.raw[mask_start..mask_end].copy_from_slice(mask_pixels);
-
-		true
+		let frame = Frame::new(*entry, sprite_pixels.to_vec(), mask_pixels.to_vec());
+		self.replace_frame(index, &frame).is_ok()
 	}
 
 	/// Updates a complete frame (entry + sprite + mask) in the file.
 	///
+	/// The size and hotspot are taken from the frame entry, the data offsets
+	/// are managed by the file, see [`replace_frame`](Self::replace_frame).
+	///
 	/// # Arguments
 	///
 	/// * `index` - Frame index (0-based)
@@ -313,29 +326,139 @@ impl File {
 	///
 	/// `true` if the frame was updated successfully, `false` otherwise.
 	pub fn update_complete_frame(&mut self, index: usize, frame: &Frame) -> bool {
-		// Update pixel data first
-		if !self.update_frame(index, frame.sprite_pixels(), frame.mask_pixels()) {
-			return false;
+		self.replace_frame(index, frame).is_ok()
+	}
+
+	/// Replaces a frame, keeping every other byte of the file as it is.
+	///
+	/// SPR files may point several descriptors at the same or overlapping
+	/// pixel data. To keep such files intact, only the data of the edited
+	/// frame is touched:
+	///
+	/// - A frame equal to the stored one leaves the file unchanged, so
+	///   [`to_bytes`](Self::to_bytes) still returns the original bytes
+	/// - A changed hotspot only rewrites the frame descriptor
+	/// - New pixels of the same size are written in place if no other frame
+	///   uses the frame's data
+	/// - Otherwise the pixels are appended to the data area and the
+	///   descriptor is pointed there; the old data stays for the frames
+	///   sharing it
+	///
+	/// The offsets in `frame.entry()` are ignored.
+	///
+	/// # Returns
+	///
+	/// `true` if the file changed.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the index is out of range, the pixel data does not
+	/// match the frame size or the data area would exceed 4 GiB.
+	pub fn replace_frame(&mut self, index: usize, frame: &Frame) -> Result<bool, DvFileError> {
+		let Some(current) = self.entries.get(index).copied() else {
+			return Err(DvFileError::EntryNotFound {
+				file_type: FileType::Spr,
+				message: format!("Frame {} of {}", index, self.frame_count),
+			});
+		};
+
+		let pixel_count = frame.entry().pixel_count();
+		if frame.sprite_pixels().len() != pixel_count || frame.mask_pixels().len() != pixel_count {
+			return Err(DvFileError::CompressionError {
+				file_type: FileType::Spr,
+				message: format!(
+					"Frame {} pixel data does not match its {}x{} size",
+					index,
+					frame.width(),
+					frame.height()
+				),
+			});
 		}
 
-		// Update entry metadata in the descriptor table
-		if let Some(entry) = self.entries.get_mut(index) {
-			*entry = *frame.entry();
-
-			// Update the raw bytes for the frame descriptor
-			let offset = constants::HEADER_SIZE + index * constants::FRAME_DESCRIPTOR_SIZE;
-			if offset + constants::FRAME_DESCRIPTOR_SIZE <= self.raw.len() {
-				self.raw[offset..offset + 4].copy_from_slice(&entry.color_offset.to_le_bytes());
-				self.raw[offset + 4..offset + 8].copy_from_slice(&entry.mask_offset.to_le_bytes());
-				self.raw[offset + 8..offset + 12].copy_from_slice(&entry.width.to_le_bytes());
-				self.raw[offset + 12..offset + 16].copy_from_slice(&entry.height.to_le_bytes());
-				self.raw[offset + 16..offset + 20].copy_from_slice(&entry.hotspot_x.to_le_bytes());
-				self.raw[offset + 20..offset + 24].copy_from_slice(&entry.hotspot_y.to_le_bytes());
-				return true;
+		let stored = self.get_frame(index);
+		let same_size =
+			stored.is_some() && (current.width, current.height) == (frame.width(), frame.height());
+		let same_pixels = same_size
+			&& stored.as_ref().is_some_and(|stored| {
+				stored.sprite_pixels() == frame.sprite_pixels()
+					&& stored.mask_pixels() == frame.mask_pixels()
+			});
+
+		let (color_offset, mask_offset) = if same_pixels {
+			(current.color_offset, current.mask_offset)
+		} else if same_size && self.owns_data(index) {
+			let data_start = self.data_area_start();
+			let color_start = data_start + current.color_offset as usize;
+			let mask_start = data_start + current.mask_offset as usize;
+			self.raw[color_start..color_start + pixel_count].copy_from_slice(frame.sprite_pixels());
+			self.raw[mask_start..mask_start + pixel_count].copy_from_slice(frame.mask_pixels());
+			(current.color_offset, current.mask_offset)
+		} else {
+			let data_size = self.raw.len().saturating_sub(self.data_area_start());
+			let fits =
+				data_size.checked_add(pixel_count * 2).is_some_and(|end| end <= u32::MAX as usize);
+			if !fits {
+				return Err(DvFileError::CompressionError {
+					file_type: FileType::Spr,
+					message: format!("Frame {} does not fit the 4 GiB data area", index),
+				});
 			}
+			self.raw.extend_from_slice(frame.sprite_pixels());
+			self.raw.extend_from_slice(frame.mask_pixels());
+			(data_size as u32, (data_size + pixel_count) as u32)
+		};
+
+		let entry = FrameEntry::new(
+			color_offset,
+			mask_offset,
+			frame.width(),
+			frame.height(),
+			frame.hotspot_x(),
+			frame.hotspot_y(),
+		);
+		if entry == current && same_pixels {
+			return Ok(false);
+		}
+
+		self.entries[index] = entry;
+		self.write_descriptor(index);
+		Ok(true)
+	}
+
+	/// Checks whether a frame's pixel data is used by no other frame.
+	///
+	/// The frame's own color and mask data must not overlap either.
+	fn owns_data(&self, index: usize) -> bool {
+		let ranges = |entry: &FrameEntry| {
+			let count = entry.pixel_count();
+			let color = entry.color_offset as usize;
+			let mask = entry.mask_offset as usize;
+			[color..color.saturating_add(count), mask..mask.saturating_add(count)]
+		};
+		let overlaps = |a: &std::ops::Range<usize>, b: &std::ops::Range<usize>| {
+			a.start < b.end && b.start < a.end
+		};
+
+		let [color, mask] = ranges(&self.entries[index]);
+		if overlaps(&color, &mask) {
+			return false;
 		}
 
-		false
+		self.entries.iter().enumerate().filter(|&(other, _)| other != index).all(|(_, entry)| {
+			ranges(entry).iter().all(|range| !overlaps(range, &color) && !overlaps(range, &mask))
+		})
+	}
+
+	/// Writes the descriptor of a frame entry back to the raw data.
+	fn write_descriptor(&mut self, index: usize) {
+		let entry = self.entries[index];
+		let offset = constants::HEADER_SIZE + index * constants::FRAME_DESCRIPTOR_SIZE;
+		self.raw[offset..offset + 4].copy_from_slice(&entry.color_offset.to_le_bytes());
+		self.raw[offset + 4..offset + 8].copy_from_slice(&entry.mask_offset.to_le_bytes());
+		self.raw[offset + 8..offset + 12].copy_from_slice(&entry.width.to_le_bytes());
+		self.raw[offset + 12..offset + 16].copy_from_slice(&entry.height.to_le_bytes());
+		self.raw[offset + 16..offset + 20].copy_from_slice(&entry.hotspot_x.to_le_bytes());
+		self.raw[offset + 20..offset + 24].copy_from_slice(&entry.hotspot_y.to_le_bytes());
 	}
 
 	/// Adds a new frame to the SPR file.
@@ -417,6 +540,35 @@ impl File {
 		Ok(())
 	}
 
+	/// Builds a file from frame entries and a data area.
+	///
+	/// The entry offsets are relative to the start of `data` and are written
+	/// as given, so several entries may share the same pixel data.
+	fn from_parts(entries: Vec<FrameEntry>, data: &[u8]) -> Self {
+		let frame_count = entries.len() as u32;
+		let mut raw = Vec::with_capacity(
+			constants::HEADER_SIZE + entries.len() * constants::FRAME_DESCRIPTOR_SIZE + data.len(),
+		);
+
+		raw.extend_from_slice(&frame_count.to_le_bytes());
+		raw.extend_from_slice(&[0u8; constants::RESERVED_SIZE]);
+		for entry in &entries {
+			raw.extend_from_slice(&entry.color_offset.to_le_bytes());
+			raw.extend_from_slice(&entry.mask_offset.to_le_bytes());
+			raw.extend_from_slice(&entry.width.to_le_bytes());
+			raw.extend_from_slice(&entry.height.to_le_bytes());
+			raw.extend_from_slice(&entry.hotspot_x.to_le_bytes());
+			raw.extend_from_slice(&entry.hotspot_y.to_le_bytes());
+		}
+		raw.extend_from_slice(data);
+
+		Self {
+			raw,
+			frame_count,
+			entries,
+		}
+	}
+
 	/// Saves the SPR file to disk.
 	///
 	/// # Arguments
@@ -432,6 +584,10 @@ impl File {
 	}
 
 	/// Serializes the SPR file to bytes.
+	///
+	/// The data area is written as stored, so frames sharing pixel data (see
+	/// [`Optimizer`]) keep sharing it, and a file without edits serializes to
+	/// the bytes it was loaded from.
 	pub fn to_bytes(&self) -> Vec<u8> {
 		self.raw.clone()
 	}
@@ -449,8 +605,18 @@ impl File {
 	/// - Frame descriptors are invalid
 	/// - Data offsets are out of bounds
 	pub fn from_bytes(data: &[u8]) -> Result<Self, DvFileError> {
+		Self::from_bytes_with_limits(data, &ParseLimits::default())
+	}
+
+	/// Loads an SPR file from a byte slice, rejecting files over `limits`.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the file structure is invalid or the frame count,
+	/// a frame size or the file size exceeds the limits.
+	pub fn from_bytes_with_limits(data: &[u8], limits: &ParseLimits) -> Result<Self, DvFileError> {
 		let mut cursor = Cursor::new(data);
-		Self::from_reader(&mut cursor)
+		Self::from_reader_with_limits(&mut cursor, limits)
 	}
 
 	/// Loads an SPR file from any reader.
@@ -463,9 +629,21 @@ impl File {
 	///
 	/// Returns an error if the file structure is invalid.
 	pub fn from_reader<R: std::io::Read>(reader: &mut R) -> Result<Self, DvFileError> {
+		Self::from_reader_with_limits(reader, &ParseLimits::default())
+	}
+
+	/// Loads an SPR file from any reader, rejecting files over `limits`.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the file structure is invalid or the frame count,
+	/// a frame size or the file size exceeds the limits.
+	pub fn from_reader_with_limits<R: std::io::Read>(
+		reader: &mut R,
+		limits: &ParseLimits,
+	) -> Result<Self, DvFileError> {
 		// Read entire file
-		let mut raw = Vec::new();
-		reader.read_to_end(&mut raw)?;
+		let raw = limits.allocation(FileType::Spr).read_to_end(reader)?;
 
 		// Validate minimum file size
 		if raw.len() < constants::HEADER_SIZE {
@@ -483,6 +661,7 @@ impl File {
 			raw[constants::FRAME_COUNT_OFFSET + 2],
 			raw[constants::FRAME_COUNT_OFFSET + 3],
 		]);
+		limits.check_frames(FileType::Spr, frame_count as usize)?;
 
 		// Calculate expected size for header + descriptors
 		let descriptors_size = frame_count as usize * constants::FRAME_DESCRIPTOR_SIZE;
@@ -543,6 +722,7 @@ impl File {
 				raw[offset + 23],
 			]);
 
+			limits.check_dimensions(FileType::Spr, width, height)?;
 			entries.push(FrameEntry::new(
 				color_offset,
 				mask_offset,
@@ -658,4 +838,118 @@ mod tests {
 		assert_eq!(loaded_frame.sprite_pixels(), &sprite);
 		assert_eq!(loaded_frame.mask_pixels(), &mask);
 	}
+
+	/// File whose frames share and overlap pixel data, with trailing bytes
+	fn shared_file() -> Vec<u8> {
+		let descriptors: [[u32; 6]; 4] = [
+			[0, 4, 2, 2, 1, 1],
+			// Same data as frame 0, other hotspot
+			[0, 4, 2, 2, 0, 0],
+			// Mask overlapping the color data of frame 0
+			[8, 2, 2, 1, 0, 0],
+			// Exclusive data
+			[10, 12, 1, 2, 0, 1],
+		];
+		let mut raw = Vec::new();
+		raw.extend_from_slice(&4u32.to_le_bytes());
+		raw.extend_from_slice(&[0u8; constants::RESERVED_SIZE]);
+		for descriptor in descriptors {
+			for value in descriptor {
+				raw.extend_from_slice(&value.to_le_bytes());
+			}
+		}
+		raw.extend_from_slice(&[176, 177, 0xFF, 0x00, 0x00, 0xFF, 0x00, 0x00, 178, 179]);
+		raw.extend_from_slice(&[180, 181, 0x00, 0xFF, 0xAA, 0xBB]);
+		raw
+	}
+
+	#[test]
+	fn test_shared_offsets_roundtrip() {
+		let raw = shared_file();
+		let mut file = File::from_bytes(&raw).unwrap();
+		assert_eq!(file.to_bytes(), raw);
+
+		// Writing back unchanged frames keeps every byte
+		for index in 0..4 {
+			let frame = file.get_frame(index).unwrap();
+			assert!(!file.replace_frame(index, &frame).unwrap());
+		}
+		assert_eq!(file.to_bytes(), raw);
+
+		// A hotspot change only touches the descriptor
+		let mut frame = file.get_frame(1).unwrap();
+		frame.entry_mut().hotspot_x = 1;
+		assert!(file.replace_frame(1, &frame).unwrap());
+		assert_eq!(file.to_bytes().len(), raw.len());
+		assert_eq!(file.get_entry(1).unwrap().color_offset(), 0);
+	}
+
+	#[test]
+	fn test_replace_frame_relocates_shared_data() {
+		let raw = shared_file();
+		let mut file = File::from_bytes(&raw).unwrap();
+		let before: Vec<Frame> = file.iter().collect();
+
+		// Shared data is appended instead of overwritten
+		assert!(file.update_frame(1, &[190, 191, 192, 193], &[0x00; 4]));
+		let bytes = file.to_bytes();
+		assert_eq!(bytes.len(), raw.len() + 8);
+		assert_eq!(
+			&bytes[constants::HEADER_SIZE + 48..raw.len()],
+			&raw[constants::HEADER_SIZE + 48..]
+		);
+		assert_eq!(file.get_entry(1).unwrap().color_offset(), 16);
+		assert_eq!(file.get_frame(1).unwrap().sprite_pixels(), &[190, 191, 192, 193]);
+		assert_eq!(file.get_frame(0).unwrap(), before[0]);
+		assert_eq!(file.get_frame(2).unwrap(), before[2]);
+
+		// Exclusive data of the same size is rewritten in place
+		let entry = *file.get_entry(3).unwrap();
+		assert!(file.update_frame(3, &[200, 201], &[0xFF, 0x00]));
+		assert_eq!(file.to_bytes().len(), bytes.len());
+		assert_eq!(file.get_entry(3).unwrap(), &entry);
+
+		// Resized frames move to the end
+		let resized = Frame::new(FrameEntry::new(0, 0, 3, 1, 1, 0), vec![176; 3], vec![0; 3]);
+		assert!(file.update_complete_frame(3, &resized));
+		assert_eq!(file.get_entry(3).unwrap().color_offset(), 24);
+		assert_eq!(file.get_frame(3).unwrap().sprite_pixels(), resized.sprite_pixels());
+
+		let loaded = File::from_bytes(&file.to_bytes()).unwrap();
+		assert_eq!(loaded, file);
+		assert!(file.replace_frame(4, &resized).is_err());
+	}
+
+	#[test]
+	fn test_dsk_repack_after_shared_edit() {
+		use crate::file::dsk::{ArchiveBuilder, DskMemory};
+
+		let raw = shared_file();
+		let data_start = constants::HEADER_SIZE + 4 * constants::FRAME_DESCRIPTOR_SIZE;
+		let mut builder = ArchiveBuilder::new();
+		builder.add_entry("SHARED", raw.clone()).unwrap();
+		let (pft, dsk) = builder.to_bytes().unwrap();
+
+		// Edit a frame whose data is shared with frame 0 and overlapped by frame 2
+		let mut archive = DskMemory::from_bytes(dsk, pft.clone()).unwrap();
+		let mut file = File::from_bytes(&archive.extract(&pft.entries()[0]).unwrap()).unwrap();
+		let before: Vec<Frame> = file.iter().collect();
+		assert!(file.update_frame(1, &[190, 191, 192, 193], &[0x00; 4]));
+
+		let mut repacked = ArchiveBuilder::new();
+		repacked.add_entry("SHARED", file.to_bytes()).unwrap();
+		let (pft, dsk) = repacked.to_bytes().unwrap();
+		let mut archive = DskMemory::from_bytes(dsk, pft.clone()).unwrap();
+		let bytes = archive.extract(&pft.entries()[0]).unwrap();
+		let loaded = File::from_bytes(&bytes).unwrap();
+
+		// The original data area is kept byte for byte, the edit is appended
+		assert_eq!(&bytes[data_start..raw.len()], &raw[data_start..]);
+		for index in [0, 2, 3] {
+			assert_eq!(loaded.get_entry(index).unwrap(), before[index].entry());
+			assert_eq!(loaded.get_frame(index).unwrap(), before[index]);
+		}
+		assert_eq!(loaded.get_frame(1).unwrap().sprite_pixels(), &[190, 191, 192, 193]);
+		assert_eq!(loaded.get_frame(1).unwrap().mask_pixels(), &[0x00; 4]);
+	}
 }
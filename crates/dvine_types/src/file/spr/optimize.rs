@@ -0,0 +1,337 @@
+//! Size optimization for SPR files.
+//!
+//! Sprites are often drawn on a fixed canvas, leaving wide fully transparent
+//! margins, and animations repeat the same frame several times. [`Optimizer`]
+//! rebuilds an SPR file without that waste:
+//!
+//! - **Trimming** crops each frame to the bounding box of its non-transparent
+//!   mask pixels (mask values other than `0xFF`) and moves the hotspot by the
+//!   same amount, so the frame lands on the same screen pixels. Hotspots are
+//!   unsigned, so a frame is never cropped past its hotspot.
+//! - **Deduplication** writes every distinct color block and mask block to the
+//!   data area once. Frames with identical pixels point at the same offsets,
+//!   and a mask shared by differently colored frames is stored once too.
+//!
+//! The result is a regular [`File`]; [`File::to_bytes`] and [`File::save`]
+//! write the shared offsets as they are.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use dvine_types::file::spr::File;
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut spr = File::open("KATIA.SPR")?;
+//! let stats = spr.optimize()?;
+//! println!("{} -> {} bytes", stats.bytes_before, stats.bytes_after);
+//! spr.save("KATIA.SPR")?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+
+use crate::file::{DvFileError, FileType};
+
+use super::{File, Frame, FrameEntry, frame::MASK_TRANSPARENT};
+
+/// Summary of an optimization pass
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OptimizeStats {
+	/// Frames whose size was reduced by trimming
+	pub frames_trimmed: usize,
+
+	/// Frames sharing both color and mask data with an earlier frame
+	pub frames_deduplicated: usize,
+
+	/// File size before the pass
+	pub bytes_before: usize,
+
+	/// File size after the pass
+	pub bytes_after: usize,
+}
+
+/// Rebuilds SPR files with trimmed and deduplicated frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Optimizer {
+	/// Crop frames to their mask bounding box
+	trim: bool,
+
+	/// Share identical color and mask blocks
+	dedup: bool,
+}
+
+impl Default for Optimizer {
+	fn default() -> Self {
+		Self {
+			trim: true,
+			dedup: true,
+		}
+	}
+}
+
+impl Optimizer {
+	/// Creates an optimizer with trimming and deduplication enabled
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Enables or disables trimming transparent borders
+	pub fn with_trim(mut self, trim: bool) -> Self {
+		self.trim = trim;
+		self
+	}
+
+	/// Enables or disables sharing identical pixel data
+	pub fn with_dedup(mut self, dedup: bool) -> Self {
+		self.dedup = dedup;
+		self
+	}
+
+	/// Builds an optimized copy of `file`
+	///
+	/// Frame order, count and on-screen placement are kept, so frame indices
+	/// used by the game stay valid.
+	///
+	/// # Errors
+	///
+	/// Returns an error if a frame's pixel data lies outside the file.
+	pub fn optimize(&self, file: &File) -> Result<(File, OptimizeStats), DvFileError> {
+		let mut stats = OptimizeStats {
+			bytes_before: file.raw.len(),
+			..OptimizeStats::default()
+		};
+
+		let mut data = Vec::new();
+		let mut blocks: HashMap<Vec<u8>, u32> = HashMap::new();
+		let mut pairs: HashSet<(u32, u32)> = HashSet::new();
+		let mut entries = Vec::with_capacity(file.entries.len());
+
+		for (index, entry) in file.entries.iter().enumerate() {
+			let frame = file.get_frame(index).ok_or_else(|| {
+				DvFileError::insufficient_data(
+					FileType::Spr,
+					(file.data_area_start() + entry.color_offset.max(entry.mask_offset) as usize)
+						.saturating_add(entry.pixel_count()),
+					file.raw.len(),
+				)
+			})?;
+
+			let frame = if self.trim {
+				trim(&frame)
+			} else {
+				frame
+			};
+			if frame.entry().pixel_count() < entry.pixel_count() {
+				stats.frames_trimmed += 1;
+			}
+
+			let mut store = |pixels: &[u8]| -> u32 {
+				if self.dedup
+					&& let Some(&offset) = blocks.get(pixels)
+				{
+					return offset;
+				}
+				let offset = data.len() as u32;
+				data.extend_from_slice(pixels);
+				if self.dedup {
+					blocks.insert(pixels.to_vec(), offset);
+				}
+				offset
+			};
+			let color_offset = store(frame.sprite_pixels());
+			let mask_offset = store(frame.mask_pixels());
+
+			if !pairs.insert((color_offset, mask_offset)) {
+				stats.frames_deduplicated += 1;
+			}
+			entries.push(FrameEntry::new(
+				color_offset,
+				mask_offset,
+				frame.width(),
+				frame.height(),
+				frame.hotspot_x(),
+				frame.hotspot_y(),
+			));
+		}
+
+		let optimized = File::from_parts(entries, &data);
+		stats.bytes_after = optimized.raw.len();
+		Ok((optimized, stats))
+	}
+}
+
+/// Crops a frame to its non-transparent pixels without passing the hotspot
+///
+/// Fully transparent frames shrink to a single transparent pixel.
+fn trim(frame: &Frame) -> Frame {
+	let (width, height) = (frame.width() as usize, frame.height() as usize);
+	if width == 0 || height == 0 {
+		return frame.clone();
+	}
+
+	let mut bounds: Option<(usize, usize, usize, usize)> = None;
+	for (y, row) in frame.mask_pixels().chunks_exact(width).enumerate() {
+		for (x, _) in row.iter().enumerate().filter(|&(_, &mask)| mask != MASK_TRANSPARENT) {
+			bounds = Some(match bounds {
+				Some((left, top, right, bottom)) => {
+					(left.min(x), top.min(y), right.max(x + 1), bottom.max(y + 1))
+				}
+				None => (x, y, x + 1, y + 1),
+			});
+		}
+	}
+
+	// Fully transparent frames keep the pixel closest to the hotspot
+	let (left, top, right, bottom) = bounds.unwrap_or_else(|| {
+		let x = width.min(frame.hotspot_x() as usize + 1) - 1;
+		let y = height.min(frame.hotspot_y() as usize + 1) - 1;
+		(x, y, x + 1, y + 1)
+	});
+	let left = left.min(frame.hotspot_x() as usize);
+	let top = top.min(frame.hotspot_y() as usize);
+	let right = right.max(left + 1);
+	let bottom = bottom.max(top + 1);
+	if (left, top, right, bottom) == (0, 0, width, height) {
+		return frame.clone();
+	}
+
+	frame
+		.cropped(left as u32, top as u32, (right - left) as u32, (bottom - top) as u32)
+		.expect("trimmed region lies inside the frame, up to the hotspot")
+}
+
+impl File {
+	/// Trims and deduplicates all frames in place
+	///
+	/// See [`Optimizer`] for details and for enabling only one of the steps.
+	///
+	/// # Errors
+	///
+	/// Returns an error if a frame's pixel data lies outside the file; the
+	/// file is left unchanged then.
+	pub fn optimize(&mut self) -> Result<OptimizeStats, DvFileError> {
+		let (optimized, stats) = Optimizer::new().optimize(self)?;
+		*self = optimized;
+		Ok(stats)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Frame with an opaque `inner` rectangle on a transparent canvas
+	fn framed(
+		size: (u32, u32),
+		inner: (u32, u32, u32, u32),
+		hotspot: (u32, u32),
+		color: u8,
+	) -> Frame {
+		let (width, height) = size;
+		let (left, top, right, bottom) = inner;
+		let mut sprite = Vec::new();
+		let mut mask = Vec::new();
+		for y in 0..height {
+			for x in 0..width {
+				let opaque = (left..right).contains(&x) && (top..bottom).contains(&y);
+				sprite.push(if opaque {
+					color + (x - left + y - top) as u8
+				} else {
+					176
+				});
+				mask.push(if opaque {
+					0x00
+				} else {
+					MASK_TRANSPARENT
+				});
+			}
+		}
+		let entry = FrameEntry::new(0, 0, width, height, hotspot.0, hotspot.1);
+		Frame::new(entry, sprite, mask)
+	}
+
+	/// Screen pixels covered by the opaque part of a frame drawn at the origin
+	fn opaque_pixels(frame: &Frame) -> Vec<(i64, i64, u8)> {
+		let mut pixels = Vec::new();
+		for y in 0..frame.height() {
+			for x in 0..frame.width() {
+				if frame.get_mask_pixel(x, y) != Some(MASK_TRANSPARENT) {
+					pixels.push((
+						i64::from(x) - i64::from(frame.hotspot_x()),
+						i64::from(y) - i64::from(frame.hotspot_y()),
+						frame.get_sprite_pixel(x, y).unwrap(),
+					));
+				}
+			}
+		}
+		pixels
+	}
+
+	#[test]
+	fn test_optimize_trims_and_keeps_placement() {
+		let mut file = File::new();
+		file.add_frame(framed((32, 24), (10, 4, 20, 18), (16, 23), 180)).unwrap();
+		// Hotspot left of and above the opaque area limits the crop
+		file.add_frame(framed((16, 16), (8, 8, 12, 12), (2, 5), 190)).unwrap();
+		file.add_frame(framed((8, 8), (0, 0, 0, 0), (4, 8), 176)).unwrap();
+		let original: Vec<Frame> = file.iter().collect();
+
+		let (optimized, stats) = Optimizer::new().with_dedup(false).optimize(&file).unwrap();
+		assert_eq!(stats.frames_trimmed, 3);
+		assert_eq!(stats.frames_deduplicated, 0);
+		assert!(stats.bytes_after < stats.bytes_before);
+
+		let frames: Vec<Frame> = optimized.iter().collect();
+		assert_eq!((frames[0].width(), frames[0].height()), (10, 14));
+		assert_eq!((frames[0].hotspot_x(), frames[0].hotspot_y()), (6, 19));
+		assert_eq!((frames[1].width(), frames[1].height()), (10, 7));
+		assert_eq!((frames[1].hotspot_x(), frames[1].hotspot_y()), (0, 0));
+		assert_eq!((frames[2].width(), frames[2].height()), (1, 1));
+		assert_eq!((frames[2].hotspot_x(), frames[2].hotspot_y()), (0, 1));
+		assert_eq!(frames[2].mask_pixels(), &[MASK_TRANSPARENT]);
+		for (frame, original) in frames.iter().zip(&original) {
+			assert_eq!(opaque_pixels(frame), opaque_pixels(original));
+		}
+
+		// Already tight frames are left alone
+		let (again, stats) = Optimizer::new().optimize(&optimized).unwrap();
+		assert_eq!(stats.frames_trimmed, 0);
+		assert_eq!(again.to_bytes(), optimized.to_bytes());
+	}
+
+	#[test]
+	fn test_optimize_shares_identical_data() {
+		let mut file = File::new();
+		file.add_frame(framed((12, 12), (2, 2, 10, 10), (6, 11), 180)).unwrap();
+		file.add_frame(framed((16, 16), (4, 4, 12, 12), (8, 14), 180)).unwrap();
+		file.add_frame(framed((12, 12), (2, 2, 10, 10), (6, 11), 200)).unwrap();
+
+		let (optimized, stats) = Optimizer::new().optimize(&file).unwrap();
+		assert_eq!(stats.frames_deduplicated, 1);
+
+		// Same pixels after trimming share everything, a recolor only the mask
+		let entries = optimized.entries();
+		assert_eq!(entries[0].color_offset(), entries[1].color_offset());
+		assert_eq!(entries[0].mask_offset(), entries[1].mask_offset());
+		assert_ne!(entries[0].color_offset(), entries[2].color_offset());
+		assert_eq!(entries[0].mask_offset(), entries[2].mask_offset());
+		assert_ne!(
+			(entries[0].hotspot_x(), entries[0].hotspot_y()),
+			(entries[1].hotspot_x(), entries[1].hotspot_y())
+		);
+
+		// Serialization keeps the shared offsets instead of copying the data
+		let bytes = optimized.to_bytes();
+		assert_eq!(bytes.len(), 16 + 3 * 24 + 3 * 64);
+		let loaded = File::from_bytes(&bytes).unwrap();
+		assert_eq!(loaded.entries(), optimized.entries());
+		for (frame, original) in loaded.iter().zip(file.iter()) {
+			assert_eq!(opaque_pixels(&frame), opaque_pixels(&original));
+		}
+
+		let mut in_place = file.clone();
+		assert_eq!(in_place.optimize().unwrap(), stats);
+		assert_eq!(in_place, optimized);
+	}
+}
@@ -11,6 +11,9 @@ use crate::file::DvFileError;
 /// Number of colors in the SPR.PAL file
 pub const SPR_PAL_COLOR_COUNT: usize = 80;
 
+/// Index of the first SPR.PAL color in the 256-color screen palette
+pub const SPR_PAL_OFFSET: usize = 256 - SPR_PAL_COLOR_COUNT;
+
 /// Size of the SPR.PAL file in bytes (80 colors × 4 bytes)
 pub const SPR_PAL_FILE_SIZE: usize = SPR_PAL_COLOR_COUNT * 4;
 
@@ -116,6 +119,33 @@ impl Palette {
 		self.colors[offset + 3] = color.3;
 	}
 
+	/// Finds the palette color closest to an RGB color.
+	///
+	/// Distance is the squared Euclidean distance in RGB space; ties go to
+	/// the lower index.
+	///
+	/// # Returns
+	///
+	/// The palette index (0-79) of the closest color.
+	pub fn nearest_index(&self, r: u8, g: u8, b: u8) -> u8 {
+		let mut best_index = 0;
+		let mut best_distance = u32::MAX;
+
+		for (index, color) in self.colors.chunks_exact(4).enumerate() {
+			let dr = (i32::from(r) - i32::from(color[0])).unsigned_abs();
+			let dg = (i32::from(g) - i32::from(color[1])).unsigned_abs();
+			let db = (i32::from(b) - i32::from(color[2])).unsigned_abs();
+			let distance = dr * dr + dg * dg + db * db;
+
+			if distance < best_distance {
+				best_distance = distance;
+				best_index = index as u8;
+			}
+		}
+
+		best_index
+	}
+
 	/// Returns a reference to the color array.
 	#[inline]
 	pub fn colors(&self) -> &[u8; SPR_PAL_FILE_SIZE] {
@@ -184,3 +214,14 @@ impl std::ops::IndexMut<u8> for Palette {
 		&mut self.colors[offset..offset + 4]
 	}
 }
+
+/// Palette with a distinct color at every index and a non-zero fourth byte,
+/// shared by the SPR tests
+#[cfg(test)]
+pub(crate) fn test_palette() -> Palette {
+	let mut palette = Palette::new();
+	for index in 0..SPR_PAL_COLOR_COUNT as u8 {
+		palette.set(index, (index * 3, 255 - index * 2, index / 2, 0xAB));
+	}
+	palette
+}
@@ -8,6 +8,11 @@
 //! - **ITEM**: Item database files with encrypted data and checksum validation
 //! - **`StartupIni`**: Configuration file defining startup parameters for games
 //!
+//! # Features
+//!
+//! - `image`: conversions between the pixel formats and the `image` crate,
+//!   including indexed PNG, see the `file::image` module
+//!
 //! # Examples
 //!
 //! Using the prelude (recommended):
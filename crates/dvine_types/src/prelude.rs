@@ -19,6 +19,7 @@ pub use crate::file::{
 	DSK_BLOCK_SIZE,
 
 	// DSK types
+	DskArchiveBuilder,
 	DskFile,
 	DvFileError,
 
@@ -49,6 +50,7 @@ pub use crate::file::{
 	SprFile,
 	SprFrame,
 	SprFrameEntry,
+	SprFrameImporter,
 	SprFrameIterator,
 	SprFrameRowIterator,
 	SprPalette,
@@ -0,0 +1,46 @@
+//! Error types for the virtual file system.
+
+use dvine_types::file::DvFileError;
+use thiserror::Error;
+
+/// Errors that can occur while mounting or reading through a [`Vfs`](crate::Vfs)
+#[derive(Debug, Error)]
+pub enum VfsError {
+	/// No mount contains the requested logical path
+	#[error("VFS error: '{path}' not found in any mount")]
+	NotFound {
+		/// Logical path that was requested
+		path: String,
+	},
+
+	/// The logical path is empty or escapes the mount root
+	#[error("VFS error: Invalid logical path '{path}'")]
+	InvalidPath {
+		/// Logical path that was rejected
+		path: String,
+	},
+
+	/// The mount source is not usable (missing directory, unpaired PFT, ...)
+	#[error("VFS error: Invalid mount '{source_path}': {message}")]
+	InvalidMount {
+		/// Path of the mount source
+		source_path: String,
+		/// Error message
+		message: String,
+	},
+
+	/// Error raised by an underlying file format
+	#[error(transparent)]
+	File(#[from] DvFileError),
+
+	/// IO error
+	#[error(transparent)]
+	IOError(#[from] std::io::Error),
+}
+
+impl VfsError {
+	/// Returns true if this is a not found error
+	pub fn is_not_found(&self) -> bool {
+		matches!(self, Self::NotFound { .. })
+	}
+}
@@ -1 +1,37 @@
 //! Virtual File System (VFS) module for Dvine.
+//!
+//! The game stores most of its assets in PFT/DSK archive pairs (`KG.PFT` +
+//! `KG.DSK`, `SPR.PFT` + `SPR.DSK`, ...) next to a few loose files. This
+//! crate exposes all of them through a single namespace of logical paths:
+//!
+//! - `KG/TITLE` - entry `TITLE` of the `KG` archive
+//! - `SPR.PAL` - loose file in the game directory
+//!
+//! Mounts are layered: later mounts shadow earlier ones, so a directory of
+//! loose files mounted on top of the game directory overrides archive
+//! entries with the same logical path.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use dvine_vfs::Vfs;
+//!
+//! let mut vfs = Vfs::new();
+//! vfs.mount_game_dir("game").unwrap();
+//! vfs.mount_dir("KG", "extracted/kg").unwrap();
+//!
+//! for path in vfs.list() {
+//!     println!("{} -> {}", path, vfs.resolve(&path).unwrap());
+//! }
+//!
+//! let data = vfs.read("KG/TITLE").unwrap();
+//! ```
+
+mod error;
+mod mount;
+pub mod path;
+mod vfs;
+
+pub use error::VfsError;
+pub use mount::{ArchiveReader, Mount, MountKind, ReadSeek};
+pub use vfs::Vfs;
@@ -0,0 +1,427 @@
+//! Mount points of the virtual file system.
+//!
+//! A [`Mount`] maps a part of the logical namespace onto a data source:
+//!
+//! - **Archive**: a PFT/DSK pair opened through [`dsk::File`]. Every entry of
+//!   the archive is visible as `PREFIX/ENTRY`, e.g. `KG/TITLE` for the
+//!   `TITLE` entry of `KG.PFT`/`KG.DSK`.
+//! - **Directory**: a loose directory on disk. Files are indexed recursively
+//!   when the mount is created and visible as `PREFIX/RELATIVE/PATH`.
+
+use std::{
+	collections::BTreeMap,
+	fs,
+	io::{self, Read, Seek},
+	path::{Path, PathBuf},
+};
+
+use dvine_types::file::{dsk, manifest::ManifestEntry, pft};
+
+use crate::{VfsError, path};
+
+/// Helper trait for readers that can back an archive mount
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// Type-erased reader used by archive mounts
+pub type ArchiveReader = Box<dyn ReadSeek + Send>;
+
+/// Kind of data source behind a mount
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MountKind {
+	/// PFT/DSK archive pair
+	Archive,
+	/// Loose directory on disk
+	Directory,
+}
+
+impl std::fmt::Display for MountKind {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			MountKind::Archive => write!(f, "archive"),
+			MountKind::Directory => write!(f, "directory"),
+		}
+	}
+}
+
+/// Data source behind a mount
+enum Source {
+	/// Archive mount, upper-cased entry name to PFT entry
+	Archive {
+		dsk: dsk::File<ArchiveReader>,
+		entries: BTreeMap<String, pft::Entry>,
+	},
+
+	/// Directory mount, normalized relative path to file on disk
+	Directory {
+		root: PathBuf,
+		files: BTreeMap<String, PathBuf>,
+	},
+}
+
+/// A single mount point of the virtual file system
+pub struct Mount {
+	/// Normalized logical prefix, empty for the root
+	prefix: String,
+
+	/// Data source
+	source: Source,
+}
+
+impl Mount {
+	/// Creates an archive mount from an opened DSK file
+	///
+	/// # Arguments
+	///
+	/// * `prefix` - Logical prefix of the archive entries, e.g. `KG`
+	/// * `dsk` - DSK file with its associated PFT metadata
+	///
+	/// # Errors
+	///
+	/// Returns an error if the prefix contains `..`
+	///
+	/// # Examples
+	///
+	/// ```
+	/// use dvine_types::file::{dsk, pft};
+	/// use dvine_vfs::Mount;
+	///
+	/// let pft = pft::File::new(vec![pft::Entry::new("TITLE", 0, 4)]);
+	/// let dsk = dsk::File::from_bytes(vec![0u8; 2048], pft).unwrap();
+	/// let mount = Mount::archive("KG", dsk).unwrap();
+	/// assert!(mount.contains("KG/TITLE"));
+	/// ```
+	pub fn archive<R: Read + Seek + Send + 'static>(
+		prefix: &str,
+		dsk: dsk::File<R>,
+	) -> Result<Self, VfsError> {
+		let name = dsk.name().map(str::to_owned);
+		let pft = dsk.pft().clone();
+		let reader: ArchiveReader = Box::new(dsk.into_inner());
+
+		// The first entry wins if names differ only in case
+		let mut entries = BTreeMap::new();
+		for entry in pft.entries() {
+			entries.entry(entry.name().to_ascii_uppercase()).or_insert(*entry);
+		}
+
+		let dsk = match name {
+			Some(name) => dsk::File::with_name(reader, pft, name),
+			None => dsk::File::new(reader, pft),
+		};
+
+		Ok(Self {
+			prefix: normalize_prefix(prefix)?,
+			source: Source::Archive {
+				dsk,
+				entries,
+			},
+		})
+	}
+
+	/// Opens a PFT/DSK pair from disk and creates an archive mount
+	///
+	/// # Arguments
+	///
+	/// * `prefix` - Logical prefix of the archive entries
+	/// * `pft_path` - Path to the PFT file
+	/// * `dsk_path` - Path to the DSK file
+	///
+	/// # Errors
+	///
+	/// Returns an error if either file cannot be opened or parsed
+	pub fn archive_from_paths(
+		prefix: &str,
+		pft_path: impl AsRef<Path>,
+		dsk_path: impl AsRef<Path>,
+	) -> Result<Self, VfsError> {
+		let pft = pft::File::open(pft_path)?;
+		let dsk = dsk::File::open_with_pft(dsk_path, pft)?;
+		Self::archive(prefix, dsk)
+	}
+
+	/// Creates a directory mount, indexing all files below `root`
+	///
+	/// # Arguments
+	///
+	/// * `prefix` - Logical prefix of the files, empty to mount at the root
+	/// * `root` - Directory on disk
+	///
+	/// # Errors
+	///
+	/// Returns an error if `root` is not a directory or cannot be read
+	pub fn directory(prefix: &str, root: impl AsRef<Path>) -> Result<Self, VfsError> {
+		let root = root.as_ref();
+		if !root.is_dir() {
+			return Err(VfsError::InvalidMount {
+				source_path: root.display().to_string(),
+				message: "not a directory".to_string(),
+			});
+		}
+
+		let mut files = BTreeMap::new();
+		index_directory(root, "", &mut files)?;
+
+		Ok(Self {
+			prefix: normalize_prefix(prefix)?,
+			source: Source::Directory {
+				root: root.to_path_buf(),
+				files,
+			},
+		})
+	}
+
+	/// Returns the normalized logical prefix of this mount
+	pub fn prefix(&self) -> &str {
+		&self.prefix
+	}
+
+	/// Returns the kind of this mount
+	pub fn kind(&self) -> MountKind {
+		match self.source {
+			Source::Archive {
+				..
+			} => MountKind::Archive,
+			Source::Directory {
+				..
+			} => MountKind::Directory,
+		}
+	}
+
+	/// Returns a human-readable description of the mount source
+	pub fn source_name(&self) -> String {
+		match &self.source {
+			Source::Archive {
+				dsk,
+				..
+			} => dsk.name().unwrap_or("<memory>").to_string(),
+			Source::Directory {
+				root,
+				..
+			} => root.display().to_string(),
+		}
+	}
+
+	/// Returns the underlying DSK file for archive mounts
+	pub fn dsk(&self) -> Option<&dsk::File<ArchiveReader>> {
+		match &self.source {
+			Source::Archive {
+				dsk,
+				..
+			} => Some(dsk),
+			Source::Directory {
+				..
+			} => None,
+		}
+	}
+
+	/// Returns the underlying DSK file for archive mounts (mutable)
+	pub fn dsk_mut(&mut self) -> Option<&mut dsk::File<ArchiveReader>> {
+		match &mut self.source {
+			Source::Archive {
+				dsk,
+				..
+			} => Some(dsk),
+			Source::Directory {
+				..
+			} => None,
+		}
+	}
+
+	/// Removes a file from a directory mount's index
+	///
+	/// Used to hide archive containers that are mounted separately.
+	pub(crate) fn hide_disk_file(&mut self, disk_path: &Path) {
+		if let Source::Directory {
+			files,
+			..
+		} = &mut self.source
+		{
+			files.retain(|_, path| path != disk_path);
+		}
+	}
+
+	/// Checks whether this mount provides the given logical path
+	///
+	/// `path` may be given in any case and with either separator.
+	pub fn contains(&self, path: &str) -> bool {
+		path::normalize(path).is_ok_and(|path| self.locate(&path).is_some())
+	}
+
+	/// Returns the on-disk location of a logical path for directory mounts
+	pub fn disk_path(&self, path: &str) -> Option<&Path> {
+		let path = path::normalize(path).ok()?;
+		match self.locate(&path)? {
+			Location::Disk(path) => Some(path),
+			Location::Entry(_) => None,
+		}
+	}
+
+	/// Returns the archive entry of a logical path for archive mounts
+	pub fn entry(&self, path: &str) -> Option<pft::Entry> {
+		let path = path::normalize(path).ok()?;
+		match self.locate(&path)? {
+			Location::Entry(entry) => Some(entry),
+			Location::Disk(_) => None,
+		}
+	}
+
+	/// Reads the data of a logical path from this mount
+	///
+	/// # Errors
+	///
+	/// Returns [`VfsError::NotFound`] if the mount does not provide the path,
+	/// or an error if reading the underlying data fails
+	pub fn read(&mut self, path: &str) -> Result<Vec<u8>, VfsError> {
+		let normalized = path::normalize(path)?;
+		match self.locate(&normalized) {
+			Some(Location::Entry(entry)) => {
+				let Source::Archive {
+					dsk,
+					..
+				} = &mut self.source
+				else {
+					unreachable!("entries are only located in archive mounts");
+				};
+				Ok(dsk.extract(&entry)?)
+			}
+			Some(Location::Disk(file)) => Ok(fs::read(file)?),
+			None => Err(VfsError::NotFound {
+				path: normalized,
+			}),
+		}
+	}
+
+	/// Hashes the data of a logical path from this mount without loading it
+	///
+	/// Archive entries are streamed in the size their PFT entry gives, loose
+	/// files straight from disk.
+	///
+	/// # Errors
+	///
+	/// Returns [`VfsError::NotFound`] if the mount does not provide the path,
+	/// or an error if reading the underlying data fails
+	pub fn hash(&mut self, path: &str) -> Result<ManifestEntry, VfsError> {
+		let normalized = path::normalize(path)?;
+		match self.locate(&normalized) {
+			Some(Location::Entry(entry)) => {
+				let Source::Archive {
+					dsk,
+					..
+				} = &mut self.source
+				else {
+					unreachable!("entries are only located in archive mounts");
+				};
+				Ok(ManifestEntry::from_reader(&mut dsk.open_entry(&entry)?)?)
+			}
+			Some(Location::Disk(file)) => {
+				Ok(ManifestEntry::from_reader(&mut io::BufReader::new(fs::File::open(file)?))?)
+			}
+			None => Err(VfsError::NotFound {
+				path: normalized,
+			}),
+		}
+	}
+
+	/// Returns all logical paths provided by this mount, sorted
+	pub fn paths(&self) -> Vec<String> {
+		let mut paths: Vec<String> = match &self.source {
+			Source::Archive {
+				entries,
+				..
+			} => entries.keys().map(|name| path::join(&self.prefix, name)).collect(),
+			Source::Directory {
+				files,
+				..
+			} => files.keys().map(|relative| path::join(&self.prefix, relative)).collect(),
+		};
+		paths.sort();
+		paths.dedup();
+		paths
+	}
+
+	/// Looks up a normalized logical path
+	fn locate(&self, path: &str) -> Option<Location<'_>> {
+		let relative = path::strip_prefix(&self.prefix, path)?;
+
+		match &self.source {
+			Source::Archive {
+				entries,
+				..
+			} => {
+				// Archive entries are flat, nested paths can't match
+				if relative.contains(path::SEPARATOR) {
+					return None;
+				}
+				entries.get(relative).copied().map(Location::Entry)
+			}
+			Source::Directory {
+				files,
+				..
+			} => files.get(relative).map(|file| Location::Disk(file.as_path())),
+		}
+	}
+}
+
+impl std::fmt::Display for Mount {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let prefix = if self.prefix.is_empty() {
+			"/"
+		} else {
+			&self.prefix
+		};
+		write!(f, "{} -> {} ({})", prefix, self.source_name(), self.kind())
+	}
+}
+
+impl std::fmt::Debug for Mount {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("Mount")
+			.field("prefix", &self.prefix)
+			.field("kind", &self.kind())
+			.field("source", &self.source_name())
+			.finish()
+	}
+}
+
+/// Result of looking up a logical path inside a mount
+enum Location<'a> {
+	/// Entry of an archive mount
+	Entry(pft::Entry),
+	/// File of a directory mount
+	Disk(&'a Path),
+}
+
+/// Normalizes a mount prefix, an empty prefix denotes the root
+fn normalize_prefix(prefix: &str) -> Result<String, VfsError> {
+	if prefix.split(['/', '\\']).all(|c| c.is_empty() || c == ".") {
+		return Ok(String::new());
+	}
+	path::normalize(prefix)
+}
+
+/// Recursively collects files below `dir` into `files`
+fn index_directory(
+	dir: &Path,
+	relative: &str,
+	files: &mut BTreeMap<String, PathBuf>,
+) -> Result<(), VfsError> {
+	let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+	// Sort so that the winner of case-insensitive collisions is deterministic
+	entries.sort_by_key(fs::DirEntry::file_name);
+
+	for entry in entries {
+		let name = entry.file_name().to_string_lossy().to_ascii_uppercase();
+		let logical = path::join(relative, &name);
+		let file_type = entry.file_type()?;
+
+		if file_type.is_dir() {
+			index_directory(&entry.path(), &logical, files)?;
+		} else if file_type.is_file() {
+			files.entry(logical).or_insert_with(|| entry.path());
+		}
+	}
+
+	Ok(())
+}
@@ -0,0 +1,99 @@
+//! Logical path handling.
+//!
+//! Logical paths use `/` as separator (`\` is accepted as well) and are
+//! matched case-insensitively, mirroring how the game addresses its data
+//! on a Windows file system. Internally every path is stored upper-cased
+//! with empty and `.` components removed.
+
+use crate::VfsError;
+
+/// Separator used between components of a normalized logical path
+pub const SEPARATOR: char = '/';
+
+/// Normalizes a logical path
+///
+/// # Arguments
+///
+/// * `path` - Logical path such as `kg/title` or `KG\TITLE`
+///
+/// # Errors
+///
+/// Returns [`VfsError::InvalidPath`] if the path is empty or contains `..`
+///
+/// # Examples
+///
+/// ```
+/// use dvine_vfs::path::normalize;
+///
+/// assert_eq!(normalize("kg\\title").unwrap(), "KG/TITLE");
+/// assert_eq!(normalize("/./SPR.PAL").unwrap(), "SPR.PAL");
+/// assert!(normalize("../KG").is_err());
+/// ```
+pub fn normalize(path: &str) -> Result<String, VfsError> {
+	let mut components = Vec::new();
+
+	for component in path.split(['/', '\\']) {
+		match component {
+			"" | "." => {}
+			".." => {
+				return Err(VfsError::InvalidPath {
+					path: path.to_string(),
+				});
+			}
+			_ => components.push(component.to_ascii_uppercase()),
+		}
+	}
+
+	if components.is_empty() {
+		return Err(VfsError::InvalidPath {
+			path: path.to_string(),
+		});
+	}
+
+	Ok(components.join("/"))
+}
+
+/// Joins a normalized mount prefix and a normalized relative path
+pub(crate) fn join(prefix: &str, path: &str) -> String {
+	if prefix.is_empty() {
+		path.to_string()
+	} else {
+		format!("{}{}{}", prefix, SEPARATOR, path)
+	}
+}
+
+/// Strips a normalized mount prefix from a normalized logical path
+///
+/// Returns `None` if the path does not live under the prefix.
+pub(crate) fn strip_prefix<'a>(prefix: &str, path: &'a str) -> Option<&'a str> {
+	if prefix.is_empty() {
+		return Some(path);
+	}
+
+	path.strip_prefix(prefix)?.strip_prefix(SEPARATOR)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_normalize() {
+		assert_eq!(normalize("KG/TITLE").unwrap(), "KG/TITLE");
+		assert_eq!(normalize("kg//title/").unwrap(), "KG/TITLE");
+		assert_eq!(normalize("Sound\\Dvine.efc").unwrap(), "SOUND/DVINE.EFC");
+		assert!(normalize("").is_err());
+		assert!(normalize("/./").is_err());
+		assert!(normalize("KG/../SPR").is_err());
+	}
+
+	#[test]
+	fn test_strip_prefix() {
+		assert_eq!(strip_prefix("KG", "KG/TITLE"), Some("TITLE"));
+		assert_eq!(strip_prefix("", "KG/TITLE"), Some("KG/TITLE"));
+		assert_eq!(strip_prefix("KG", "KGX/TITLE"), None);
+		assert_eq!(strip_prefix("KG", "KG"), None);
+		assert_eq!(join("KG", "TITLE"), "KG/TITLE");
+		assert_eq!(join("", "SPR.PAL"), "SPR.PAL");
+	}
+}
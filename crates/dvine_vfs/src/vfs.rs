@@ -0,0 +1,392 @@
+//! Layered virtual file system.
+
+use std::{
+	collections::BTreeSet,
+	fs,
+	io::{Read, Seek},
+	path::Path,
+};
+
+use dvine_types::file::{FileType, dsk, manifest::Manifest};
+
+use crate::{Mount, VfsError, path};
+
+/// Layered virtual file system
+///
+/// Mounts are stacked in the order they are added. When resolving a logical
+/// path, the most recently added mount that provides the path wins, so later
+/// mounts shadow earlier ones. This allows loose directories (mods, patches,
+/// extracted assets) to override archive entries without repacking.
+///
+/// # Examples
+///
+/// ```no_run
+/// use dvine_vfs::Vfs;
+///
+/// let mut vfs = Vfs::new();
+/// vfs.mount_game_dir("game").unwrap();
+/// vfs.mount_dir("", "mods").unwrap();
+///
+/// let title = vfs.read("KG/TITLE").unwrap();
+/// let palette = vfs.read("SPR.PAL").unwrap();
+/// ```
+#[derive(Debug, Default)]
+pub struct Vfs {
+	/// Mounts, in mount order (last has highest priority)
+	mounts: Vec<Mount>,
+}
+
+impl Vfs {
+	/// Creates an empty virtual file system
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Adds a mount on top of the existing ones
+	pub fn mount(&mut self, mount: Mount) -> &mut Self {
+		self.mounts.push(mount);
+		self
+	}
+
+	/// Mounts a DSK file (with its PFT metadata) under the given prefix
+	///
+	/// # Errors
+	///
+	/// Returns an error if the prefix is invalid
+	pub fn mount_archive<R: Read + Seek + Send + 'static>(
+		&mut self,
+		prefix: &str,
+		dsk: dsk::File<R>,
+	) -> Result<&mut Self, VfsError> {
+		Ok(self.mount(Mount::archive(prefix, dsk)?))
+	}
+
+	/// Mounts a loose directory under the given prefix
+	///
+	/// # Errors
+	///
+	/// Returns an error if `dir` is not a readable directory
+	pub fn mount_dir(
+		&mut self,
+		prefix: &str,
+		dir: impl AsRef<Path>,
+	) -> Result<&mut Self, VfsError> {
+		Ok(self.mount(Mount::directory(prefix, dir)?))
+	}
+
+	/// Mounts a game installation directory
+	///
+	/// The directory itself is mounted first as a loose root (so files like
+	/// `SPR.PAL` or `Dvine.EFC` are reachable), then every `*.PFT`/`*.DSK`
+	/// pair found in it is mounted with its base name as prefix, in
+	/// alphabetical order. File names are matched case-insensitively and PFT
+	/// files without a matching DSK file are ignored. The container files of
+	/// mounted pairs are hidden from the root, only their entries are listed.
+	///
+	/// # Arguments
+	///
+	/// * `dir` - Game directory
+	///
+	/// # Returns
+	///
+	/// The number of archives mounted
+	///
+	/// # Errors
+	///
+	/// Returns an error if the directory cannot be read or an archive
+	/// pair fails to open
+	pub fn mount_game_dir(&mut self, dir: impl AsRef<Path>) -> Result<usize, VfsError> {
+		let dir = dir.as_ref();
+		let mut root = Mount::directory("", dir)?;
+
+		let mut files = Vec::new();
+		for entry in fs::read_dir(dir)? {
+			let entry = entry?;
+			if entry.file_type()?.is_file() {
+				files.push(entry.path());
+			}
+		}
+		files.sort();
+
+		let mut archives = Vec::new();
+		for pft_path in &files {
+			let Some(base) = archive_base_name(pft_path, "PFT") else {
+				continue;
+			};
+			let dsk_path =
+				files.iter().find(|path| archive_base_name(path, "DSK").as_deref() == Some(&base));
+			if let Some(dsk_path) = dsk_path {
+				archives.push(Mount::archive_from_paths(&base, pft_path, dsk_path)?);
+				root.hide_disk_file(pft_path);
+				root.hide_disk_file(dsk_path);
+			}
+		}
+
+		let count = archives.len();
+		self.mount(root);
+		self.mounts.extend(archives);
+		Ok(count)
+	}
+
+	/// Removes and returns the topmost mount
+	pub fn unmount(&mut self) -> Option<Mount> {
+		self.mounts.pop()
+	}
+
+	/// Returns all mounts in mount order
+	pub fn mounts(&self) -> &[Mount] {
+		&self.mounts
+	}
+
+	/// Returns the number of mounts
+	pub fn num_mounts(&self) -> usize {
+		self.mounts.len()
+	}
+
+	/// Checks whether any mount provides the given logical path
+	pub fn exists(&self, path: &str) -> bool {
+		self.resolve(path).is_ok()
+	}
+
+	/// Returns the mount that a logical path resolves to
+	///
+	/// # Errors
+	///
+	/// Returns an error if the path is invalid or not provided by any mount
+	pub fn resolve(&self, path: &str) -> Result<&Mount, VfsError> {
+		let index = self.resolve_index(path)?;
+		Ok(&self.mounts[index])
+	}
+
+	/// Reads the data of a logical path from the topmost mount providing it
+	///
+	/// # Arguments
+	///
+	/// * `path` - Logical path such as `KG/TITLE`
+	///
+	/// # Errors
+	///
+	/// Returns an error if the path is invalid, not found, or reading fails
+	pub fn read(&mut self, path: &str) -> Result<Vec<u8>, VfsError> {
+		let index = self.resolve_index(path)?;
+		self.mounts[index].read(path)
+	}
+
+	/// Guesses the file type of a logical path from its contents
+	///
+	/// See [`FileType::detect`] for the returned confidence score.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the path is invalid, not found, or reading fails
+	pub fn detect(&mut self, path: &str) -> Result<Option<(FileType, f32)>, VfsError> {
+		Ok(FileType::detect(&self.read(path)?))
+	}
+
+	/// Returns every logical path visible through the VFS, sorted and
+	/// without duplicates
+	pub fn list(&self) -> Vec<String> {
+		let paths: BTreeSet<String> = self.mounts.iter().flat_map(Mount::paths).collect();
+		paths.into_iter().collect()
+	}
+
+	/// Builds a content hash manifest of every visible logical path
+	///
+	/// Shadowed entries are not included: each path is hashed from the mount
+	/// it resolves to, so the manifest describes what the game would load.
+	/// Data is streamed through [`Mount::hash`], never loaded as a whole.
+	///
+	/// # Errors
+	///
+	/// Returns an error if reading any path fails
+	pub fn manifest(&mut self) -> Result<Manifest, VfsError> {
+		let mut manifest = Manifest::new();
+		for path in self.list() {
+			let index = self.resolve_index(&path)?;
+			let entry = self.mounts[index].hash(&path)?;
+			manifest.insert_entry(&path, entry);
+		}
+		Ok(manifest)
+	}
+
+	/// Returns the index of the topmost mount providing `path`
+	fn resolve_index(&self, path: &str) -> Result<usize, VfsError> {
+		let normalized = path::normalize(path)?;
+		self.mounts.iter().rposition(|mount| mount.contains(&normalized)).ok_or(
+			VfsError::NotFound {
+				path: normalized,
+			},
+		)
+	}
+}
+
+impl std::fmt::Display for Vfs {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		writeln!(f, "VFS with {} mounts:", self.mounts.len())?;
+		for (index, mount) in self.mounts.iter().enumerate() {
+			writeln!(f, "  [{}] {}", index, mount)?;
+		}
+		Ok(())
+	}
+}
+
+/// Returns the upper-cased base name if `path` has the given extension
+fn archive_base_name(path: &Path, extension: &str) -> Option<String> {
+	let ext = path.extension()?.to_str()?;
+	if !ext.eq_ignore_ascii_case(extension) {
+		return None;
+	}
+	Some(path.file_stem()?.to_str()?.to_ascii_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::PathBuf;
+
+	use dvine_types::file::{dsk::DskMemory, manifest::ManifestEntry, pft};
+
+	use super::*;
+
+	fn temp_dir(name: &str) -> PathBuf {
+		let dir = std::env::temp_dir().join(format!("dvine_vfs_{}_{}", name, std::process::id()));
+		let _ = fs::remove_dir_all(&dir);
+		fs::create_dir_all(&dir).unwrap();
+		dir
+	}
+
+	#[test]
+	fn test_resolve_archive_entry() {
+		let mut builder = dsk::ArchiveBuilder::new();
+		builder.add_entry("TITLE", b"title").unwrap();
+		builder.add_entry("BLACK", b"black").unwrap();
+		let (pft, dsk) = builder.to_bytes().unwrap();
+
+		let mut vfs = Vfs::new();
+		vfs.mount_archive("KG", DskMemory::from_bytes(dsk, pft).unwrap()).unwrap();
+
+		assert!(vfs.exists("KG/TITLE"));
+		assert!(vfs.exists("kg\\black"));
+		assert!(!vfs.exists("KG/MISSING"));
+		assert!(!vfs.exists("SPR/TITLE"));
+		assert_eq!(vfs.read("kg/title").unwrap(), b"title");
+		assert!(vfs.read("KG/MISSING").unwrap_err().is_not_found());
+		assert_eq!(vfs.list(), vec!["KG/BLACK".to_string(), "KG/TITLE".to_string()]);
+	}
+
+	#[test]
+	fn test_later_mounts_shadow_earlier() {
+		let mut base = dsk::ArchiveBuilder::new();
+		base.add_entry("TITLE", b"old").unwrap();
+		base.add_entry("BLACK", b"black").unwrap();
+		let (base_pft, base_dsk) = base.to_bytes().unwrap();
+		let mut update = dsk::ArchiveBuilder::new();
+		update.add_entry("TITLE", b"new").unwrap();
+		let (update_pft, update_dsk) = update.to_bytes().unwrap();
+
+		let mut vfs = Vfs::new();
+		vfs.mount_archive("KG", DskMemory::from_bytes(base_dsk, base_pft).unwrap()).unwrap();
+		vfs.mount_archive("KG", DskMemory::from_bytes(update_dsk, update_pft).unwrap()).unwrap();
+
+		assert_eq!(vfs.read("KG/TITLE").unwrap(), b"new");
+		assert_eq!(vfs.read("KG/BLACK").unwrap(), b"black");
+		assert_eq!(vfs.list().len(), 2);
+
+		vfs.unmount();
+		assert_eq!(vfs.read("KG/TITLE").unwrap(), b"old");
+	}
+
+	#[test]
+	fn test_directory_mount_shadows_archive() {
+		let dir = temp_dir("shadow");
+		fs::create_dir_all(dir.join("kg")).unwrap();
+		fs::write(dir.join("kg").join("Title"), b"loose").unwrap();
+		fs::write(dir.join("SPR.PAL"), b"palette").unwrap();
+
+		let mut builder = dsk::ArchiveBuilder::new();
+		builder.add_entry("TITLE", b"packed").unwrap();
+		let (pft, dsk) = builder.to_bytes().unwrap();
+
+		let mut vfs = Vfs::new();
+		vfs.mount_archive("KG", DskMemory::from_bytes(dsk, pft).unwrap()).unwrap();
+		vfs.mount_dir("", &dir).unwrap();
+
+		assert_eq!(vfs.read("KG/TITLE").unwrap(), b"loose");
+		assert_eq!(vfs.read("spr.pal").unwrap(), b"palette");
+		assert_eq!(vfs.resolve("KG/TITLE").unwrap().kind(), crate::MountKind::Directory);
+
+		let manifest = vfs.manifest().unwrap();
+		assert_eq!(manifest.get("KG/TITLE"), Some(&ManifestEntry::from_bytes(b"loose")));
+
+		fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn test_manifest_uses_visible_entries() {
+		let mut base = dsk::ArchiveBuilder::new();
+		base.add_entry("TITLE", b"old").unwrap();
+		base.add_entry("BLACK", b"black").unwrap();
+		let (base_pft, base_dsk) = base.to_bytes().unwrap();
+		let mut update = dsk::ArchiveBuilder::new();
+		update.add_entry("TITLE", b"new").unwrap();
+		let (update_pft, update_dsk) = update.to_bytes().unwrap();
+
+		let mut vfs = Vfs::new();
+		vfs.mount_archive("KG", DskMemory::from_bytes(base_dsk, base_pft).unwrap()).unwrap();
+		vfs.mount_archive("KG", DskMemory::from_bytes(update_dsk, update_pft).unwrap()).unwrap();
+
+		let manifest = vfs.manifest().unwrap();
+		assert_eq!(manifest.len(), 2);
+		assert_eq!(manifest.get("KG/TITLE"), Some(&ManifestEntry::from_bytes(b"new")));
+	}
+
+	#[test]
+	fn test_detect_entry_type() {
+		let mut kg = b"KG".to_vec();
+		kg.resize(64, 0);
+
+		let mut builder = dsk::ArchiveBuilder::new();
+		builder.add_entry("TITLE", kg).unwrap();
+		builder.add_entry("TEXT", b"text").unwrap();
+		let (pft, dsk) = builder.to_bytes().unwrap();
+
+		let mut vfs = Vfs::new();
+		vfs.mount_archive("KG", DskMemory::from_bytes(dsk, pft).unwrap()).unwrap();
+
+		assert_eq!(vfs.detect("KG/TITLE").unwrap().map(|(t, _)| t), Some(FileType::Kg));
+		assert_eq!(vfs.detect("KG/TEXT").unwrap(), None);
+		assert!(vfs.detect("KG/MISSING").unwrap_err().is_not_found());
+	}
+
+	#[test]
+	fn test_mount_game_dir() {
+		let dir = temp_dir("game");
+		let mut builder = dsk::ArchiveBuilder::new();
+		builder.add_entry("TITLE", b"title").unwrap();
+		let (kg_pft, kg_dsk) = builder.to_bytes().unwrap();
+		fs::write(dir.join("KG.PFT"), kg_pft.to_bytes()).unwrap();
+		fs::write(dir.join("kg.dsk"), kg_dsk).unwrap();
+		fs::write(dir.join("ORPHAN.PFT"), pft::File::empty().to_bytes()).unwrap();
+		fs::write(dir.join("SPR.PAL"), [0u8; 4]).unwrap();
+
+		let mut vfs = Vfs::new();
+		assert_eq!(vfs.mount_game_dir(&dir).unwrap(), 1);
+		assert_eq!(vfs.num_mounts(), 2);
+		assert_eq!(vfs.read("KG/TITLE").unwrap(), b"title");
+		assert_eq!(vfs.read("SPR.PAL").unwrap(), [0u8; 4]);
+		assert_eq!(vfs.resolve("KG/TITLE").unwrap().kind(), crate::MountKind::Archive);
+		assert!(!vfs.exists("KG.PFT"));
+		assert!(!vfs.exists("KG.DSK"));
+		assert!(vfs.exists("ORPHAN.PFT"));
+		assert!(!vfs.list().iter().any(|path| path.starts_with("KG.")));
+
+		fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn test_invalid_paths() {
+		let mut vfs = Vfs::new();
+		assert!(matches!(vfs.read(""), Err(VfsError::InvalidPath { .. })));
+		assert!(matches!(vfs.read("../KG/TITLE"), Err(VfsError::InvalidPath { .. })));
+		assert!(Vfs::new().mount_dir("", "/nonexistent/dvine_vfs").is_err());
+	}
+}
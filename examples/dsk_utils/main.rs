@@ -5,10 +5,15 @@
 //! # Features
 //!
 //! - **info**: Display DSK/PFT information and statistics
-//! - **list**: List all files in the container with detailed information
+//! - **list**: List all files in the container with detailed information and detected type
 //! - **extract**: Extract files from DSK container (single, multiple, or all)
 //! - **pack**: Create DSK/PFT from a directory or file list
 //! - **verify**: Validate DSK/PFT integrity and consistency
+//! - **compact**: Rewrite DSK/PFT without orphaned blocks
+//! - **manifest**: Hash an archive or installation, or check it against a reference
+//! - **diff**: Create a patch file from the differences between two archives
+//! - **apply**: Apply a patch file to an archive
+//! - **thumbs**: Regenerate THUMB.DSK thumbnails from the CGs in KG.DSK
 //!
 //! # File Format
 //!
@@ -35,12 +40,30 @@
 //!
 //! # Verify integrity
 //! cargo run --example dsk_utils -- verify bin/DATA
+//!
+//! # Drop unreferenced blocks left over from in-place patching
+//! cargo run --example dsk_utils -- compact bin/DATA.DSK -o out/DATA
+//!
+//! # Record MD5 hashes of a whole installation, then check another one against it
+//! cargo run --example dsk_utils -- manifest game/ -o reference.json
+//! cargo run --example dsk_utils -- manifest other_game/ --check reference.json
+//!
+//! # Ship only the entries a translation changed, then apply them on the user's side
+//! cargo run --example dsk_utils -- diff original/KG.DSK translated/KG.DSK -o KG.DVP
+//! cargo run --example dsk_utils -- apply game/KG.DSK KG.DVP
+//!
+//! # Rebuild stale thumbnails after replacing CGs, adding one for a new CG
+//! cargo run --example dsk_utils -- thumbs game/KG.DSK game/THUMB.DSK -o out/THUMB --add CG99
 //! ```
 
 use clap::{Parser, Subcommand, ValueEnum};
-use dvine_rs::prelude::file::{dsk, pft};
+use dvine_rs::prelude::Vfs;
+use dvine_rs::prelude::file::dsk::thumb::{ThumbnailBuilder, ThumbnailStatus};
+use dvine_rs::prelude::file::kg::{CompressionLevel, resample::Filter};
+use dvine_rs::prelude::file::{DETECT_PREFIX_LEN, FileType, dsk, manifest::Manifest, pft};
 use serde::{Deserialize, Serialize};
 use std::fs;
+use std::io::Read;
 use std::path::{Path, PathBuf};
 
 #[derive(Parser)]
@@ -173,6 +196,144 @@ enum Commands {
 		#[arg(short, long)]
 		verbose: bool,
 	},
+
+	/// Hash entries into an MD5 manifest, or check them against a reference
+	Manifest {
+		/// Game directory, or a DSK/PFT pair (directory with --name, or .DSK file)
+		#[arg(value_name = "INPUT")]
+		input: PathBuf,
+
+		/// Base name (e.g., "DATA" for DATA.DSK/DATA.PFT)
+		#[arg(short, long, value_name = "NAME")]
+		name: Option<String>,
+
+		/// Write the manifest as JSON to this file
+		#[arg(short, long, value_name = "OUTPUT")]
+		output: Option<PathBuf>,
+
+		/// Reference manifest JSON to verify against
+		#[arg(short, long, value_name = "REFERENCE")]
+		check: Option<PathBuf>,
+	},
+
+	/// Rewrite DSK/PFT without orphaned blocks
+	Compact {
+		/// Input path (directory with NAME.DSK/NAME.PFT or direct .DSK/.PFT file)
+		#[arg(value_name = "INPUT")]
+		input: PathBuf,
+
+		/// Base name (e.g., "DATA" for DATA.DSK/DATA.PFT)
+		#[arg(short, long, value_name = "NAME")]
+		name: Option<String>,
+
+		/// Output base path (defaults to overwriting the input)
+		#[arg(short, long, value_name = "OUTPUT")]
+		output: Option<PathBuf>,
+
+		/// Show verbose output
+		#[arg(short, long)]
+		verbose: bool,
+	},
+
+	/// Create a patch file from the differences between two archives
+	Diff {
+		/// Base archive (directory with --name, or .DSK file)
+		#[arg(value_name = "BASE")]
+		base: PathBuf,
+
+		/// Target archive (directory with --name, or .DSK file)
+		#[arg(value_name = "TARGET")]
+		target: PathBuf,
+
+		/// Base name (e.g., "DATA" for DATA.DSK/DATA.PFT)
+		#[arg(short, long, value_name = "NAME")]
+		name: Option<String>,
+
+		/// Output patch file
+		#[arg(short, long, value_name = "OUTPUT")]
+		output: PathBuf,
+
+		/// Show verbose output
+		#[arg(short, long)]
+		verbose: bool,
+	},
+
+	/// Apply a patch file to an archive
+	Apply {
+		/// Archive to patch (directory with --name, or .DSK file)
+		#[arg(value_name = "INPUT")]
+		input: PathBuf,
+
+		/// Patch file
+		#[arg(value_name = "PATCH")]
+		patch: PathBuf,
+
+		/// Base name (e.g., "DATA" for DATA.DSK/DATA.PFT)
+		#[arg(short, long, value_name = "NAME")]
+		name: Option<String>,
+
+		/// Output base path for a new archive (defaults to patching the input in place)
+		#[arg(short, long, value_name = "OUTPUT")]
+		output: Option<PathBuf>,
+
+		/// Show verbose output
+		#[arg(short, long)]
+		verbose: bool,
+	},
+
+	/// Regenerate THUMB.DSK thumbnails from the CGs in KG.DSK
+	Thumbs {
+		/// CG archive (.DSK file)
+		#[arg(value_name = "KG_DSK")]
+		sources: PathBuf,
+
+		/// Thumbnail archive (.DSK file)
+		#[arg(value_name = "THUMB_DSK")]
+		thumbs: PathBuf,
+
+		/// Output base path (e.g., "out/THUMB" for THUMB.DSK/THUMB.PFT)
+		#[arg(short, long, value_name = "OUTPUT")]
+		output: PathBuf,
+
+		/// Resampling filter
+		#[arg(short, long, value_enum, default_value_t = ThumbFilter::Lanczos3)]
+		filter: ThumbFilter,
+
+		/// Add a thumbnail for a CG of the same name that has none yet (repeatable)
+		#[arg(short, long, value_name = "NAME")]
+		add: Vec<String>,
+
+		/// Use the fast greedy KG encoder instead of the size-optimal one
+		#[arg(long)]
+		fast: bool,
+
+		/// Only show how thumbnails map to CGs, without writing anything
+		#[arg(long)]
+		dry_run: bool,
+	},
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ThumbFilter {
+	/// Area average
+	Box,
+	/// Bilinear
+	Triangle,
+	/// Catmull-Rom cubic
+	CatmullRom,
+	/// Three-lobe Lanczos
+	Lanczos3,
+}
+
+impl From<ThumbFilter> for Filter {
+	fn from(filter: ThumbFilter) -> Self {
+		match filter {
+			ThumbFilter::Box => Filter::Box,
+			ThumbFilter::Triangle => Filter::Triangle,
+			ThumbFilter::CatmullRom => Filter::CatmullRom,
+			ThumbFilter::Lanczos3 => Filter::Lanczos3,
+		}
+	}
 }
 
 #[derive(Debug, Clone, Copy, ValueEnum)]
@@ -356,10 +517,10 @@ fn handle_list(
 		println!("Loading DSK/PFT from: {}", input.display());
 	}
 
-	let dsk = open_dsk(input, name)?;
+	let mut dsk = open_dsk(input, name)?;
 
 	// Collect entries
-	let mut entries: Vec<_> = dsk.entries().collect();
+	let mut entries: Vec<_> = dsk.entries().copied().collect();
 
 	// Apply filter
 	if let Some(ref pattern) = filter {
@@ -379,23 +540,49 @@ fn handle_list(
 		SortOrder::Blocks => entries.sort_by_key(|e| e.blocks_needed()),
 	}
 
+	// Sniff entry headers, since PFT names carry no extension
+	let mut types = Vec::with_capacity(entries.len());
+	for entry in &entries {
+		let mut prefix = Vec::with_capacity(DETECT_PREFIX_LEN);
+		dsk.open_entry(entry)
+			.map_err(|e| e.to_string())
+			.and_then(|reader| {
+				reader
+					.take(DETECT_PREFIX_LEN as u64)
+					.read_to_end(&mut prefix)
+					.map_err(|e| e.to_string())
+			})
+			.map_err(|e| format!("Failed to read entry {}: {}", entry.name(), e))?;
+		types.push(FileType::detect_prefix(&prefix, entry.actual_size as usize));
+	}
+
 	// Output
 	match format {
 		OutputFormat::Table => {
 			println!(
-				"\n{:>5} | {:8} | {:>10} | {:>6} | {:>10}",
-				"Index", "Name", "Size", "Blocks", "Block Idx"
+				"\n{:>5} | {:8} | {:>10} | {:>6} | {:>10} | {:9}",
+				"Index", "Name", "Size", "Blocks", "Block Idx", "Type"
+			);
+			println!(
+				"{:-<5}-+-{:-<8}-+-{:-<10}-+-{:-<6}-+-{:-<10}-+-{:-<9}",
+				"", "", "", "", "", ""
 			);
-			println!("{:-<5}-+-{:-<8}-+-{:-<10}-+-{:-<6}-+-{:-<10}", "", "", "", "", "");
 
-			for entry in &entries {
+			for (entry, detected) in entries.iter().zip(&types) {
+				let label = match detected {
+					Some((file_type, confidence)) => {
+						format!("{} {:.0}%", file_type, confidence * 100.0)
+					}
+					None => "?".to_string(),
+				};
 				println!(
-					"{:>5} | {:8} | {:>10} | {:>6} | {:>10}",
+					"{:>5} | {:8} | {:>10} | {:>6} | {:>10} | {:9}",
 					entry.index,
 					entry.name(),
 					entry.actual_size,
 					entry.blocks_needed(),
-					entry.index
+					entry.index,
+					label
 				);
 			}
 
@@ -404,12 +591,15 @@ fn handle_list(
 		OutputFormat::Json => {
 			let json_entries: Vec<_> = entries
 				.iter()
-				.map(|e| {
+				.zip(&types)
+				.map(|(e, detected)| {
 					serde_json::json!({
 						"index": e.index,
 						"name": e.name(),
 						"size": e.actual_size,
 						"blocks": e.blocks_needed(),
+						"type": detected.map(|(t, _)| t.to_string()),
+						"confidence": detected.map(|(_, c)| c),
 					})
 				})
 				.collect();
@@ -417,15 +607,21 @@ fn handle_list(
 			println!("{}", serde_json::to_string_pretty(&json_entries).unwrap());
 		}
 		OutputFormat::Csv => {
-			println!("index,name,size,blocks,block_index");
-			for entry in entries {
+			println!("index,name,size,blocks,block_index,type,confidence");
+			for (entry, detected) in entries.iter().zip(&types) {
+				let (file_type, confidence) = match detected {
+					Some((t, c)) => (t.to_string(), format!("{:.2}", c)),
+					None => (String::new(), String::new()),
+				};
 				println!(
-					"{},{},{},{},{}",
+					"{},{},{},{},{},{},{}",
 					entry.index,
 					entry.name(),
 					entry.actual_size,
 					entry.blocks_needed(),
-					entry.index
+					entry.index,
+					file_type,
+					confidence
 				);
 			}
 		}
@@ -634,10 +830,8 @@ fn handle_pack(
 		}
 	}
 
-	// Allocate blocks and create PFT entries
-	let mut pft_entries = Vec::new();
-	let mut current_block = 0u32;
-	let mut dsk_data = Vec::new();
+	// Collect entries in pack order, the builder assigns blocks sequentially
+	let mut builder = dsk::ArchiveBuilder::new();
 
 	for file in &files {
 		let data = fs::read(&file.path)
@@ -652,36 +846,27 @@ fn handle_pack(
 			));
 		}
 
-		let blocks_needed = (file.size as usize).div_ceil(pft::Entry::block_size());
-
 		if verbose {
 			println!(
 				"  {} - {} bytes ({} blocks) @ block {}",
-				file.name, file.size, blocks_needed, current_block
+				file.name,
+				file.size,
+				(file.size as usize).div_ceil(pft::Entry::block_size()),
+				builder.num_blocks()
 			);
 		}
 
-		// Create PFT entry
-		let entry = pft::Entry::new(&file.name, current_block, file.size);
-		pft_entries.push(entry);
-
-		// Write data to DSK with padding
-		dsk_data.extend_from_slice(&data);
-
-		// Pad to block boundary
-		let padding_needed = blocks_needed * pft::Entry::block_size() - data.len();
-		dsk_data.extend(vec![0u8; padding_needed]);
-
-		current_block += blocks_needed as u32;
+		builder
+			.add_entry(&file.name, data)
+			.map_err(|e| format!("Failed to add '{}': {}", file.name, e))?;
 	}
 
-	// Create and save PFT file
-	let pft_file = pft::File::new(pft_entries);
-	let pft_bytes = pft_file.to_bytes();
-	fs::write(&pft_path, pft_bytes).map_err(|e| format!("Failed to write PFT file: {}", e))?;
+	let current_block = builder.num_blocks();
 
-	// Save DSK file
-	fs::write(&dsk_path, dsk_data).map_err(|e| format!("Failed to write DSK file: {}", e))?;
+	// Write both files (via temporary files, then renamed into place)
+	let pft_file = builder
+		.save_to_paths(&pft_path, &dsk_path)
+		.map_err(|e| format!("Failed to write DSK/PFT files: {}", e))?;
 
 	println!("✓ Created {}.DSK ({} files, {} blocks)", base_name, files.len(), current_block);
 	println!("  DSK: {}", dsk_path.display());
@@ -703,7 +888,7 @@ fn handle_pack(
 				blocks: Some(e.blocks_needed()),
 			})
 			.collect(),
-		total_blocks: current_block as usize,
+		total_blocks: current_block,
 		block_size: pft::Entry::block_size(),
 	};
 
@@ -743,11 +928,34 @@ fn handle_verify(
 
 	println!("✓ DSK structure is valid");
 
+	// Block usage analysis
+	let analysis = dsk.analyze().map_err(|e| format!("Failed to analyze blocks: {}", e))?;
+	if analysis.is_clean() {
+		println!("✓ Every block is referenced exactly once");
+	}
+
 	// Check each entry
 	let total_blocks = dsk.num_blocks().map_err(|e| format!("Failed to get block count: {}", e))?;
 	let mut errors = Vec::new();
 	let mut warnings = Vec::new();
 
+	for range in &analysis.orphan_blocks {
+		warnings.push(format!(
+			"Blocks {}-{} are not referenced by any entry",
+			range.start,
+			range.end - 1
+		));
+	}
+	for overlap in &analysis.overlaps {
+		warnings.push(format!(
+			"Entries {} and {} share blocks {}-{}",
+			overlap.first,
+			overlap.second,
+			overlap.blocks.start,
+			overlap.blocks.end - 1
+		));
+	}
+
 	let entries_vec: Vec<_> = dsk.pft().entries().to_vec();
 	for (idx, entry) in entries_vec.iter().enumerate() {
 		// Check if entry is valid
@@ -839,6 +1047,199 @@ fn handle_verify(
 	Ok(())
 }
 
+/// Handles the 'manifest' command
+fn handle_manifest(
+	input: &PathBuf,
+	name: Option<String>,
+	output: Option<PathBuf>,
+	check: Option<PathBuf>,
+) -> Result<(), String> {
+	let manifest = if input.is_dir() && name.is_none() {
+		// Whole installation: every archive plus loose files, as the game sees them
+		let mut vfs = Vfs::new();
+		vfs.mount_game_dir(input).map_err(|e| format!("Failed to mount game directory: {}", e))?;
+		vfs.manifest().map_err(|e| format!("Failed to hash installation: {}", e))?
+	} else {
+		let mut dsk = open_dsk(input, name)?;
+		dsk.manifest().map_err(|e| format!("Failed to hash archive: {}", e))?
+	};
+
+	if let Some(output) = &output {
+		let json = serde_json::to_string_pretty(&manifest)
+			.map_err(|e| format!("Failed to serialize manifest: {}", e))?;
+		fs::write(output, json).map_err(|e| format!("Failed to write manifest: {}", e))?;
+		println!("✓ Wrote manifest with {} entries to {}", manifest.len(), output.display());
+	}
+
+	if let Some(check) = &check {
+		let json =
+			fs::read_to_string(check).map_err(|e| format!("Failed to read reference: {}", e))?;
+		let reference: Manifest =
+			serde_json::from_str(&json).map_err(|e| format!("Failed to parse reference: {}", e))?;
+
+		let report = reference.verify(&manifest);
+		print!("{}", report);
+		if !report.is_ok() {
+			return Err("Installation does not match the reference manifest".to_string());
+		}
+		println!("✓ Installation matches the reference manifest");
+	} else if output.is_none() {
+		print!("{}", manifest);
+	}
+
+	Ok(())
+}
+
+/// Handles the 'compact' command
+fn handle_compact(
+	input: &PathBuf,
+	name: Option<String>,
+	output: Option<PathBuf>,
+	verbose: bool,
+) -> Result<(), String> {
+	let mut dsk = open_dsk(input, name.clone())?;
+
+	let analysis = dsk.analyze().map_err(|e| format!("Failed to analyze blocks: {}", e))?;
+	if verbose {
+		print!("{}", analysis);
+	}
+
+	// Default to rewriting the input pair in place
+	let (pft_path, dsk_path) = match output {
+		Some(output) => (output.with_extension("PFT"), output.with_extension("DSK")),
+		None if input.is_dir() => {
+			let base = name.ok_or("Base name required when input is a directory")?;
+			(input.join(format!("{}.PFT", base)), input.join(format!("{}.DSK", base)))
+		}
+		None => (input.with_extension("PFT"), input.clone()),
+	};
+
+	let pft_file = dsk
+		.compact_to_paths(&pft_path, &dsk_path)
+		.map_err(|e| format!("Failed to compact: {}", e))?;
+
+	let new_blocks: u32 =
+		pft_file.entries().iter().map(|e| e.index + e.blocks_needed()).max().unwrap_or(0);
+	println!(
+		"✓ Compacted {} blocks to {} ({} orphaned blocks removed)",
+		analysis.total_blocks,
+		new_blocks,
+		analysis.total_blocks.saturating_sub(new_blocks)
+	);
+	println!("  DSK: {}", dsk_path.display());
+	println!("  PFT: {}", pft_path.display());
+
+	Ok(())
+}
+
+/// Handles the 'diff' command
+fn handle_diff(
+	base: &PathBuf,
+	target: &PathBuf,
+	name: Option<String>,
+	output: &Path,
+	verbose: bool,
+) -> Result<(), String> {
+	let mut base_dsk = open_dsk(base, name.clone())?;
+	let mut target_dsk = open_dsk(target, name)?;
+
+	let patch = dsk::Patch::diff(&mut base_dsk, &mut target_dsk)
+		.map_err(|e| format!("Failed to diff archives: {}", e))?;
+	if verbose {
+		print!("{}", patch);
+	}
+
+	patch.save(output).map_err(|e| format!("Failed to write patch: {}", e))?;
+	println!("✓ Wrote patch with {} operations to {}", patch.len(), output.display());
+
+	Ok(())
+}
+
+/// Handles the 'apply' command
+fn handle_apply(
+	input: &PathBuf,
+	patch: &Path,
+	name: Option<String>,
+	output: Option<PathBuf>,
+	verbose: bool,
+) -> Result<(), String> {
+	let patch = dsk::Patch::open(patch).map_err(|e| format!("Failed to read patch: {}", e))?;
+	if verbose {
+		print!("{}", patch);
+	}
+
+	match output {
+		Some(output) => {
+			let mut dsk = open_dsk(input, name)?;
+			let builder =
+				patch.apply(&mut dsk).map_err(|e| format!("Failed to apply patch: {}", e))?;
+			let (pft_path, dsk_path) = (output.with_extension("PFT"), output.with_extension("DSK"));
+			builder
+				.save_to_paths(&pft_path, &dsk_path)
+				.map_err(|e| format!("Failed to write archive: {}", e))?;
+			println!("✓ Applied {} operations", patch.len());
+			println!("  DSK: {}", dsk_path.display());
+			println!("  PFT: {}", pft_path.display());
+		}
+		None => {
+			let (pft_path, dsk_path) = if input.is_dir() {
+				let base = name.ok_or("Base name required when input is a directory")?;
+				(input.join(format!("{}.PFT", base)), input.join(format!("{}.DSK", base)))
+			} else {
+				(input.with_extension("PFT"), input.clone())
+			};
+			let mut dsk = dsk::File::open_rw_with_paths(&pft_path, &dsk_path)
+				.map_err(|e| format!("Failed to open DSK/PFT for writing: {}", e))?;
+			patch.apply_in_place(&mut dsk).map_err(|e| format!("Failed to apply patch: {}", e))?;
+			println!("✓ Applied {} operations to {}", patch.len(), dsk_path.display());
+		}
+	}
+
+	Ok(())
+}
+
+/// Handles the 'thumbs' command
+fn handle_thumbs(
+	sources: &PathBuf,
+	thumbs: &PathBuf,
+	output: &Path,
+	builder: ThumbnailBuilder,
+	dry_run: bool,
+) -> Result<(), String> {
+	let mut sources = open_dsk(sources, None)?;
+	let mut thumbs = open_dsk(thumbs, None)?;
+
+	let report = if dry_run {
+		builder
+			.map(&mut sources, &mut thumbs)
+			.map_err(|e| format!("Failed to map thumbnails: {}", e))?
+	} else {
+		let (archive, report) = builder
+			.regenerate(&mut sources, &mut thumbs)
+			.map_err(|e| format!("Failed to regenerate thumbnails: {}", e))?;
+		let (pft_path, dsk_path) = (output.with_extension("PFT"), output.with_extension("DSK"));
+		archive
+			.save_to_paths(&pft_path, &dsk_path)
+			.map_err(|e| format!("Failed to write archive: {}", e))?;
+		report
+	};
+
+	for entry in &report {
+		println!("{}", entry);
+	}
+
+	let kept = report.iter().filter(|entry| matches!(entry.status, ThumbnailStatus::Kept)).count();
+	if dry_run {
+		println!("✓ {} thumbnails to regenerate, {} to keep", report.len() - kept, kept);
+	} else {
+		println!("✓ Regenerated {} thumbnails, kept {}", report.len() - kept, kept);
+		println!("  DSK: {}", output.with_extension("DSK").display());
+		println!("  PFT: {}", output.with_extension("PFT").display());
+	}
+
+	Ok(())
+}
+
 fn main() {
 	let cli = Cli::parse();
 
@@ -879,6 +1280,52 @@ fn main() {
 			strict,
 			verbose,
 		} => handle_verify(&input, name, strict, verbose),
+		Commands::Manifest {
+			input,
+			name,
+			output,
+			check,
+		} => handle_manifest(&input, name, output, check),
+		Commands::Compact {
+			input,
+			name,
+			output,
+			verbose,
+		} => handle_compact(&input, name, output, verbose),
+		Commands::Diff {
+			base,
+			target,
+			name,
+			output,
+			verbose,
+		} => handle_diff(&base, &target, name, &output, verbose),
+		Commands::Apply {
+			input,
+			patch,
+			name,
+			output,
+			verbose,
+		} => handle_apply(&input, &patch, name, output, verbose),
+		Commands::Thumbs {
+			sources,
+			thumbs,
+			output,
+			filter,
+			add,
+			fast,
+			dry_run,
+		} => {
+			let level = if fast {
+				CompressionLevel::Fast
+			} else {
+				CompressionLevel::Best
+			};
+			let builder = add.into_iter().fold(
+				ThumbnailBuilder::new().with_filter(filter.into()).with_level(level),
+				|builder, name| builder.with_new_entry(name.clone(), name),
+			);
+			handle_thumbs(&sources, &thumbs, &output, builder, dry_run)
+		}
 	};
 
 	if let Err(e) = result {
@@ -58,7 +58,7 @@ pub fn test() -> Result<(), Box<dyn std::error::Error>> {
 
 			// Show first few items
 			for (index, item) in file.iter().enumerate() {
-				let entry = ItemEntry::from(item.as_slice());
+				let entry = ItemEntry::from(item);
 				let name = entry.name().unwrap_or_else(|| {
 					let n = hex::encode(entry.raw_name());
 					let extra = hex::encode(entry.extra());
@@ -40,7 +40,7 @@
 
 use clap::{Parser, Subcommand};
 use dvine_rs::prelude::file::{FntFile, Glyph, GlyphBitmap};
-use image::{ImageBuffer, Rgb, RgbImage};
+use image::{Rgba, RgbaImage, imageops};
 use std::fs;
 use std::path::PathBuf;
 
@@ -244,11 +244,11 @@ fn handle_dump(
 	}
 
 	// Create image with white background
-	let white = Rgb([255, 255, 255]);
-	let mut img: RgbImage = ImageBuffer::from_pixel(img_width, img_height, white);
+	let white = Rgba([255, 255, 255, 255]);
+	let mut img = RgbaImage::from_pixel(img_width, img_height, white);
 
 	// Draw green separators (1px grid lines)
-	let green = Rgb([0, 255, 0]);
+	let green = Rgba([0, 255, 0, 255]);
 
 	// Vertical separators
 	for col in 0..=grid {
@@ -267,8 +267,6 @@ fn handle_dump(
 	}
 
 	// Draw each glyph in black
-	let black = Rgb([0, 0, 0]);
-
 	for (idx, glyph) in glyphs.iter().enumerate() {
 		let grid_row = idx / grid;
 		let grid_col = idx % grid;
@@ -277,16 +275,9 @@ fn handle_dump(
 		let start_x = grid_col as u32 * (glyph_size + separator_width) + separator_width;
 		let start_y = grid_row as u32 * (glyph_size + separator_width) + separator_width;
 
-		// Convert glyph to bitmap and draw
+		// Convert glyph to bitmap and draw it over the background
 		let bitmap: GlyphBitmap = glyph.into();
-
-		for (y_offset, line) in bitmap.line_iterator().enumerate() {
-			for (x_offset, &pixel) in line.iter().enumerate() {
-				if pixel {
-					img.put_pixel(start_x + x_offset as u32, start_y + y_offset as u32, black);
-				}
-			}
-		}
+		imageops::overlay(&mut img, &RgbaImage::from(&bitmap), start_x.into(), start_y.into());
 	}
 
 	// Save the image
@@ -386,30 +377,20 @@ fn handle_render(
 	}
 
 	// Create image with white background
-	let white = Rgb([255, 255, 255]);
-	let mut img: RgbImage = ImageBuffer::from_pixel(img_width, img_height, white);
+	let white = Rgba([255, 255, 255, 255]);
+	let mut img = RgbaImage::from_pixel(img_width, img_height, white);
 
 	// Draw each line
-	let black = Rgb([0, 0, 0]);
 	let mut current_y = padding;
 
 	for line_glyphs in all_line_glyphs.iter() {
 		let mut current_x = padding;
 
 		for glyph in line_glyphs.iter() {
-			// Convert glyph to bitmap
+			// Convert glyph to bitmap and draw it over the background
 			let bitmap: GlyphBitmap = glyph.into();
-
-			// Draw glyph using line iterator
-			for (y_offset, line) in bitmap.line_iterator().enumerate() {
-				for (x_offset, &pixel) in line.iter().enumerate() {
-					if pixel {
-						let px = current_x + x_offset as u32;
-						let py = current_y + y_offset as u32;
-						img.put_pixel(px, py, black);
-					}
-				}
-			}
+			let glyph_image = RgbaImage::from(&bitmap);
+			imageops::overlay(&mut img, &glyph_image, current_x.into(), current_y.into());
 
 			// Move to next character position
 			current_x += glyph_size + char_spacing;
@@ -490,24 +471,9 @@ fn handle_extract(
 		println!("Extracting glyph 0x{:04X} to: {}", glyph.code(), output_path.display());
 	}
 
-	// Create bitmap
+	// Create bitmap and render it black on white
 	let bitmap: GlyphBitmap = (&glyph).into();
-	let glyph_size = font.font_size() as u32;
-
-	// Create image with white background
-	let white = Rgb([255, 255, 255]);
-	let mut img: RgbImage = ImageBuffer::from_pixel(glyph_size, glyph_size, white);
-
-	// Draw glyph in black
-	let black = Rgb([0, 0, 0]);
-
-	for (y_offset, line) in bitmap.line_iterator().enumerate() {
-		for (x_offset, &pixel) in line.iter().enumerate() {
-			if pixel {
-				img.put_pixel(x_offset as u32, y_offset as u32, black);
-			}
-		}
-	}
+	let img = bitmap.to_gray_image();
 
 	// Save the image
 	img.save(&output_path).map_err(|e| format!("Failed to save image: {}", e))?;
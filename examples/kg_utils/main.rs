@@ -4,9 +4,12 @@
 //!
 //! # Features
 //!
-//! - **encode**: Convert BMP images to KG format
-//! - **decode**: Convert KG images to BMP format
+//! - **encode**: Convert BMP images to KG format, quantizing true-color input
+//!   and storing transparent pixels as the chroma key
+//! - **decode**: Convert KG images to BMP format, optionally with the chroma
+//!   key as alpha
 //! - **verify**: Validate KG encoder/decoder round-trip accuracy
+//! - **trace**: Disassemble a KG bitstream and summarize its operations
 //!
 //! # Usage
 //!
@@ -17,19 +20,42 @@
 //! # Encode with custom output path
 //! cargo run --example kg_utils encode input.bmp output.kg
 //!
+//! # Encode an anti-aliased edit with dithering, keeping magenta as the chroma key
+//! cargo run --example kg_utils encode edited.png output.kg --dither --chroma-key FF00FF
+//!
 //! # Decode a KG file to BMP (auto output: input.bmp)
 //! cargo run --example kg_utils decode input.kg
 //!
 //! # Decode with custom output path
 //! cargo run --example kg_utils decode input.kg output.bmp
 //!
+//! # Decode an overlay to PNG with the cyan chroma key as transparency
+//! cargo run --example kg_utils decode overlay.kg overlay.png --alpha
+//!
+//! # Decode a Type 2/3 true-color file with the experimental decoder
+//! cargo run --example kg_utils decode truecolor.kg --experimental
+//!
 //! # Verify encoder/decoder correctness
 //! cargo run --example kg_utils verify input.kg
+//!
+//! # Compare with the greedy encoder's output size
+//! cargo run --example kg_utils verify input.kg --fast
+//!
+//! # Show the opcode statistics of a file next to our encoder's
+//! cargo run --example kg_utils trace input.kg --compare
+//!
+//! # Dump every operation as JSON lines
+//! cargo run --example kg_utils trace input.kg --json
 //! ```
 
 use clap::{Parser, Subcommand};
-use dvine_rs::prelude::file::kg::{File as KgFile, compress};
-use image::{ImageBuffer, RgbImage};
+use dvine_rs::prelude::file::ParseLimits;
+use dvine_rs::prelude::file::kg::quantize::{Dither, QuantizeOptions};
+use dvine_rs::prelude::file::kg::{
+	self, CompressionLevel, File as KgFile, compress_file_with_level, compress_quantized,
+	compress_rgba,
+};
+use image::DynamicImage;
 use std::fs;
 use std::path::PathBuf;
 
@@ -59,6 +85,23 @@ enum Commands {
 		#[arg(short, long)]
 		flip: bool,
 
+		/// Maximum palette size used when the image has more colors
+		#[arg(short, long, default_value_t = 256, value_parser = clap::value_parser!(u16).range(1..=256))]
+		colors: u16,
+
+		/// Apply Floyd–Steinberg dithering when reducing colors
+		#[arg(short, long)]
+		dither: bool,
+
+		/// Pin palette index 0 to this color (hex RRGGBB); transparent pixels
+		/// are stored as this key, 00FFFF if not given
+		#[arg(short = 'k', long, value_name = "RRGGBB", value_parser = parse_hex_color)]
+		chroma_key: Option<[u8; 3]>,
+
+		/// Use the fast greedy encoder instead of the size-optimal one
+		#[arg(long)]
+		fast: bool,
+
 		/// Show verbose output
 		#[arg(short, long)]
 		verbose: bool,
@@ -78,6 +121,18 @@ enum Commands {
 		#[arg(short, long)]
 		flip: bool,
 
+		/// Output RGBA with the chroma-key color transparent (use a PNG output)
+		#[arg(short, long)]
+		alpha: bool,
+
+		/// Chroma-key color (hex RRGGBB) for --alpha, defaults to 00FFFF
+		#[arg(short = 'k', long, value_name = "RRGGBB", value_parser = parse_hex_color, requires = "alpha")]
+		chroma_key: Option<[u8; 3]>,
+
+		/// Decode Type 2/3 true-color files with the experimental decoder
+		#[arg(short, long)]
+		experimental: bool,
+
 		/// Show verbose output
 		#[arg(short, long)]
 		verbose: bool,
@@ -96,43 +151,96 @@ enum Commands {
 		/// Save intermediate files for debugging
 		#[arg(short, long)]
 		save_intermediate: bool,
+
+		/// Use the fast greedy encoder instead of the size-optimal one
+		#[arg(long)]
+		fast: bool,
+	},
+
+	/// Disassemble a KG bitstream and summarize its operations
+	Trace {
+		/// Input KG file path
+		#[arg(value_name = "INPUT_KG")]
+		input: PathBuf,
+
+		/// Print every operation
+		#[arg(short, long)]
+		listing: bool,
+
+		/// Print every operation as a JSON line instead of the summary
+		#[arg(short, long, conflicts_with = "listing")]
+		json: bool,
+
+		/// Re-encode the image and compare the encoder's choices
+		#[arg(short, long)]
+		compare: bool,
+
+		/// Compare against the fast greedy encoder instead of the size-optimal one
+		#[arg(long, requires = "compare")]
+		fast: bool,
 	},
 }
 
-/// Load a BMP file and convert to RGB data
-fn load_bmp(path: &PathBuf, flip: bool) -> Result<(Vec<u8>, u32, u32), Box<dyn std::error::Error>> {
+/// Load an image file as RGBA data
+fn load_image(
+	path: &PathBuf,
+	flip: bool,
+) -> Result<(Vec<u8>, u32, u32), Box<dyn std::error::Error>> {
 	let img = image::open(path)?;
-	let mut rgb_img = img.to_rgb8();
+	let mut rgba_img = img.to_rgba8();
 
 	if flip {
-		image::imageops::flip_vertical_in_place(&mut rgb_img);
+		image::imageops::flip_vertical_in_place(&mut rgba_img);
 	}
 
-	let (width, height) = rgb_img.dimensions();
-	let pixels = rgb_img.into_raw();
+	let (width, height) = rgba_img.dimensions();
+	let pixels = rgba_img.into_raw();
 
 	Ok((pixels, width, height))
 }
 
-/// Save RGB data as BMP
-fn save_bmp(
+/// Save a decoded KG file as an image, in the format given by the extension
+///
+/// With a chroma key the image is RGBA with pixels of that color transparent,
+/// otherwise it is RGB.
+fn save_image(
 	path: &PathBuf,
-	rgb_data: &[u8],
-	width: u32,
-	height: u32,
+	kg_file: &KgFile,
+	chroma_key: Option<[u8; 3]>,
 	flip: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
-	let mut img: RgbImage = ImageBuffer::from_raw(width, height, rgb_data.to_vec())
-		.ok_or("Failed to create image buffer")?;
+	let mut img = match chroma_key {
+		Some(key) => DynamicImage::ImageRgba8(kg_file.to_rgba_image_with_key(key)),
+		None => DynamicImage::from(kg_file),
+	};
 
 	if flip {
-		image::imageops::flip_vertical_in_place(&mut img);
+		img = img.flipv();
 	}
 
 	img.save(path)?;
 	Ok(())
 }
 
+/// Parse a hex RRGGBB color
+fn parse_hex_color(value: &str) -> Result<[u8; 3], String> {
+	let value = value.trim_start_matches('#');
+	let rgb = u32::from_str_radix(value, 16)
+		.ok()
+		.filter(|_| value.len() == 6)
+		.ok_or_else(|| format!("Invalid color '{}', expected RRGGBB", value))?;
+	Ok([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8])
+}
+
+/// Map the `--fast` flag to a compression level
+fn compression_level(fast: bool) -> CompressionLevel {
+	if fast {
+		CompressionLevel::Fast
+	} else {
+		CompressionLevel::Best
+	}
+}
+
 /// Count unique colors in RGB data
 fn count_unique_colors(rgb_data: &[u8]) -> usize {
 	let mut colors = std::collections::HashSet::new();
@@ -150,6 +258,8 @@ fn handle_encode(
 	input: PathBuf,
 	output: Option<PathBuf>,
 	flip: bool,
+	options: QuantizeOptions,
+	level: CompressionLevel,
 	verbose: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
 	// Generate output path if not specified
@@ -161,16 +271,22 @@ fn handle_encode(
 		if flip {
 			println!("   Flip:   Enabled (Y-axis)");
 		}
+		println!("   Level:  {}", level);
 	}
 
 	// Load BMP
 	if verbose {
 		println!("\n📖 Loading BMP file...");
 	}
-	let (rgb_data, width, height) = load_bmp(&input, flip)?;
+	let (rgba_data, width, height) = load_image(&input, flip)?;
+	let rgb_data: Vec<u8> = rgba_data.chunks(4).flat_map(|pixel| pixel[..3].to_vec()).collect();
+	let transparent = rgba_data.chunks(4).filter(|pixel| pixel[3] < 128).count();
 
 	if verbose {
 		println!("   ✓ Loaded {}x{} image ({} bytes)", width, height, rgb_data.len());
+		if transparent > 0 {
+			println!("   ✓ {} transparent pixels stored as the chroma key", transparent);
+		}
 	}
 
 	// Check color count
@@ -180,17 +296,27 @@ fn handle_encode(
 		println!("   ✓ Found {} unique colors", color_count);
 	}
 
-	if color_count > 256 {
-		eprintln!("❌ Error: Image has {} unique colors (maximum 256)", color_count);
-		eprintln!("   Consider using color quantization to reduce the palette.");
-		return Err("Too many colors".into());
+	if verbose && color_count > options.max_colors {
+		println!(
+			"   ✓ Reducing to {} colors{}",
+			options.max_colors,
+			if options.dither == Dither::FloydSteinberg {
+				" with dithering"
+			} else {
+				""
+			}
+		);
 	}
 
 	// Encode to KG
 	if verbose {
 		println!("\n🔧 Encoding to KG format...");
 	}
-	let compressed_data = compress(&rgb_data, width as u16, height as u16)?;
+	let compressed_data = if transparent > 0 {
+		compress_rgba(&rgba_data, width as u16, height as u16, &options, level)?
+	} else {
+		compress_quantized(&rgb_data, width as u16, height as u16, &options, level)?
+	};
 
 	let original_size = rgb_data.len();
 	let compressed_size = compressed_data.len();
@@ -227,6 +353,8 @@ fn handle_decode(
 	input: PathBuf,
 	output: Option<PathBuf>,
 	flip: bool,
+	chroma_key: Option<[u8; 3]>,
+	options: kg::DecodeOptions,
 	verbose: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
 	// Generate output path if not specified
@@ -238,13 +366,17 @@ fn handle_decode(
 		if flip {
 			println!("   Flip:   Enabled (Y-axis)");
 		}
+		if let Some([r, g, b]) = chroma_key {
+			println!("   Alpha:  #{:02X}{:02X}{:02X} transparent", r, g, b);
+		}
 	}
 
 	// Load KG file
 	if verbose {
 		println!("\n📖 Loading KG file...");
 	}
-	let kg_file = KgFile::open(&input)?;
+	let kg_file =
+		KgFile::from_bytes_with_options(&fs::read(&input)?, &ParseLimits::default(), &options)?;
 
 	let width = kg_file.header().width() as u32;
 	let height = kg_file.header().height() as u32;
@@ -270,7 +402,7 @@ fn handle_decode(
 	if verbose {
 		println!("\n💾 Saving BMP file...");
 	}
-	save_bmp(&output, rgb_data, width, height, flip)?;
+	save_image(&output, &kg_file, chroma_key, flip)?;
 
 	if verbose {
 		println!("   ✓ Saved to {}", output.display());
@@ -287,6 +419,7 @@ fn handle_verify(
 	input: PathBuf,
 	verbose: bool,
 	save_intermediate: bool,
+	level: CompressionLevel,
 ) -> Result<(), Box<dyn std::error::Error>> {
 	if verbose {
 		println!("🔍 Verifying KG encoder/decoder round-trip");
@@ -311,7 +444,7 @@ fn handle_verify(
 	// Optionally save intermediate BMP
 	if save_intermediate {
 		let intermediate_bmp = input.with_extension("decoded.bmp");
-		save_bmp(&intermediate_bmp, &decoded_rgb, width as u32, height as u32, false)?;
+		save_image(&intermediate_bmp, &kg_file, None, false)?;
 		if verbose {
 			println!("   ✓ Saved intermediate BMP: {}", intermediate_bmp.display());
 		}
@@ -321,7 +454,7 @@ fn handle_verify(
 	if verbose {
 		println!("\n🔧 Step 2: Re-encoding to KG format...");
 	}
-	let reencoded_kg_data = compress(&decoded_rgb, width, height)?;
+	let reencoded_kg_data = compress_file_with_level(&kg_file, level)?;
 
 	if verbose {
 		println!("   ✓ Re-encoded to {} bytes", reencoded_kg_data.len());
@@ -435,6 +568,51 @@ fn handle_verify(
 	Ok(())
 }
 
+/// Handle trace command
+fn handle_trace(
+	input: PathBuf,
+	listing: bool,
+	json: bool,
+	compare: Option<CompressionLevel>,
+) -> Result<(), Box<dyn std::error::Error>> {
+	let data = fs::read(&input)?;
+	let trace = kg::trace_bitstream(&data)?;
+
+	if json {
+		for record in trace.records() {
+			println!("{}", serde_json::to_string(record)?);
+		}
+		return Ok(());
+	}
+
+	if listing {
+		println!("{}", trace);
+	} else {
+		println!("{}", trace.header());
+		println!("{}", trace.stats());
+	}
+
+	if let Some(level) = compare {
+		let kg_file = KgFile::from_reader(&mut data.as_slice())?;
+		let reencoded = compress_file_with_level(&kg_file, level)?;
+		let reencoded_trace = kg::trace_bitstream(&reencoded)?;
+
+		println!("\n🔧 Re-encoded ({}):", level);
+		println!("{}", reencoded_trace.stats());
+
+		let original = trace.stats().total.bits;
+		let ours = reencoded_trace.stats().total.bits;
+		println!(
+			"\n   Bitstream: {} bits original, {} bits re-encoded ({:+.2}%)",
+			original,
+			ours,
+			(ours as f64 - original as f64) * 100.0 / original.max(1) as f64
+		);
+	}
+
+	Ok(())
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
 	let cli = Cli::parse();
 
@@ -443,20 +621,49 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 			input,
 			output,
 			flip,
+			colors,
+			dither,
+			chroma_key,
+			fast,
 			verbose,
-		} => handle_encode(input, output, flip, verbose),
+		} => {
+			let mut options = QuantizeOptions::default().with_max_colors(colors as usize);
+			if dither {
+				options = options.with_dither(Dither::FloydSteinberg);
+			}
+			if let Some(chroma_key) = chroma_key {
+				options = options.with_chroma_key(chroma_key);
+			}
+			handle_encode(input, output, flip, options, compression_level(fast), verbose)
+		}
 
 		Commands::Decode {
 			input,
 			output,
 			flip,
+			alpha,
+			chroma_key,
+			experimental,
 			verbose,
-		} => handle_decode(input, output, flip, verbose),
+		} => {
+			let chroma_key = alpha.then(|| chroma_key.unwrap_or(kg::constants::DEFAULT_CHROMA_KEY));
+			let options = kg::DecodeOptions::default().with_experimental_true_color(experimental);
+			handle_decode(input, output, flip, chroma_key, options, verbose)
+		}
 
 		Commands::Verify {
 			input,
 			verbose,
 			save_intermediate,
-		} => handle_verify(input, verbose, save_intermediate),
+			fast,
+		} => handle_verify(input, verbose, save_intermediate, compression_level(fast)),
+
+		Commands::Trace {
+			input,
+			listing,
+			json,
+			compare,
+			fast,
+		} => handle_trace(input, listing, json, compare.then(|| compression_level(fast))),
 	}
 }
@@ -67,7 +67,7 @@
 
 use clap::{Parser, Subcommand};
 use dvine_rs::prelude::file::mfd::{AnimationEntry, File as MfdFile, FileBuilder, Frame};
-use image::{GrayImage, ImageBuffer, Luma};
+use image::DynamicImage;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::PathBuf;
@@ -191,17 +191,12 @@ mod hex_array {
 	}
 }
 
-/// Convert indexed pixel to grayscale value
+/// Grayscale `[transparent, outline, fill]` palette of the BMP frames
 /// - 0 (transparent) -> 255 (white)
 /// - 1 (outline) -> 128 (gray)
 /// - 255/0xFF (fill) -> 0 (black)
-fn indexed_to_grayscale(pixel: u8) -> u8 {
-	match pixel {
-		0 => 255, // transparent -> white
-		1 => 128, // outline -> gray
-		_ => 0,   // fill (0xFF or others) -> black
-	}
-}
+const GRAYSCALE_PALETTE: [[u8; 4]; 3] =
+	[[255, 255, 255, 255], [128, 128, 128, 255], [0, 0, 0, 255]];
 
 /// Convert grayscale value to indexed pixel
 /// - 255 (white) -> 0 (transparent)
@@ -220,19 +215,8 @@ fn grayscale_to_indexed(gray: u8) -> u8 {
 
 /// Save frame as grayscale BMP
 fn save_frame_bmp(path: &PathBuf, frame: &Frame) -> Result<(), Box<dyn std::error::Error>> {
-	let width = frame.width() as u32;
-	let height = frame.height() as u32;
-
-	let mut img: GrayImage = ImageBuffer::new(width, height);
-
-	for (i, &pixel) in frame.pixels().iter().enumerate() {
-		let x = (i % width as usize) as u32;
-		let y = (i / width as usize) as u32;
-		let gray = indexed_to_grayscale(pixel);
-		img.put_pixel(x, y, Luma([gray]));
-	}
-
-	img.save(path)?;
+	let img = DynamicImage::from(frame.to_rgba_image_with_palette(&GRAYSCALE_PALETTE));
+	img.to_luma8().save(path)?;
 	Ok(())
 }
 
@@ -241,13 +225,7 @@ fn load_frame_bmp(path: &PathBuf) -> Result<(Vec<u8>, u16, u16), Box<dyn std::er
 	let img = image::open(path)?.to_luma8();
 	let (width, height) = img.dimensions();
 
-	let mut pixels = Vec::with_capacity((width * height) as usize);
-	for y in 0..height {
-		for x in 0..width {
-			let gray = img.get_pixel(x, y).0[0];
-			pixels.push(grayscale_to_indexed(gray));
-		}
-	}
+	let pixels = img.pixels().map(|pixel| grayscale_to_indexed(pixel.0[0])).collect();
 
 	Ok((pixels, width as u16, height as u16))
 }
@@ -6,6 +6,10 @@
 //!
 //! - **unpack**: Extract all frames from an SPR file to PNG images with JSON metadata
 //! - **pack**: Combine PNG images and JSON metadata into an SPR file
+//! - **import**: Add RGBA PNG images as frames, with the mask taken from alpha
+//! - **atlas**: Pack the frames of SPR files into power-of-two PNG pages with a JSON descriptor
+//! - **optimize**: Trim transparent frame borders and share identical frame data
+//! - **mirror**: Flip frames horizontally or vertically around their hotspots
 //! - **verify**: Validate SPR encoder/decoder round-trip accuracy
 //! - **extract-frame**: Extract a specific frame to PNG files (sprite and mask)
 //! - **info**: Display information about an SPR file
@@ -48,6 +52,21 @@
 //! # Pack PNG files to SPR
 //! cargo run --example spr_utils -- pack frames/ output.SPR -p bin/SPR.PAL
 //!
+//! # Repack edited frames into a copy of the original, keeping untouched frames byte-for-byte
+//! cargo run --example spr_utils -- pack frames/ KATIA_NEW.SPR -p bin/SPR.PAL --base KATIA.SPR
+//!
+//! # Import RGBA PNG files drawn in any editor, hotspot at (32, 120)
+//! cargo run --example spr_utils -- import walk_*.png -o NEW.SPR -p bin/SPR.PAL --hotspot-x 32 --hotspot-y 120
+//!
+//! # Pack two SPR files into 1024x1024 atlas pages plus atlas.json
+//! cargo run --example spr_utils -- atlas KATIA.SPR AG.SPR -p bin/SPR.PAL -o atlas/ --max-size 1024
+//!
+//! # Trim and deduplicate frames, writing the result to a new file
+//! cargo run --example spr_utils -- optimize KATIA.SPR -o KATIA_OPT.SPR
+//!
+//! # Append left-right mirrored copies of frames 0-7
+//! cargo run --example spr_utils -- mirror KATIA.SPR -o KATIA_MIRROR.SPR --frames 0-7 --append
+//!
 //! # Verify encoder/decoder correctness
 //! cargo run --example spr_utils -- verify KATIA.SPR -p bin/SPR.PAL
 //!
@@ -59,8 +78,11 @@
 //! ```
 
 use clap::{Parser, Subcommand};
-use dvine_rs::prelude::file::{SprFile, SprFrame, SprFrameEntry, SprPalette};
-use image::{ImageBuffer, Luma, RgbImage, Rgba, RgbaImage};
+use dvine_rs::prelude::file::{
+	SprFile, SprFrame, SprFrameEntry, SprFrameImporter, SprOptimizer, SprPalette,
+	spr::atlas::AtlasBuilder,
+};
+use image::{GrayImage, Luma, Rgba, RgbaImage, imageops};
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::PathBuf;
@@ -114,11 +136,119 @@ enum Commands {
 		#[arg(short, long, value_name = "PALETTE")]
 		palette: PathBuf,
 
+		/// Original SPR file; unchanged frames keep its exact bytes
+		#[arg(short, long, value_name = "BASE_SPR")]
+		base: Option<PathBuf>,
+
+		/// Show verbose output
+		#[arg(short, long)]
+		verbose: bool,
+	},
+
+	/// Import RGBA PNG images as frames, mapping colors to the palette
+	Import {
+		/// Input PNG images, one frame each
+		#[arg(value_name = "IMAGES", required = true)]
+		images: Vec<PathBuf>,
+
+		/// Output SPR file path
+		#[arg(short, long, value_name = "OUTPUT_SPR")]
+		output: PathBuf,
+
+		/// Path to SPR.PAL palette file
+		#[arg(short, long, value_name = "PALETTE")]
+		palette: PathBuf,
+
+		/// Hotspot X coordinate of every frame
+		#[arg(long, default_value_t = 0)]
+		hotspot_x: u32,
+
+		/// Hotspot Y coordinate of every frame
+		#[arg(long, default_value_t = 0)]
+		hotspot_y: u32,
+
+		/// Alpha value from which a pixel is opaque
+		#[arg(long, default_value_t = 128)]
+		alpha_threshold: u8,
+
+		/// Append the frames to the output file if it exists
+		#[arg(short, long)]
+		append: bool,
+
 		/// Show verbose output
 		#[arg(short, long)]
 		verbose: bool,
 	},
 
+	/// Pack the frames of SPR files into texture atlas pages
+	Atlas {
+		/// Input SPR files
+		#[arg(value_name = "INPUT_SPR", required = true)]
+		inputs: Vec<PathBuf>,
+
+		/// Output directory for the pages and `atlas.json`
+		#[arg(short, long, value_name = "OUTPUT_DIR", default_value = "atlas")]
+		output: PathBuf,
+
+		/// Path to SPR.PAL palette file
+		#[arg(short, long, value_name = "PALETTE")]
+		palette: Option<PathBuf>,
+
+		/// Maximum page width and height, rounded up to a power of two
+		#[arg(long, default_value_t = 2048)]
+		max_size: u32,
+
+		/// Transparent pixels between frames
+		#[arg(long, default_value_t = 1)]
+		padding: u32,
+
+		/// Show verbose output
+		#[arg(short, long)]
+		verbose: bool,
+	},
+
+	/// Trim transparent borders from frames and share identical frame data
+	Optimize {
+		/// Input SPR file path
+		#[arg(value_name = "INPUT_SPR")]
+		input: PathBuf,
+
+		/// Output SPR file path (defaults to overwriting the input)
+		#[arg(short, long, value_name = "OUTPUT_SPR")]
+		output: Option<PathBuf>,
+
+		/// Keep transparent frame borders
+		#[arg(long)]
+		no_trim: bool,
+
+		/// Keep a separate copy of every frame's data
+		#[arg(long)]
+		no_dedup: bool,
+	},
+
+	/// Flip frames around their hotspots
+	Mirror {
+		/// Input SPR file path
+		#[arg(value_name = "INPUT_SPR")]
+		input: PathBuf,
+
+		/// Output SPR file path (defaults to overwriting the input)
+		#[arg(short, long, value_name = "OUTPUT_SPR")]
+		output: Option<PathBuf>,
+
+		/// Frames to flip, e.g. `3` or `0-7` (defaults to all frames)
+		#[arg(short, long, value_name = "RANGE")]
+		frames: Option<String>,
+
+		/// Flip top to bottom instead of left to right
+		#[arg(long)]
+		vertical: bool,
+
+		/// Append the flipped frames instead of replacing the originals
+		#[arg(short, long)]
+		append: bool,
+	},
+
 	/// Verify SPR encoder/decoder round-trip accuracy
 	Verify {
 		/// Input SPR file path to verify
@@ -250,20 +380,13 @@ fn save_sprite_png(
 	palette: &SprPalette,
 	path: &PathBuf,
 ) -> Result<(), Box<dyn std::error::Error>> {
-	let rgb_data = frame.apply_palette_rgb(palette);
-	let img: RgbImage = ImageBuffer::from_raw(frame.width(), frame.height(), rgb_data)
-		.ok_or("Failed to create sprite image")?;
-	img.save(path)?;
+	frame.to_dynamic_image(palette).to_rgb8().save(path)?;
 	Ok(())
 }
 
 /// Save frame mask as grayscale PNG
 fn save_mask_png(frame: &SprFrame, path: &PathBuf) -> Result<(), Box<dyn std::error::Error>> {
-	let mask_data = frame.mask_pixels();
-	let img: image::GrayImage =
-		ImageBuffer::from_raw(frame.width(), frame.height(), mask_data.to_vec())
-			.ok_or("Failed to create mask image")?;
-	img.save(path)?;
+	frame.mask_image().save(path)?;
 	Ok(())
 }
 
@@ -282,25 +405,8 @@ fn load_sprite_png(
 		let g = chunk[1];
 		let b = chunk[2];
 
-		// Find closest palette color
-		let mut best_index = 0u8;
-		let mut best_distance = u32::MAX;
-
-		for i in 0..80u8 {
-			let (pr, pg, pb, _) = palette.get(i);
-			let dr = (r as i32 - pr as i32).unsigned_abs();
-			let dg = (g as i32 - pg as i32).unsigned_abs();
-			let db = (b as i32 - pb as i32).unsigned_abs();
-			let distance = dr * dr + dg * dg + db * db;
-
-			if distance < best_distance {
-				best_distance = distance;
-				best_index = i;
-			}
-		}
-
 		// SPR uses indices 176-255 for palette 0-79
-		indexed.push(176 + best_index);
+		indexed.push(SprFrame::encode_sprite_pixel(palette.nearest_index(r, g, b)));
 	}
 
 	Ok(indexed)
@@ -484,22 +590,18 @@ fn create_sprite_sheets(
 	let sheet_height = grid_rows as u32 * cell_height;
 
 	// Create sprite sheet with checkerboard background
-	let mut sprite_sheet: RgbaImage = ImageBuffer::new(sheet_width, sheet_height);
-	for y in 0..sheet_height {
-		for x in 0..sheet_width {
-			let checker = ((x / 8) + (y / 8)) % 2 == 0;
-			let gray = if checker {
-				200
-			} else {
-				220
-			};
-			sprite_sheet.put_pixel(x, y, Rgba([gray, gray, gray, 255]));
-		}
-	}
+	let mut sprite_sheet = RgbaImage::from_fn(sheet_width, sheet_height, |x, y| {
+		let checker = ((x / 8) + (y / 8)) % 2 == 0;
+		let gray = if checker {
+			200
+		} else {
+			220
+		};
+		Rgba([gray, gray, gray, 255])
+	});
 
 	// Create mask sheet
-	let mut mask_sheet: image::GrayImage =
-		ImageBuffer::from_pixel(sheet_width, sheet_height, Luma([0u8]));
+	let mut mask_sheet = GrayImage::from_pixel(sheet_width, sheet_height, Luma([0u8]));
 
 	// Place frames in grid
 	for (grid_idx, (_, frame)) in valid_frames.iter().enumerate() {
@@ -512,45 +614,11 @@ fn create_sprite_sheets(
 		let frame_x = cell_x + (cell_width - frame.width()) / 2;
 		let frame_y = cell_y + (cell_height - frame.height()) / 2;
 
-		// Get sprite data with mask
-		let rgba_data = frame.apply_palette_with_mask(palette);
-
-		// Copy sprite pixels
-		for y in 0..frame.height() {
-			for x in 0..frame.width() {
-				let pixel_idx = ((y * frame.width() + x) * 4) as usize;
-				let r = rgba_data[pixel_idx];
-				let g = rgba_data[pixel_idx + 1];
-				let b = rgba_data[pixel_idx + 2];
-				let a = rgba_data[pixel_idx + 3];
-
-				let px = frame_x + x;
-				let py = frame_y + y;
-
-				if px < sheet_width && py < sheet_height {
-					// Only draw opaque pixels (a == 0 means opaque in SPR)
-					if a == 0 {
-						sprite_sheet.put_pixel(px, py, Rgba([r, g, b, 255]));
-					}
-				}
-			}
-		}
-
-		// Copy mask pixels
-		let mask_data = frame.mask_pixels();
-		for y in 0..frame.height() {
-			for x in 0..frame.width() {
-				let pixel_idx = (y * frame.width() + x) as usize;
-				let mask_value = mask_data[pixel_idx];
-
-				let px = frame_x + x;
-				let py = frame_y + y;
-
-				if px < sheet_width && py < sheet_height {
-					mask_sheet.put_pixel(px, py, Luma([mask_value]));
-				}
-			}
-		}
+		// Draw the sprite over the background, the mask keeps transparent
+		// pixels out, and copy the mask as it is
+		let (frame_x, frame_y) = (i64::from(frame_x), i64::from(frame_y));
+		imageops::overlay(&mut sprite_sheet, &frame.to_rgba_image(palette), frame_x, frame_y);
+		imageops::replace(&mut mask_sheet, &frame.mask_image(), frame_x, frame_y);
 	}
 
 	// Save sheets
@@ -573,6 +641,7 @@ fn handle_pack(
 	input: PathBuf,
 	output: PathBuf,
 	palette_path: PathBuf,
+	base: Option<PathBuf>,
 	verbose: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
 	if verbose {
@@ -580,6 +649,9 @@ fn handle_pack(
 		println!("   Input:  {}", input.display());
 		println!("   Output: {}", output.display());
 		println!("   Palette: {}", palette_path.display());
+		if let Some(base) = &base {
+			println!("   Base:   {}", base.display());
+		}
 	}
 
 	// Load palette
@@ -597,8 +669,17 @@ fn handle_pack(
 		println!("   ✓ Total frames: {}", metadata.frame_count);
 	}
 
-	// Create SPR file
-	let mut spr = SprFile::new();
+	// Start from the base file so untouched frames keep their data and offsets
+	let mut spr = match &base {
+		Some(base) => SprFile::open(base)?,
+		None => SprFile::new(),
+	};
+	let base_frame_count = if base.is_some() {
+		spr.frame_count() as usize
+	} else {
+		0
+	};
+	let (mut kept, mut replaced) = (0, 0);
 
 	if verbose {
 		println!("\n🔧 Loading and encoding frames...");
@@ -623,12 +704,35 @@ fn handle_pack(
 		);
 
 		let frame = SprFrame::new(entry, sprite_pixels, mask_pixels);
-		spr.add_frame(frame)?;
+		let status = if frame_meta.index < base_frame_count {
+			// PNGs only hold colors, so compare what is drawn rather than the
+			// indices picked for palette entries sharing a color
+			let drawn_unchanged = spr.get_frame(frame_meta.index).is_some_and(|stored| {
+				stored.width() == frame.width()
+					&& stored.height() == frame.height()
+					&& stored.hotspot_x() == frame.hotspot_x()
+					&& stored.hotspot_y() == frame.hotspot_y()
+					&& stored.mask_pixels() == frame.mask_pixels()
+					&& stored.apply_palette_rgb(&palette) == frame.apply_palette_rgb(&palette)
+			});
+			if drawn_unchanged {
+				kept += 1;
+				"kept"
+			} else {
+				spr.replace_frame(frame_meta.index, &frame)?;
+				replaced += 1;
+				"replaced"
+			}
+		} else {
+			spr.add_frame(frame)?;
+			"added"
+		};
 
 		if verbose {
 			println!(
-				"   ✓ Frame {:3}: {}x{} (hotspot: {}, {})",
+				"   ✓ Frame {:3} {:8}: {}x{} (hotspot: {}, {})",
 				frame_meta.index,
+				status,
 				frame_meta.width,
 				frame_meta.height,
 				frame_meta.hotspot_x,
@@ -648,6 +752,9 @@ fn handle_pack(
 	if verbose {
 		println!("   ✓ Saved to {}", output.display());
 		println!("   ✓ File size: {} bytes", file_size);
+		if base.is_some() {
+			println!("   ✓ Frames kept: {}, replaced: {}", kept, replaced);
+		}
 		println!("\n✅ Packing completed successfully!");
 	} else {
 		println!(
@@ -662,6 +769,123 @@ fn handle_pack(
 	Ok(())
 }
 
+/// Handle import command
+#[allow(clippy::too_many_arguments)]
+fn handle_import(
+	images: Vec<PathBuf>,
+	output: PathBuf,
+	palette_path: PathBuf,
+	hotspot_x: u32,
+	hotspot_y: u32,
+	alpha_threshold: u8,
+	append: bool,
+	verbose: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+	if verbose {
+		println!("🔧 Importing frames");
+		println!("   Images:  {}", images.len());
+		println!("   Output:  {}", output.display());
+		println!("   Palette: {}", palette_path.display());
+	}
+
+	let palette = SprPalette::from_file(&palette_path)?;
+	let importer = SprFrameImporter::new(&palette).with_alpha_threshold(alpha_threshold);
+
+	let mut spr = if append && output.exists() {
+		SprFile::open(&output)?
+	} else {
+		SprFile::new()
+	};
+	let first_index = spr.frame_count();
+
+	for (offset, path) in images.iter().enumerate() {
+		let image = image::open(path)?.to_rgba8();
+		let frame =
+			importer.import(image.as_raw(), image.width(), image.height(), hotspot_x, hotspot_y)?;
+
+		if verbose {
+			let opaque = frame.mask_pixels().iter().filter(|&&mask| mask == 0x00).count();
+			println!(
+				"   ✓ Frame {:3}: {} -> {}x{}, {} opaque pixels",
+				first_index as usize + offset,
+				path.display(),
+				frame.width(),
+				frame.height(),
+				opaque
+			);
+		}
+		spr.add_frame(frame)?;
+	}
+
+	spr.save(&output)?;
+
+	println!(
+		"✓ Imported {} images -> {} ({} frames)",
+		images.len(),
+		output.display(),
+		spr.frame_count()
+	);
+
+	Ok(())
+}
+
+/// Handle atlas command
+fn handle_atlas(
+	inputs: Vec<PathBuf>,
+	output: PathBuf,
+	palette_path: Option<PathBuf>,
+	max_size: u32,
+	padding: u32,
+	verbose: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+	let palette_path = find_palette(palette_path, &inputs[0])?;
+	let palette = SprPalette::from_file(&palette_path)?;
+
+	if verbose {
+		println!("🧩 Packing texture atlas");
+		println!("   Inputs:  {}", inputs.len());
+		println!("   Output:  {}", output.display());
+		println!("   Palette: {}", palette_path.display());
+	}
+
+	let files = inputs
+		.iter()
+		.map(|path| {
+			let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+			SprFile::open(path).map(|file| (name, file))
+		})
+		.collect::<Result<Vec<_>, _>>()?;
+
+	let mut builder = AtlasBuilder::new().with_max_size(max_size).with_padding(padding);
+	for (name, file) in &files {
+		builder = builder.add_file(name.as_str(), file);
+	}
+	let atlas = builder.build(&palette)?;
+
+	fs::create_dir_all(&output)?;
+	for (index, (page, info)) in atlas.pages.iter().zip(&atlas.descriptor.pages).enumerate() {
+		RgbaImage::from(page).save(output.join(&info.image))?;
+
+		if verbose {
+			let frames = atlas.descriptor.frames.iter().filter(|frame| frame.page == index).count();
+			println!("   ✓ {}: {}x{}, {} frames", info.image, page.width, page.height, frames);
+		}
+	}
+
+	let descriptor_path = output.join("atlas.json");
+	fs::write(&descriptor_path, serde_json::to_string_pretty(&atlas.descriptor)?)?;
+
+	println!(
+		"✓ Packed {} frames from {} files -> {} pages, {}",
+		atlas.descriptor.frames.len(),
+		files.len(),
+		atlas.pages.len(),
+		descriptor_path.display()
+	);
+
+	Ok(())
+}
+
 /// Handle verify command
 fn handle_verify(
 	input: PathBuf,
@@ -937,6 +1161,114 @@ fn handle_extract_frame(
 	Ok(())
 }
 
+/// Handle optimize command
+fn handle_optimize(
+	input: PathBuf,
+	output: Option<PathBuf>,
+	no_trim: bool,
+	no_dedup: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+	let output = output.unwrap_or_else(|| input.clone());
+
+	println!("🗜️  Optimizing SPR file...");
+	println!("   Input:  {}", input.display());
+	println!("   Output: {}", output.display());
+
+	let spr = SprFile::open(&input)?;
+	let optimizer = SprOptimizer::new().with_trim(!no_trim).with_dedup(!no_dedup);
+	let (optimized, stats) = optimizer.optimize(&spr)?;
+	optimized.save(&output)?;
+
+	println!("   Trimmed frames:      {}", stats.frames_trimmed);
+	println!("   Deduplicated frames: {}", stats.frames_deduplicated);
+	println!(
+		"✓ {} -> {} bytes ({:.1}%)",
+		stats.bytes_before,
+		stats.bytes_after,
+		stats.bytes_after as f64 * 100.0 / stats.bytes_before.max(1) as f64
+	);
+
+	Ok(())
+}
+
+/// Parse a frame range like `3` or `0-7`
+fn parse_frame_range(
+	range: &str,
+	frame_count: usize,
+) -> Result<std::ops::RangeInclusive<usize>, Box<dyn std::error::Error>> {
+	let (start, end) = match range.split_once('-') {
+		Some((start, end)) => (start.trim().parse()?, end.trim().parse()?),
+		None => {
+			let index = range.trim().parse()?;
+			(index, index)
+		}
+	};
+	if start > end || end >= frame_count {
+		return Err(format!("Invalid frame range {} for {} frames", range, frame_count).into());
+	}
+	Ok(start..=end)
+}
+
+/// Handle mirror command
+fn handle_mirror(
+	input: PathBuf,
+	output: Option<PathBuf>,
+	frames: Option<String>,
+	vertical: bool,
+	append: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+	let output = output.unwrap_or_else(|| input.clone());
+	let mut spr = SprFile::open(&input)?;
+	let frame_count = spr.frame_count() as usize;
+	let range = match frames {
+		Some(frames) => parse_frame_range(&frames, frame_count)?,
+		None if frame_count > 0 => 0..=frame_count - 1,
+		None => return Err("SPR file has no frames".into()),
+	};
+
+	let mut mirrored: Vec<(usize, SprFrame)> = Vec::new();
+	for (index, frame) in range.filter_map(|index| spr.get_frame(index).map(|frame| (index, frame)))
+	{
+		let flipped = if vertical {
+			frame.flipped_vertical()
+		} else {
+			frame.flipped_horizontal()
+		};
+		let flipped = flipped.ok_or_else(|| {
+			format!("Frame {} has its hotspot outside the frame and cannot be mirrored", index)
+		})?;
+		mirrored.push((index, flipped));
+	}
+
+	for (index, frame) in &mirrored {
+		if append {
+			spr.add_frame(frame.clone())?;
+		} else {
+			spr.replace_frame(*index, frame)?;
+		}
+	}
+	spr.save(&output)?;
+
+	println!(
+		"✓ {} {} frames {} -> {} ({} frames)",
+		if append {
+			"Appended"
+		} else {
+			"Flipped"
+		},
+		mirrored.len(),
+		if vertical {
+			"top to bottom"
+		} else {
+			"left to right"
+		},
+		output.display(),
+		spr.frame_count()
+	);
+
+	Ok(())
+}
+
 /// Handle info command
 fn handle_info(input: PathBuf, detailed: bool) -> Result<(), Box<dyn std::error::Error>> {
 	println!("📄 SPR File Information");
@@ -993,8 +1325,53 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 			input,
 			output,
 			palette,
+			base,
 			verbose,
-		} => handle_pack(input, output, palette, verbose),
+		} => handle_pack(input, output, palette, base, verbose),
+
+		Commands::Import {
+			images,
+			output,
+			palette,
+			hotspot_x,
+			hotspot_y,
+			alpha_threshold,
+			append,
+			verbose,
+		} => handle_import(
+			images,
+			output,
+			palette,
+			hotspot_x,
+			hotspot_y,
+			alpha_threshold,
+			append,
+			verbose,
+		),
+
+		Commands::Atlas {
+			inputs,
+			output,
+			palette,
+			max_size,
+			padding,
+			verbose,
+		} => handle_atlas(inputs, output, palette, max_size, padding, verbose),
+
+		Commands::Optimize {
+			input,
+			output,
+			no_trim,
+			no_dedup,
+		} => handle_optimize(input, output, no_trim, no_dedup),
+
+		Commands::Mirror {
+			input,
+			output,
+			frames,
+			vertical,
+			append,
+		} => handle_mirror(input, output, frames, vertical, append),
 
 		Commands::Verify {
 			input,